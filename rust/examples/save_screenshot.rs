@@ -5,8 +5,13 @@ fn main() {
 
     let args: Vec<String> = std::env::args().collect();
     let active_flag = args.iter().any(|a| a == "--active-window" || a == "active");
+    let options = autoanchor_core::CaptureOptions {
+        active_window: active_flag,
+        draw_cursor: args.iter().any(|a| a == "--cursor"),
+        ..Default::default()
+    };
 
-    match autoanchor_core::screen::windows::take_screenshot(active_flag) {
+    match autoanchor_core::take_screenshot(&options) {
         Ok(bytes) => {
             let filename = if active_flag { "screenshot_example_active.png" } else { "screenshot_example.png" };
             if let Err(e) = std::fs::write(filename, &bytes) {
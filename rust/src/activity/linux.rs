@@ -0,0 +1,98 @@
+//! Input activity on X11, from the MIT-SCREEN-SAVER extension's idle counter and the
+//! core keyboard and pointer state.
+
+use super::LockKeys;
+use crate::events::linux::{button_name, key_name};
+use crate::xserver::XConnection;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_uint};
+use std::ptr;
+use std::time::Duration;
+use x11::{xlib, xss};
+
+pub fn idle_time() -> Result<Duration, String> {
+    let conn = XConnection::open()?;
+    let (mut event_base, mut error_base) = (0, 0);
+    if unsafe { xss::XScreenSaverQueryExtension(conn.display, &mut event_base, &mut error_base) }
+        == 0
+    {
+        return Err("The X server lacks the MIT-SCREEN-SAVER extension".to_string());
+    }
+    let mut info: xss::XScreenSaverInfo = unsafe { std::mem::zeroed() };
+    if unsafe { xss::XScreenSaverQueryInfo(conn.display, conn.root, &mut info) } == 0 {
+        return Err("Cannot query the idle time".to_string());
+    }
+    Ok(Duration::from_millis(info.idle as u64))
+}
+
+pub fn pressed_keys() -> Result<Vec<String>, String> {
+    let conn = XConnection::open()?;
+    let mut keymap: [c_char; 32] = [0; 32];
+    unsafe { xlib::XQueryKeymap(conn.display, keymap.as_mut_ptr()) };
+    let mut keys: Vec<String> = Vec::new();
+    for keycode in 8..=255u8 {
+        if keymap[keycode as usize / 8] as u8 & (1 << (keycode % 8)) != 0 {
+            let name = key_name(conn.display, keycode);
+            if !keys.contains(&name) {
+                keys.push(name);
+            }
+        }
+    }
+    Ok(keys)
+}
+
+pub fn pressed_buttons() -> Result<Vec<String>, String> {
+    let conn = XConnection::open()?;
+    let (mut root, mut child): (xlib::Window, xlib::Window) = (0, 0);
+    let (mut x, mut y, mut window_x, mut window_y) = (0, 0, 0, 0);
+    let mut mask: c_uint = 0;
+    unsafe {
+        xlib::XQueryPointer(
+            conn.display,
+            conn.root,
+            &mut root,
+            &mut child,
+            &mut x,
+            &mut y,
+            &mut window_x,
+            &mut window_y,
+            &mut mask,
+        )
+    };
+    // Buttons 4 and 5 are wheel steps, never held for long
+    let buttons = [
+        (1, xlib::Button1Mask),
+        (2, xlib::Button2Mask),
+        (3, xlib::Button3Mask),
+    ];
+    Ok(buttons
+        .iter()
+        .filter(|(_, bit)| mask & bit != 0)
+        .map(|(button, _)| button_name(*button))
+        .collect())
+}
+
+pub fn lock_keys() -> Result<LockKeys, String> {
+    let conn = XConnection::open()?;
+    let indicator = |name: &str| {
+        let name = CString::new(name).unwrap();
+        let mut on: c_int = 0;
+        unsafe {
+            let atom = xlib::XInternAtom(conn.display, name.as_ptr(), xlib::False);
+            let found = xlib::XkbGetNamedIndicator(
+                conn.display,
+                atom,
+                ptr::null_mut(),
+                &mut on,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            );
+            (found != 0).then_some(on != 0)
+        }
+    };
+    Ok(LockKeys {
+        caps_lock: indicator("Caps Lock"),
+        num_lock: indicator("Num Lock"),
+        scroll_lock: indicator("Scroll Lock"),
+    })
+}
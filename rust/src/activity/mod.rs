@@ -0,0 +1,300 @@
+//! The person at the computer: how long since they last gave input, what they are
+//! holding down, and getting out of their way when they take over the mouse or keyboard
+//! while automation runs.
+
+#[cfg(target_os = "linux")]
+pub mod linux;
+#[cfg(target_os = "windows")]
+pub mod windows;
+
+#[cfg(target_os = "linux")]
+use linux as platform;
+#[cfg(target_os = "windows")]
+use windows as platform;
+
+use super::{get_cursor_position, AutomationError, Point};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+mod platform {
+    use super::LockKeys;
+    use std::time::Duration;
+
+    const UNSUPPORTED: &str = "Unsupported platform - Windows and X11 only for now";
+
+    pub fn idle_time() -> Result<Duration, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn pressed_keys() -> Result<Vec<String>, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn pressed_buttons() -> Result<Vec<String>, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn lock_keys() -> Result<LockKeys, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+}
+
+/// Idle counters tick coarsely and noting an action takes a moment; input this close
+/// to the last action is taken to be the action itself.
+const IDLE_SLACK: Duration = Duration::from_millis(100);
+/// How often a paused action checks whether the user has let go.
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+static CONFIG: Mutex<Option<YieldOnActivity>> = Mutex::new(None);
+static LAST_ACTION: Mutex<Option<Baseline>> = Mutex::new(None);
+
+/// What an input operation does when the user has given input since the last one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum YieldPolicy {
+    /// Carry on regardless.
+    #[default]
+    Off,
+    /// Wait until the user has been idle for [`YieldOnActivity::resume_after_ms`].
+    Pause,
+    /// Fail with [`AutomationError::UserActivity`].
+    Abort,
+}
+
+impl FromStr for YieldPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" | "none" => Ok(YieldPolicy::Off),
+            "pause" | "wait" => Ok(YieldPolicy::Pause),
+            "abort" | "stop" => Ok(YieldPolicy::Abort),
+            _ => Err(format!("Unsupported yield policy: {}", s)),
+        }
+    }
+}
+
+/// Yield-on-activity settings; off by default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YieldOnActivity {
+    pub policy: YieldPolicy,
+    /// Pixels the cursor may drift from where the last action left it.
+    pub tolerance: u32,
+    /// How long the user must stay idle before a paused action goes ahead.
+    pub resume_after_ms: u64,
+    /// Give up pausing after this long and fail as with [`YieldPolicy::Abort`];
+    /// `None` waits for as long as the user keeps going.
+    pub max_pause_ms: Option<u64>,
+}
+
+impl Default for YieldOnActivity {
+    fn default() -> Self {
+        YieldOnActivity {
+            policy: YieldPolicy::Off,
+            tolerance: 2,
+            resume_after_ms: 2000,
+            max_pause_ms: None,
+        }
+    }
+}
+
+/// Real user input noticed since the last automated action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Activity {
+    /// The cursor is no longer where the last action left it.
+    CursorMoved { expected: Point, actual: Point },
+    /// Keyboard or pointer input arrived after the last action, `idle_ms` ago.
+    Input { idle_ms: u64 },
+}
+
+impl fmt::Display for Activity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Activity::CursorMoved { expected, actual } => write!(
+                f,
+                "cursor moved from ({}, {}) to ({}, {})",
+                expected.x, expected.y, actual.x, actual.y
+            ),
+            Activity::Input { idle_ms } => write!(f, "input {}ms ago", idle_ms),
+        }
+    }
+}
+
+/// Which toggle keys are on. A key is `None` when its state cannot be told, e.g. when the
+/// X keyboard map has no indicator for it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockKeys {
+    pub caps_lock: Option<bool>,
+    pub num_lock: Option<bool>,
+    pub scroll_lock: Option<bool>,
+}
+
+/// Where things stood right after the last automated action.
+#[derive(Debug, Clone, Copy)]
+struct Baseline {
+    cursor: Option<Point>,
+    at: Instant,
+}
+
+/// Time since the last keyboard or pointer input from any source, synthetic input
+/// included.
+pub fn idle_time() -> Result<Duration, String> {
+    platform::idle_time()
+}
+
+/// Keys held down right now, named as [`crate::press_key`] accepts them; left and right
+/// modifiers both show as e.g. `ctrl`.
+pub fn pressed_keys() -> Result<Vec<String>, String> {
+    platform::pressed_keys()
+}
+
+/// Mouse buttons held down right now: `left`, `middle`, `right`, and on Windows `x1`
+/// and `x2`.
+pub fn pressed_buttons() -> Result<Vec<String>, String> {
+    platform::pressed_buttons()
+}
+
+pub fn lock_keys() -> Result<LockKeys, String> {
+    platform::lock_keys()
+}
+
+pub fn set_yield_on_activity(config: YieldOnActivity) {
+    *CONFIG.lock().unwrap() = Some(config);
+    *LAST_ACTION.lock().unwrap() = None;
+}
+
+pub fn yield_on_activity() -> YieldOnActivity {
+    CONFIG.lock().unwrap().clone().unwrap_or_default()
+}
+
+/// Looks for user input since the last automated action and pauses or fails as the
+/// policy says. Nothing is checked before the first action, and each kind of check
+/// is skipped where the cursor or the idle time cannot be read.
+pub fn check() -> Result<(), AutomationError> {
+    let config = yield_on_activity();
+    if config.policy == YieldPolicy::Off {
+        return Ok(());
+    }
+    let Some(activity) = detect(&config) else {
+        return Ok(());
+    };
+    if config.policy == YieldPolicy::Abort {
+        return Err(AutomationError::UserActivity { activity });
+    }
+    wait_for_idle(&config, activity)?;
+    note_action();
+    Ok(())
+}
+
+/// Records the state an automated action left things in, for the next [`check`].
+pub(crate) fn note_action() {
+    if yield_on_activity().policy == YieldPolicy::Off {
+        return;
+    }
+    *LAST_ACTION.lock().unwrap() = Some(Baseline {
+        cursor: get_cursor_position().ok(),
+        at: Instant::now(),
+    });
+}
+
+fn detect(config: &YieldOnActivity) -> Option<Activity> {
+    let baseline = (*LAST_ACTION.lock().unwrap())?;
+    if let (Some(expected), Ok(actual)) = (baseline.cursor, get_cursor_position()) {
+        if drifted(expected, actual, config.tolerance) {
+            return Some(Activity::CursorMoved { expected, actual });
+        }
+    }
+    let idle = idle_time().ok()?;
+    is_newer_input(baseline.at.elapsed(), idle).then_some(Activity::Input {
+        idle_ms: idle.as_millis() as u64,
+    })
+}
+
+fn drifted(expected: Point, actual: Point, tolerance: u32) -> bool {
+    let tolerance = tolerance as i64;
+    (actual.x as i64 - expected.x as i64).abs() > tolerance
+        || (actual.y as i64 - expected.y as i64).abs() > tolerance
+}
+
+/// Whether the last input came after the last action, `since_action` ago.
+fn is_newer_input(since_action: Duration, idle: Duration) -> bool {
+    idle + IDLE_SLACK < since_action
+}
+
+/// Blocks until the user has been idle long enough; where the idle time cannot be
+/// read, that is simply a pause of that length.
+fn wait_for_idle(config: &YieldOnActivity, activity: Activity) -> Result<(), AutomationError> {
+    let started = Instant::now();
+    let resume_after = Duration::from_millis(config.resume_after_ms);
+    let max_pause = config.max_pause_ms.map(Duration::from_millis);
+    loop {
+        let idle = idle_time().unwrap_or_else(|_| started.elapsed());
+        if idle >= resume_after {
+            return Ok(());
+        }
+        if max_pause.is_some_and(|max| started.elapsed() >= max) {
+            return Err(AutomationError::UserActivity { activity });
+        }
+        thread::sleep(PAUSE_POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_user_input_from_own_actions() {
+        let at = |x, y| Point { x, y };
+        assert!(!drifted(at(100, 100), at(102, 99), 2));
+        assert!(drifted(at(100, 100), at(103, 100), 2));
+        assert!(drifted(at(0, 0), at(0, -5), 2));
+
+        let ms = Duration::from_millis;
+        // Idle since about when the last action ran: nobody else touched anything
+        assert!(!is_newer_input(ms(500), ms(480)));
+        assert!(!is_newer_input(ms(500), ms(900)));
+        // Input long after the last action
+        assert!(is_newer_input(ms(500), ms(200)));
+    }
+
+    #[test]
+    fn policies_parse_and_activity_reads_well() {
+        assert_eq!("Pause".parse::<YieldPolicy>(), Ok(YieldPolicy::Pause));
+        assert_eq!("abort".parse::<YieldPolicy>(), Ok(YieldPolicy::Abort));
+        assert!("later".parse::<YieldPolicy>().is_err());
+
+        let message: String = AutomationError::UserActivity {
+            activity: Activity::CursorMoved {
+                expected: Point { x: 10, y: 20 },
+                actual: Point { x: 300, y: 40 },
+            },
+        }
+        .into();
+        assert_eq!(
+            message,
+            "User activity detected: cursor moved from (10, 20) to (300, 40)"
+        );
+        assert!(AutomationError::is_user_activity(&message));
+        assert!(!AutomationError::is_fail_safe(&message));
+    }
+
+    #[test]
+    fn unknown_lock_keys_serialize_as_null() {
+        let locks = LockKeys {
+            caps_lock: Some(true),
+            num_lock: Some(false),
+            scroll_lock: None,
+        };
+        assert_eq!(
+            serde_json::to_value(locks).unwrap(),
+            serde_json::json!({"caps_lock": true, "num_lock": false, "scroll_lock": null})
+        );
+    }
+}
@@ -0,0 +1,67 @@
+//! Input activity on Windows.
+
+use super::LockKeys;
+use crate::events::windows::key_name;
+use std::mem;
+use std::time::Duration;
+use winapi::um::sysinfoapi::GetTickCount;
+use winapi::um::winuser::*;
+
+/// Mouse buttons by virtual-key code, which `GetAsyncKeyState` reports like keys.
+const BUTTONS: [(i32, &str); 5] = [
+    (VK_LBUTTON, "left"),
+    (VK_RBUTTON, "right"),
+    (VK_MBUTTON, "middle"),
+    (VK_XBUTTON1, "x1"),
+    (VK_XBUTTON2, "x2"),
+];
+
+fn is_down(vk: i32) -> bool {
+    unsafe { GetAsyncKeyState(vk) as u16 & 0x8000 != 0 }
+}
+
+pub fn idle_time() -> Result<Duration, String> {
+    let mut info = LASTINPUTINFO {
+        cbSize: mem::size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    if unsafe { GetLastInputInfo(&mut info) } == 0 {
+        return Err("GetLastInputInfo failed".to_string());
+    }
+    // Both are tick counts, which wrap around every 49.7 days
+    let idle = unsafe { GetTickCount() }.wrapping_sub(info.dwTime);
+    Ok(Duration::from_millis(idle as u64))
+}
+
+pub fn pressed_keys() -> Result<Vec<String>, String> {
+    let mut keys: Vec<String> = Vec::new();
+    // Codes below 0x08 are mouse buttons
+    for vk in 0x08..=0xFE {
+        if !is_down(vk) {
+            continue;
+        }
+        // Modifiers are down as both the generic and the left or right key
+        let name = key_name(vk as u16);
+        if !keys.contains(&name) {
+            keys.push(name);
+        }
+    }
+    Ok(keys)
+}
+
+pub fn pressed_buttons() -> Result<Vec<String>, String> {
+    Ok(BUTTONS
+        .iter()
+        .filter(|(vk, _)| is_down(*vk))
+        .map(|(_, name)| name.to_string())
+        .collect())
+}
+
+pub fn lock_keys() -> Result<LockKeys, String> {
+    let toggled = |vk| Some(unsafe { GetKeyState(vk) } & 1 != 0);
+    Ok(LockKeys {
+        caps_lock: toggled(VK_CAPITAL),
+        num_lock: toggled(VK_NUMLOCK),
+        scroll_lock: toggled(VK_SCROLL),
+    })
+}
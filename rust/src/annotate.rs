@@ -0,0 +1,402 @@
+use super::{font, Point, Rect};
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// Red used for markers unless a color is given.
+pub const DEFAULT_COLOR: [u8; 4] = [255, 40, 40, 255];
+/// Largest accepted crosshair size, in pixels.
+pub const MAX_CROSSHAIR_SIZE: u32 = 512;
+/// Largest accepted rectangle outline thickness, in pixels.
+pub const MAX_THICKNESS: u32 = 64;
+/// Largest accepted label scale.
+pub const MAX_LABEL_SCALE: u32 = 16;
+
+/// Something to draw onto a capture, in screen coordinates.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Annotation {
+    /// Cross with a ring around `at`, e.g. where a click is about to land.
+    Crosshair {
+        at: Point,
+        #[serde(default = "default_crosshair_size")]
+        size: u32,
+        #[serde(default = "default_color")]
+        color: [u8; 4],
+    },
+    /// Outline of `rect`.
+    Rectangle {
+        rect: Rect,
+        #[serde(default = "default_thickness")]
+        thickness: u32,
+        #[serde(default = "default_color")]
+        color: [u8; 4],
+    },
+    /// Text with its top-left corner at `at`, drawn with the bundled glyph font.
+    Label {
+        at: Point,
+        text: String,
+        #[serde(default = "default_label_scale")]
+        scale: u32,
+        #[serde(default = "default_color")]
+        color: [u8; 4],
+        /// Box behind the text so it stays readable on busy backgrounds.
+        #[serde(default = "default_label_background")]
+        background: Option<[u8; 4]>,
+    },
+}
+
+fn default_color() -> [u8; 4] {
+    DEFAULT_COLOR
+}
+
+fn default_crosshair_size() -> u32 {
+    24
+}
+
+fn default_thickness() -> u32 {
+    2
+}
+
+fn default_label_scale() -> u32 {
+    2
+}
+
+fn default_label_background() -> Option<[u8; 4]> {
+    Some([255, 255, 255, 220])
+}
+
+/// Rejects annotations drawn bigger than a screen could show, before anything is captured.
+pub fn check(annotations: &[Annotation]) -> Result<(), String> {
+    for annotation in annotations {
+        let (name, value, max) = match annotation {
+            Annotation::Crosshair { size, .. } => ("Crosshair size", *size, MAX_CROSSHAIR_SIZE),
+            Annotation::Rectangle { thickness, .. } => {
+                ("Rectangle thickness", *thickness, MAX_THICKNESS)
+            }
+            Annotation::Label { scale, .. } => ("Label scale", *scale, MAX_LABEL_SCALE),
+        };
+        if value > max {
+            return Err(format!("{} {} exceeds the maximum of {}", name, value, max));
+        }
+    }
+    Ok(())
+}
+
+/// Draws `annotations` onto `image`, which was captured with its top-left corner at
+/// `origin` on the screen.
+pub fn annotate(image: &mut RgbaImage, origin: Point, annotations: &[Annotation]) {
+    for annotation in annotations {
+        match annotation {
+            Annotation::Crosshair { at, size, color } => {
+                draw_crosshair(image, relative(*at, origin), *size, Rgba(*color))
+            }
+            Annotation::Rectangle {
+                rect,
+                thickness,
+                color,
+            } => {
+                let at = relative(
+                    Point {
+                        x: rect.x,
+                        y: rect.y,
+                    },
+                    origin,
+                );
+                let rect = Rect {
+                    x: at.x,
+                    y: at.y,
+                    ..*rect
+                };
+                draw_rectangle(image, rect, *thickness, Rgba(*color))
+            }
+            Annotation::Label {
+                at,
+                text,
+                scale,
+                color,
+                background,
+            } => draw_label(
+                image,
+                relative(*at, origin),
+                text,
+                *scale,
+                Rgba(*color),
+                background.map(Rgba),
+            ),
+        }
+    }
+}
+
+fn relative(point: Point, origin: Point) -> Point {
+    Point {
+        x: point.x - origin.x,
+        y: point.y - origin.y,
+    }
+}
+
+/// Alpha-blends `color` over the pixel at `x`, `y`, ignoring points outside the image.
+fn blend(image: &mut RgbaImage, x: i64, y: i64, color: Rgba<u8>) {
+    if x < 0 || y < 0 || x >= image.width() as i64 || y >= image.height() as i64 {
+        return;
+    }
+    let pixel = image.get_pixel_mut(x as u32, y as u32);
+    let alpha = color.0[3] as u32;
+    for channel in 0..3 {
+        let under = pixel.0[channel] as u32;
+        pixel.0[channel] = ((color.0[channel] as u32 * alpha + under * (255 - alpha)) / 255) as u8;
+    }
+    pixel.0[3] = pixel.0[3].max(color.0[3]);
+}
+
+fn fill(image: &mut RgbaImage, x: i64, y: i64, width: i64, height: i64, color: Rgba<u8>) {
+    // Only the part inside the image is visited, however large the area
+    let (x0, x1) = (x.max(0), x.saturating_add(width).min(image.width() as i64));
+    let (y0, y1) = (
+        y.max(0),
+        y.saturating_add(height).min(image.height() as i64),
+    );
+    for py in y0..y1 {
+        for px in x0..x1 {
+            blend(image, px, py, color);
+        }
+    }
+}
+
+pub fn draw_crosshair(image: &mut RgbaImage, at: Point, size: u32, color: Rgba<u8>) {
+    let (x, y, half) = (at.x as i64, at.y as i64, size as i64 / 2);
+    // Leave the centre pixel clear so the exact target stays visible
+    fill(image, x - half, y, half - 1, 1, color);
+    fill(image, x + 2, y, half - 1, 1, color);
+    fill(image, x, y - half, 1, half - 1, color);
+    fill(image, x, y + 2, 1, half - 1, color);
+    draw_ring(image, at, (half / 2).max(3), 1, color);
+}
+
+pub fn draw_rectangle(image: &mut RgbaImage, rect: Rect, thickness: u32, color: Rgba<u8>) {
+    let (x, y) = (rect.x as i64, rect.y as i64);
+    let (width, height) = (rect.width as i64, rect.height as i64);
+    let t = (thickness as i64).clamp(1, width.min(height).max(1));
+    fill(image, x, y, width, t, color);
+    fill(image, x, y + height - t, width, t, color);
+    fill(image, x, y + t, t, height - 2 * t, color);
+    fill(image, x + width - t, y + t, t, height - 2 * t, color);
+}
+
+pub fn draw_label(
+    image: &mut RgbaImage,
+    at: Point,
+    text: &str,
+    scale: u32,
+    color: Rgba<u8>,
+    background: Option<Rgba<u8>>,
+) {
+    let scale = scale.max(1);
+    let padding = 2 * scale as i64;
+    if let Some(background) = background {
+        let (width, height) = font::text_size(text, scale);
+        fill(
+            image,
+            at.x as i64,
+            at.y as i64,
+            width as i64 + 2 * padding,
+            height as i64 + 2 * padding,
+            background,
+        );
+    }
+    let origin = Point {
+        x: (at.x as i64 + padding).min(i32::MAX as i64) as i32,
+        y: (at.y as i64 + padding).min(i32::MAX as i64) as i32,
+    };
+    font::draw_text(image, origin, text, scale, color);
+}
+
+/// Circle outline of `radius` around `at`, `thickness` pixels wide inwards.
+pub fn draw_ring(image: &mut RgbaImage, at: Point, radius: i64, thickness: i64, color: Rgba<u8>) {
+    let inner = (radius - thickness).max(0);
+    let (x, y) = (at.x as i64, at.y as i64);
+    // Offsets that land inside the image
+    let dys = (-radius).max(-y)..=radius.min(image.height() as i64 - 1 - y);
+    let dxs = (-radius).max(-x)..=radius.min(image.width() as i64 - 1 - x);
+    for dy in dys {
+        for dx in dxs.clone() {
+            // Squares of far-off points and huge radii do not fit an i64
+            let d = (dx as i128).pow(2) + (dy as i128).pow(2);
+            if d <= (radius as i128).pow(2) && d > (inner as i128).pow(2) {
+                blend(image, x + dx, y + dy, color);
+            }
+        }
+    }
+}
+
+/// Marks a click that already happened; used by screen recordings.
+pub fn draw_click_marker(image: &mut RgbaImage, at: Point) {
+    draw_ring(image, at, 12, 3, Rgba(DEFAULT_COLOR));
+}
+
+/// Classic arrow pointer, hotspot at the top-left: `B` outline, `W` fill.
+const CURSOR_SPRITE: [&str; 16] = [
+    "B",
+    "BB",
+    "BWB",
+    "BWWB",
+    "BWWWB",
+    "BWWWWB",
+    "BWWWWWB",
+    "BWWWWWWB",
+    "BWWWWWWWB",
+    "BWWWWWWWWB",
+    "BWWWWWBBBB",
+    "BWWBWWB",
+    "BWB BWWB",
+    "BB  BWWB",
+    "B    BWWB",
+    "     BBB",
+];
+
+/// Draws a mouse pointer with its hotspot at `at`; screen capture APIs leave the real
+/// one out.
+pub fn draw_cursor(image: &mut RgbaImage, at: Point) {
+    for (dy, row) in CURSOR_SPRITE.iter().enumerate() {
+        for (dx, c) in row.chars().enumerate() {
+            let color = match c {
+                'B' => Rgba([0, 0, 0, 255]),
+                'W' => Rgba([255, 255, 255, 255]),
+                _ => continue,
+            };
+            blend(
+                image,
+                at.x as i64 + dx as i64,
+                at.y as i64 + dy as i64,
+                color,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAY: Rgba<u8> = Rgba([128, 128, 128, 255]);
+    const RED: Rgba<u8> = Rgba(DEFAULT_COLOR);
+
+    #[test]
+    fn annotations_are_drawn_in_screen_coordinates() {
+        let mut image = RgbaImage::from_pixel(60, 40, GRAY);
+        let annotations = [Annotation::Rectangle {
+            rect: Rect {
+                x: 110,
+                y: 205,
+                width: 20,
+                height: 10,
+            },
+            thickness: 1,
+            color: DEFAULT_COLOR,
+        }];
+        annotate(&mut image, Point { x: 100, y: 200 }, &annotations);
+
+        assert_eq!(image.get_pixel(10, 5), &RED);
+        assert_eq!(image.get_pixel(29, 14), &RED);
+        // Outline only
+        assert_eq!(image.get_pixel(20, 10), &GRAY);
+        assert_eq!(image.get_pixel(9, 5), &GRAY);
+    }
+
+    #[test]
+    fn crosshair_leaves_target_pixel_visible() {
+        let mut image = RgbaImage::from_pixel(60, 60, GRAY);
+        draw_crosshair(&mut image, Point { x: 30, y: 30 }, 24, RED);
+        assert_eq!(image.get_pixel(30, 30), &GRAY);
+        assert_eq!(image.get_pixel(20, 30), &RED);
+        assert_eq!(image.get_pixel(30, 40), &RED);
+    }
+
+    #[test]
+    fn label_has_background_and_clips_at_edges() {
+        let mut image = RgbaImage::from_pixel(30, 20, GRAY);
+        let white = Rgba([255, 255, 255, 255]);
+        draw_label(
+            &mut image,
+            Point { x: 1, y: 1 },
+            "Submit",
+            1,
+            RED,
+            Some(white),
+        );
+        assert_eq!(image.get_pixel(1, 1), &white);
+        assert!(image.pixels().any(|p| *p == RED));
+    }
+
+    #[test]
+    fn huge_annotations_only_visit_the_image() {
+        let mut image = RgbaImage::from_pixel(40, 30, GRAY);
+        draw_crosshair(&mut image, Point { x: 20, y: 15 }, u32::MAX, RED);
+        draw_ring(
+            &mut image,
+            Point { x: -5, y: 4 },
+            i64::from(u32::MAX),
+            1,
+            RED,
+        );
+        let rect = Rect {
+            x: i32::MIN,
+            y: i32::MIN,
+            width: u32::MAX,
+            height: u32::MAX,
+        };
+        draw_rectangle(&mut image, rect, u32::MAX, RED);
+        draw_label(
+            &mut image,
+            Point { x: i32::MAX, y: 0 },
+            "Submit",
+            u32::MAX,
+            RED,
+            Some(RED),
+        );
+        assert_eq!(image.dimensions(), (40, 30));
+    }
+
+    #[test]
+    fn oversized_annotations_are_refused() {
+        let label = |scale| Annotation::Label {
+            at: Point { x: 0, y: 0 },
+            text: "step 3".to_string(),
+            scale,
+            color: DEFAULT_COLOR,
+            background: None,
+        };
+        assert_eq!(check(&[label(MAX_LABEL_SCALE)]), Ok(()));
+        assert!(check(&[label(MAX_LABEL_SCALE + 1)]).is_err());
+        let crosshair = Annotation::Crosshair {
+            at: Point { x: 0, y: 0 },
+            size: u32::MAX,
+            color: DEFAULT_COLOR,
+        };
+        assert!(check(&[crosshair]).is_err());
+    }
+
+    #[test]
+    fn annotation_json_uses_defaults() {
+        let parsed: Vec<Annotation> = serde_json::from_str(
+            r#"[{"kind": "crosshair", "at": {"x": 5, "y": 6}},
+                {"kind": "label", "at": {"x": 0, "y": 0}, "text": "step 3", "background": null}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            parsed[0],
+            Annotation::Crosshair {
+                at: Point { x: 5, y: 6 },
+                size: 24,
+                color: DEFAULT_COLOR
+            }
+        );
+        assert!(matches!(
+            parsed[1],
+            Annotation::Label {
+                scale: 2,
+                background: None,
+                ..
+            }
+        ));
+    }
+}
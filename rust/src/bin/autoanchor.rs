@@ -1,6 +1,26 @@
-use clap::{Parser, Subcommand};
-use serde_json;
-use autoanchor_core::{Point, AutomationResult, get_cursor_position, move_cursor, click, type_text, press_key, get_screen_size};
+use clap::{Args, Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+use autoanchor_core::{Point, Rect, AutomationResult, get_cursor_position, move_cursor, move_cursor_in_window, click, click_in_window, type_text_with, TypeMode, press_key, get_screen_size, capture_screen, take_screenshot, encode_png, CaptureOptions};
+use autoanchor_core::activity::{self, YieldOnActivity, YieldPolicy};
+use autoanchor_core::annotate::{self, Annotation};
+use autoanchor_core::clipboard;
+use autoanchor_core::events;
+use autoanchor_core::failsafe::{self, FailSafe};
+use autoanchor_core::hash::{region_hash, HashAlgorithm, ImageHash};
+use autoanchor_core::hotkey;
+use autoanchor_core::macros::{self, Macro, MacroInput, MacroRecorder, PlayOptions};
+use autoanchor_core::mcp::{self, McpOptions};
+use autoanchor_core::process::{self, LaunchOptions};
+use autoanchor_core::record::{RecordFormat, RecordOptions, Recorder};
+use autoanchor_core::redact::{self, Mask, MaskStyle, MaskTarget};
+use autoanchor_core::http::{self, Reply, Response};
+use autoanchor_core::rpc::{self, RpcError};
+use autoanchor_core::script::{self, RunOptions, StepResult};
+use autoanchor_core::websocket::WebSocket;
+use autoanchor_core::window::{self, WindowMatcher, WindowTarget};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 #[derive(Parser)]
 #[command(name = "autoanchor")]
@@ -8,6 +28,48 @@ use autoanchor_core::{Point, AutomationResult, get_cursor_position, move_cursor,
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// JSON file with masks applied to every screenshot and recording
+    #[arg(long, global = true, env = "AUTOANCHOR_MASKS")]
+    masks: Option<PathBuf>,
+    /// Do not abort input operations while the cursor is in the top-left screen corner
+    #[arg(long, global = true)]
+    no_failsafe: bool,
+    /// What input operations do after the user touched the mouse or keyboard: off, pause (until they are idle again) or abort
+    #[arg(long, global = true, default_value = "off", env = "AUTOANCHOR_YIELD")]
+    yield_on_activity: YieldPolicy,
+    /// Milliseconds the user must stay idle before paused operations go ahead
+    #[arg(long, global = true, default_value_t = 2000)]
+    resume_after_ms: u64,
+}
+
+#[derive(Args)]
+struct MaskArgs {
+    /// Hide a screen area given as X Y WIDTH HEIGHT (repeatable)
+    #[arg(long, num_args = 4, value_names = ["X", "Y", "WIDTH", "HEIGHT"], allow_negative_numbers = true, action = clap::ArgAction::Append)]
+    mask: Vec<i32>,
+    /// How --mask areas are hidden: blackout or blur
+    #[arg(long, default_value = "blackout")]
+    mask_style: MaskStyle,
+}
+
+#[derive(Args)]
+struct WindowArgs {
+    /// Make coordinates relative to the client area of the topmost window whose title matches this regular expression
+    #[arg(long, conflicts_with = "window_id")]
+    window: Option<String>,
+    /// Make coordinates relative to the client area of this window id
+    #[arg(long)]
+    window_id: Option<u64>,
+}
+
+impl WindowArgs {
+    fn target(self) -> Option<WindowTarget> {
+        match (self.window, self.window_id) {
+            (_, Some(id)) => Some(WindowTarget::Id(id)),
+            (Some(title), None) => Some(WindowTarget::Matcher(WindowMatcher::title(&title))),
+            (None, None) => None,
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -18,18 +80,25 @@ enum Commands {
     MoveCursor {
         x: i32,
         y: i32,
+        #[command(flatten)]
+        window: WindowArgs,
     },
     /// Click at specified coordinates or current position
     Click {
         button: String,
         x: Option<i32>,
         y: Option<i32>,
+        #[command(flatten)]
+        window: WindowArgs,
     },
     /// Type text
     TypeText {
         text: String,
         /// Optional per-key delay in milliseconds (slower typing)
         delay_ms: Option<u64>,
+        /// keys (one key press per character) or paste (through the clipboard, restored afterwards)
+        #[arg(long, default_value = "keys")]
+        mode: TypeMode,
     },
     /// Press a key with optional modifiers
     PressKey {
@@ -38,18 +107,445 @@ enum Commands {
     },
     /// Get screen size
     ScreenSize,
+    /// Milliseconds since the last keyboard or mouse input
+    IdleTime,
+    /// List the keys held down right now
+    PressedKeys,
+    /// List the mouse buttons held down right now
+    PressedButtons,
+    /// Get the Caps Lock, Num Lock and Scroll Lock states (null where unknown)
+    LockKeys,
     /// Take a screenshot and return it as base64-encoded PNG
     Screenshot {
         /// Capture only the active/foreground window
         #[arg(long)]
         active_window: bool,
+        /// Region to capture as X Y WIDTH HEIGHT (defaults to the primary screen, or the whole window with --window)
+        #[arg(long, num_args = 4, value_names = ["X", "Y", "WIDTH", "HEIGHT"], allow_negative_numbers = true)]
+        region: Option<Vec<i32>>,
+        #[command(flatten)]
+        window: WindowArgs,
+        /// Draw the mouse cursor into the screenshot
+        #[arg(long)]
+        cursor: bool,
+        /// Mark a screen point with a crosshair (repeatable)
+        #[arg(long, num_args = 2, value_names = ["X", "Y"], allow_negative_numbers = true, action = clap::ArgAction::Append)]
+        crosshair: Vec<i32>,
+        /// JSON array of annotations, e.g. '[{"kind":"label","at":{"x":10,"y":10},"text":"step 3"}]'
+        #[arg(long)]
+        annotations: Option<String>,
+        #[command(flatten)]
+        mask: MaskArgs,
+    },
+    /// Compute a perceptual hash of the screen or an image file
+    Hash {
+        /// Hash algorithm: ahash, dhash or phash
+        #[arg(long, default_value = "dhash")]
+        algo: HashAlgorithm,
+        /// Region to hash as X Y WIDTH HEIGHT (defaults to the whole image)
+        #[arg(long, num_args = 4, value_names = ["X", "Y", "WIDTH", "HEIGHT"], allow_negative_numbers = true)]
+        region: Option<Vec<i32>>,
+        /// Hash this PNG file instead of capturing the primary screen
+        #[arg(long)]
+        file: Option<String>,
+        /// Previously computed hash to report the Hamming distance against
+        #[arg(long)]
+        compare: Option<ImageHash>,
+    },
+    /// Record the screen to an animated GIF, APNG or a directory of PNG frames
+    Record {
+        /// Frames per second
+        #[arg(long, default_value_t = 5.0)]
+        fps: f64,
+        /// Seconds to record; without it, recording stops on Enter or end of stdin
+        #[arg(long)]
+        duration: Option<f64>,
+        /// Output file (.gif, .png) or directory for a PNG sequence
+        #[arg(long)]
+        output: String,
+        /// Output format: gif, apng or png (sequence); guessed from --output by default
+        #[arg(long)]
+        format: Option<RecordFormat>,
+        /// Region to record as X Y WIDTH HEIGHT (defaults to the primary screen)
+        #[arg(long, num_args = 4, value_names = ["X", "Y", "WIDTH", "HEIGHT"], allow_negative_numbers = true)]
+        region: Option<Vec<i32>>,
+        /// Do not draw the mouse cursor into frames
+        #[arg(long)]
+        no_cursor: bool,
+        /// Do not mark clicks issued by autoanchor
+        #[arg(long)]
+        no_clicks: bool,
+        #[command(flatten)]
+        mask: MaskArgs,
+    },
+    /// List, find and control top-level windows
+    Window {
+        #[command(subcommand)]
+        action: WindowAction,
+    },
+    /// Read, set or clear the clipboard
+    Clipboard {
+        #[command(subcommand)]
+        action: ClipboardAction,
+    },
+    /// Start an application, printing its process id and optionally its first window
+    Launch {
+        /// Program to run, searched for in PATH
+        app: String,
+        /// Arguments passed to the program
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+        /// Environment variable as KEY=VALUE (repeatable)
+        #[arg(long, value_name = "KEY=VALUE")]
+        env: Vec<String>,
+        /// Working directory for the program
+        #[arg(long)]
+        cwd: Option<PathBuf>,
+        /// Wait up to SECONDS (default 10) for the program's first window
+        #[arg(long, value_name = "SECONDS", num_args = 0..=1, default_missing_value = "10")]
+        wait_window: Option<f64>,
+    },
+    /// Query, wait for and kill processes
+    Process {
+        #[command(subcommand)]
+        action: ProcessAction,
+    },
+    /// Wait for a window to appear, close or get focus, printing the window's info
+    WaitWindow {
+        #[command(flatten)]
+        matcher: MatcherArgs,
+        /// Wait until no window matches instead
+        #[arg(long, conflicts_with = "focused")]
+        closed: bool,
+        /// Wait until the matching window has keyboard focus
+        #[arg(long)]
+        focused: bool,
+        /// Seconds to wait before failing
+        #[arg(long, default_value_t = 10.0)]
+        timeout: f64,
+    },
+    /// Record keyboard and mouse input to a JSON file and replay it
+    Macro {
+        #[command(subcommand)]
+        action: MacroAction,
+    },
+    /// Wait for system-wide hotkeys
+    Hotkey {
+        #[command(subcommand)]
+        action: HotkeyAction,
+    },
+    /// Print real keyboard and mouse input as one JSON object per line
+    Listen {
+        /// Seconds to listen; without it or --count, listening stops on Enter or end of stdin
+        #[arg(long)]
+        duration: Option<f64>,
+        /// Stop after this many events
+        #[arg(long)]
+        count: Option<usize>,
+        /// Leave out mouse movement
+        #[arg(long)]
+        no_motion: bool,
+    },
+    /// Run a JSON script of move, click, type, press, wait, screenshot and assert steps in order, printing a result per step
+    Run {
+        /// Script file, or - to read it from stdin
+        script: String,
+        /// Run the remaining steps after one fails
+        #[arg(long)]
+        keep_going: bool,
+    },
+    /// Keep running and answer requests, one method per subcommand (e.g. move-cursor, clipboard.get), as JSON-RPC 2.0 on stdio or REST and WebSocket over HTTP
+    #[command(group(clap::ArgGroup::new("transport").required(true).args(["stdio", "http"])))]
+    Serve {
+        /// Read newline-delimited JSON-RPC requests from stdin and write responses to stdout
+        #[arg(long)]
+        stdio: bool,
+        /// Listen for HTTP on ADDR, or on PORT of the loopback interface
+        #[arg(long, value_name = "ADDR|PORT")]
+        http: Option<String>,
+        /// Bearer token every HTTP request must carry; one is generated and printed if not given, except beyond loopback where it is required
+        #[arg(long, env = "AUTOANCHOR_TOKEN")]
+        token: Option<String>,
+        /// Web page origin allowed to open the HTTP WebSockets, e.g. http://localhost:3000; may be repeated
+        #[arg(long, value_name = "ORIGIN")]
+        allow_origin: Vec<String>,
+    },
+    /// Keep running as a Model Context Protocol server on stdio, offering screenshot, click, type, key, scroll, window list and image search tools to AI agents
+    Mcp,
+    /// Recognize text on the screen or in an image file
+    #[cfg(feature = "ocr")]
+    Ocr {
+        /// Region to read as X Y WIDTH HEIGHT (defaults to the whole image)
+        #[arg(long, num_args = 4, value_names = ["X", "Y", "WIDTH", "HEIGHT"], allow_negative_numbers = true)]
+        region: Option<Vec<i32>>,
+        /// Read this PNG file instead of capturing the primary screen
+        #[arg(long)]
+        file: Option<String>,
+        /// Only return the bounds of this text, for clicking by label
+        #[arg(long)]
+        find: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum WindowAction {
+    /// List all top-level windows, topmost first
+    List,
+    /// Find the topmost window matching all given criteria
+    Find {
+        #[command(flatten)]
+        matcher: MatcherArgs,
+        /// Return every matching window instead of the topmost one
+        #[arg(long)]
+        all: bool,
+    },
+    /// Restore, raise and focus a window, waiting until it has focus
+    Focus {
+        #[command(flatten)]
+        target: TargetArgs,
+    },
+    /// Bring a window to the top without focusing it
+    Raise {
+        #[command(flatten)]
+        target: TargetArgs,
+    },
+    /// Move a window's outer top-left corner to X Y
+    Move {
+        #[command(flatten)]
+        target: TargetArgs,
+        #[arg(allow_negative_numbers = true)]
+        x: i32,
+        #[arg(allow_negative_numbers = true)]
+        y: i32,
+    },
+    /// Resize a window's client area
+    Resize {
+        #[command(flatten)]
+        target: TargetArgs,
+        width: u32,
+        height: u32,
+    },
+    /// Minimize a window
+    Minimize {
+        #[command(flatten)]
+        target: TargetArgs,
+    },
+    /// Maximize a window
+    Maximize {
+        #[command(flatten)]
+        target: TargetArgs,
+    },
+    /// Undo minimize and maximize
+    Restore {
+        #[command(flatten)]
+        target: TargetArgs,
+    },
+    /// Ask a window to close; the application may still prompt
+    Close {
+        #[command(flatten)]
+        target: TargetArgs,
+    },
+    /// Capture a window's own contents as base64-encoded PNG, even when it is covered
+    Capture {
+        #[command(flatten)]
+        target: TargetArgs,
+        /// Include the title bar and borders
+        #[arg(long)]
+        frame: bool,
+        #[command(flatten)]
+        mask: MaskArgs,
+    },
+}
+
+#[derive(Subcommand)]
+enum ClipboardAction {
+    /// Print what is on the clipboard in one format (null if it is not there)
+    Get {
+        /// text, html, image (base64-encoded PNG) or files
+        #[arg(long, value_enum, default_value_t = ClipboardFormat::Text)]
+        format: ClipboardFormat,
+    },
+    /// Put text, HTML, an image or files on the clipboard
+    Set(ClipboardSetArgs),
+    /// Empty the clipboard
+    Clear,
+    /// List the formats on the clipboard as MIME types
+    Formats,
+    /// Set the clipboard as described by JSON on stdin and keep providing it until
+    /// replaced (X11 helper)
+    #[command(hide = true)]
+    Hold,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum ClipboardFormat {
+    Text,
+    Html,
+    Image,
+    Files,
+}
+
+#[derive(Args, Serialize, Deserialize)]
+struct ClipboardSetArgs {
+    /// Text to copy, or the plain-text version of --html; read from stdin when nothing else is given
+    text: Option<String>,
+    /// HTML to copy; without TEXT a plain-text version is derived from it
+    #[arg(long, conflicts_with_all = ["image", "files"])]
+    html: Option<String>,
+    /// Image file (PNG, JPEG, BMP, ...) to copy
+    #[arg(long, conflicts_with_all = ["text", "files"])]
+    image: Option<PathBuf>,
+    /// Files to copy, as a file manager would
+    #[arg(long, num_args = 1.., conflicts_with = "text")]
+    files: Vec<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum MacroAction {
+    /// Record input until Enter or end of stdin, or for --duration seconds
+    Record {
+        /// JSON file to write
+        #[arg(long)]
+        output: PathBuf,
+        /// Seconds to record
+        #[arg(long)]
+        duration: Option<f64>,
+    },
+    /// Replay a macro written by `macro record`
+    Play {
+        file: PathBuf,
+        /// Playback speed multiplier; 2 plays twice as fast
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+        /// Times to play the macro
+        #[arg(long, default_value_t = 1)]
+        loops: u32,
+        /// Keep the recorded coordinates even if the screen size differs
+        #[arg(long)]
+        no_rescale: bool,
     },
 }
 
+#[derive(Subcommand)]
+enum HotkeyAction {
+    /// Block until one of the hotkeys is pressed, printing which
+    Wait {
+        /// Key chords such as ctrl+alt+p
+        #[arg(required = true)]
+        chords: Vec<String>,
+        /// Seconds to wait before failing; waits forever by default
+        #[arg(long)]
+        timeout: Option<f64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProcessAction {
+    /// Report whether a process is running
+    Running { pid: u32 },
+    /// Terminate a process forcefully
+    Kill { pid: u32 },
+    /// Wait for a process to exit
+    WaitExit {
+        pid: u32,
+        /// Seconds to wait before failing
+        #[arg(long, default_value_t = 10.0)]
+        timeout: f64,
+    },
+    /// Print the topmost window of a process or its children
+    Window { pid: u32 },
+}
+
+/// The window a control action applies to, by id or by matcher.
+#[derive(Args)]
+struct TargetArgs {
+    /// Window id as reported by `window list`
+    #[arg(long, conflicts_with_all = ["title", "class", "pid"])]
+    id: Option<u64>,
+    #[command(flatten)]
+    matcher: MatcherArgs,
+}
+
+impl TargetArgs {
+    fn resolve(self) -> Result<u64, String> {
+        if let Some(id) = self.id {
+            return Ok(id);
+        }
+        let matcher = WindowMatcher::from(self.matcher);
+        if matcher == WindowMatcher::default() {
+            return Err("Specify --id or --title/--class/--pid".to_string());
+        }
+        Ok(window::require_window(&matcher)?.id)
+    }
+}
+
+#[derive(Args)]
+struct MatcherArgs {
+    /// Regular expression searched for in the window title
+    #[arg(long)]
+    title: Option<String>,
+    /// Window class (case-insensitive)
+    #[arg(long)]
+    class: Option<String>,
+    /// Owning process id
+    #[arg(long)]
+    pid: Option<u32>,
+}
+
+impl From<MatcherArgs> for WindowMatcher {
+    fn from(args: MatcherArgs) -> Self {
+        WindowMatcher {
+            title: args.title,
+            class: args.class,
+            pid: args.pid,
+        }
+    }
+}
+
 fn main() {
     let cli = Cli::parse();
+    failsafe::set_failsafe(FailSafe { enabled: !cli.no_failsafe, ..FailSafe::default() });
+    activity::set_yield_on_activity(YieldOnActivity {
+        policy: cli.yield_on_activity,
+        resume_after_ms: cli.resume_after_ms,
+        ..YieldOnActivity::default()
+    });
+
+    if let Commands::Serve { http, token, allow_origin, .. } = cli.command {
+        let served = match http {
+            Some(address) => serve_http(&address, token, allow_origin, cli.masks),
+            None => serve_stdio(cli.masks.as_deref()),
+        };
+        if let Err(e) = served {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Commands::Mcp = cli.command {
+        let served = masks_from_args(cli.masks.as_deref(), MaskArgs { mask: Vec::new(), mask_style: MaskStyle::Blackout })
+            .and_then(|masks| {
+                let stdin = std::io::stdin();
+                let stdout = std::io::stdout();
+                mcp::serve(stdin.lock(), stdout.lock(), &McpOptions { masks })
+                    .map_err(|e| format!("Serving failed: {}", e))
+            });
+        if let Err(e) = served {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let result = run_command(cli.command, cli.masks.as_deref());
+
+    // Output result as JSON
+    let json_output = serde_json::to_string(&result).unwrap();
+    println!("{}", json_output);
+}
 
-    let result = match cli.command {
+/// Runs one subcommand, whether given on the command line or to `serve`.
+fn run_command(command: Commands, masks_file: Option<&Path>) -> AutomationResult {
+    match command {
         Commands::CursorPosition => {
             match get_cursor_position() {
                 Ok(pos) => AutomationResult {
@@ -64,8 +560,12 @@ fn main() {
                 },
             }
         }
-        Commands::MoveCursor { x, y } => {
-            match move_cursor(x, y) {
+        Commands::MoveCursor { x, y, window } => {
+            let result = match window.target() {
+                Some(target) => move_cursor_in_window(&target, x, y),
+                None => move_cursor(x, y),
+            };
+            match result {
                 Ok(result) => result,
                 Err(e) => AutomationResult {
                     success: false,
@@ -74,8 +574,13 @@ fn main() {
                 },
             }
         }
-        Commands::Click { button, x, y } => {
-            match click(&button, x, y) {
+        Commands::Click { button, x, y, window } => {
+            let result = match (window.target(), x, y) {
+                (Some(target), Some(x), Some(y)) => click_in_window(&target, &button, x, y),
+                (Some(_), _, _) => Err("--window needs X and Y within the window".to_string()),
+                (None, x, y) => click(&button, x, y),
+            };
+            match result {
                 Ok(result) => result,
                 Err(e) => AutomationResult {
                     success: false,
@@ -84,8 +589,8 @@ fn main() {
                 },
             }
         }
-        Commands::TypeText { text, delay_ms } => {
-            match type_text(&text, delay_ms) {
+        Commands::TypeText { text, delay_ms, mode } => {
+            match type_text_with(&text, mode, delay_ms) {
                 Ok(result) => result,
                 Err(e) => AutomationResult {
                     success: false,
@@ -118,8 +623,67 @@ fn main() {
                 },
             }
         }
-        Commands::Screenshot { active_window } => {
-            match autoanchor_core::screen::windows::take_screenshot(active_window) {
+        Commands::IdleTime => {
+            match activity::idle_time() {
+                Ok(idle) => AutomationResult {
+                    success: true,
+                    message: None,
+                    data: Some(serde_json::json!({ "idle_ms": idle.as_millis() as u64 })),
+                },
+                Err(e) => AutomationResult {
+                    success: false,
+                    message: Some(e),
+                    data: None,
+                },
+            }
+        }
+        Commands::PressedKeys => {
+            match activity::pressed_keys() {
+                Ok(keys) => AutomationResult {
+                    success: true,
+                    message: None,
+                    data: Some(serde_json::json!({ "keys": keys })),
+                },
+                Err(e) => AutomationResult {
+                    success: false,
+                    message: Some(e),
+                    data: None,
+                },
+            }
+        }
+        Commands::PressedButtons => {
+            match activity::pressed_buttons() {
+                Ok(buttons) => AutomationResult {
+                    success: true,
+                    message: None,
+                    data: Some(serde_json::json!({ "buttons": buttons })),
+                },
+                Err(e) => AutomationResult {
+                    success: false,
+                    message: Some(e),
+                    data: None,
+                },
+            }
+        }
+        Commands::LockKeys => {
+            match activity::lock_keys() {
+                Ok(locks) => AutomationResult {
+                    success: true,
+                    message: None,
+                    data: Some(serde_json::to_value(locks).unwrap()),
+                },
+                Err(e) => AutomationResult {
+                    success: false,
+                    message: Some(e),
+                    data: None,
+                },
+            }
+        }
+        Commands::Screenshot { active_window, region, window, cursor, crosshair, annotations, mask } => {
+            let screenshot = masks_from_args(masks_file, mask).and_then(|masks| {
+                screenshot_command(active_window, region, window.target(), cursor, crosshair, annotations, masks)
+            });
+            match screenshot {
                 Ok(bytes) => {
                     // base64-encode the PNG bytes and return in JSON
                     use base64::{engine::general_purpose, Engine as _};
@@ -137,9 +701,1059 @@ fn main() {
                 },
             }
         }
+        Commands::Hash { algo, region, file, compare } => {
+            match hash_command(algo, region, file, compare) {
+                Ok(data) => AutomationResult {
+                    success: true,
+                    message: None,
+                    data: Some(data),
+                },
+                Err(e) => AutomationResult {
+                    success: false,
+                    message: Some(e),
+                    data: None,
+                },
+            }
+        }
+        Commands::Record { fps, duration, output, format, region, no_cursor, no_clicks, mask } => {
+            let recording = masks_from_args(masks_file, mask).and_then(|masks| {
+                record_command(fps, duration, output, format, region, no_cursor, no_clicks, masks)
+            });
+            match recording {
+                Ok(data) => AutomationResult {
+                    success: true,
+                    message: None,
+                    data: Some(data),
+                },
+                Err(e) => AutomationResult {
+                    success: false,
+                    message: Some(e),
+                    data: None,
+                },
+            }
+        }
+        Commands::Window { action } => {
+            match window_command(action, masks_file) {
+                Ok(result) => result,
+                Err(e) => AutomationResult {
+                    success: false,
+                    message: Some(e),
+                    data: None,
+                },
+            }
+        }
+        Commands::Clipboard { action } => {
+            match clipboard_command(action) {
+                Ok(result) => result,
+                Err(e) => AutomationResult {
+                    success: false,
+                    message: Some(e),
+                    data: None,
+                },
+            }
+        }
+        Commands::Launch { app, args, env, cwd, wait_window } => {
+            match launch_command(app, args, env, cwd, wait_window) {
+                Ok(data) => AutomationResult {
+                    success: true,
+                    message: None,
+                    data: Some(data),
+                },
+                Err(e) => AutomationResult {
+                    success: false,
+                    message: Some(e),
+                    data: None,
+                },
+            }
+        }
+        Commands::Process { action } => {
+            match process_command(action) {
+                Ok(result) => result,
+                Err(e) => AutomationResult {
+                    success: false,
+                    message: Some(e),
+                    data: None,
+                },
+            }
+        }
+        Commands::WaitWindow { matcher, closed, focused, timeout } => {
+            match wait_window_command(matcher, closed, focused, timeout) {
+                Ok(data) => AutomationResult {
+                    success: true,
+                    message: None,
+                    data: Some(data),
+                },
+                Err(e) => AutomationResult {
+                    success: false,
+                    message: Some(e),
+                    data: None,
+                },
+            }
+        }
+        Commands::Macro { action } => {
+            match macro_command(action) {
+                Ok(result) => result,
+                Err(e) => AutomationResult {
+                    success: false,
+                    message: Some(e),
+                    data: None,
+                },
+            }
+        }
+        Commands::Hotkey { action } => {
+            match hotkey_command(action) {
+                Ok(data) => AutomationResult {
+                    success: true,
+                    message: None,
+                    data: Some(data),
+                },
+                Err(e) => AutomationResult {
+                    success: false,
+                    message: Some(e),
+                    data: None,
+                },
+            }
+        }
+        Commands::Run { script, keep_going } => {
+            match masks_from_args(masks_file, MaskArgs { mask: Vec::new(), mask_style: MaskStyle::Blackout }) {
+                Ok(masks) => run_script_command(&script, RunOptions { keep_going, masks }),
+                Err(e) => AutomationResult {
+                    success: false,
+                    message: Some(e),
+                    data: None,
+                },
+            }
+        }
+        Commands::Listen { duration, count, no_motion } => {
+            match listen_command(duration, count, no_motion) {
+                Ok(data) => AutomationResult {
+                    success: true,
+                    message: None,
+                    data: Some(data),
+                },
+                Err(e) => AutomationResult {
+                    success: false,
+                    message: Some(e),
+                    data: None,
+                },
+            }
+        }
+        #[cfg(feature = "ocr")]
+        Commands::Ocr { region, file, find } => {
+            match ocr_command(region, file, find) {
+                Ok(data) => AutomationResult {
+                    success: true,
+                    message: None,
+                    data: Some(data),
+                },
+                Err(e) => AutomationResult {
+                    success: false,
+                    message: Some(e),
+                    data: None,
+                },
+            }
+        }
+        Commands::Serve { .. } | Commands::Mcp => AutomationResult {
+            success: false,
+            message: Some("Already serving".to_string()),
+            data: None,
+        },
+    }
+}
+
+/// Answers JSON-RPC requests on stdin until it ends. A method is a subcommand path joined
+/// by dots (`move-cursor`, `clipboard.get`); its params are either an object of the
+/// subcommand's arguments by name or an array of raw command-line arguments.
+fn serve_stdio(masks_file: Option<&Path>) -> Result<(), String> {
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    rpc::serve(stdin.lock(), stdout.lock(), &mut |method, params| {
+        call_method(method, params, masks_file)
+    })
+    .map_err(|e| format!("Serving failed: {}", e))
+}
+
+/// Held while a served call runs, so calls from concurrent HTTP clients take turns
+/// instead of interleaving their input.
+static AUTOMATION: Mutex<()> = Mutex::new(());
+
+/// Answers HTTP until killed, after printing the address it listens on and the token
+/// it generated, if any. Any POST path runs the method it spells (`POST /clipboard/set`)
+/// with params from the JSON body; `POST /rpc` takes JSON-RPC; and the WebSockets
+/// `GET /ws/events` and `GET /ws/screenshots` stream input events and screenshots.
+fn serve_http(
+    address: &str,
+    token: Option<String>,
+    origins: Vec<String>,
+    masks_file: Option<PathBuf>,
+) -> Result<(), String> {
+    use std::io::Write;
+    use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+
+    let address = match address.parse::<u16>() {
+        Ok(port) => SocketAddr::from(([127, 0, 0, 1], port)),
+        Err(_) => address
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+            .ok_or_else(|| format!("Invalid address: {}", address))?,
+    };
+    if !address.ip().is_loopback() && token.is_none() {
+        return Err(format!("Refusing to listen on {} without --token", address));
+    }
+    let listener = TcpListener::bind(address).map_err(|e| format!("Cannot listen on {}: {}", address, e))?;
+    let bound = listener.local_addr().map_err(|e| e.to_string())?;
+    let mut data = serde_json::json!({ "address": bound.to_string() });
+    let token = match token {
+        Some(token) => token,
+        None => {
+            let token = http::generate_token()?;
+            data["token"] = serde_json::Value::String(token.clone());
+            token
+        }
+    };
+    let started = AutomationResult {
+        success: true,
+        message: Some(format!("Listening on http://{}", bound)),
+        data: Some(data),
     };
+    println!("{}", serde_json::to_string(&started).unwrap());
+    let _ = std::io::stdout().flush();
 
-    // Output result as JSON
-    let json_output = serde_json::to_string(&result).unwrap();
-    println!("{}", json_output);
+    let access = http::Access {
+        token: Some(token),
+        origins,
+    };
+    http::serve(listener, access, move |request| http_route(request, masks_file.as_deref()))
+}
+
+fn http_route(request: &http::Request, masks_file: Option<&Path>) -> Reply {
+    let path = request.path.trim_matches('/');
+    let params = || -> Result<serde_json::Value, String> {
+        if request.body.iter().all(|b| b.is_ascii_whitespace()) {
+            return Ok(serde_json::Value::Null);
+        }
+        serde_json::from_slice(&request.body).map_err(|e| format!("Invalid JSON body: {}", e))
+    };
+    match (request.method.as_str(), path) {
+        ("GET", "ws/events") => {
+            let no_motion = request.query_value("no_motion").is_some_and(|v| v != "false");
+            Reply::Upgrade(Box::new(move |socket| stream_events(socket, no_motion)))
+        }
+        ("GET", "ws/screenshots") => {
+            let interval = match request.query_value("interval_ms").map(str::parse::<u64>) {
+                None => std::time::Duration::from_secs(1),
+                Some(Ok(ms)) if ms > 0 => std::time::Duration::from_millis(ms),
+                Some(_) => return Reply::Respond(Response::failure(400, "interval_ms must be a positive number")),
+            };
+            let params = query_params(request, &["access_token", "interval_ms"]);
+            let masks_file = masks_file.map(Path::to_path_buf);
+            Reply::Upgrade(Box::new(move |socket| stream_screenshots(socket, interval, params, masks_file)))
+        }
+        ("POST", "rpc") => {
+            let text = String::from_utf8_lossy(&request.body);
+            let _turn = AUTOMATION.lock().unwrap_or_else(|e| e.into_inner());
+            match rpc::handle_message(&text, &mut |method, params| call_method(method, params, masks_file)) {
+                Some(response) => Reply::Respond(Response::json(200, &response)),
+                None => Reply::Respond(Response::empty(204)),
+            }
+        }
+        ("POST", _) => {
+            let params = match params() {
+                Ok(params) => params,
+                Err(e) => return Reply::Respond(Response::failure(400, &e)),
+            };
+            let called = {
+                let _turn = AUTOMATION.lock().unwrap_or_else(|e| e.into_inner());
+                call_method(&path.replace('/', "."), params, masks_file)
+            };
+            Reply::Respond(match called {
+                Ok(result) => Response::json(200, &result),
+                Err(error) => {
+                    let status = match error.code {
+                        rpc::METHOD_NOT_FOUND => 404,
+                        rpc::INVALID_PARAMS => 400,
+                        rpc::FAIL_SAFE_TRIGGERED | rpc::USER_ACTIVITY => 409,
+                        _ => 500,
+                    };
+                    Response::json(status, &failure_body(&error))
+                }
+            })
+        }
+        _ => Reply::Respond(Response::failure(405, "Call methods with POST; GET only opens the ws/ streams")),
+    }
+}
+
+/// A failed call as the CLI would report it, with the JSON-RPC error code as data.
+fn failure_body(error: &RpcError) -> serde_json::Value {
+    serde_json::json!({
+        "success": false,
+        "message": error.message,
+        "data": { "code": error.code },
+    })
+}
+
+/// Query parameters as screenshot stream params: repeated keys become arrays and `true`/`false`
+/// booleans, the rest stays text for the argument parser.
+fn query_params(request: &http::Request, skip: &[&str]) -> serde_json::Value {
+    use serde_json::Value;
+
+    let mut params = serde_json::Map::new();
+    for (key, value) in request.query.iter().filter(|(key, _)| !skip.contains(&key.as_str())) {
+        let value = match value.as_str() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => Value::String(value.clone()),
+        };
+        match params.get_mut(key) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(first) => *first = Value::Array(vec![first.take(), value]),
+            None => {
+                params.insert(key.clone(), value);
+            }
+        }
+    }
+    Value::Object(params)
+}
+
+/// A flag that turns true once the client closes `socket`.
+fn watch_close(socket: &WebSocket) -> Arc<AtomicBool> {
+    let closed = Arc::new(AtomicBool::new(false));
+    if let Ok(mut reader) = socket.try_clone() {
+        let closed = closed.clone();
+        std::thread::spawn(move || {
+            while let Ok(Some(_)) = reader.receive() {}
+            closed.store(true, Ordering::SeqCst);
+        });
+    }
+    closed
+}
+
+/// Sends each input event as a JSON text message until the client goes away.
+fn stream_events(socket: WebSocket, no_motion: bool) {
+    let closed = watch_close(&socket);
+    let (listener, received) = match events::listen() {
+        Ok(listening) => listening,
+        Err(e) => {
+            let failure = AutomationResult { success: false, message: Some(e), data: None };
+            let _ = socket.send_text(&serde_json::to_string(&failure).unwrap());
+            let _ = socket.close();
+            return;
+        }
+    };
+    while !closed.load(Ordering::SeqCst) {
+        let Ok(event) = received.recv_timeout(std::time::Duration::from_millis(100)) else {
+            continue;
+        };
+        if no_motion && event.kind.is_motion() {
+            continue;
+        }
+        if socket.send_text(&serde_json::to_string(&event).unwrap()).is_err() {
+            break;
+        }
+    }
+    let _ = listener.stop();
+    let _ = socket.close();
+}
+
+/// Sends the `screenshot` method's result every `interval` until the client goes away.
+fn stream_screenshots(socket: WebSocket, interval: std::time::Duration, params: serde_json::Value, masks_file: Option<PathBuf>) {
+    let closed = watch_close(&socket);
+    while !closed.load(Ordering::SeqCst) {
+        let taken = {
+            let _turn = AUTOMATION.lock().unwrap_or_else(|e| e.into_inner());
+            call_method("screenshot", params.clone(), masks_file.as_deref())
+        };
+        let message = taken.unwrap_or_else(|error| failure_body(&error));
+        if socket.send_text(&message.to_string()).is_err() {
+            break;
+        }
+        let next = std::time::Instant::now() + interval;
+        while std::time::Instant::now() < next && !closed.load(Ordering::SeqCst) {
+            std::thread::sleep(std::time::Duration::from_millis(50).min(interval));
+        }
+    }
+    let _ = socket.close();
+}
+
+fn call_method(method: &str, params: serde_json::Value, masks_file: Option<&Path>) -> Result<serde_json::Value, RpcError> {
+    use clap::parser::ValueSource;
+    use clap::{CommandFactory, FromArgMatches};
+
+    let args = args_from_params(method, params)?;
+    let invalid = |e: clap::Error| {
+        let message = e.to_string();
+        let first_line = message.lines().next().unwrap_or_default();
+        RpcError::new(rpc::INVALID_PARAMS, first_line.trim_start_matches("error: "))
+    };
+    let matches = Cli::command().try_get_matches_from(args).map_err(invalid)?;
+    // Global flags parse with any call, but only the server's own settings take effect
+    let command = Cli::command();
+    let global = command
+        .get_arguments()
+        .filter(|arg| arg.is_global_set())
+        .find(|arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine));
+    if let Some(arg) = global {
+        return Err(server_flag_refusal(arg));
+    }
+    let cli = Cli::from_arg_matches(&matches).map_err(invalid)?;
+    if let Some(reason) = served_refusal(&cli.command) {
+        return Err(RpcError::new(rpc::INVALID_PARAMS, reason));
+    }
+    let result = run_command(cli.command, masks_file);
+    if result.success {
+        Ok(serde_json::to_value(result).unwrap())
+    } else {
+        Err(RpcError::automation(result.message.unwrap_or_default()))
+    }
+}
+
+/// Refuses a global flag such as `--no-failsafe` in a served call; the server applies its
+/// own to every call.
+fn server_flag_refusal(arg: &clap::Arg) -> RpcError {
+    let long = arg.get_long().unwrap_or(arg.get_id().as_str());
+    RpcError::new(rpc::INVALID_PARAMS, format!("--{} applies to the whole server; pass it to autoanchor serve instead", long))
+}
+
+/// Why a subcommand cannot run while serving, where stdin and stdout carry requests
+/// and responses.
+fn served_refusal(command: &Commands) -> Option<&'static str> {
+    match command {
+        Commands::Serve { .. } | Commands::Mcp => Some("Already serving"),
+        Commands::Listen { .. } => Some("listen writes events to stdout and cannot be served"),
+        Commands::Record { duration: None, .. }
+        | Commands::Macro { action: MacroAction::Record { duration: None, .. } } => {
+            Some("Recording needs a duration when served, as stdin cannot stop it")
+        }
+        Commands::Hotkey { action: HotkeyAction::Wait { timeout: None, .. } } => {
+            Some("hotkey.wait needs a timeout when served, as stdin cannot stop it")
+        }
+        Commands::Clipboard { action: ClipboardAction::Hold } => Some("clipboard.hold cannot be served"),
+        Commands::Run { script, .. } if script == "-" => Some("A served script must be read from a file"),
+        Commands::Clipboard { action: ClipboardAction::Set(args) }
+            if args.text.is_none() && args.html.is_none() && args.image.is_none() && args.files.is_empty() =>
+        {
+            Some("Nothing to put on the clipboard")
+        }
+        _ => None,
+    }
+}
+
+/// The command line `method` called with `params` stands for, to be parsed as if typed.
+fn args_from_params(method: &str, params: serde_json::Value) -> Result<Vec<String>, RpcError> {
+    use clap::{ArgAction, CommandFactory};
+    use serde_json::Value;
+
+    fn text(value: &Value) -> String {
+        match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }
+    }
+    fn flatten(value: &Value, out: &mut Vec<String>) {
+        match value {
+            Value::Array(items) => items.iter().for_each(|item| flatten(item, out)),
+            other => out.push(text(other)),
+        }
+    }
+
+    let unknown = || RpcError::new(rpc::METHOD_NOT_FOUND, format!("Unknown method: {}", method));
+    let mut args = vec!["autoanchor".to_string()];
+    let mut command = Cli::command();
+    for name in method.split('.') {
+        command = command.find_subcommand(name).ok_or_else(unknown)?.clone();
+        args.push(name.to_string());
+    }
+    if command.has_subcommands() {
+        return Err(unknown());
+    }
+
+    let named = match params {
+        Value::Null => return Ok(args),
+        Value::Array(raw) => {
+            args.extend(raw.iter().map(text));
+            return Ok(args);
+        }
+        Value::Object(named) => named,
+        _ => return Err(RpcError::new(rpc::INVALID_PARAMS, "Params must be an array or an object")),
+    };
+    let root = Cli::command();
+    for (key, value) in &named {
+        let named_by_key = |arg: &&clap::Arg| arg.get_id() == key.as_str() || arg.get_long() == Some(key.as_str());
+        if let Some(global) = root.get_arguments().filter(|arg| arg.is_global_set()).find(named_by_key) {
+            return Err(server_flag_refusal(global));
+        }
+        let arg = command
+            .get_arguments()
+            .find(named_by_key)
+            .ok_or_else(|| RpcError::new(rpc::INVALID_PARAMS, format!("Unknown parameter for {}: {}", method, key)))?;
+        let Some(long) = arg.get_long() else {
+            continue;
+        };
+        let flag = format!("--{}", long);
+        let range = arg.get_num_args();
+        match (arg.get_action(), value) {
+            (_, Value::Null) | (ArgAction::SetTrue, Value::Bool(false)) => {}
+            (ArgAction::SetTrue, Value::Bool(true)) => args.push(flag),
+            (ArgAction::SetTrue, _) => {
+                return Err(RpcError::new(rpc::INVALID_PARAMS, format!("Parameter {} must be true or false", key)));
+            }
+            // Options whose value may be left out, like --wait-window
+            (_, Value::Bool(true)) if range.is_some_and(|r| r.min_values() == 0) => args.push(flag),
+            (action, value) if matches!(action, ArgAction::Append) || range.is_some_and(|r| r.max_values() > 1) => {
+                let mut values = Vec::new();
+                flatten(value, &mut values);
+                // Options taking N values at a time, like --crosshair X Y, are repeated per N
+                let per_flag = match range {
+                    Some(r) if r.min_values() == r.max_values() => r.min_values(),
+                    Some(r) if r.max_values() > 1 => values.len().max(1),
+                    _ => 1,
+                };
+                for chunk in values.chunks(per_flag) {
+                    args.push(flag.clone());
+                    args.extend(chunk.iter().cloned());
+                }
+            }
+            (_, value) => {
+                args.push(flag);
+                args.push(text(value));
+            }
+        }
+    }
+    let mut positionals = Vec::new();
+    for arg in command.get_positionals() {
+        if let Some(value) = named.get(arg.get_id().as_str()) {
+            if !value.is_null() {
+                flatten(value, &mut positionals);
+            }
+        }
+    }
+    if !positionals.is_empty() {
+        args.push("--".to_string());
+        args.extend(positionals);
+    }
+    Ok(args)
+}
+
+fn region_from_args(values: Option<Vec<i32>>) -> Result<Option<Rect>, String> {
+    match values.as_deref() {
+        None => Ok(None),
+        Some([x, y, width, height]) if *width > 0 && *height > 0 => Ok(Some(Rect {
+            x: *x,
+            y: *y,
+            width: *width as u32,
+            height: *height as u32,
+        })),
+        Some(_) => Err("Region width and height must be positive".to_string()),
+    }
+}
+
+/// The global mask list from `--masks`/`AUTOANCHOR_MASKS` plus this call's `--mask` areas.
+fn masks_from_args(file: Option<&Path>, args: MaskArgs) -> Result<Vec<Mask>, String> {
+    let mut masks: Vec<Mask> = match file {
+        Some(path) => {
+            let json = std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read masks file {}: {}", path.display(), e))?;
+            serde_json::from_str(&json)
+                .map_err(|e| format!("Invalid masks file {}: {}", path.display(), e))?
+        }
+        None => Vec::new(),
+    };
+    for values in args.mask.chunks(4) {
+        let rect = region_from_args(Some(values.to_vec()))?.expect("region is present");
+        masks.push(Mask {
+            target: MaskTarget::Rect { rect },
+            style: args.mask_style,
+        });
+    }
+    Ok(masks)
+}
+
+fn screenshot_command(
+    active_window: bool,
+    region: Option<Vec<i32>>,
+    window: Option<WindowTarget>,
+    cursor: bool,
+    crosshair: Vec<i32>,
+    annotations: Option<String>,
+    masks: Vec<Mask>,
+) -> Result<Vec<u8>, String> {
+    let mut options = CaptureOptions {
+        active_window,
+        region: region_from_args(region)?,
+        window,
+        draw_cursor: cursor,
+        annotations: Vec::new(),
+        masks,
+    };
+    for point in crosshair.chunks(2) {
+        options.annotations.push(Annotation::Crosshair {
+            at: Point { x: point[0], y: point[1] },
+            size: 24,
+            color: annotate::DEFAULT_COLOR,
+        });
+    }
+    if let Some(json) = annotations {
+        let parsed: Vec<Annotation> =
+            serde_json::from_str(&json).map_err(|e| format!("Invalid annotations: {}", e))?;
+        annotate::check(&parsed)?;
+        options.annotations.extend(parsed);
+    }
+    take_screenshot(&options)
+}
+
+fn hash_command(
+    algo: HashAlgorithm,
+    region: Option<Vec<i32>>,
+    file: Option<String>,
+    compare: Option<ImageHash>,
+) -> Result<serde_json::Value, String> {
+    let rect = region_from_args(region)?;
+    let hash = match file {
+        Some(path) => {
+            let image = image::open(&path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+            region_hash(&image.to_rgba8(), rect, algo)?
+        }
+        // Capture only the requested area rather than cropping a full-screen grab
+        None => region_hash(&capture_screen(rect)?, None, algo)?,
+    };
+
+    let mut data = serde_json::json!({ "hash": hash });
+    if let Some(other) = compare {
+        data["distance"] = serde_json::to_value(hash.distance(&other)?).unwrap();
+    }
+    Ok(data)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn record_command(
+    fps: f64,
+    duration: Option<f64>,
+    output: String,
+    format: Option<RecordFormat>,
+    region: Option<Vec<i32>>,
+    no_cursor: bool,
+    no_clicks: bool,
+    masks: Vec<Mask>,
+) -> Result<serde_json::Value, String> {
+    let duration = match duration {
+        Some(secs) if secs > 0.0 && secs.is_finite() => Some(std::time::Duration::from_secs_f64(secs)),
+        Some(_) => return Err("Duration must be a positive number of seconds".to_string()),
+        None => None,
+    };
+    let options = RecordOptions {
+        fps,
+        duration,
+        region: region_from_args(region)?,
+        draw_cursor: !no_cursor,
+        mark_clicks: !no_clicks,
+        masks,
+    };
+    let path = Path::new(&output);
+    let format = format.unwrap_or_else(|| RecordFormat::from_path(path));
+    let recorder = Recorder::start(options, path, format)?;
+
+    let summary = if duration.is_some() {
+        recorder.wait()?
+    } else {
+        // Any line or EOF on stdin stops the recording
+        let mut line = String::new();
+        let _ = std::io::stdin().read_line(&mut line);
+        recorder.stop()?
+    };
+    Ok(serde_json::json!({
+        "output": output,
+        "format": format,
+        "frames": summary.frames,
+        "duration_ms": summary.duration_ms,
+    }))
+}
+
+fn macro_command(action: MacroAction) -> Result<AutomationResult, String> {
+    match action {
+        MacroAction::Record { output, duration } => {
+            let recorded = match duration {
+                Some(secs) if secs > 0.0 && secs.is_finite() => {
+                    macros::record_macro(std::time::Duration::from_secs_f64(secs))?
+                }
+                Some(_) => return Err("Duration must be a positive number of seconds".to_string()),
+                None => {
+                    let recorder = MacroRecorder::start()?;
+                    // Any line or EOF on stdin stops the recording
+                    let mut line = String::new();
+                    let _ = std::io::stdin().read_line(&mut line);
+                    let mut recorded = recorder.stop()?;
+                    // Leave out the Enter that stopped it, if typed into this terminal
+                    while matches!(
+                        recorded.events.last().map(|event| &event.input),
+                        Some(MacroInput::Keyboard(event)) if event.key == "enter"
+                    ) {
+                        recorded.events.pop();
+                    }
+                    recorded
+                }
+            };
+            recorded.save(&output)?;
+            Ok(AutomationResult {
+                success: true,
+                message: None,
+                data: Some(serde_json::json!({
+                    "output": output,
+                    "events": recorded.events.len(),
+                    "duration_ms": recorded.duration().as_millis() as u64,
+                })),
+            })
+        }
+        MacroAction::Play { file, speed, loops, no_rescale } => {
+            let options = PlayOptions {
+                speed,
+                loops,
+                rescale: !no_rescale,
+            };
+            macros::play_macro(&Macro::load(&file)?, &options)
+        }
+    }
+}
+
+fn hotkey_command(action: HotkeyAction) -> Result<serde_json::Value, String> {
+    match action {
+        HotkeyAction::Wait { chords, timeout } => {
+            let timeout = timeout.map(seconds_from_arg).transpose()?;
+            let chords: Vec<&str> = chords.iter().map(String::as_str).collect();
+            let chord = hotkey::wait_for_hotkey(&chords, timeout)?;
+            Ok(serde_json::json!({ "hotkey": chord }))
+        }
+    }
+}
+
+fn listen_command(duration: Option<f64>, count: Option<usize>, no_motion: bool) -> Result<serde_json::Value, String> {
+    let deadline = match duration {
+        Some(secs) if secs > 0.0 && secs.is_finite() => {
+            Some(std::time::Instant::now() + std::time::Duration::from_secs_f64(secs))
+        }
+        Some(_) => return Err("Duration must be a positive number of seconds".to_string()),
+        None => None,
+    };
+    let (ended, end) = std::sync::mpsc::channel();
+    if duration.is_none() && count.is_none() {
+        // Any line or EOF on stdin stops listening
+        std::thread::spawn(move || {
+            let mut line = String::new();
+            let _ = std::io::stdin().read_line(&mut line);
+            let _ = ended.send(());
+        });
+    }
+
+    let (listener, received) = events::listen()?;
+    let mut printed = 0;
+    while count.is_none_or(|count| printed < count)
+        && deadline.is_none_or(|deadline| std::time::Instant::now() < deadline)
+        && end.try_recv() != Ok(())
+    {
+        let Ok(event) = received.recv_timeout(std::time::Duration::from_millis(50)) else {
+            continue;
+        };
+        if no_motion && event.kind.is_motion() {
+            continue;
+        }
+        println!("{}", serde_json::to_string(&event).unwrap());
+        printed += 1;
+    }
+    listener.stop()?;
+    Ok(serde_json::json!({ "events": printed }))
+}
+
+fn window_command(action: WindowAction, masks_file: Option<&Path>) -> Result<AutomationResult, String> {
+    let value = match action {
+        WindowAction::List => serde_json::to_value(window::list_windows()?),
+        WindowAction::Find { matcher, all } => {
+            let matcher = WindowMatcher::from(matcher);
+            let found = window::find_windows(&matcher)?;
+            if found.is_empty() {
+                return Err(format!("No window matches {}", matcher));
+            }
+            if all {
+                serde_json::to_value(found)
+            } else {
+                serde_json::to_value(&found[0])
+            }
+        }
+        WindowAction::Focus { target } => return window::focus_window(target.resolve()?),
+        WindowAction::Raise { target } => return window::raise_window(target.resolve()?),
+        WindowAction::Move { target, x, y } => return window::move_window(target.resolve()?, x, y),
+        WindowAction::Resize { target, width, height } => {
+            return window::resize_window(target.resolve()?, width, height)
+        }
+        WindowAction::Minimize { target } => return window::minimize_window(target.resolve()?),
+        WindowAction::Maximize { target } => return window::maximize_window(target.resolve()?),
+        WindowAction::Restore { target } => return window::restore_window(target.resolve()?),
+        WindowAction::Close { target } => return window::close_window(target.resolve()?),
+        WindowAction::Capture { target, frame, mask } => {
+            let id = target.resolve()?;
+            let masks = masks_from_args(masks_file, mask)?;
+            let mut image = window::capture_window(id, frame)?;
+            if !masks.is_empty() {
+                // Masks are in screen coordinates, placed as if the window were uncovered
+                let info = WindowTarget::Id(id).resolve()?;
+                let area = if frame { info.bounds } else { info.client_area };
+                redact::redact(&mut image, Point { x: area.x, y: area.y }, &masks)?;
+            }
+            use base64::{engine::general_purpose, Engine as _};
+            serde_json::to_value(general_purpose::STANDARD.encode(encode_png(&image)?))
+        }
+    };
+    Ok(AutomationResult {
+        success: true,
+        message: None,
+        data: Some(value.unwrap()),
+    })
+}
+
+fn clipboard_command(action: ClipboardAction) -> Result<AutomationResult, String> {
+    let value = match action {
+        ClipboardAction::Get { format } => match format {
+            ClipboardFormat::Text => serde_json::to_value(clipboard::get_text()?),
+            ClipboardFormat::Html => serde_json::to_value(clipboard::get_html()?),
+            ClipboardFormat::Image => {
+                use base64::{engine::general_purpose, Engine as _};
+                let png = clipboard::get_image()?.map(|image| encode_png(&image)).transpose()?;
+                serde_json::to_value(png.map(|png| general_purpose::STANDARD.encode(png)))
+            }
+            ClipboardFormat::Files => serde_json::to_value(clipboard::get_files()?),
+        },
+        ClipboardAction::Set(mut args) => {
+            if args.text.is_none() && args.html.is_none() && args.image.is_none() && args.files.is_empty() {
+                args.text = Some(read_stdin()?);
+            }
+            return set_clipboard(args);
+        }
+        ClipboardAction::Clear => return clipboard::clear(),
+        ClipboardAction::Formats => serde_json::to_value(clipboard::formats()?),
+        ClipboardAction::Hold => {
+            let result = read_stdin()
+                .and_then(|json| serde_json::from_str(&json).map_err(|e| format!("Invalid clipboard contents: {}", e)))
+                .and_then(apply_clipboard_set);
+            let failed = result.is_err();
+            // Report to the waiting parent right away, then keep serving the clipboard
+            let result = result.unwrap_or_else(|e| AutomationResult {
+                success: false,
+                message: Some(e),
+                data: None,
+            });
+            println!("{}", serde_json::to_string(&result).unwrap());
+            if !failed {
+                clipboard::wait_until_replaced();
+            }
+            std::process::exit(if failed { 1 } else { 0 });
+        }
+    };
+    Ok(AutomationResult {
+        success: true,
+        message: None,
+        data: Some(value.unwrap()),
+    })
+}
+
+fn apply_clipboard_set(args: ClipboardSetArgs) -> Result<AutomationResult, String> {
+    if let Some(path) = args.image {
+        let image = image::open(&path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        return clipboard::set_image(&image.to_rgba8());
+    }
+    if !args.files.is_empty() {
+        return clipboard::set_files(&args.files);
+    }
+    match args.html {
+        Some(html) => clipboard::set_html(&html, args.text.as_deref()),
+        None => clipboard::set_text(&args.text.unwrap_or_default()),
+    }
+}
+
+/// Runs a script file, or stdin for `-`. Fails naming the first step that failed, with
+/// the results of every step that ran as data either way.
+fn run_script_command(path: &str, options: RunOptions) -> AutomationResult {
+    let json = if path == "-" {
+        read_stdin()
+    } else {
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read script {}: {}", path, e))
+    };
+    let steps = match json.and_then(|json| script::parse(&json)) {
+        Ok(steps) => steps,
+        Err(e) => {
+            return AutomationResult {
+                success: false,
+                message: Some(e),
+                data: None,
+            }
+        }
+    };
+    let results = script::run(&steps, &options);
+    let failed: Vec<&StepResult> = results.iter().filter(|result| !result.success).collect();
+    let message = failed.first().map(|first| {
+        let mut message = format!(
+            "Step {} ({}) failed: {}",
+            first.index,
+            first.action,
+            first.message.as_deref().unwrap_or("Failed")
+        );
+        if failed.len() > 1 {
+            message.push_str(&format!(" ({} of {} steps failed)", failed.len(), steps.len()));
+        }
+        message
+    });
+    AutomationResult {
+        success: failed.is_empty(),
+        message,
+        data: Some(serde_json::to_value(&results).unwrap()),
+    }
+}
+
+fn read_stdin() -> Result<String, String> {
+    use std::io::Read;
+
+    let mut text = String::new();
+    std::io::stdin()
+        .read_to_string(&mut text)
+        .map_err(|e| format!("Failed to read stdin: {}", e))?;
+    Ok(text)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_clipboard(args: ClipboardSetArgs) -> Result<AutomationResult, String> {
+    apply_clipboard_set(args)
+}
+
+/// X11 clipboard contents vanish with the process that set them, so a detached copy of
+/// this program holds them until another application takes over the clipboard.
+#[cfg(target_os = "linux")]
+fn set_clipboard(args: ClipboardSetArgs) -> Result<AutomationResult, String> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    let exe = std::env::current_exe().map_err(|e| format!("Cannot find own executable: {}", e))?;
+    let mut holder = Command::new(exe)
+        .args(["clipboard", "hold"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        // Out of the terminal's process group so Ctrl+C in the shell leaves it alone
+        .process_group(0)
+        .spawn()
+        .map_err(|e| format!("Failed to start clipboard holder: {}", e))?;
+    let mut stdin = holder.stdin.take().expect("stdin is piped");
+    stdin
+        .write_all(serde_json::to_string(&args).unwrap().as_bytes())
+        .map_err(|e| format!("Failed to pass contents to clipboard holder: {}", e))?;
+    drop(stdin);
+
+    let mut line = String::new();
+    BufReader::new(holder.stdout.take().expect("stdout is piped"))
+        .read_line(&mut line)
+        .map_err(|e| format!("Clipboard holder did not report: {}", e))?;
+    serde_json::from_str(&line).map_err(|_| "Clipboard holder exited without reporting".to_string())
+}
+
+fn seconds_from_arg(seconds: f64) -> Result<std::time::Duration, String> {
+    if !(seconds >= 0.0 && seconds.is_finite()) {
+        return Err("Timeout must be a non-negative number of seconds".to_string());
+    }
+    Ok(std::time::Duration::from_secs_f64(seconds))
+}
+
+fn launch_command(
+    app: String,
+    args: Vec<String>,
+    env: Vec<String>,
+    cwd: Option<PathBuf>,
+    wait_window: Option<f64>,
+) -> Result<serde_json::Value, String> {
+    let mut options = LaunchOptions {
+        args,
+        cwd,
+        wait_for_window: wait_window.map(seconds_from_arg).transpose()?,
+        ..LaunchOptions::default()
+    };
+    for pair in env {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("Expected KEY=VALUE, got {:?}", pair))?;
+        options.env.insert(key.to_string(), value.to_string());
+    }
+    let handle = process::launch(&app, &options)?;
+    Ok(serde_json::json!({
+        "pid": handle.pid(),
+        "window": handle.window(),
+    }))
+}
+
+fn process_command(action: ProcessAction) -> Result<AutomationResult, String> {
+    let value = match action {
+        ProcessAction::Running { pid } => serde_json::to_value(process::is_running(pid)?),
+        ProcessAction::Kill { pid } => return process::kill(pid),
+        ProcessAction::WaitExit { pid, timeout } => {
+            process::wait_exit(pid, seconds_from_arg(timeout)?)?;
+            return Ok(AutomationResult {
+                success: true,
+                message: Some(format!("Process {} exited", pid)),
+                data: None,
+            });
+        }
+        ProcessAction::Window { pid } => match process::main_window(pid)? {
+            Some(window) => serde_json::to_value(window),
+            None => return Err(format!("Process {} has no window", pid)),
+        },
+    };
+    Ok(AutomationResult {
+        success: true,
+        message: None,
+        data: Some(value.unwrap()),
+    })
+}
+
+fn wait_window_command(
+    matcher: MatcherArgs,
+    closed: bool,
+    focused: bool,
+    timeout: f64,
+) -> Result<serde_json::Value, String> {
+    let matcher = WindowMatcher::from(matcher);
+    if matcher == WindowMatcher::default() {
+        return Err("Specify --title/--class/--pid".to_string());
+    }
+    let timeout = seconds_from_arg(timeout)?;
+    if closed {
+        window::wait_for_window_closed(&matcher, timeout)?;
+        return Ok(serde_json::Value::Null);
+    }
+    let window = if focused {
+        window::wait_for_focus(&matcher, timeout)?
+    } else {
+        window::wait_for_window(&matcher, timeout)?
+    };
+    Ok(serde_json::to_value(window).unwrap())
+}
+
+#[cfg(feature = "ocr")]
+fn ocr_command(
+    region: Option<Vec<i32>>,
+    file: Option<String>,
+    find: Option<String>,
+) -> Result<serde_json::Value, String> {
+    use autoanchor_core::ocr;
+
+    let rect = region_from_args(region)?;
+    let value = match (file, find) {
+        (Some(path), find) => {
+            let image = image::open(&path)
+                .map_err(|e| format!("Failed to open {}: {}", path, e))?
+                .to_rgba8();
+            match find {
+                Some(needle) => serde_json::to_value(ocr::find_text_in(&image, rect, &needle)),
+                None => serde_json::to_value(ocr::recognize(&image, rect)),
+            }
+        }
+        (None, Some(needle)) => serde_json::to_value(ocr::find_text(&needle, rect)?),
+        (None, None) => serde_json::to_value(ocr::read_text(rect)?),
+    };
+    Ok(value.unwrap())
 }
@@ -0,0 +1,286 @@
+//! Conversions between clipboard representations that do not depend on the platform.
+
+use std::path::{Path, PathBuf};
+
+/// The first of `preferred` that is `offered`, compared case-insensitively. A
+/// preference ending in `/*` matches any subtype, e.g. `image/*`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub(crate) fn negotiate(offered: &[String], preferred: &[&str]) -> Option<String> {
+    preferred.iter().find_map(|wanted| {
+        offered
+            .iter()
+            .find(|target| match wanted.strip_suffix('*') {
+                Some(prefix) => target.to_ascii_lowercase().starts_with(prefix),
+                None => target.eq_ignore_ascii_case(wanted),
+            })
+            .cloned()
+    })
+}
+
+/// Decodes clipboard text that is UTF-8, or UTF-16 when it starts with a byte order mark
+/// as Firefox's `text/html` does.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub(crate) fn decode_text(data: &[u8]) -> String {
+    let utf16 = |bytes: &[u8], decode: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| decode([pair[0], pair[1]]))
+            .take_while(|unit| *unit != 0)
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+    match data {
+        [0xff, 0xfe, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xfe, 0xff, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        _ => String::from_utf8_lossy(data.strip_suffix(&[0]).unwrap_or(data)).into_owned(),
+    }
+}
+
+/// A plain-text rendering of HTML for applications that cannot paste markup.
+pub(crate) fn html_to_text(html: &str) -> String {
+    const BREAKS: [&str; 12] = [
+        "br", "/p", "/div", "/li", "/tr", "/h1", "/h2", "/h3", "/h4", "/h5", "/h6", "/pre",
+    ];
+    let mut text = String::new();
+    let mut rest = html;
+    let mut skipping: Option<&str> = None;
+    while let Some(start) = rest.find('<') {
+        if skipping.is_none() {
+            push_collapsed(&mut text, &rest[..start]);
+        }
+        let Some(end) = rest[start..].find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = rest[start + 1..start + end].trim().to_ascii_lowercase();
+        let name = tag
+            .split(|c: char| c.is_whitespace() || c == '/' && !tag.starts_with('/'))
+            .next()
+            .unwrap_or("");
+        match skipping {
+            Some(closing) if name == closing => skipping = None,
+            Some(_) => {}
+            None if name == "script" || name == "style" => {
+                skipping = Some(if name == "script" {
+                    "/script"
+                } else {
+                    "/style"
+                })
+            }
+            None if BREAKS.contains(&name) => {
+                text.truncate(text.trim_end_matches(' ').len());
+                text.push('\n');
+            }
+            None => {}
+        }
+        rest = &rest[start + end + 1..];
+    }
+    if skipping.is_none() {
+        push_collapsed(&mut text, rest);
+    }
+    decode_entities(&text).trim().to_string()
+}
+
+/// Appends HTML text with whitespace runs collapsed to one space, as browsers show it.
+fn push_collapsed(text: &mut String, chunk: &str) {
+    for c in chunk.chars() {
+        if c.is_whitespace() {
+            if !text.is_empty() && !text.ends_with([' ', '\n']) {
+                text.push(' ');
+            }
+        } else {
+            text.push(c);
+        }
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| &rest[1..end + 1]);
+        let character = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => {
+                let number = entity.strip_prefix('#')?;
+                let code = match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => number.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// A `text/uri-list` (RFC 2483) of `file://` URIs for absolute paths.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub(crate) fn to_uri_list(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| format!("{}\r\n", file_uri(path)))
+        .collect()
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub(crate) fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+/// Local paths from a URI list, skipping comments and anything but `file://` URIs on
+/// this host.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub(crate) fn from_uri_list(list: &str) -> Vec<PathBuf> {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|uri| {
+            let rest = uri.strip_prefix("file://")?;
+            let path = match rest.find('/')? {
+                0 => rest,
+                host if &rest[..host] == "localhost" => &rest[host..],
+                _ => return None,
+            };
+            Some(PathBuf::from(percent_decode(path)?))
+        })
+        .collect()
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+const CF_HTML_PREFIX: &str = "<html><body>\r\n<!--StartFragment-->";
+const CF_HTML_SUFFIX: &str = "<!--EndFragment-->\r\n</body></html>";
+
+/// Wraps an HTML fragment in the Windows "HTML Format" header, whose byte offsets let
+/// applications find the fragment.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+pub(crate) fn to_cf_html(fragment: &str) -> Vec<u8> {
+    let header = |start_html: usize, end_html: usize, start: usize, end: usize| {
+        format!(
+            "Version:0.9\r\nStartHTML:{:010}\r\nEndHTML:{:010}\r\nStartFragment:{:010}\r\nEndFragment:{:010}\r\n",
+            start_html, end_html, start, end
+        )
+    };
+    let start_html = header(0, 0, 0, 0).len();
+    let start = start_html + CF_HTML_PREFIX.len();
+    let end = start + fragment.len();
+    let end_html = end + CF_HTML_SUFFIX.len();
+    let mut data = header(start_html, end_html, start, end).into_bytes();
+    data.extend_from_slice(CF_HTML_PREFIX.as_bytes());
+    data.extend_from_slice(fragment.as_bytes());
+    data.extend_from_slice(CF_HTML_SUFFIX.as_bytes());
+    data
+}
+
+/// The fragment from Windows "HTML Format" data.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+pub(crate) fn from_cf_html(data: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(data);
+    let offset = |name: &str| -> Option<usize> {
+        let value = &text[text.find(name)? + name.len()..];
+        value.lines().next()?.trim().parse().ok()
+    };
+    let start = offset("StartFragment:")?;
+    let end = offset("EndFragment:")?;
+    let fragment = data.get(start..end)?;
+    Some(String::from_utf8_lossy(fragment).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiation_follows_preference_order() {
+        let offered: Vec<String> = ["TARGETS", "image/jpeg", "image/png", "text/html"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            negotiate(&offered, &["image/png", "image/*"]).as_deref(),
+            Some("image/png")
+        );
+        assert_eq!(
+            negotiate(&offered, &["image/bmp", "image/*"]).as_deref(),
+            Some("image/jpeg")
+        );
+        assert_eq!(negotiate(&offered, &["text/uri-list"]), None);
+    }
+
+    #[test]
+    fn text_decoding_detects_utf16() {
+        assert_eq!(decode_text(b"caf\xc3\xa9\0"), "café");
+        assert_eq!(decode_text(&[0xff, 0xfe, b'h', 0, b'i', 0]), "hi");
+    }
+
+    #[test]
+    fn html_becomes_readable_text() {
+        let html = "<h1>Title</h1>\n<p>One   <b>bold</b>&amp;more</p><script>x()</script><ul><li>a&#x41;</li><li>b&nbsp;</li></ul>";
+        assert_eq!(html_to_text(html), "Title\nOne bold&more\naA\nb");
+        assert_eq!(html_to_text("a &unknown; b"), "a &unknown; b");
+    }
+
+    #[test]
+    fn uri_lists_round_trip() {
+        let paths = vec![PathBuf::from("/tmp/a b.txt"), PathBuf::from("/home/ü/x#1")];
+        let list = to_uri_list(&paths);
+        assert_eq!(list.lines().next(), Some("file:///tmp/a%20b.txt"));
+        assert_eq!(from_uri_list(&list), paths);
+        let mixed = "# comment\nhttp://example.com/\nfile://localhost/etc/hosts\nfile://other/x\n";
+        assert_eq!(from_uri_list(mixed), [PathBuf::from("/etc/hosts")]);
+    }
+
+    #[test]
+    fn cf_html_offsets_point_at_fragment() {
+        let data = to_cf_html("<b>grüße</b>");
+        let text = String::from_utf8(data.clone()).unwrap();
+        assert!(text.starts_with("Version:0.9\r\nStartHTML:0000000"));
+        assert_eq!(from_cf_html(&data).as_deref(), Some("<b>grüße</b>"));
+        assert_eq!(from_cf_html(b"Version:0.9\r\n"), None);
+    }
+}
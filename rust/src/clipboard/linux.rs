@@ -0,0 +1,504 @@
+//! The X11 CLIPBOARD selection. Unlike on Windows, the server keeps no copy: whoever
+//! set the clipboard must answer every paste until someone else takes it over, so
+//! [`write`] starts a thread that does so.
+
+use super::encoding;
+use crate::xserver::XConnection;
+use image::RgbaImage;
+use std::os::raw::{c_int, c_long, c_ulong};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use x11::xlib;
+
+/// How long the selection owner gets to answer a request.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+/// Contents larger than this are sent in pieces with the INCR protocol, staying well
+/// below the server's maximum request size.
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Property on our own window that owners write the converted selection to.
+const TRANSFER_PROPERTY: &str = "AUTOANCHOR_SELECTION";
+
+/// Targets that describe the selection rather than hold its contents.
+const META_TARGETS: [&str; 5] = ["TARGETS", "TIMESTAMP", "MULTIPLE", "SAVE_TARGETS", "DELETE"];
+
+/// Requests for our contents fully answered since the last [`write`], i.e. pastes.
+static DELIVERED: AtomicUsize = AtomicUsize::new(0);
+
+/// The thread currently answering requests for our clipboard contents.
+static OWNER: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+
+/// One representation of the clipboard contents.
+struct Entry {
+    /// Target name clients ask for, e.g. `UTF8_STRING` or `image/png`.
+    target: String,
+    /// Property type the data is written with.
+    kind: String,
+    data: Vec<u8>,
+}
+
+impl Entry {
+    fn new(target: &str, kind: &str, data: Vec<u8>) -> Self {
+        Entry {
+            target: target.to_string(),
+            kind: kind.to_string(),
+            data,
+        }
+    }
+}
+
+/// An INCR transfer waiting for the requestor to take the next piece.
+struct Transfer {
+    requestor: xlib::Window,
+    property: xlib::Atom,
+    kind: xlib::Atom,
+    data: Vec<u8>,
+    offset: usize,
+}
+
+pub fn formats() -> Result<Vec<String>, String> {
+    let conn = XConnection::open()?;
+    Ok(offered(&conn)?
+        .into_iter()
+        .filter(|target| !META_TARGETS.contains(&target.as_str()))
+        .collect())
+}
+
+pub fn get_text() -> Result<Option<String>, String> {
+    let conn = XConnection::open()?;
+    let preferred = [
+        "UTF8_STRING",
+        "text/plain;charset=utf-8",
+        "STRING",
+        "text/plain",
+    ];
+    Ok(read_preferred(&conn, &preferred)?.map(|(target, data)| {
+        if target == "STRING" {
+            // STRING is Latin-1
+            data.iter().map(|b| *b as char).collect()
+        } else {
+            encoding::decode_text(&data)
+        }
+    }))
+}
+
+pub fn get_html() -> Result<Option<String>, String> {
+    let conn = XConnection::open()?;
+    Ok(read_preferred(&conn, &["text/html"])?.map(|(_, data)| encoding::decode_text(&data)))
+}
+
+/// The clipboard image in its encoded form, preferring PNG.
+pub fn get_image() -> Result<Option<(String, Vec<u8>)>, String> {
+    let conn = XConnection::open()?;
+    read_preferred(&conn, &["image/png", "image/*"])
+}
+
+pub fn get_files() -> Result<Option<Vec<PathBuf>>, String> {
+    let conn = XConnection::open()?;
+    // GNOME's list starts with a "copy" or "cut" line, which is skipped as not a URI
+    let preferred = ["text/uri-list", "x-special/gnome-copied-files"];
+    Ok(read_preferred(&conn, &preferred)?
+        .map(|(_, data)| encoding::from_uri_list(&encoding::decode_text(&data))))
+}
+
+pub fn set_text(text: &str) -> Result<(), String> {
+    write(text_entries(text))
+}
+
+pub fn set_html(html: &str, text: &str) -> Result<(), String> {
+    let mut entries = vec![Entry::new(
+        "text/html",
+        "text/html",
+        html.as_bytes().to_vec(),
+    )];
+    entries.extend(text_entries(text));
+    write(entries)
+}
+
+pub fn set_image(image: &RgbaImage) -> Result<(), String> {
+    let png = crate::encode_png(image)?;
+    write(vec![Entry::new("image/png", "image/png", png)])
+}
+
+pub fn set_files(paths: &[PathBuf]) -> Result<(), String> {
+    let uris = encoding::to_uri_list(paths);
+    let gnome = format!("copy\n{}", uris.trim_end().replace("\r\n", "\n"));
+    let text: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+    let mut entries = vec![
+        Entry::new("text/uri-list", "text/uri-list", uris.into_bytes()),
+        Entry::new(
+            "x-special/gnome-copied-files",
+            "x-special/gnome-copied-files",
+            gnome.into_bytes(),
+        ),
+    ];
+    entries.extend(text_entries(&text.join("\n")));
+    write(entries)
+}
+
+fn text_entries(text: &str) -> Vec<Entry> {
+    let utf8 = text.as_bytes().to_vec();
+    let latin1 = text
+        .chars()
+        .map(|c| u8::try_from(c).unwrap_or(b'?'))
+        .collect();
+    vec![
+        Entry::new("UTF8_STRING", "UTF8_STRING", utf8.clone()),
+        Entry::new(
+            "text/plain;charset=utf-8",
+            "text/plain;charset=utf-8",
+            utf8.clone(),
+        ),
+        Entry::new("TEXT", "UTF8_STRING", utf8),
+        Entry::new("STRING", "STRING", latin1),
+    ]
+}
+
+pub fn clear() -> Result<(), String> {
+    let conn = XConnection::open()?;
+    unsafe {
+        // Any client may drop the selection; our owner thread then gets SelectionClear
+        xlib::XSetSelectionOwner(conn.display, conn.atom("CLIPBOARD"), 0, xlib::CurrentTime);
+        xlib::XSync(conn.display, xlib::False);
+    }
+    Ok(())
+}
+
+pub fn wait_for_paste(timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    while DELIVERED.load(Ordering::SeqCst) == 0 {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    true
+}
+
+pub fn wait_until_replaced() {
+    let owner = OWNER.lock().unwrap().take();
+    if let Some(owner) = owner {
+        let _ = owner.join();
+    }
+}
+
+/// Takes over the clipboard with `contents`, returning once we own it.
+fn write(contents: Vec<Entry>) -> Result<(), String> {
+    let (ready, started) = mpsc::channel();
+    DELIVERED.store(0, Ordering::SeqCst);
+    let owner = thread::spawn(move || serve(contents, ready));
+    started
+        .recv()
+        .unwrap_or_else(|_| Err("Clipboard thread ended unexpectedly".to_string()))?;
+    // The previous owner thread, if any, ends by itself on SelectionClear
+    *OWNER.lock().unwrap() = Some(owner);
+    Ok(())
+}
+
+/// Target names the clipboard owner offers.
+fn offered(conn: &XConnection) -> Result<Vec<String>, String> {
+    let Some(data) = read(conn, "TARGETS")? else {
+        return Ok(Vec::new());
+    };
+    let size = std::mem::size_of::<c_ulong>();
+    Ok(data
+        .chunks_exact(size)
+        .map(|chunk| c_ulong::from_ne_bytes(chunk.try_into().unwrap()))
+        .filter_map(|atom| conn.atom_name(atom))
+        .collect())
+}
+
+/// The clipboard in the first of the `preferred` targets its owner offers.
+fn read_preferred(
+    conn: &XConnection,
+    preferred: &[&str],
+) -> Result<Option<(String, Vec<u8>)>, String> {
+    let Some(target) = encoding::negotiate(&offered(conn)?, preferred) else {
+        return Ok(None);
+    };
+    Ok(read(conn, &target)?.map(|data| (target, data)))
+}
+
+/// Converts the clipboard to `target`, or `None` if it is empty or the owner does not
+/// offer that target.
+fn read(conn: &XConnection, target: &str) -> Result<Option<Vec<u8>>, String> {
+    let clipboard = conn.atom("CLIPBOARD");
+    if unsafe { xlib::XGetSelectionOwner(conn.display, clipboard) } == 0 {
+        return Ok(None);
+    }
+    let window = hidden_window(conn);
+    let result = convert(conn, window, clipboard, conn.atom(target));
+    unsafe { xlib::XDestroyWindow(conn.display, window) };
+    result
+}
+
+fn convert(
+    conn: &XConnection,
+    window: xlib::Window,
+    selection: xlib::Atom,
+    target: xlib::Atom,
+) -> Result<Option<Vec<u8>>, String> {
+    let property = conn.atom(TRANSFER_PROPERTY);
+    unsafe {
+        xlib::XConvertSelection(
+            conn.display,
+            selection,
+            target,
+            property,
+            window,
+            xlib::CurrentTime,
+        );
+        xlib::XFlush(conn.display);
+    }
+    let notify = wait_event(conn, window, xlib::SelectionNotify, |_| true)?;
+    if unsafe { notify.selection.property } == 0 {
+        return Ok(None);
+    }
+
+    let (kind, first) = take_property(conn, window, property);
+    if kind != conn.atom("INCR") {
+        return Ok(Some(first));
+    }
+    // Deleting the INCR property asked for the first piece; an empty one ends the data
+    let mut data = Vec::new();
+    loop {
+        wait_event(conn, window, xlib::PropertyNotify, |event| unsafe {
+            event.property.atom == property && event.property.state == xlib::PropertyNewValue
+        })?;
+        let (_, chunk) = take_property(conn, window, property);
+        if chunk.is_empty() {
+            return Ok(Some(data));
+        }
+        data.extend(chunk);
+    }
+}
+
+/// Reads and deletes a property, which tells the owner we have it.
+fn take_property(
+    conn: &XConnection,
+    window: xlib::Window,
+    property: xlib::Atom,
+) -> (xlib::Atom, Vec<u8>) {
+    let value = conn.property(window, TRANSFER_PROPERTY);
+    unsafe {
+        xlib::XDeleteProperty(conn.display, window, property);
+        xlib::XFlush(conn.display);
+    }
+    value.map_or((0, Vec::new()), |value| (value.kind, value.data))
+}
+
+fn wait_event(
+    conn: &XConnection,
+    window: xlib::Window,
+    kind: c_int,
+    accept: impl Fn(&xlib::XEvent) -> bool,
+) -> Result<xlib::XEvent, String> {
+    let deadline = Instant::now() + REPLY_TIMEOUT;
+    loop {
+        let mut event: xlib::XEvent = unsafe { std::mem::zeroed() };
+        while unsafe { xlib::XCheckTypedWindowEvent(conn.display, window, kind, &mut event) } != 0 {
+            if accept(&event) {
+                return Ok(event);
+            }
+        }
+        if Instant::now() >= deadline {
+            return Err("The clipboard owner did not answer".to_string());
+        }
+        thread::sleep(Duration::from_millis(2));
+    }
+}
+
+/// An unmapped window to own the selection or receive it.
+fn hidden_window(conn: &XConnection) -> xlib::Window {
+    unsafe {
+        let window = xlib::XCreateSimpleWindow(conn.display, conn.root, 0, 0, 1, 1, 0, 0, 0);
+        xlib::XSelectInput(conn.display, window, xlib::PropertyChangeMask);
+        window
+    }
+}
+
+fn serve(contents: Vec<Entry>, ready: mpsc::Sender<Result<(), String>>) {
+    let conn = match XConnection::open() {
+        Ok(conn) => conn,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+    let window = hidden_window(&conn);
+    let clipboard = conn.atom("CLIPBOARD");
+    unsafe {
+        xlib::XSetSelectionOwner(conn.display, clipboard, window, xlib::CurrentTime);
+        if xlib::XGetSelectionOwner(conn.display, clipboard) != window {
+            let _ = ready.send(Err("Failed to take over the clipboard".to_string()));
+            xlib::XDestroyWindow(conn.display, window);
+            return;
+        }
+    }
+    let _ = ready.send(Ok(()));
+
+    let mut transfers: Vec<Transfer> = Vec::new();
+    loop {
+        let mut event: xlib::XEvent = unsafe { std::mem::zeroed() };
+        unsafe { xlib::XNextEvent(conn.display, &mut event) };
+        match event.get_type() {
+            xlib::SelectionClear => break,
+            xlib::SelectionRequest => {
+                let request = unsafe { event.selection_request };
+                answer(&conn, &request, &contents, &mut transfers);
+            }
+            xlib::PropertyNotify => {
+                let notify = unsafe { event.property };
+                if notify.state == xlib::PropertyDelete {
+                    continue_transfer(&conn, &notify, &mut transfers);
+                }
+            }
+            _ => {}
+        }
+    }
+    // Transfers still running are abandoned, as other clients do when losing ownership
+    unsafe { xlib::XDestroyWindow(conn.display, window) };
+}
+
+fn answer(
+    conn: &XConnection,
+    request: &xlib::XSelectionRequestEvent,
+    contents: &[Entry],
+    transfers: &mut Vec<Transfer>,
+) {
+    // Obsolete clients leave the property out and expect the target's name to be used
+    let property = if request.property == 0 {
+        request.target
+    } else {
+        request.property
+    };
+    let entry = contents
+        .iter()
+        .find(|entry| conn.atom(&entry.target) == request.target);
+    let answered = if request.target == conn.atom("TARGETS") {
+        let mut targets: Vec<c_ulong> = vec![conn.atom("TARGETS")];
+        targets.extend(contents.iter().map(|entry| conn.atom(&entry.target)));
+        unsafe {
+            xlib::XChangeProperty(
+                conn.display,
+                request.requestor,
+                property,
+                xlib::XA_ATOM,
+                32,
+                xlib::PropModeReplace,
+                targets.as_ptr() as *const u8,
+                targets.len() as c_int,
+            );
+        }
+        true
+    } else if let Some(entry) = entry {
+        let kind = conn.atom(&entry.kind);
+        if entry.data.len() > CHUNK_SIZE {
+            let size = entry.data.len() as c_long;
+            unsafe {
+                xlib::XSelectInput(conn.display, request.requestor, xlib::PropertyChangeMask);
+                xlib::XChangeProperty(
+                    conn.display,
+                    request.requestor,
+                    property,
+                    conn.atom("INCR"),
+                    32,
+                    xlib::PropModeReplace,
+                    &size as *const c_long as *const u8,
+                    1,
+                );
+            }
+            transfers.push(Transfer {
+                requestor: request.requestor,
+                property,
+                kind,
+                data: entry.data.clone(),
+                offset: 0,
+            });
+        } else {
+            write_chunk(conn, request.requestor, property, kind, &entry.data);
+            DELIVERED.fetch_add(1, Ordering::SeqCst);
+        }
+        true
+    } else {
+        false
+    };
+
+    let mut notify = xlib::XSelectionEvent {
+        type_: xlib::SelectionNotify,
+        serial: 0,
+        send_event: xlib::True,
+        display: conn.display,
+        requestor: request.requestor,
+        selection: request.selection,
+        target: request.target,
+        property: if answered { property } else { 0 },
+        time: request.time,
+    };
+    unsafe {
+        xlib::XSendEvent(
+            conn.display,
+            request.requestor,
+            xlib::False,
+            xlib::NoEventMask,
+            &mut notify as *mut xlib::XSelectionEvent as *mut xlib::XEvent,
+        );
+        xlib::XFlush(conn.display);
+    }
+}
+
+/// Sends the next piece once the requestor deleted the previous one, finishing with an
+/// empty piece.
+fn continue_transfer(
+    conn: &XConnection,
+    notify: &xlib::XPropertyEvent,
+    transfers: &mut Vec<Transfer>,
+) {
+    let Some(index) = transfers
+        .iter()
+        .position(|t| t.requestor == notify.window && t.property == notify.atom)
+    else {
+        return;
+    };
+    let transfer = &mut transfers[index];
+    let end = (transfer.offset + CHUNK_SIZE).min(transfer.data.len());
+    let chunk = &transfer.data[transfer.offset..end];
+    write_chunk(
+        conn,
+        transfer.requestor,
+        transfer.property,
+        transfer.kind,
+        chunk,
+    );
+    if chunk.is_empty() {
+        unsafe { xlib::XSelectInput(conn.display, transfer.requestor, xlib::NoEventMask) };
+        transfers.remove(index);
+        DELIVERED.fetch_add(1, Ordering::SeqCst);
+    } else {
+        transfer.offset = end;
+    }
+    unsafe { xlib::XFlush(conn.display) };
+}
+
+fn write_chunk(
+    conn: &XConnection,
+    window: xlib::Window,
+    property: xlib::Atom,
+    kind: xlib::Atom,
+    data: &[u8],
+) {
+    unsafe {
+        xlib::XChangeProperty(
+            conn.display,
+            window,
+            property,
+            kind,
+            8,
+            xlib::PropModeReplace,
+            data.as_ptr(),
+            data.len() as c_int,
+        );
+    }
+}
@@ -0,0 +1,296 @@
+#[cfg(target_os = "linux")]
+pub mod linux;
+#[cfg(target_os = "windows")]
+pub mod windows;
+
+#[cfg(target_os = "linux")]
+use linux as platform;
+#[cfg(target_os = "windows")]
+use windows as platform;
+
+mod encoding;
+
+use super::events::{self, EventKind};
+use super::AutomationResult;
+use image::RgbaImage;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long [`paste_text`] waits for the target application to read the clipboard.
+const PASTE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+mod platform {
+    use std::path::PathBuf;
+
+    const UNSUPPORTED: &str = "Unsupported platform - Windows and X11 only for now";
+
+    pub fn formats() -> Result<Vec<String>, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn get_text() -> Result<Option<String>, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn set_text(_text: &str) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn get_html() -> Result<Option<String>, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn set_html(_html: &str, _text: &str) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn get_image() -> Result<Option<(String, Vec<u8>)>, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn set_image(_image: &image::RgbaImage) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn get_files() -> Result<Option<Vec<PathBuf>>, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn set_files(_paths: &[PathBuf]) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn clear() -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn wait_for_paste(_timeout: std::time::Duration) -> bool {
+        false
+    }
+
+    pub fn wait_until_replaced() {}
+}
+
+/// Clipboard contents kept by [`save`] to put back with [`restore`]. Only the formats
+/// this module handles survive the round trip.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Saved {
+    Empty,
+    Text(String),
+    Html { html: String, text: Option<String> },
+    Image(RgbaImage),
+    Files(Vec<PathBuf>),
+}
+
+fn done(message: String) -> AutomationResult {
+    AutomationResult {
+        success: true,
+        message: Some(message),
+        data: None,
+    }
+}
+
+/// MIME types (or platform format names where there is none) of everything on the
+/// clipboard.
+pub fn formats() -> Result<Vec<String>, String> {
+    platform::formats()
+}
+
+/// Text on the clipboard, or `None` if it is empty or holds no text.
+pub fn get_text() -> Result<Option<String>, String> {
+    platform::get_text()
+}
+
+/// Replaces the clipboard contents with `text`, e.g. to paste long strings instead of
+/// typing them.
+pub fn set_text(text: &str) -> Result<AutomationResult, String> {
+    platform::set_text(text)?;
+    Ok(done(format!("Copied {} characters", text.chars().count())))
+}
+
+/// HTML on the clipboard, e.g. copied from a browser; use [`get_text`] for its
+/// plain-text version.
+pub fn get_html() -> Result<Option<String>, String> {
+    platform::get_html()
+}
+
+/// Puts HTML on the clipboard together with a plain-text version for applications that
+/// cannot paste markup, derived from the HTML unless given.
+pub fn set_html(html: &str, text: Option<&str>) -> Result<AutomationResult, String> {
+    let derived;
+    let text = match text {
+        Some(text) => text,
+        None => {
+            derived = encoding::html_to_text(html);
+            &derived
+        }
+    };
+    platform::set_html(html, text)?;
+    Ok(done(format!("Copied {} bytes of HTML", html.len())))
+}
+
+/// The image on the clipboard, decoded from whichever image format is offered.
+pub fn get_image() -> Result<Option<RgbaImage>, String> {
+    let Some((format, data)) = platform::get_image()? else {
+        return Ok(None);
+    };
+    image::load_from_memory(&data)
+        .map(|image| Some(image.to_rgba8()))
+        .map_err(|e| format!("Cannot decode the clipboard image ({}): {}", format, e))
+}
+
+/// Puts an image on the clipboard, e.g. a screenshot from [`crate::capture`].
+pub fn set_image(image: &RgbaImage) -> Result<AutomationResult, String> {
+    platform::set_image(image)?;
+    Ok(done(format!(
+        "Copied {}x{} image",
+        image.width(),
+        image.height()
+    )))
+}
+
+/// Files copied in a file manager.
+pub fn get_files() -> Result<Option<Vec<PathBuf>>, String> {
+    platform::get_files()
+}
+
+/// Puts files on the clipboard for pasting into a file manager or an upload field.
+/// Relative paths are taken against the working directory.
+pub fn set_files<P: AsRef<Path>>(paths: &[P]) -> Result<AutomationResult, String> {
+    let paths = paths
+        .iter()
+        .map(|path| {
+            std::path::absolute(path)
+                .map_err(|e| format!("Invalid path {}: {}", path.as_ref().display(), e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    platform::set_files(&paths)?;
+    Ok(done(format!("Copied {} files", paths.len())))
+}
+
+pub fn clear() -> Result<AutomationResult, String> {
+    platform::clear()?;
+    Ok(done("Clipboard cleared".to_string()))
+}
+
+/// The current contents in the richest format available; applications offering several
+/// formats at once keep only that one after [`restore`].
+pub fn save() -> Result<Saved, String> {
+    if let Some(files) = get_files()? {
+        return Ok(Saved::Files(files));
+    }
+    if let Some(image) = get_image()? {
+        return Ok(Saved::Image(image));
+    }
+    if let Some(html) = get_html()? {
+        return Ok(Saved::Html {
+            html,
+            text: get_text()?,
+        });
+    }
+    Ok(get_text()?.map_or(Saved::Empty, Saved::Text))
+}
+
+pub fn restore(saved: &Saved) -> Result<AutomationResult, String> {
+    match saved {
+        Saved::Empty => clear(),
+        Saved::Text(text) => set_text(text),
+        Saved::Html { html, text } => set_html(html, text.as_deref()),
+        Saved::Image(image) => set_image(image),
+        Saved::Files(files) => set_files(files),
+    }
+}
+
+/// Enters `text` into the focused window by putting it on the clipboard and pressing
+/// the paste shortcut, which is far faster than typing long text. The previous
+/// clipboard contents are restored afterwards, whether or not the paste worked.
+pub fn paste_text(text: &str) -> Result<AutomationResult, String> {
+    crate::before_input()?;
+    paste_with(text, press_paste_shortcut)
+}
+
+/// [`paste_text`], with the shortcut sent by `press_shortcut`.
+fn paste_with(
+    text: &str,
+    press_shortcut: impl FnOnce() -> Result<(), String>,
+) -> Result<AutomationResult, String> {
+    let saved = save()?;
+    platform::set_text(text)?;
+    let pasted = press_shortcut().and_then(|_| {
+        if platform::wait_for_paste(PASTE_TIMEOUT) {
+            Ok(())
+        } else {
+            Err(format!(
+                "No application read the clipboard within {}s; is the target focused?",
+                PASTE_TIMEOUT.as_secs()
+            ))
+        }
+    });
+    let restored = restore(&saved);
+    pasted?;
+    restored?;
+    Ok(done(format!("Pasted {} characters", text.chars().count())))
+}
+
+/// Presses Ctrl+V, releasing whatever went down even if a press fails.
+fn press_paste_shortcut() -> Result<(), String> {
+    let key = |key: &str, down: bool| {
+        let (key, modifiers) = (key.to_string(), Vec::new());
+        if down {
+            EventKind::KeyDown { key, modifiers }
+        } else {
+            EventKind::KeyUp { key, modifiers }
+        }
+    };
+    events::inject(&key("ctrl", true))?;
+    let pressed = events::inject(&key("v", true)).and_then(|_| events::inject(&key("v", false)));
+    events::inject(&key("ctrl", false)).and(pressed)
+}
+
+/// On X11 the application that set the clipboard has to hand out its contents, so they
+/// disappear when it exits. This blocks while this process still provides the contents
+/// it set, returning once another application takes over the clipboard. Returns at once
+/// on Windows, which keeps a copy.
+pub fn wait_until_replaced() {
+    platform::wait_until_replaced()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paste_restores_the_clipboard_whatever_happens() {
+        // Needs an X server, e.g. `Xvfb :99 & DISPLAY=:99 cargo test`
+        if std::env::var_os("DISPLAY").is_none() {
+            return;
+        }
+        set_text("original").unwrap();
+        let current = || get_text().unwrap();
+
+        let error = paste_with("secret", || Err("No keyboard".to_string())).unwrap_err();
+        assert_eq!(error, "No keyboard");
+        assert_eq!(current().as_deref(), Some("original"));
+
+        let error = paste_with("secret", || Ok(())).unwrap_err();
+        assert!(
+            error.starts_with("No application read the clipboard"),
+            "{}",
+            error
+        );
+        assert_eq!(current().as_deref(), Some("original"));
+
+        // As an application would on Ctrl+V
+        let mut read = None;
+        paste_with("secret", || {
+            read = get_text()?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(read.as_deref(), Some("secret"));
+        assert_eq!(current().as_deref(), Some("original"));
+
+        clear().unwrap();
+    }
+}
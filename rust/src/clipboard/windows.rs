@@ -0,0 +1,291 @@
+use std::thread;
+use std::time::Duration;
+use winapi::shared::minwindef::UINT;
+use winapi::um::winbase::{
+    GlobalAlloc, GlobalFree, GlobalLock, GlobalSize, GlobalUnlock, GMEM_MOVEABLE,
+};
+use winapi::um::winuser::{
+    CloseClipboard, EmptyClipboard, GetClipboardData, IsClipboardFormatAvailable, OpenClipboard,
+    SetClipboardData, CF_UNICODETEXT,
+};
+
+/// The open clipboard, closed again on drop.
+struct Clipboard;
+
+impl Clipboard {
+    fn open() -> Result<Self, String> {
+        // Other applications keep the clipboard open for short moments
+        for _ in 0..10 {
+            if unsafe { OpenClipboard(std::ptr::null_mut()) } != 0 {
+                return Ok(Clipboard);
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        Err("The clipboard is in use by another application".to_string())
+    }
+
+    fn get(&self, format: UINT) -> Option<Vec<u8>> {
+        unsafe {
+            if IsClipboardFormatAvailable(format) == 0 {
+                return None;
+            }
+            let handle = GetClipboardData(format);
+            if handle.is_null() {
+                return None;
+            }
+            let data = GlobalLock(handle) as *const u8;
+            if data.is_null() {
+                return None;
+            }
+            let bytes = std::slice::from_raw_parts(data, GlobalSize(handle)).to_vec();
+            GlobalUnlock(handle);
+            Some(bytes)
+        }
+    }
+
+    /// Adds a format; the clipboard must have been emptied since opening it.
+    fn set(&self, format: UINT, bytes: &[u8]) -> Result<(), String> {
+        unsafe {
+            let handle = GlobalAlloc(GMEM_MOVEABLE, bytes.len());
+            if handle.is_null() {
+                return Err("Out of memory for the clipboard".to_string());
+            }
+            let data = GlobalLock(handle) as *mut u8;
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len());
+            GlobalUnlock(handle);
+            // On success the clipboard owns the memory
+            if SetClipboardData(format, handle).is_null() {
+                GlobalFree(handle);
+                return Err("Failed to set the clipboard".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Clipboard {
+    fn drop(&mut self) {
+        unsafe {
+            CloseClipboard();
+        }
+    }
+}
+
+/// Id of a format applications register by name, like "PNG" or "HTML Format".
+fn registered(name: &str) -> UINT {
+    let wide: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+    unsafe { RegisterClipboardFormatW(wide.as_ptr()) }
+}
+
+/// Formats on the clipboard, named by MIME type where there is one.
+pub fn formats() -> Result<Vec<String>, String> {
+    let (png, html) = (registered("PNG"), registered("HTML Format"));
+    let _clipboard = Clipboard::open()?;
+    let mut names: Vec<String> = Vec::new();
+    let mut format = 0;
+    loop {
+        format = unsafe { EnumClipboardFormats(format) };
+        if format == 0 {
+            break;
+        }
+        let name = match format {
+            CF_UNICODETEXT | CF_TEXT | CF_OEMTEXT => "text/plain".to_string(),
+            CF_DIB | CF_DIBV5 | CF_BITMAP => "image/bmp".to_string(),
+            CF_HDROP => "text/uri-list".to_string(),
+            _ if format == png => "image/png".to_string(),
+            _ if format == html => "text/html".to_string(),
+            _ => {
+                let mut buffer = [0u16; 256];
+                let length = unsafe {
+                    GetClipboardFormatNameW(format, buffer.as_mut_ptr(), buffer.len() as i32)
+                };
+                if length > 0 {
+                    String::from_utf16_lossy(&buffer[..length as usize])
+                } else {
+                    format!("CF_{}", format)
+                }
+            }
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+pub fn get_text() -> Result<Option<String>, String> {
+    let clipboard = Clipboard::open()?;
+    Ok(clipboard.get(CF_UNICODETEXT).map(|bytes| {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .take_while(|unit| *unit != 0)
+            .collect();
+        String::from_utf16_lossy(&units)
+    }))
+}
+
+pub fn set_text(text: &str) -> Result<(), String> {
+    let bytes: Vec<u8> = text
+        .encode_utf16()
+        .chain(Some(0))
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    let clipboard = Clipboard::open()?;
+    unsafe { EmptyClipboard() };
+    clipboard.set(CF_UNICODETEXT, &bytes)
+}
+
+pub fn get_html() -> Result<Option<String>, String> {
+    let html = registered("HTML Format");
+    let clipboard = Clipboard::open()?;
+    Ok(clipboard
+        .get(html)
+        .and_then(|data| encoding::from_cf_html(&data)))
+}
+
+pub fn set_html(html: &str, text: &str) -> Result<(), String> {
+    let format = registered("HTML Format");
+    let mut data = encoding::to_cf_html(html);
+    data.push(0);
+    let text: Vec<u8> = text
+        .encode_utf16()
+        .chain(Some(0))
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    let clipboard = Clipboard::open()?;
+    unsafe { EmptyClipboard() };
+    clipboard.set(format, &data)?;
+    clipboard.set(CF_UNICODETEXT, &text)
+}
+
+/// The clipboard image in an encoded form, PNG if an application put one there and
+/// otherwise the device-independent bitmap as a BMP file.
+pub fn get_image() -> Result<Option<(String, Vec<u8>)>, String> {
+    let png = registered("PNG");
+    let clipboard = Clipboard::open()?;
+    if let Some(data) = clipboard.get(png) {
+        return Ok(Some(("image/png".to_string(), data)));
+    }
+    Ok(clipboard
+        .get(CF_DIB)
+        .and_then(|dib| bmp_file(&dib))
+        .map(|bmp| ("image/bmp".to_string(), bmp)))
+}
+
+/// Prepends the `BITMAPFILEHEADER` that a DIB lacks to be a BMP file.
+fn bmp_file(dib: &[u8]) -> Option<Vec<u8>> {
+    let u32_at = |at: usize| Some(u32::from_le_bytes(dib.get(at..at + 4)?.try_into().ok()?));
+    let header_size = u32_at(0)?;
+    let bit_count = u16::from_le_bytes(dib.get(14..16)?.try_into().ok()?);
+    let compression = u32_at(16)?;
+    let colors_used = u32_at(32)?;
+    let palette = if bit_count <= 8 {
+        if colors_used == 0 {
+            1 << bit_count
+        } else {
+            colors_used
+        }
+    } else {
+        0
+    };
+    // A plain BITMAPINFOHEADER is followed by the three colour masks
+    let masks = if compression == BI_BITFIELDS && header_size == 40 {
+        12
+    } else {
+        0
+    };
+    let offset = 14 + header_size + palette * 4 + masks;
+    let mut bmp = Vec::with_capacity(14 + dib.len());
+    bmp.extend_from_slice(b"BM");
+    bmp.extend_from_slice(&((14 + dib.len()) as u32).to_le_bytes());
+    bmp.extend_from_slice(&[0; 4]);
+    bmp.extend_from_slice(&offset.to_le_bytes());
+    bmp.extend_from_slice(dib);
+    Some(bmp)
+}
+
+/// Puts the image on the clipboard as PNG, which keeps transparency, and as a 32-bit
+/// DIB for applications that only read bitmaps.
+pub fn set_image(image: &RgbaImage) -> Result<(), String> {
+    let png = crate::encode_png(image)?;
+    let (width, height) = image.dimensions();
+    let mut dib = Vec::with_capacity(40 + image.as_raw().len());
+    dib.extend_from_slice(&40u32.to_le_bytes());
+    dib.extend_from_slice(&(width as i32).to_le_bytes());
+    // Positive height: rows bottom-up
+    dib.extend_from_slice(&(height as i32).to_le_bytes());
+    dib.extend_from_slice(&1u16.to_le_bytes());
+    dib.extend_from_slice(&32u16.to_le_bytes());
+    dib.extend_from_slice(&[0; 24]);
+    for row in image.rows().rev() {
+        for pixel in row {
+            let [r, g, b, a] = pixel.0;
+            dib.extend_from_slice(&[b, g, r, a]);
+        }
+    }
+    let format = registered("PNG");
+    let clipboard = Clipboard::open()?;
+    unsafe { EmptyClipboard() };
+    clipboard.set(format, &png)?;
+    clipboard.set(CF_DIB, &dib)
+}
+
+pub fn get_files() -> Result<Option<Vec<PathBuf>>, String> {
+    let clipboard = Clipboard::open()?;
+    Ok(clipboard.get(CF_HDROP).map(|data| {
+        let offset = data.get(0..4).map_or(DROPFILES_SIZE, |b| {
+            u32::from_le_bytes(b.try_into().unwrap()) as usize
+        });
+        let units: Vec<u16> = data
+            .get(offset..)
+            .unwrap_or_default()
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        // NUL-separated paths, ending with an empty one
+        units
+            .split(|unit| *unit == 0)
+            .take_while(|path| !path.is_empty())
+            .map(|path| PathBuf::from(String::from_utf16_lossy(path)))
+            .collect()
+    }))
+}
+
+pub fn set_files(paths: &[PathBuf]) -> Result<(), String> {
+    // DROPFILES: offset of the list, drop point, non-client flag, wide characters
+    let mut data = Vec::new();
+    data.extend_from_slice(&(DROPFILES_SIZE as u32).to_le_bytes());
+    data.extend_from_slice(&[0; 12]);
+    data.extend_from_slice(&1u32.to_le_bytes());
+    for path in paths {
+        let path = path.to_string_lossy();
+        data.extend(
+            path.encode_utf16()
+                .chain(Some(0))
+                .flat_map(u16::to_le_bytes),
+        );
+    }
+    data.extend_from_slice(&[0, 0]);
+    let clipboard = Clipboard::open()?;
+    unsafe { EmptyClipboard() };
+    clipboard.set(CF_HDROP, &data)
+}
+
+pub fn clear() -> Result<(), String> {
+    let _clipboard = Clipboard::open()?;
+    if unsafe { EmptyClipboard() } == 0 {
+        return Err("Failed to clear the clipboard".to_string());
+    }
+    Ok(())
+}
+
+/// Windows does not tell when an application reads the clipboard, so this only gives
+/// the target time to handle the paste shortcut.
+pub fn wait_for_paste(timeout: Duration) -> bool {
+    thread::sleep(PASTE_SETTLE.min(timeout));
+    true
+}
+
+/// Windows keeps its own copy of the clipboard, so there is nothing to wait for.
+pub fn wait_until_replaced() {}
@@ -0,0 +1,309 @@
+use super::{Point, Rect};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+/// How far a pixel may drift from the target color and still count as a match.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tolerance {
+    /// Maximum absolute difference allowed on each of the R, G, B and A channels.
+    PerChannel(u8),
+    /// Maximum CIE76 ΔE distance in L*a*b* space. Alpha is ignored.
+    DeltaE(f64),
+}
+
+/// A connected group of matching pixels (8-connectivity).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColorCluster {
+    pub centroid: Point,
+    pub bounds: Rect,
+    pub pixel_count: u32,
+}
+
+/// Scans `region` (or the whole image) top-to-bottom, left-to-right and returns the
+/// centroid of the first cluster of pixels matching `rgba` within `tolerance`.
+/// Coordinates are relative to the image, not to the region.
+pub fn find_color(
+    image: &RgbaImage,
+    region: Option<Rect>,
+    rgba: [u8; 4],
+    tolerance: Tolerance,
+) -> Option<Point> {
+    let area = search_area(image, region)?;
+    let matcher = Matcher::new(rgba, tolerance);
+    let mut mask = MatchMask::new(image, area, &matcher);
+
+    for y in 0..area.height {
+        for x in 0..area.width {
+            if mask.take(x, y) {
+                return Some(mask.flood(x, y).centroid);
+            }
+        }
+    }
+    None
+}
+
+/// Returns every cluster of pixels matching `rgba` within `tolerance`, ordered by the
+/// position of each cluster's first pixel in scan order.
+pub fn find_all_colors(
+    image: &RgbaImage,
+    region: Option<Rect>,
+    rgba: [u8; 4],
+    tolerance: Tolerance,
+) -> Vec<ColorCluster> {
+    let area = match search_area(image, region) {
+        Some(area) => area,
+        None => return Vec::new(),
+    };
+    let matcher = Matcher::new(rgba, tolerance);
+    let mut mask = MatchMask::new(image, area, &matcher);
+
+    let mut clusters = Vec::new();
+    for y in 0..area.height {
+        for x in 0..area.width {
+            if mask.take(x, y) {
+                clusters.push(mask.flood(x, y));
+            }
+        }
+    }
+    clusters
+}
+
+fn search_area(image: &RgbaImage, region: Option<Rect>) -> Option<Rect> {
+    let full = Rect {
+        x: 0,
+        y: 0,
+        width: image.width(),
+        height: image.height(),
+    };
+    region
+        .unwrap_or(full)
+        .clamp_to(image.width(), image.height())
+}
+
+enum Matcher {
+    PerChannel { target: [u8; 4], max: u8 },
+    DeltaE { target: [f64; 3], max: f64 },
+}
+
+impl Matcher {
+    fn new(rgba: [u8; 4], tolerance: Tolerance) -> Self {
+        match tolerance {
+            Tolerance::PerChannel(max) => Matcher::PerChannel { target: rgba, max },
+            Tolerance::DeltaE(max) => Matcher::DeltaE {
+                target: rgb_to_lab(rgba[0], rgba[1], rgba[2]),
+                max,
+            },
+        }
+    }
+
+    fn matches(&self, pixel: [u8; 4]) -> bool {
+        match self {
+            Matcher::PerChannel { target, max } => target
+                .iter()
+                .zip(pixel.iter())
+                .all(|(a, b)| a.abs_diff(*b) <= *max),
+            Matcher::DeltaE { target, max } => {
+                let lab = rgb_to_lab(pixel[0], pixel[1], pixel[2]);
+                delta_e(target, &lab) <= *max
+            }
+        }
+    }
+}
+
+/// Per-pixel match flags for the search area; pixels are cleared as they are
+/// assigned to a cluster so each one is visited once.
+struct MatchMask {
+    area: Rect,
+    flags: Vec<bool>,
+}
+
+impl MatchMask {
+    fn new(image: &RgbaImage, area: Rect, matcher: &Matcher) -> Self {
+        let mut flags = Vec::with_capacity((area.width * area.height) as usize);
+        for y in 0..area.height {
+            for x in 0..area.width {
+                let pixel = image.get_pixel(area.x as u32 + x, area.y as u32 + y);
+                flags.push(matcher.matches(pixel.0));
+            }
+        }
+        MatchMask { area, flags }
+    }
+
+    fn take(&mut self, x: u32, y: u32) -> bool {
+        let index = (y * self.area.width + x) as usize;
+        std::mem::replace(&mut self.flags[index], false)
+    }
+
+    /// Collects the cluster containing the (already taken) pixel at `x`, `y`.
+    fn flood(&mut self, x: u32, y: u32) -> ColorCluster {
+        let mut stack = vec![(x, y)];
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (x, y, x, y);
+        let (mut sum_x, mut sum_y, mut count) = (0u64, 0u64, 0u32);
+
+        while let Some((cx, cy)) = stack.pop() {
+            sum_x += cx as u64;
+            sum_y += cy as u64;
+            count += 1;
+            min_x = min_x.min(cx);
+            min_y = min_y.min(cy);
+            max_x = max_x.max(cx);
+            max_y = max_y.max(cy);
+
+            for dy in -1i64..=1 {
+                for dx in -1i64..=1 {
+                    let nx = cx as i64 + dx;
+                    let ny = cy as i64 + dy;
+                    if nx < 0
+                        || ny < 0
+                        || nx >= self.area.width as i64
+                        || ny >= self.area.height as i64
+                    {
+                        continue;
+                    }
+                    if self.take(nx as u32, ny as u32) {
+                        stack.push((nx as u32, ny as u32));
+                    }
+                }
+            }
+        }
+
+        let half = count as u64 / 2;
+        ColorCluster {
+            centroid: Point {
+                x: self.area.x + ((sum_x + half) / count as u64) as i32,
+                y: self.area.y + ((sum_y + half) / count as u64) as i32,
+            },
+            bounds: Rect {
+                x: self.area.x + min_x as i32,
+                y: self.area.y + min_y as i32,
+                width: max_x - min_x + 1,
+                height: max_y - min_y + 1,
+            },
+            pixel_count: count,
+        }
+    }
+}
+
+/// Converts an sRGB color to CIE L*a*b* using the D65 white point.
+pub fn rgb_to_lab(r: u8, g: u8, b: u8) -> [f64; 3] {
+    fn linearize(c: u8) -> f64 {
+        let c = c as f64 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    }
+    fn f(t: f64) -> f64 {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    }
+
+    let (r, g, b) = (linearize(r), linearize(g), linearize(b));
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// CIE76 color difference between two L*a*b* colors.
+pub fn delta_e(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+    fn canvas_with_blocks() -> RgbaImage {
+        let mut image = RgbaImage::from_pixel(40, 30, WHITE);
+        // A 4x4 block at (5, 5) and a 3x3 slightly-off red block at (20, 10)
+        for y in 5..9 {
+            for x in 5..9 {
+                image.put_pixel(x, y, Rgba(RED));
+            }
+        }
+        for y in 10..13 {
+            for x in 20..23 {
+                image.put_pixel(x, y, Rgba([250, 6, 3, 255]));
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn find_color_returns_centroid_of_first_cluster() {
+        let image = canvas_with_blocks();
+        let found = find_color(&image, None, RED, Tolerance::PerChannel(0));
+        assert_eq!(found, Some(Point { x: 7, y: 7 }));
+    }
+
+    #[test]
+    fn find_color_respects_region() {
+        let image = canvas_with_blocks();
+        let region = Rect {
+            x: 15,
+            y: 0,
+            width: 25,
+            height: 30,
+        };
+        assert_eq!(
+            find_color(&image, Some(region), RED, Tolerance::PerChannel(0)),
+            None
+        );
+        assert_eq!(
+            find_color(&image, Some(region), RED, Tolerance::PerChannel(8)),
+            Some(Point { x: 21, y: 11 })
+        );
+    }
+
+    #[test]
+    fn find_all_colors_reports_each_cluster() {
+        let image = canvas_with_blocks();
+        let clusters = find_all_colors(&image, None, RED, Tolerance::DeltaE(5.0));
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(
+            clusters[0].bounds,
+            Rect {
+                x: 5,
+                y: 5,
+                width: 4,
+                height: 4
+            }
+        );
+        assert_eq!(clusters[0].pixel_count, 16);
+        assert_eq!(clusters[1].centroid, Point { x: 21, y: 11 });
+        assert_eq!(clusters[1].pixel_count, 9);
+    }
+
+    #[test]
+    fn delta_e_tolerance_rejects_distinct_colors() {
+        let image = RgbaImage::from_pixel(8, 8, Rgba([0, 0, 255, 255]));
+        assert!(find_all_colors(&image, None, RED, Tolerance::DeltaE(20.0)).is_empty());
+    }
+
+    #[test]
+    fn region_outside_image_finds_nothing() {
+        let image = canvas_with_blocks();
+        let region = Rect {
+            x: 100,
+            y: 100,
+            width: 10,
+            height: 10,
+        };
+        assert_eq!(
+            find_color(&image, Some(region), RED, Tolerance::PerChannel(255)),
+            None
+        );
+    }
+}
@@ -0,0 +1,533 @@
+//! Input events on X11: the RECORD extension observes them and XTEST synthesizes them.
+
+use super::EventKind;
+use crate::xserver::XConnection;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_uchar, c_uint, c_ulong, c_ushort, c_void};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::OnceLock;
+use x11::xlib;
+
+/// How long to wait for recorded data before checking whether to stop.
+const POLL_INTERVAL_MS: c_int = 50;
+/// `XRecordAllClients`: record devices regardless of which client they go to.
+const ALL_CLIENTS: c_ulong = 3;
+/// `XRecordFromServer`: intercepted data that is an event sent by the server.
+const FROM_SERVER: c_int = 0;
+
+/// Keysyms whose names differ from the ones [`crate::press_key`] uses.
+const KEY_NAMES: [(&str, &str); 16] = [
+    ("enter", "Return"),
+    ("backspace", "BackSpace"),
+    ("escape", "Escape"),
+    ("ctrl", "Control_L"),
+    ("ctrl", "Control_R"),
+    ("shift", "Shift_L"),
+    ("shift", "Shift_R"),
+    ("alt", "Alt_L"),
+    ("alt", "Alt_R"),
+    ("win", "Super_L"),
+    ("win", "Super_R"),
+    ("page_up", "Prior"),
+    ("page_down", "Next"),
+    // Aliases accepted as on Windows; names found above take precedence when decoding
+    ("esc", "Escape"),
+    ("control", "Control_L"),
+    ("windows", "Super_L"),
+];
+
+#[repr(C)]
+struct Range8 {
+    first: c_uchar,
+    last: c_uchar,
+}
+
+#[repr(C)]
+struct Range16 {
+    first: c_ushort,
+    last: c_ushort,
+}
+
+#[repr(C)]
+struct ExtRange {
+    major: Range8,
+    minor: Range16,
+}
+
+/// `XRecordRange`
+#[repr(C)]
+struct RecordRange {
+    core_requests: Range8,
+    core_replies: Range8,
+    ext_requests: ExtRange,
+    ext_replies: ExtRange,
+    delivered_events: Range8,
+    device_events: Range8,
+    errors: Range8,
+    client_started: xlib::Bool,
+    client_died: xlib::Bool,
+}
+
+/// `XRecordInterceptData`
+#[repr(C)]
+struct InterceptData {
+    id_base: xlib::XID,
+    server_time: xlib::Time,
+    client_seq: c_ulong,
+    category: c_int,
+    client_swapped: xlib::Bool,
+    data: *mut c_uchar,
+    /// In 4-byte units.
+    data_len: c_ulong,
+}
+
+type InterceptProc = unsafe extern "C" fn(*mut c_char, *mut InterceptData);
+
+/// The parts of libXtst used here. It is loaded at runtime rather than linked so that
+/// everything else keeps working on systems without it.
+struct Xtst {
+    record_query_version:
+        unsafe extern "C" fn(*mut xlib::Display, *mut c_int, *mut c_int) -> xlib::Status,
+    record_alloc_range: unsafe extern "C" fn() -> *mut RecordRange,
+    record_create_context: unsafe extern "C" fn(
+        *mut xlib::Display,
+        c_int,
+        *mut c_ulong,
+        c_int,
+        *mut *mut RecordRange,
+        c_int,
+    ) -> c_ulong,
+    record_enable_context_async: unsafe extern "C" fn(
+        *mut xlib::Display,
+        c_ulong,
+        Option<InterceptProc>,
+        *mut c_char,
+    ) -> xlib::Status,
+    record_process_replies: unsafe extern "C" fn(*mut xlib::Display),
+    record_disable_context: unsafe extern "C" fn(*mut xlib::Display, c_ulong) -> xlib::Status,
+    record_free_context: unsafe extern "C" fn(*mut xlib::Display, c_ulong) -> xlib::Status,
+    record_free_data: unsafe extern "C" fn(*mut InterceptData),
+    test_query_extension: unsafe extern "C" fn(
+        *mut xlib::Display,
+        *mut c_int,
+        *mut c_int,
+        *mut c_int,
+        *mut c_int,
+    ) -> xlib::Bool,
+    test_fake_key_event:
+        unsafe extern "C" fn(*mut xlib::Display, c_uint, xlib::Bool, c_ulong) -> c_int,
+    test_fake_button_event:
+        unsafe extern "C" fn(*mut xlib::Display, c_uint, xlib::Bool, c_ulong) -> c_int,
+    test_fake_motion_event:
+        unsafe extern "C" fn(*mut xlib::Display, c_int, c_int, c_int, c_ulong) -> c_int,
+}
+
+impl Xtst {
+    fn get() -> Result<&'static Xtst, String> {
+        static LIBRARY: OnceLock<Result<Xtst, String>> = OnceLock::new();
+        LIBRARY
+            .get_or_init(Xtst::load)
+            .as_ref()
+            .map_err(Clone::clone)
+    }
+
+    fn load() -> Result<Xtst, String> {
+        // Never unloaded: the functions are kept for the life of the process
+        let handle = ["libXtst.so.6", "libXtst.so"]
+            .iter()
+            .map(|name| CString::new(*name).unwrap())
+            .map(|name| unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) })
+            .find(|handle| !handle.is_null())
+            .ok_or("libXtst is not installed; it is needed to observe and synthesize input")?;
+
+        macro_rules! symbol {
+            ($name:literal) => {{
+                let symbol: *mut c_void =
+                    unsafe { libc::dlsym(handle, concat!($name, "\0").as_ptr() as *const c_char) };
+                if symbol.is_null() {
+                    return Err(format!("libXtst lacks {}", $name));
+                }
+                unsafe { std::mem::transmute_copy(&symbol) }
+            }};
+        }
+
+        Ok(Xtst {
+            record_query_version: symbol!("XRecordQueryVersion"),
+            record_alloc_range: symbol!("XRecordAllocRange"),
+            record_create_context: symbol!("XRecordCreateContext"),
+            record_enable_context_async: symbol!("XRecordEnableContextAsync"),
+            record_process_replies: symbol!("XRecordProcessReplies"),
+            record_disable_context: symbol!("XRecordDisableContext"),
+            record_free_context: symbol!("XRecordFreeContext"),
+            record_free_data: symbol!("XRecordFreeData"),
+            test_query_extension: symbol!("XTestQueryExtension"),
+            test_fake_key_event: symbol!("XTestFakeKeyEvent"),
+            test_fake_button_event: symbol!("XTestFakeButtonEvent"),
+            test_fake_motion_event: symbol!("XTestFakeMotionEvent"),
+        })
+    }
+}
+
+/// An XRecord context for all device events. Data arrives on its own connection, while
+/// the context is managed, and keysyms looked up, on the control connection.
+struct Recording {
+    xtst: &'static Xtst,
+    control: XConnection,
+    data: XConnection,
+    context: c_ulong,
+}
+
+impl Recording {
+    fn new() -> Result<Self, String> {
+        let xtst = Xtst::get()?;
+        let control = XConnection::open()?;
+        let data = XConnection::open()?;
+
+        let (mut major, mut minor) = (0, 0);
+        if unsafe { (xtst.record_query_version)(control.display, &mut major, &mut minor) } == 0 {
+            return Err("The X server does not support the RECORD extension".to_string());
+        }
+
+        let range = unsafe { (xtst.record_alloc_range)() };
+        if range.is_null() {
+            return Err("Cannot allocate an XRecord range".to_string());
+        }
+        unsafe {
+            (*range).device_events = Range8 {
+                first: xlib::KeyPress as c_uchar,
+                last: xlib::MotionNotify as c_uchar,
+            };
+        }
+        let mut clients = ALL_CLIENTS;
+        let mut ranges = range;
+        let context = unsafe {
+            let context =
+                (xtst.record_create_context)(control.display, 0, &mut clients, 1, &mut ranges, 1);
+            xlib::XFree(range as *mut c_void);
+            // The data connection must not use the context before the server has it
+            xlib::XSync(control.display, xlib::False);
+            context
+        };
+        if context == 0 {
+            return Err("Cannot create an XRecord context".to_string());
+        }
+        Ok(Recording {
+            xtst,
+            control,
+            data,
+            context,
+        })
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        unsafe {
+            (self.xtst.record_disable_context)(self.control.display, self.context);
+            (self.xtst.record_free_context)(self.control.display, self.context);
+            xlib::XSync(self.control.display, xlib::False);
+        }
+    }
+}
+
+/// What [`intercept`] gets as its closure argument.
+struct Intercept<'a> {
+    xtst: &'static Xtst,
+    display: *mut xlib::Display,
+    emit: &'a mut dyn FnMut(EventKind),
+}
+
+unsafe extern "C" fn intercept(closure: *mut c_char, data: *mut InterceptData) {
+    let intercept = &mut *(closure as *mut Intercept);
+    let record = &*data;
+    // Device events are delivered as 32-byte wire events
+    if record.category == FROM_SERVER && record.data_len >= 8 && !record.data.is_null() {
+        let event = std::slice::from_raw_parts(record.data, 32);
+        if let Some(kind) = decode(event, |keycode| key_name(intercept.display, keycode)) {
+            (intercept.emit)(kind);
+        }
+    }
+    (intercept.xtst.record_free_data)(data);
+}
+
+pub fn run(
+    stop: &AtomicBool,
+    ready: Sender<Result<(), String>>,
+    emit: &mut dyn FnMut(EventKind),
+) -> Result<(), String> {
+    let recording = match Recording::new() {
+        Ok(recording) => recording,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return Ok(());
+        }
+    };
+    let mut closure = Intercept {
+        xtst: recording.xtst,
+        display: recording.control.display,
+        emit,
+    };
+    let enabled = unsafe {
+        (recording.xtst.record_enable_context_async)(
+            recording.data.display,
+            recording.context,
+            Some(intercept),
+            &mut closure as *mut Intercept as *mut c_char,
+        )
+    };
+    if enabled == 0 {
+        let _ = ready.send(Err("Cannot enable the XRecord context".to_string()));
+        return Ok(());
+    }
+    let _ = ready.send(Ok(()));
+
+    let fd = unsafe { xlib::XConnectionNumber(recording.data.display) };
+    while !stop.load(Ordering::SeqCst) {
+        let mut readable = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe {
+            libc::poll(&mut readable, 1, POLL_INTERVAL_MS);
+            (recording.xtst.record_process_replies)(recording.data.display);
+        }
+    }
+    Ok(())
+}
+
+/// Turns a wire-format device event into an [`EventKind`], naming keys with `key_name`.
+fn decode(event: &[u8], key_name: impl FnOnce(u8) -> String) -> Option<EventKind> {
+    let detail = event[1];
+    let x = i16::from_ne_bytes([event[20], event[21]]) as i32;
+    let y = i16::from_ne_bytes([event[22], event[23]]) as i32;
+    // The top bit marks events sent with XSendEvent
+    let kind = match (event[0] & 0x7f) as c_int {
+        xlib::KeyPress => EventKind::KeyDown {
+            key: key_name(detail),
+            modifiers: Vec::new(),
+        },
+        xlib::KeyRelease => EventKind::KeyUp {
+            key: key_name(detail),
+            modifiers: Vec::new(),
+        },
+        // Buttons 4 to 7 are the wheel, which only reports presses that matter
+        xlib::ButtonPress | xlib::ButtonRelease if (4..=7).contains(&detail) => {
+            if event[0] & 0x7f != xlib::ButtonPress as u8 {
+                return None;
+            }
+            let (dx, dy) = match detail {
+                4 => (0, 1),
+                5 => (0, -1),
+                6 => (-1, 0),
+                _ => (1, 0),
+            };
+            EventKind::Wheel { dx, dy, x, y }
+        }
+        xlib::ButtonPress => EventKind::ButtonDown {
+            button: button_name(detail),
+            x,
+            y,
+        },
+        xlib::ButtonRelease => EventKind::ButtonUp {
+            button: button_name(detail),
+            x,
+            y,
+        },
+        xlib::MotionNotify => EventKind::MouseMove { x, y },
+        _ => return None,
+    };
+    Some(kind)
+}
+
+pub(crate) fn button_name(button: u8) -> String {
+    match button {
+        1 => "left".to_string(),
+        2 => "middle".to_string(),
+        3 => "right".to_string(),
+        8 => "x1".to_string(),
+        9 => "x2".to_string(),
+        n => format!("button{}", n),
+    }
+}
+
+fn button_number(name: &str) -> Result<c_uint, String> {
+    match name.to_lowercase().as_str() {
+        "left" => Ok(1),
+        "middle" => Ok(2),
+        "right" => Ok(3),
+        "x1" => Ok(8),
+        "x2" => Ok(9),
+        other => other
+            .strip_prefix("button")
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| format!("Unsupported button: {}", name)),
+    }
+}
+
+/// The unshifted keysym of `keycode`, named as [`crate::press_key`] expects.
+pub(crate) fn key_name(display: *mut xlib::Display, keycode: u8) -> String {
+    let keysym = unsafe { xlib::XkbKeycodeToKeysym(display, keycode, 0, 0) };
+    let name = unsafe { xlib::XKeysymToString(keysym) };
+    if keysym == 0 || name.is_null() {
+        return format!("keycode{}", keycode);
+    }
+    let name = unsafe { CStr::from_ptr(name) }.to_string_lossy();
+    KEY_NAMES
+        .iter()
+        .find(|(_, keysym_name)| *keysym_name == name)
+        .map_or_else(|| name.to_lowercase(), |(key, _)| key.to_string())
+}
+
+/// The keycode producing the key `name`, which may also be a keysym name such as `Home`.
+pub(crate) fn keycode(display: *mut xlib::Display, name: &str) -> Result<c_uint, String> {
+    let lower = name.to_lowercase();
+    if let Some(code) = lower.strip_prefix("keycode").and_then(|n| n.parse().ok()) {
+        return Ok(code);
+    }
+    // Keysym names are case-sensitive: try ours, then as given, then Title_Case
+    let title: Vec<String> = lower
+        .split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map_or_else(String::new, |c| c.to_uppercase().chain(chars).collect())
+        })
+        .collect();
+    let candidates = KEY_NAMES
+        .iter()
+        .filter(|(key, _)| *key == lower)
+        .map(|(_, keysym_name)| keysym_name.to_string())
+        .chain([name.to_string(), lower.clone(), title.join("_")]);
+    for candidate in candidates {
+        let Ok(candidate) = CString::new(candidate) else {
+            continue;
+        };
+        let keysym = unsafe { xlib::XStringToKeysym(candidate.as_ptr()) };
+        if keysym == 0 {
+            continue;
+        }
+        let code = unsafe { xlib::XKeysymToKeycode(display, keysym) };
+        if code != 0 {
+            return Ok(code as c_uint);
+        }
+    }
+    Err(format!("Unsupported key: {}", name))
+}
+
+pub fn inject(event: &EventKind) -> Result<(), String> {
+    let xtst = Xtst::get()?;
+    let conn = XConnection::open()?;
+    let display = conn.display;
+    let (mut event_base, mut error_base, mut major, mut minor) = (0, 0, 0, 0);
+    let supported = unsafe {
+        (xtst.test_query_extension)(
+            display,
+            &mut event_base,
+            &mut error_base,
+            &mut major,
+            &mut minor,
+        )
+    };
+    if supported == 0 {
+        return Err("The X server does not support the XTEST extension".to_string());
+    }
+
+    let move_to = |x: i32, y: i32| unsafe {
+        // Screen -1 is the one the pointer is on
+        (xtst.test_fake_motion_event)(display, -1, x, y, 0);
+    };
+    let button = |number: c_uint, down: bool| unsafe {
+        (xtst.test_fake_button_event)(display, number, down as xlib::Bool, 0);
+    };
+    match event {
+        EventKind::KeyDown { key, .. } | EventKind::KeyUp { key, .. } => {
+            let code = keycode(display, key)?;
+            let down = matches!(event, EventKind::KeyDown { .. });
+            unsafe { (xtst.test_fake_key_event)(display, code, down as xlib::Bool, 0) };
+        }
+        EventKind::MouseMove { x, y } => move_to(*x, *y),
+        EventKind::ButtonDown { button: name, x, y }
+        | EventKind::ButtonUp { button: name, x, y } => {
+            let number = button_number(name)?;
+            move_to(*x, *y);
+            button(number, matches!(event, EventKind::ButtonDown { .. }));
+        }
+        EventKind::Wheel { dx, dy, x, y } => {
+            move_to(*x, *y);
+            let vertical = if *dy > 0 { 4 } else { 5 };
+            let horizontal = if *dx > 0 { 7 } else { 6 };
+            for (number, notches) in [
+                (vertical, dy.unsigned_abs()),
+                (horizontal, dx.unsigned_abs()),
+            ] {
+                for _ in 0..notches {
+                    button(number, true);
+                    button(number, false);
+                }
+            }
+        }
+    }
+    unsafe { xlib::XSync(display, xlib::False) };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wire(kind: c_int, detail: u8, x: i16, y: i16) -> [u8; 32] {
+        let mut event = [0u8; 32];
+        event[0] = kind as u8;
+        event[1] = detail;
+        event[20..22].copy_from_slice(&x.to_ne_bytes());
+        event[22..24].copy_from_slice(&y.to_ne_bytes());
+        event
+    }
+
+    #[test]
+    fn decodes_wire_events() {
+        let name = |code: u8| format!("k{}", code);
+        assert_eq!(
+            decode(&wire(xlib::KeyPress, 38, 0, 0), name),
+            Some(EventKind::KeyDown {
+                key: "k38".to_string(),
+                modifiers: Vec::new()
+            })
+        );
+        assert_eq!(
+            decode(&wire(xlib::ButtonRelease, 3, -5, 20), name),
+            Some(EventKind::ButtonUp {
+                button: "right".to_string(),
+                x: -5,
+                y: 20
+            })
+        );
+        assert_eq!(
+            decode(&wire(xlib::ButtonPress, 5, 1, 2), name),
+            Some(EventKind::Wheel {
+                dx: 0,
+                dy: -1,
+                x: 1,
+                y: 2
+            })
+        );
+        assert_eq!(decode(&wire(xlib::ButtonRelease, 5, 1, 2), name), None);
+        assert_eq!(
+            decode(&wire(xlib::MotionNotify | 0x80, 0, 640, 480), name),
+            Some(EventKind::MouseMove { x: 640, y: 480 })
+        );
+    }
+
+    #[test]
+    fn buttons_round_trip_through_names() {
+        for number in 1..=12 {
+            if !(4..=7).contains(&number) {
+                assert_eq!(button_number(&button_name(number)), Ok(number as c_uint));
+            }
+        }
+        assert!(button_number("thumb").is_err());
+    }
+}
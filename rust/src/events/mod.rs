@@ -0,0 +1,268 @@
+//! Observing real keyboard and mouse input, and synthesizing it.
+
+#[cfg(target_os = "linux")]
+pub mod linux;
+#[cfg(target_os = "windows")]
+pub mod windows;
+
+#[cfg(target_os = "linux")]
+use linux as platform;
+#[cfg(target_os = "windows")]
+use windows as platform;
+
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+mod platform {
+    use super::EventKind;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc::Sender;
+
+    const UNSUPPORTED: &str = "Unsupported platform - Windows and X11 only for now";
+
+    pub fn run(
+        _stop: &AtomicBool,
+        ready: Sender<Result<(), String>>,
+        _emit: &mut dyn FnMut(EventKind),
+    ) -> Result<(), String> {
+        let _ = ready.send(Err(UNSUPPORTED.to_string()));
+        Ok(())
+    }
+
+    pub fn inject(_event: &EventKind) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+}
+
+/// One piece of user input, as produced by a [`Listener`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputEvent {
+    /// Milliseconds since the Unix epoch when the event was received.
+    pub time: u64,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// What happened. Keys use the names [`crate::press_key`] accepts where one exists;
+/// `modifiers` are those pressed since the listener started and still held before
+/// the event. Coordinates are in screen pixels.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    KeyDown {
+        key: String,
+        modifiers: Vec<String>,
+    },
+    KeyUp {
+        key: String,
+        modifiers: Vec<String>,
+    },
+    MouseMove {
+        x: i32,
+        y: i32,
+    },
+    ButtonDown {
+        button: String,
+        x: i32,
+        y: i32,
+    },
+    ButtonUp {
+        button: String,
+        x: i32,
+        y: i32,
+    },
+    /// Wheel notches: `dy` positive away from the user, `dx` positive to the right.
+    Wheel {
+        dx: i32,
+        dy: i32,
+        x: i32,
+        y: i32,
+    },
+}
+
+impl EventKind {
+    pub fn is_motion(&self) -> bool {
+        matches!(self, EventKind::MouseMove { .. })
+    }
+}
+
+/// Streams input events on a background thread until stopped.
+pub struct Listener {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Result<(), String>>,
+}
+
+impl Listener {
+    /// Starts passing every input event to `callback`, which runs on the listener
+    /// thread and should return quickly. Fails if input cannot be observed, e.g. when
+    /// the X server lacks the RECORD extension.
+    pub fn start<F>(mut callback: F) -> Result<Listener, String>
+    where
+        F: FnMut(InputEvent) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = stop.clone();
+        let (ready, started) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let mut held = Held::default();
+            platform::run(&flag, ready, &mut |mut kind| {
+                held.apply(&mut kind);
+                callback(InputEvent {
+                    time: now_ms(),
+                    kind,
+                })
+            })
+        });
+        match started.recv() {
+            Ok(Ok(())) => Ok(Listener { stop, handle }),
+            Ok(Err(e)) => Err(e),
+            Err(_) => match handle.join() {
+                Ok(Err(e)) => Err(e),
+                _ => Err("Input listener thread exited unexpectedly".to_string()),
+            },
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Stops listening; no events are delivered once this returns.
+    pub fn stop(self) -> Result<(), String> {
+        self.stop.store(true, Ordering::SeqCst);
+        self.handle
+            .join()
+            .map_err(|_| "Input listener thread panicked".to_string())?
+    }
+}
+
+/// Like [`Listener::start`], delivering events through a channel.
+pub fn listen() -> Result<(Listener, mpsc::Receiver<InputEvent>), String> {
+    let (sender, receiver) = mpsc::channel();
+    let listener = Listener::start(move |event| {
+        let _ = sender.send(event);
+    })?;
+    Ok((listener, receiver))
+}
+
+/// Synthesizes `event` as if it came from a real device, so listeners see it too
+/// (XTest on X11, `SendInput` on Windows). Pointer events move the pointer to their
+/// coordinates first. Presses and moves are refused while the fail-safe is triggered
+/// (see [`crate::failsafe`]) and may wait for the user (see [`crate::activity`]);
+/// releases always go through at once, so nothing is left held down.
+pub fn inject(event: &EventKind) -> Result<(), String> {
+    if matches!(event, EventKind::KeyUp { .. } | EventKind::ButtonUp { .. }) {
+        return platform::inject(event);
+    }
+    crate::before_input()?;
+    let result = platform::inject(event);
+    crate::activity::note_action();
+    result
+}
+
+const MODIFIERS: [&str; 4] = ["ctrl", "shift", "alt", "win"];
+
+/// Modifier keys currently down, tracked from the key events themselves so that every
+/// platform reports them the same way.
+#[derive(Debug, Default)]
+struct Held(Vec<String>);
+
+impl Held {
+    /// Fills in the modifiers of a key event and notes any modifier it presses or releases.
+    fn apply(&mut self, kind: &mut EventKind) {
+        let (key, modifiers, down) = match kind {
+            EventKind::KeyDown { key, modifiers } => (key, modifiers, true),
+            EventKind::KeyUp { key, modifiers } => (key, modifiers, false),
+            _ => return,
+        };
+        *modifiers = self.0.clone();
+        if !MODIFIERS.contains(&key.as_str()) {
+            return;
+        }
+        if !down {
+            self.0.retain(|held| held != key);
+        } else if !self.0.contains(key) {
+            self.0.push(key.clone());
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(down: bool, key: &str) -> EventKind {
+        let (key, modifiers) = (key.to_string(), Vec::new());
+        if down {
+            EventKind::KeyDown { key, modifiers }
+        } else {
+            EventKind::KeyUp { key, modifiers }
+        }
+    }
+
+    #[test]
+    fn modifiers_are_tracked_across_key_events() {
+        let mut held = Held::default();
+        let mut modifiers_of = |mut kind: EventKind| {
+            held.apply(&mut kind);
+            match kind {
+                EventKind::KeyDown { modifiers, .. } | EventKind::KeyUp { modifiers, .. } => {
+                    modifiers
+                }
+                _ => unreachable!(),
+            }
+        };
+        assert!(modifiers_of(key(true, "ctrl")).is_empty());
+        assert_eq!(modifiers_of(key(true, "shift")), ["ctrl"]);
+        // Auto-repeat must not list a modifier twice
+        assert_eq!(modifiers_of(key(true, "shift")), ["ctrl", "shift"]);
+        assert_eq!(modifiers_of(key(true, "a")), ["ctrl", "shift"]);
+        assert_eq!(modifiers_of(key(false, "ctrl")), ["ctrl", "shift"]);
+        assert_eq!(modifiers_of(key(false, "a")), ["shift"]);
+    }
+
+    #[test]
+    fn events_serialize_as_flat_tagged_objects() {
+        let event = InputEvent {
+            time: 1700000000123,
+            kind: EventKind::KeyDown {
+                key: "a".to_string(),
+                modifiers: vec!["shift".to_string()],
+            },
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "time": 1700000000123u64,
+                "type": "key_down",
+                "key": "a",
+                "modifiers": ["shift"],
+            })
+        );
+        assert_eq!(serde_json::from_value::<InputEvent>(json).unwrap(), event);
+
+        let wheel: InputEvent =
+            serde_json::from_str(r#"{"time":5,"type":"wheel","dx":0,"dy":-1,"x":10,"y":20}"#)
+                .unwrap();
+        assert_eq!(
+            wheel.kind,
+            EventKind::Wheel {
+                dx: 0,
+                dy: -1,
+                x: 10,
+                y: 20
+            }
+        );
+    }
+}
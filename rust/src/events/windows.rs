@@ -0,0 +1,302 @@
+//! Input events on Windows: low-level hooks observe them and `SendInput` synthesizes them.
+
+use super::EventKind;
+use std::cell::RefCell;
+use std::os::raw::c_int;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use winapi::shared::minwindef::{DWORD, HIWORD, LPARAM, LRESULT, UINT, WPARAM};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::winuser::*;
+
+/// How long to wait for messages before checking whether to stop.
+const POLL_INTERVAL_MS: DWORD = 50;
+
+/// Virtual-key codes by the names [`crate::press_key`] uses; letters, digits and
+/// function keys are handled separately.
+const KEYS: [(&str, u16); 28] = [
+    ("enter", 0x0D),
+    ("space", 0x20),
+    ("tab", 0x09),
+    ("escape", 0x1B),
+    ("backspace", 0x08),
+    ("delete", 0x2E),
+    ("insert", 0x2D),
+    ("home", 0x24),
+    ("end", 0x23),
+    ("page_up", 0x21),
+    ("page_down", 0x22),
+    ("up", 0x26),
+    ("down", 0x28),
+    ("left", 0x25),
+    ("right", 0x27),
+    ("caps_lock", 0x14),
+    ("ctrl", 0x11),
+    ("ctrl", 0xA2),
+    ("ctrl", 0xA3),
+    ("shift", 0x10),
+    ("shift", 0xA0),
+    ("shift", 0xA1),
+    ("alt", 0x12),
+    ("alt", 0xA4),
+    ("alt", 0xA5),
+    ("win", 0x5B),
+    ("win", 0x5C),
+    ("menu", 0x5D),
+];
+
+/// Keys that need `KEYEVENTF_EXTENDEDKEY` to be told apart from their numpad twins.
+const EXTENDED: [u16; 10] = [0x2E, 0x2D, 0x24, 0x23, 0x21, 0x22, 0x26, 0x28, 0x25, 0x27];
+
+thread_local! {
+    /// Events seen by the hooks, which run on the listener thread while it pumps messages.
+    static PENDING: RefCell<Vec<EventKind>> = const { RefCell::new(Vec::new()) };
+}
+
+fn queue(kind: EventKind) {
+    PENDING.with(|pending| pending.borrow_mut().push(kind));
+}
+
+unsafe extern "system" fn keyboard_hook(code: c_int, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code == HC_ACTION {
+        let info = &*(lparam as *const KBDLLHOOKSTRUCT);
+        let key = key_name(info.vkCode as u16);
+        let modifiers = Vec::new();
+        match wparam as UINT {
+            WM_KEYDOWN | WM_SYSKEYDOWN => queue(EventKind::KeyDown { key, modifiers }),
+            WM_KEYUP | WM_SYSKEYUP => queue(EventKind::KeyUp { key, modifiers }),
+            _ => {}
+        }
+    }
+    CallNextHookEx(ptr::null_mut(), code, wparam, lparam)
+}
+
+unsafe extern "system" fn mouse_hook(code: c_int, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code == HC_ACTION {
+        let info = &*(lparam as *const MSLLHOOKSTRUCT);
+        let (x, y) = (info.pt.x, info.pt.y);
+        let high = HIWORD(info.mouseData);
+        let xbutton = if high == XBUTTON1 { "x1" } else { "x2" };
+        let kind = match wparam as UINT {
+            WM_MOUSEMOVE => Some(EventKind::MouseMove { x, y }),
+            WM_LBUTTONDOWN => Some(button_event(true, "left", x, y)),
+            WM_LBUTTONUP => Some(button_event(false, "left", x, y)),
+            WM_RBUTTONDOWN => Some(button_event(true, "right", x, y)),
+            WM_RBUTTONUP => Some(button_event(false, "right", x, y)),
+            WM_MBUTTONDOWN => Some(button_event(true, "middle", x, y)),
+            WM_MBUTTONUP => Some(button_event(false, "middle", x, y)),
+            WM_XBUTTONDOWN => Some(button_event(true, xbutton, x, y)),
+            WM_XBUTTONUP => Some(button_event(false, xbutton, x, y)),
+            WM_MOUSEWHEEL => Some(EventKind::Wheel {
+                dx: 0,
+                dy: notches(high as i16),
+                x,
+                y,
+            }),
+            WM_MOUSEHWHEEL => Some(EventKind::Wheel {
+                dx: notches(high as i16),
+                dy: 0,
+                x,
+                y,
+            }),
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            queue(kind);
+        }
+    }
+    CallNextHookEx(ptr::null_mut(), code, wparam, lparam)
+}
+
+fn button_event(down: bool, button: &str, x: i32, y: i32) -> EventKind {
+    let button = button.to_string();
+    if down {
+        EventKind::ButtonDown { button, x, y }
+    } else {
+        EventKind::ButtonUp { button, x, y }
+    }
+}
+
+/// Whole wheel notches in `delta`, counting a partial turn from a smooth wheel as one.
+fn notches(delta: i16) -> i32 {
+    let delta = delta as i32;
+    if delta.abs() < WHEEL_DELTA as i32 {
+        delta.signum()
+    } else {
+        delta / WHEEL_DELTA as i32
+    }
+}
+
+pub fn run(
+    stop: &AtomicBool,
+    ready: Sender<Result<(), String>>,
+    emit: &mut dyn FnMut(EventKind),
+) -> Result<(), String> {
+    let (keyboard, mouse) = unsafe {
+        let module = GetModuleHandleW(ptr::null());
+        (
+            SetWindowsHookExW(WH_KEYBOARD_LL, Some(keyboard_hook), module, 0),
+            SetWindowsHookExW(WH_MOUSE_LL, Some(mouse_hook), module, 0),
+        )
+    };
+    if keyboard.is_null() || mouse.is_null() {
+        let error = unsafe { GetLastError() };
+        for hook in [keyboard, mouse] {
+            if !hook.is_null() {
+                unsafe { UnhookWindowsHookEx(hook) };
+            }
+        }
+        let _ = ready.send(Err(format!("Failed to install input hooks: {}", error)));
+        return Ok(());
+    }
+    let _ = ready.send(Ok(()));
+
+    // The hooks are called from within this thread's message retrieval
+    let mut msg: MSG = unsafe { std::mem::zeroed() };
+    while !stop.load(Ordering::SeqCst) {
+        unsafe {
+            MsgWaitForMultipleObjects(0, ptr::null(), 0, POLL_INTERVAL_MS, QS_ALLINPUT);
+            while PeekMessageW(&mut msg, ptr::null_mut(), 0, 0, PM_REMOVE) != 0 {
+                TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        }
+        for kind in PENDING.with(|pending| pending.take()) {
+            emit(kind);
+        }
+    }
+    unsafe {
+        UnhookWindowsHookEx(keyboard);
+        UnhookWindowsHookEx(mouse);
+    }
+    Ok(())
+}
+
+pub(crate) fn key_name(vk: u16) -> String {
+    match vk {
+        0x41..=0x5A => ((vk as u8) as char).to_ascii_lowercase().to_string(),
+        0x30..=0x39 => ((vk as u8) as char).to_string(),
+        0x70..=0x87 => format!("f{}", vk - 0x6F),
+        _ => KEYS
+            .iter()
+            .find(|(_, code)| *code == vk)
+            .map_or_else(|| format!("vk{}", vk), |(name, _)| name.to_string()),
+    }
+}
+
+pub(crate) fn key_code(name: &str) -> Result<u16, String> {
+    let lower = name.to_lowercase();
+    let mut chars = lower.chars();
+    if let (Some(ch), None) = (chars.next(), chars.next()) {
+        if ch.is_ascii_alphanumeric() {
+            return Ok(ch.to_ascii_uppercase() as u16);
+        }
+    }
+    if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u16>().ok()) {
+        if (1..=24).contains(&n) {
+            return Ok(0x6F + n);
+        }
+    }
+    if let Some(vk) = lower.strip_prefix("vk").and_then(|n| n.parse().ok()) {
+        return Ok(vk);
+    }
+    KEYS.iter()
+        .find(|(key, _)| *key == lower)
+        .map(|(_, code)| *code)
+        .ok_or_else(|| format!("Unsupported key: {}", name))
+}
+
+fn send(mut input: INPUT) -> Result<(), String> {
+    if unsafe { SendInput(1, &mut input, std::mem::size_of::<INPUT>() as i32) } == 0 {
+        let error = unsafe { GetLastError() };
+        return Err(format!("Failed to send input: {}", error));
+    }
+    Ok(())
+}
+
+fn send_mouse(flags: DWORD, data: DWORD) -> Result<(), String> {
+    let mut input = INPUT {
+        type_: INPUT_MOUSE,
+        u: unsafe { std::mem::zeroed() },
+    };
+    unsafe {
+        *input.u.mi_mut() = MOUSEINPUT {
+            dx: 0,
+            dy: 0,
+            mouseData: data,
+            dwFlags: flags,
+            time: 0,
+            dwExtraInfo: 0,
+        };
+    }
+    send(input)
+}
+
+fn move_to(x: i32, y: i32) -> Result<(), String> {
+    if unsafe { SetCursorPos(x, y) } == 0 {
+        let error = unsafe { GetLastError() };
+        return Err(format!("Failed to move cursor: {}", error));
+    }
+    Ok(())
+}
+
+pub fn inject(event: &EventKind) -> Result<(), String> {
+    match event {
+        EventKind::KeyDown { key, .. } | EventKind::KeyUp { key, .. } => {
+            let vk = key_code(key)?;
+            let mut flags = 0;
+            if matches!(event, EventKind::KeyUp { .. }) {
+                flags |= KEYEVENTF_KEYUP;
+            }
+            if EXTENDED.contains(&vk) {
+                flags |= KEYEVENTF_EXTENDEDKEY;
+            }
+            let mut input = INPUT {
+                type_: INPUT_KEYBOARD,
+                u: unsafe { std::mem::zeroed() },
+            };
+            unsafe {
+                *input.u.ki_mut() = KEYBDINPUT {
+                    wVk: vk,
+                    wScan: 0,
+                    dwFlags: flags,
+                    time: 0,
+                    dwExtraInfo: 0,
+                };
+            }
+            send(input)
+        }
+        EventKind::MouseMove { x, y } => move_to(*x, *y),
+        EventKind::ButtonDown { button, x, y } | EventKind::ButtonUp { button, x, y } => {
+            let down = matches!(event, EventKind::ButtonDown { .. });
+            let (flags, data) = match (button.to_lowercase().as_str(), down) {
+                ("left", true) => (MOUSEEVENTF_LEFTDOWN, 0),
+                ("left", false) => (MOUSEEVENTF_LEFTUP, 0),
+                ("right", true) => (MOUSEEVENTF_RIGHTDOWN, 0),
+                ("right", false) => (MOUSEEVENTF_RIGHTUP, 0),
+                ("middle", true) => (MOUSEEVENTF_MIDDLEDOWN, 0),
+                ("middle", false) => (MOUSEEVENTF_MIDDLEUP, 0),
+                ("x1", true) => (MOUSEEVENTF_XDOWN, XBUTTON1 as DWORD),
+                ("x1", false) => (MOUSEEVENTF_XUP, XBUTTON1 as DWORD),
+                ("x2", true) => (MOUSEEVENTF_XDOWN, XBUTTON2 as DWORD),
+                ("x2", false) => (MOUSEEVENTF_XUP, XBUTTON2 as DWORD),
+                _ => return Err(format!("Unsupported button: {}", button)),
+            };
+            move_to(*x, *y)?;
+            send_mouse(flags, data)
+        }
+        EventKind::Wheel { dx, dy, x, y } => {
+            move_to(*x, *y)?;
+            let delta = WHEEL_DELTA as i32;
+            if *dy != 0 {
+                send_mouse(MOUSEEVENTF_WHEEL, (dy * delta) as DWORD)?;
+            }
+            if *dx != 0 {
+                send_mouse(MOUSEEVENTF_HWHEEL, (dx * delta) as DWORD)?;
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Aborting runaway automation: while the fail-safe is on, input operations refuse to
+//! run when the user has pushed the mouse into one of its screen corners.
+//!
+//! The library leaves it off, so that embedding programs decide; the `autoanchor`
+//! command line turns it on unless given `--no-failsafe`.
+
+use super::{get_cursor_position, get_screen_size, AutomationError, Point};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+static CONFIG: Mutex<Option<FailSafe>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl FromStr for Corner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "top-left" => Ok(Corner::TopLeft),
+            "top-right" => Ok(Corner::TopRight),
+            "bottom-left" => Ok(Corner::BottomLeft),
+            "bottom-right" => Ok(Corner::BottomRight),
+            _ => Err(format!("Unsupported corner: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Corner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Corner::TopLeft => "top-left",
+            Corner::TopRight => "top-right",
+            Corner::BottomLeft => "bottom-left",
+            Corner::BottomRight => "bottom-right",
+        })
+    }
+}
+
+/// Fail-safe settings; off by default, for the top-left corner of the primary screen
+/// once enabled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FailSafe {
+    pub enabled: bool,
+    pub corners: Vec<Corner>,
+    /// Pixels from the exact corner that still count as in it.
+    pub margin: u32,
+}
+
+impl Default for FailSafe {
+    fn default() -> Self {
+        FailSafe {
+            enabled: false,
+            corners: vec![Corner::TopLeft],
+            margin: 0,
+        }
+    }
+}
+
+impl FailSafe {
+    /// The corner of a `screen`-sized primary screen that `position` is in, if it is one
+    /// of ours. Without a screen size only the top-left corner can be told.
+    pub fn corner_at(&self, position: Point, screen: Option<Point>) -> Option<Corner> {
+        let margin = self.margin as i64;
+        let (x, y) = (position.x as i64, position.y as i64);
+        let near_start = |v: i64| (0..=margin).contains(&v);
+        let near_end = |v: i64, size: i32| (size as i64 - 1 - margin..size as i64).contains(&v);
+        self.corners
+            .iter()
+            .copied()
+            .find(|corner| match (corner, screen) {
+                (Corner::TopLeft, _) => near_start(x) && near_start(y),
+                (Corner::TopRight, Some(s)) => near_end(x, s.x) && near_start(y),
+                (Corner::BottomLeft, Some(s)) => near_start(x) && near_end(y, s.y),
+                (Corner::BottomRight, Some(s)) => near_end(x, s.x) && near_end(y, s.y),
+                (_, None) => false,
+            })
+    }
+}
+
+pub fn set_failsafe(config: FailSafe) {
+    *CONFIG.lock().unwrap() = Some(config);
+}
+
+pub fn failsafe() -> FailSafe {
+    CONFIG.lock().unwrap().clone().unwrap_or_default()
+}
+
+/// Fails with [`AutomationError::FailSafeTriggered`] if the fail-safe is on and the cursor
+/// is in one of its corners. Nothing is checked where the cursor cannot be located.
+pub fn check() -> Result<(), AutomationError> {
+    let config = failsafe();
+    if !config.enabled || config.corners.is_empty() {
+        return Ok(());
+    }
+    let Ok(position) = get_cursor_position() else {
+        return Ok(());
+    };
+    let screen = if config.corners.iter().all(|c| *c == Corner::TopLeft) {
+        None
+    } else {
+        get_screen_size().ok()
+    };
+    match config.corner_at(position, screen) {
+        Some(corner) => Err(AutomationError::FailSafeTriggered { corner, position }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_configured_corners() {
+        let screen = Some(Point { x: 1920, y: 1080 });
+        let config = FailSafe {
+            enabled: true,
+            corners: vec![Corner::TopLeft, Corner::BottomRight],
+            margin: 2,
+        };
+        let at = |x, y| config.corner_at(Point { x, y }, screen);
+        assert_eq!(at(0, 0), Some(Corner::TopLeft));
+        assert_eq!(at(2, 1), Some(Corner::TopLeft));
+        assert_eq!(at(3, 0), None);
+        assert_eq!(at(1919, 1079), Some(Corner::BottomRight));
+        assert_eq!(at(1917, 1077), Some(Corner::BottomRight));
+        assert_eq!(at(1916, 1079), None);
+        assert_eq!(at(1919, 0), None);
+        // Unknown screen size: only the top-left corner is known
+        assert_eq!(config.corner_at(Point { x: 1919, y: 1079 }, None), None);
+    }
+
+    #[test]
+    fn error_converts_to_recognizable_message() {
+        let error = AutomationError::FailSafeTriggered {
+            corner: Corner::TopLeft,
+            position: Point { x: 0, y: 0 },
+        };
+        let message: String = error.into();
+        assert_eq!(
+            message,
+            "Fail-safe triggered: cursor at (0, 0) in the top-left screen corner"
+        );
+        assert!(AutomationError::is_fail_safe(&message));
+        assert!(!AutomationError::is_fail_safe("Unsupported key: hyper"));
+        assert_eq!("Bottom_Right".parse::<Corner>(), Ok(Corner::BottomRight));
+    }
+
+    #[test]
+    fn off_until_enabled() {
+        assert!(!FailSafe::default().enabled);
+        assert!(!failsafe().enabled);
+        assert_eq!(check(), Ok(()));
+    }
+}
@@ -0,0 +1,223 @@
+use super::Point;
+use image::{Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Cell size of the bundled glyph model, in font units.
+pub const GLYPH_WIDTH: u32 = 5;
+pub const GLYPH_HEIGHT: u32 = 9;
+/// Rows above the baseline; the remaining rows are descenders.
+pub const BASELINE: u32 = 7;
+/// Horizontal distance between the starts of two neighbouring cells.
+pub const ADVANCE: u32 = GLYPH_WIDTH + 1;
+pub const LINE_HEIGHT: u32 = GLYPH_HEIGHT + 2;
+
+const MODEL: &str = include_str!("../models/glyphs-5x9.txt");
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glyph {
+    pub ch: char,
+    /// One bitmask per row, bit 4 being the leftmost column.
+    pub rows: [u8; GLYPH_HEIGHT as usize],
+}
+
+impl Glyph {
+    pub fn is_set(&self, x: u32, y: u32) -> bool {
+        x < GLYPH_WIDTH
+            && y < GLYPH_HEIGHT
+            && self.rows[y as usize] & (1 << (GLYPH_WIDTH - 1 - x)) != 0
+    }
+
+    /// First and last inked column.
+    pub fn ink_columns(&self) -> (u32, u32) {
+        let columns: Vec<u32> = (0..GLYPH_WIDTH)
+            .filter(|x| (0..GLYPH_HEIGHT).any(|y| self.is_set(*x, y)))
+            .collect();
+        (columns[0], columns[columns.len() - 1])
+    }
+}
+
+/// Parses a glyph model in the format of `models/glyphs-5x9.txt`.
+pub fn parse_model(source: &str) -> Result<Vec<Glyph>, String> {
+    fn finish(glyphs: &mut Vec<Glyph>, current: Option<(char, Vec<u8>)>) -> Result<(), String> {
+        if let Some((ch, bitmap)) = current {
+            if bitmap.len() < BASELINE as usize || bitmap.iter().all(|row| *row == 0) {
+                return Err(format!(
+                    "Glyph {:?} needs at least {} rows and some ink",
+                    ch, BASELINE
+                ));
+            }
+            let mut rows = [0u8; GLYPH_HEIGHT as usize];
+            rows[..bitmap.len()].copy_from_slice(&bitmap);
+            glyphs.push(Glyph { ch, rows });
+        }
+        Ok(())
+    }
+
+    let mut glyphs = Vec::new();
+    let mut current: Option<(char, Vec<u8>)> = None;
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.trim_end();
+        // Comments are only allowed before the first glyph, where rows cannot start
+        if line.is_empty() || (line.starts_with('#') && current.is_none()) {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("glyph ") {
+            let mut chars = rest.chars();
+            let ch = match (chars.next(), chars.next()) {
+                (Some(ch), None) => ch,
+                _ => {
+                    return Err(format!(
+                        "Line {}: expected a single character after 'glyph'",
+                        number + 1
+                    ))
+                }
+            };
+            finish(&mut glyphs, current.take())?;
+            current = Some((ch, Vec::new()));
+            continue;
+        }
+
+        let (ch, bitmap) = current
+            .as_mut()
+            .ok_or_else(|| format!("Line {}: bitmap row outside of a glyph", number + 1))?;
+        if line.len() != GLYPH_WIDTH as usize || bitmap.len() == GLYPH_HEIGHT as usize {
+            return Err(format!(
+                "Line {}: glyph {:?} must be {}x{} at most",
+                number + 1,
+                ch,
+                GLYPH_WIDTH,
+                GLYPH_HEIGHT
+            ));
+        }
+        let mut mask = 0u8;
+        for c in line.chars() {
+            mask = mask << 1
+                | match c {
+                    '#' => 1,
+                    '.' => 0,
+                    _ => {
+                        return Err(format!(
+                            "Line {}: unexpected {:?} in glyph bitmap",
+                            number + 1,
+                            c
+                        ))
+                    }
+                };
+        }
+        bitmap.push(mask);
+    }
+    finish(&mut glyphs, current)?;
+    Ok(glyphs)
+}
+
+fn model() -> &'static HashMap<char, Glyph> {
+    static GLYPHS: OnceLock<HashMap<char, Glyph>> = OnceLock::new();
+    GLYPHS.get_or_init(|| {
+        parse_model(MODEL)
+            .expect("bundled glyph model is valid")
+            .into_iter()
+            .map(|glyph| (glyph.ch, glyph))
+            .collect()
+    })
+}
+
+/// All glyphs of the bundled model.
+pub fn glyphs() -> impl Iterator<Item = &'static Glyph> {
+    model().values()
+}
+
+/// The glyph for `ch`, falling back to `?` for characters the model does not cover.
+pub fn glyph(ch: char) -> &'static Glyph {
+    let glyphs = model();
+    glyphs.get(&ch).unwrap_or_else(|| &glyphs[&'?'])
+}
+
+/// Size in pixels of `text` rendered at `scale`, accounting for `\n` line breaks.
+pub fn text_size(text: &str, scale: u32) -> (u32, u32) {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let columns = lines
+        .iter()
+        .map(|line| line.chars().count() as u32)
+        .max()
+        .unwrap_or(0);
+    let width = columns
+        .saturating_mul(ADVANCE)
+        .saturating_sub(1)
+        .saturating_mul(scale);
+    let height = (lines.len() as u32 - 1)
+        .saturating_mul(LINE_HEIGHT)
+        .saturating_add(GLYPH_HEIGHT)
+        .saturating_mul(scale);
+    (width, height)
+}
+
+/// Renders `text` with its top-left cell corner at `origin`. Pixels falling outside the
+/// image are clipped.
+pub fn draw_text(image: &mut RgbaImage, origin: Point, text: &str, scale: u32, color: Rgba<u8>) {
+    let scale = scale.max(1) as i64;
+    for (line_index, line) in text.split('\n').enumerate() {
+        let top = origin.y as i64 + line_index as i64 * LINE_HEIGHT as i64 * scale;
+        for (column, ch) in line.chars().enumerate() {
+            if ch == ' ' {
+                continue;
+            }
+            let left = origin.x as i64 + column as i64 * ADVANCE as i64 * scale;
+            let glyph = glyph(ch);
+            for gy in 0..GLYPH_HEIGHT {
+                for gx in 0..GLYPH_WIDTH {
+                    if !glyph.is_set(gx, gy) {
+                        continue;
+                    }
+                    // The scaled cell, clipped to the image
+                    let (x, y) = (left + gx as i64 * scale, top + gy as i64 * scale);
+                    let (x0, x1) = (x.max(0), (x + scale).min(image.width() as i64));
+                    let (y0, y1) = (y.max(0), (y + scale).min(image.height() as i64));
+                    for py in y0..y1 {
+                        for px in x0..x1 {
+                            image.put_pixel(px as u32, py as u32, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_model_parses() {
+        assert!(glyphs().count() > 80);
+        assert!(glyph('A').is_set(0, 1));
+        assert_eq!(glyph('\u{2603}').ch, '?');
+    }
+
+    #[test]
+    fn model_rejects_malformed_rows() {
+        assert!(parse_model("glyph x\n#..#.\n##\n").is_err());
+        assert!(parse_model(".#.#.\n").is_err());
+    }
+
+    #[test]
+    fn draw_text_stays_within_text_size() {
+        let mut image = RgbaImage::new(40, 30);
+        let (width, height) = text_size("Hi\nyo", 1);
+        draw_text(
+            &mut image,
+            Point { x: 2, y: 3 },
+            "Hi\nyo",
+            1,
+            Rgba([255, 0, 0, 255]),
+        );
+        for (x, y, pixel) in image.enumerate_pixels() {
+            if pixel.0[3] != 0 {
+                assert!(x >= 2 && x < 2 + width && y >= 3 && y < 3 + height);
+            }
+        }
+        assert!(image.get_pixel(2, 3).0[3] != 0);
+    }
+}
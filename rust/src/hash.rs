@@ -0,0 +1,273 @@
+use super::Rect;
+use image::imageops::{self, FilterType};
+use image::{GrayImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashAlgorithm {
+    /// Average hash: each bit is whether a pixel of the 8x8 thumbnail is above the mean.
+    #[serde(rename = "ahash")]
+    Average,
+    /// Difference hash: each bit is whether a pixel is brighter than its right neighbour.
+    #[serde(rename = "dhash")]
+    Difference,
+    /// Perceptual hash: sign of the low-frequency DCT coefficients against their median.
+    #[serde(rename = "phash")]
+    Perceptual,
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ahash" | "average" => Ok(HashAlgorithm::Average),
+            "dhash" | "difference" => Ok(HashAlgorithm::Difference),
+            "phash" | "perceptual" => Ok(HashAlgorithm::Perceptual),
+            _ => Err(format!("Unsupported hash algorithm: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HashAlgorithm::Average => "ahash",
+            HashAlgorithm::Difference => "dhash",
+            HashAlgorithm::Perceptual => "phash",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A 64-bit perceptual hash. Serialized as `"<algorithm>:<16 hex digits>"` so hashes
+/// from different algorithms are never compared by accident.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageHash {
+    pub algorithm: HashAlgorithm,
+    pub bits: u64,
+}
+
+impl ImageHash {
+    /// Number of differing bits between two hashes of the same algorithm.
+    pub fn distance(&self, other: &ImageHash) -> Result<u32, String> {
+        if self.algorithm != other.algorithm {
+            return Err(format!(
+                "Cannot compare {} hash with {} hash",
+                self.algorithm, other.algorithm
+            ));
+        }
+        Ok(hamming_distance(self.bits, other.bits))
+    }
+}
+
+impl fmt::Display for ImageHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{:016x}", self.algorithm, self.bits)
+    }
+}
+
+impl FromStr for ImageHash {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (algorithm, hex) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid hash (expected <algorithm>:<hex>): {}", s))?;
+        let bits =
+            u64::from_str_radix(hex, 16).map_err(|e| format!("Invalid hash {}: {}", s, e))?;
+        Ok(ImageHash {
+            algorithm: algorithm.parse()?,
+            bits,
+        })
+    }
+}
+
+impl Serialize for ImageHash {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ImageHash {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Hashes `rect` of `capture` (or the whole capture when `rect` is `None`).
+pub fn region_hash(
+    capture: &RgbaImage,
+    rect: Option<Rect>,
+    algo: HashAlgorithm,
+) -> Result<ImageHash, String> {
+    let full = Rect {
+        x: 0,
+        y: 0,
+        width: capture.width(),
+        height: capture.height(),
+    };
+    let area = rect
+        .unwrap_or(full)
+        .clamp_to(capture.width(), capture.height())
+        .ok_or_else(|| "Hash region is outside the captured image".to_string())?;
+
+    let region = imageops::crop_imm(
+        capture,
+        area.x as u32,
+        area.y as u32,
+        area.width,
+        area.height,
+    )
+    .to_image();
+    let gray = imageops::grayscale(&region);
+
+    let bits = match algo {
+        HashAlgorithm::Average => average_hash(&gray),
+        HashAlgorithm::Difference => difference_hash(&gray),
+        HashAlgorithm::Perceptual => perceptual_hash(&gray),
+    };
+    Ok(ImageHash {
+        algorithm: algo,
+        bits,
+    })
+}
+
+fn average_hash(gray: &GrayImage) -> u64 {
+    let small = imageops::resize(gray, 8, 8, FilterType::Triangle);
+    let mean = small.pixels().map(|p| p.0[0] as u32).sum::<u32>() / 64;
+    small.pixels().enumerate().fold(0u64, |acc, (i, p)| {
+        if p.0[0] as u32 > mean {
+            acc | 1 << i
+        } else {
+            acc
+        }
+    })
+}
+
+fn difference_hash(gray: &GrayImage) -> u64 {
+    let small = imageops::resize(gray, 9, 8, FilterType::Triangle);
+    let mut bits = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            if small.get_pixel(x, y).0[0] > small.get_pixel(x + 1, y).0[0] {
+                bits |= 1 << (y * 8 + x);
+            }
+        }
+    }
+    bits
+}
+
+fn perceptual_hash(gray: &GrayImage) -> u64 {
+    const SIZE: usize = 32;
+    let small = imageops::resize(gray, SIZE as u32, SIZE as u32, FilterType::Triangle);
+    let pixels: Vec<f64> = small.pixels().map(|p| p.0[0] as f64).collect();
+
+    // Only the top-left 8x8 block of the 2D DCT-II is needed
+    let mut cosines = [[0f64; SIZE]; 8];
+    for (u, row) in cosines.iter_mut().enumerate() {
+        for (x, c) in row.iter_mut().enumerate() {
+            *c = ((2 * x + 1) as f64 * u as f64 * std::f64::consts::PI / (2 * SIZE) as f64).cos();
+        }
+    }
+    let mut rows = vec![[0f64; 8]; SIZE];
+    for (y, row) in rows.iter_mut().enumerate() {
+        for (u, out) in row.iter_mut().enumerate() {
+            *out = (0..SIZE)
+                .map(|x| pixels[y * SIZE + x] * cosines[u][x])
+                .sum();
+        }
+    }
+    let mut coefficients = [0f64; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            coefficients[v * 8 + u] = (0..SIZE).map(|y| rows[y][u] * cosines[v][y]).sum();
+        }
+    }
+
+    // The DC term carries overall brightness only, so leave it out of the median
+    let mut sorted: Vec<f64> = coefficients[1..].to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let median = sorted[sorted.len() / 2];
+
+    coefficients.iter().enumerate().fold(
+        0u64,
+        |acc, (i, c)| if *c > median { acc | 1 << i } else { acc },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn dialog(offset: u8) -> RgbaImage {
+        RgbaImage::from_fn(64, 48, |x, y| {
+            let shade = if y < 10 {
+                40
+            } else if (20..30).contains(&y) && (16..48).contains(&x) {
+                200
+            } else {
+                120 + (x as u8 / 4)
+            };
+            Rgba([shade.saturating_add(offset), shade, shade, 255])
+        })
+    }
+
+    #[test]
+    fn identical_regions_hash_equal() {
+        for algo in [
+            HashAlgorithm::Average,
+            HashAlgorithm::Difference,
+            HashAlgorithm::Perceptual,
+        ] {
+            let a = region_hash(&dialog(0), None, algo).unwrap();
+            let b = region_hash(&dialog(0), None, algo).unwrap();
+            assert_eq!(a.distance(&b), Ok(0));
+        }
+    }
+
+    #[test]
+    fn similar_regions_are_close_and_different_regions_are_far() {
+        let base = region_hash(&dialog(0), None, HashAlgorithm::Perceptual).unwrap();
+        let tinted = region_hash(&dialog(6), None, HashAlgorithm::Perceptual).unwrap();
+        let flipped = imageops::flip_vertical(&dialog(0));
+        let other = region_hash(&flipped, None, HashAlgorithm::Perceptual).unwrap();
+
+        assert!(base.distance(&tinted).unwrap() <= 4);
+        assert!(base.distance(&other).unwrap() > 10);
+    }
+
+    #[test]
+    fn hash_round_trips_through_string() {
+        let hash = region_hash(&dialog(0), None, HashAlgorithm::Difference).unwrap();
+        let text = hash.to_string();
+        assert!(text.starts_with("dhash:"));
+        assert_eq!(text.parse::<ImageHash>(), Ok(hash));
+    }
+
+    #[test]
+    fn comparing_different_algorithms_fails() {
+        let a = region_hash(&dialog(0), None, HashAlgorithm::Average).unwrap();
+        let b = region_hash(&dialog(0), None, HashAlgorithm::Difference).unwrap();
+        assert!(a.distance(&b).is_err());
+    }
+
+    #[test]
+    fn region_outside_capture_is_an_error() {
+        let rect = Rect {
+            x: 500,
+            y: 0,
+            width: 10,
+            height: 10,
+        };
+        assert!(region_hash(&dialog(0), Some(rect), HashAlgorithm::Average).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+//! Hotkeys on X11, as passive key grabs on the root window.
+
+use super::Chord;
+use crate::events::linux::keycode;
+use crate::xserver::XConnection;
+use std::os::raw::{c_int, c_uint};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use x11::{keysym, xlib};
+
+/// How long to wait for key events before checking whether to stop.
+const POLL_INTERVAL_MS: c_int = 50;
+/// `BadAccess`: another client already grabs the key.
+const BAD_ACCESS: u8 = 10;
+
+fn modifier_mask(name: &str) -> c_uint {
+    match name {
+        "ctrl" => xlib::ControlMask,
+        "alt" => xlib::Mod1Mask,
+        "shift" => xlib::ShiftMask,
+        _ => xlib::Mod4Mask,
+    }
+}
+
+/// The modifier bit Num Lock is mapped to, which varies between keyboard setups.
+fn num_lock_mask(conn: &XConnection) -> c_uint {
+    unsafe {
+        let num_lock = xlib::XKeysymToKeycode(conn.display, keysym::XK_Num_Lock as xlib::KeySym);
+        let map = xlib::XGetModifierMapping(conn.display);
+        if map.is_null() {
+            return xlib::Mod2Mask;
+        }
+        let per_modifier = (*map).max_keypermod as usize;
+        let keys = std::slice::from_raw_parts((*map).modifiermap, 8 * per_modifier);
+        let index = keys
+            .iter()
+            .position(|&key| num_lock != 0 && key == num_lock);
+        xlib::XFreeModifiermap(map);
+        index.map_or(xlib::Mod2Mask, |i| 1 << (i / per_modifier))
+    }
+}
+
+pub fn run(
+    chord: &Chord,
+    stop: &AtomicBool,
+    ready: Sender<Result<(), String>>,
+    pressed: &mut dyn FnMut(),
+) -> Result<(), String> {
+    let conn = match XConnection::open() {
+        Ok(conn) => conn,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return Ok(());
+        }
+    };
+    let code = match keycode(conn.display, &chord.key) {
+        Ok(code) => code as c_int,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return Ok(());
+        }
+    };
+    let modifiers = chord
+        .modifiers
+        .iter()
+        .fold(0, |mask, name| mask | modifier_mask(name));
+    // Caps Lock and Num Lock are modifiers too, so grab the chord with each of them on
+    let locks = xlib::LockMask | num_lock_mask(&conn);
+    let variants = [0, xlib::LockMask, locks & !xlib::LockMask, locks];
+
+    for extra in variants {
+        unsafe {
+            xlib::XGrabKey(
+                conn.display,
+                code,
+                modifiers | extra,
+                conn.root,
+                xlib::False,
+                xlib::GrabModeAsync,
+                xlib::GrabModeAsync,
+            );
+        }
+    }
+    let ungrab = || {
+        for extra in variants {
+            unsafe { xlib::XUngrabKey(conn.display, code, modifiers | extra, conn.root) };
+        }
+        conn.sync_error();
+    };
+    if let Some(error) = conn.sync_error() {
+        ungrab();
+        let _ = ready.send(Err(if error == BAD_ACCESS {
+            format!("Hotkey {} is already taken by another application", chord)
+        } else {
+            format!("Cannot register hotkey {} (X error {})", chord, error)
+        }));
+        return Ok(());
+    }
+    // Holding the keys sends repeated presses; without releases in between, those are
+    // easy to tell from new presses
+    unsafe { xlib::XkbSetDetectableAutoRepeat(conn.display, xlib::True, ptr::null_mut()) };
+    let _ = ready.send(Ok(()));
+
+    let fd = unsafe { xlib::XConnectionNumber(conn.display) };
+    let mut down = false;
+    while !stop.load(Ordering::SeqCst) {
+        while unsafe { xlib::XPending(conn.display) } > 0 {
+            let mut event: xlib::XEvent = unsafe { std::mem::zeroed() };
+            unsafe { xlib::XNextEvent(conn.display, &mut event) };
+            let key = unsafe { event.key };
+            if key.keycode != code as c_uint {
+                continue;
+            }
+            match event.get_type() {
+                xlib::KeyPress if key.state & !locks == modifiers && !down => {
+                    down = true;
+                    pressed();
+                }
+                xlib::KeyRelease => down = false,
+                _ => {}
+            }
+        }
+        let mut readable = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe { libc::poll(&mut readable, 1, POLL_INTERVAL_MS) };
+    }
+    ungrab();
+    Ok(())
+}
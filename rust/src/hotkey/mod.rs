@@ -0,0 +1,230 @@
+//! System-wide hotkeys, e.g. for starting, pausing and aborting a running bot.
+
+#[cfg(target_os = "linux")]
+pub mod linux;
+#[cfg(target_os = "windows")]
+pub mod windows;
+
+#[cfg(target_os = "linux")]
+use linux as platform;
+#[cfg(target_os = "windows")]
+use windows as platform;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+mod platform {
+    use super::Chord;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc::Sender;
+
+    pub fn run(
+        _chord: &Chord,
+        _stop: &AtomicBool,
+        ready: Sender<Result<(), String>>,
+        _pressed: &mut dyn FnMut(),
+    ) -> Result<(), String> {
+        let _ = ready.send(Err(
+            "Unsupported platform - Windows and X11 only for now".to_string()
+        ));
+        Ok(())
+    }
+}
+
+/// Modifier names in the order chords are written in.
+const MODIFIERS: [&str; 4] = ["ctrl", "alt", "shift", "win"];
+
+/// A key with the modifiers that must be held with it, written like `ctrl+alt+p`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Chord {
+    /// Key name as [`crate::press_key`] accepts it.
+    pub key: String,
+    /// Any of `ctrl`, `alt`, `shift` and `win`, in that order.
+    pub modifiers: Vec<String>,
+}
+
+impl FromStr for Chord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<String> = s.split('+').map(|p| p.trim().to_lowercase()).collect();
+        let key = parts.pop().unwrap_or_default();
+        if key.is_empty() || parts.iter().any(|p| p.is_empty()) {
+            return Err(format!("Invalid hotkey: {}", s));
+        }
+        let mut held = Vec::new();
+        for part in parts {
+            let modifier = match part.as_str() {
+                "ctrl" | "control" => "ctrl",
+                "alt" | "option" => "alt",
+                "shift" => "shift",
+                "win" | "windows" | "super" | "meta" | "cmd" => "win",
+                _ => return Err(format!("Unknown modifier in hotkey {}: {}", s, part)),
+            };
+            held.push(modifier);
+        }
+        Ok(Chord {
+            key,
+            modifiers: MODIFIERS
+                .iter()
+                .filter(|m| held.contains(m))
+                .map(|m| m.to_string())
+                .collect(),
+        })
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for modifier in &self.modifiers {
+            write!(f, "{}+", modifier)?;
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+impl TryFrom<String> for Chord {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        s.parse()
+    }
+}
+
+impl From<Chord> for String {
+    fn from(chord: Chord) -> String {
+        chord.to_string()
+    }
+}
+
+/// A registered hotkey; dropping it unregisters it too.
+pub struct HotkeyHandle {
+    chord: Chord,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<Result<(), String>>>,
+}
+
+impl HotkeyHandle {
+    pub fn chord(&self) -> &Chord {
+        &self.chord
+    }
+
+    pub fn unregister(mut self) -> Result<(), String> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<(), String> {
+        self.stop.store(true, Ordering::SeqCst);
+        match self.handle.take() {
+            Some(handle) => handle
+                .join()
+                .map_err(|_| "Hotkey thread panicked".to_string())?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for HotkeyHandle {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+/// Calls `callback` on a background thread each time `chord` is pressed anywhere on
+/// the desktop; holding the keys down counts once. The keys are taken from other
+/// applications while registered, and registering fails if one already has them.
+pub fn register_hotkey<F>(chord: &str, mut callback: F) -> Result<HotkeyHandle, String>
+where
+    F: FnMut() + Send + 'static,
+{
+    let chord: Chord = chord.parse()?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let flag = stop.clone();
+    let (ready, registered) = mpsc::channel();
+    let registering = chord.clone();
+    let handle = thread::spawn(move || platform::run(&registering, &flag, ready, &mut callback));
+    match registered.recv() {
+        Ok(Ok(())) => Ok(HotkeyHandle {
+            chord,
+            stop,
+            handle: Some(handle),
+        }),
+        Ok(Err(e)) => Err(e),
+        Err(_) => match handle.join() {
+            Ok(Err(e)) => Err(e),
+            _ => Err("Hotkey thread exited unexpectedly".to_string()),
+        },
+    }
+}
+
+pub fn unregister_hotkey(handle: HotkeyHandle) -> Result<(), String> {
+    handle.unregister()
+}
+
+/// Blocks until one of `chords` is pressed, returning it; `None` waits forever.
+pub fn wait_for_hotkey(chords: &[&str], timeout: Option<Duration>) -> Result<Chord, String> {
+    if chords.is_empty() {
+        return Err("No hotkey to wait for".to_string());
+    }
+    let (sender, pressed) = mpsc::channel();
+    let handles = chords
+        .iter()
+        .map(|chord| {
+            let which: Chord = chord.parse()?;
+            let sender = sender.clone();
+            register_hotkey(chord, move || {
+                let _ = sender.send(which.clone());
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let chord = match timeout {
+        Some(timeout) => pressed.recv_timeout(timeout).map_err(|_| {
+            format!(
+                "Timed out after {:.1}s waiting for a hotkey",
+                timeout.as_secs_f64()
+            )
+        })?,
+        None => pressed
+            .recv()
+            .map_err(|_| "Hotkey threads stopped".to_string())?,
+    };
+    drop(handles);
+    Ok(chord)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chords_parse_into_canonical_form() {
+        let chord: Chord = "Shift + Control+ALT+P".parse().unwrap();
+        assert_eq!(chord.key, "p");
+        assert_eq!(chord.modifiers, ["ctrl", "alt", "shift"]);
+        assert_eq!(chord.to_string(), "ctrl+alt+shift+p");
+        assert_eq!("super+f12".parse::<Chord>().unwrap().to_string(), "win+f12");
+        assert_eq!("escape".parse::<Chord>().unwrap().modifiers.len(), 0);
+
+        assert!("ctrl+".parse::<Chord>().is_err());
+        assert!("ctrl++p".parse::<Chord>().is_err());
+        assert_eq!(
+            "hyper+p".parse::<Chord>().unwrap_err(),
+            "Unknown modifier in hotkey hyper+p: hyper"
+        );
+    }
+
+    #[test]
+    fn chords_serialize_as_strings() {
+        let chord: Chord = serde_json::from_str(r#""alt+f4""#).unwrap();
+        assert_eq!(chord.modifiers, ["alt"]);
+        assert_eq!(serde_json::to_string(&chord).unwrap(), r#""alt+f4""#);
+        assert!(serde_json::from_str::<Chord>(r#""""#).is_err());
+    }
+}
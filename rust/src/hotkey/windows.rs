@@ -0,0 +1,72 @@
+//! Hotkeys on Windows, registered with `RegisterHotKey` on the hotkey thread.
+
+use super::Chord;
+use crate::events::windows::key_code;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use winapi::shared::minwindef::{DWORD, UINT};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::winuser::*;
+
+/// How long to wait for messages before checking whether to stop.
+const POLL_INTERVAL_MS: DWORD = 50;
+const ERROR_HOTKEY_ALREADY_REGISTERED: DWORD = 1409;
+/// Each hotkey has a thread of its own, so the id only needs to be unique there.
+const HOTKEY_ID: i32 = 1;
+
+fn modifier_flag(name: &str) -> UINT {
+    (match name {
+        "ctrl" => MOD_CONTROL,
+        "alt" => MOD_ALT,
+        "shift" => MOD_SHIFT,
+        _ => MOD_WIN,
+    }) as UINT
+}
+
+pub fn run(
+    chord: &Chord,
+    stop: &AtomicBool,
+    ready: Sender<Result<(), String>>,
+    pressed: &mut dyn FnMut(),
+) -> Result<(), String> {
+    let vk = match key_code(&chord.key) {
+        Ok(vk) => vk,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return Ok(());
+        }
+    };
+    // Without MOD_NOREPEAT, holding the keys would fire repeatedly
+    let flags = chord
+        .modifiers
+        .iter()
+        .fold(MOD_NOREPEAT as UINT, |flags, name| {
+            flags | modifier_flag(name)
+        });
+    // With no window, WM_HOTKEY is posted to this thread's queue
+    if unsafe { RegisterHotKey(ptr::null_mut(), HOTKEY_ID, flags, vk as UINT) } == 0 {
+        let error = unsafe { GetLastError() };
+        let _ = ready.send(Err(if error == ERROR_HOTKEY_ALREADY_REGISTERED {
+            format!("Hotkey {} is already taken by another application", chord)
+        } else {
+            format!("Cannot register hotkey {}: {}", chord, error)
+        }));
+        return Ok(());
+    }
+    let _ = ready.send(Ok(()));
+
+    let mut msg: MSG = unsafe { std::mem::zeroed() };
+    while !stop.load(Ordering::SeqCst) {
+        unsafe {
+            MsgWaitForMultipleObjects(0, ptr::null(), 0, POLL_INTERVAL_MS, QS_ALLINPUT);
+            while PeekMessageW(&mut msg, ptr::null_mut(), 0, 0, PM_REMOVE) != 0 {
+                if msg.message == WM_HOTKEY && msg.wParam == HOTKEY_ID as usize {
+                    pressed();
+                }
+            }
+        }
+    }
+    unsafe { UnregisterHotKey(ptr::null_mut(), HOTKEY_ID) };
+    Ok(())
+}
@@ -0,0 +1,527 @@
+//! A small HTTP/1.1 server for local control clients: a thread and a single request per
+//! connection, bearer-token auth, and upgrades to [`crate::websocket`].
+//!
+//! Web pages can reach a loopback server too, so requests are refused unless their
+//! `Host` names the server (against DNS rebinding), they carry no `Origin` (browsers
+//! send one on cross-site requests) except for WebSockets from allowed origins, and
+//! POST bodies are declared as JSON (which pages cannot send without a preflight).
+
+use crate::websocket::{accept_key, WebSocket};
+use crate::AutomationResult;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Limit on the request line and headers together.
+const MAX_HEAD_LEN: usize = 64 * 1024;
+const MAX_BODY_LEN: usize = 32 * 1024 * 1024;
+/// How long a client may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    /// Percent-decoded path without the query string.
+    pub path: String,
+    /// Percent-decoded query parameters in order; a key may repeat.
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// The first header called `name`, which is matched ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn query_value(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn is_websocket(&self) -> bool {
+        self.header("upgrade")
+            .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+    }
+
+    /// Whether the request carries `token`, as an `Authorization: Bearer` header or, for
+    /// browsers' WebSockets which cannot set headers, an `access_token` query parameter.
+    pub fn is_authorized(&self, token: &str) -> bool {
+        let given = self
+            .header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .or_else(|| self.query_value("access_token"));
+        // Compare every byte, so timing does not tell how much of a guess was right
+        given.is_some_and(|given| {
+            given.len() == token.len()
+                && given
+                    .bytes()
+                    .zip(token.bytes())
+                    .fold(0, |diff, (a, b)| diff | (a ^ b))
+                    == 0
+        })
+    }
+
+    /// Whether the `Host` header names `local`, the address the client connected to,
+    /// or the loopback interface, on `local`'s port.
+    pub fn is_for_host(&self, local: SocketAddr) -> bool {
+        let Some(host) = self.header("host") else {
+            return false;
+        };
+        let (name, port) = match host.strip_prefix('[') {
+            // An IPv6 address, as in [::1]:8080
+            Some(rest) => match rest.split_once(']') {
+                Some((name, "")) => (name, None),
+                Some((name, port)) => match port.strip_prefix(':') {
+                    Some(port) => (name, Some(port)),
+                    None => return false,
+                },
+                None => return false,
+            },
+            None => match host.split_once(':') {
+                Some((name, port)) => (name, Some(port)),
+                None => (host, None),
+            },
+        };
+        let port_matches = match port {
+            Some(port) => port.parse::<u16>() == Ok(local.port()),
+            None => local.port() == 80,
+        };
+        let name_matches = name.eq_ignore_ascii_case("localhost")
+            || name
+                .parse::<IpAddr>()
+                .is_ok_and(|ip| ip == local.ip() || ip.is_loopback());
+        port_matches && name_matches
+    }
+
+    /// Whether the body is declared as JSON, charset parameters aside.
+    pub fn is_json(&self) -> bool {
+        self.header("content-type")
+            .and_then(|value| value.split(';').next())
+            .is_some_and(|media| media.trim().eq_ignore_ascii_case("application/json"))
+    }
+
+    /// Reads one request; `Ok(None)` if the client closed the connection first, and
+    /// otherwise failures as the status and message to answer with.
+    pub fn read_from(reader: &mut impl BufRead) -> Result<Option<Request>, (u16, String)> {
+        let mut head_len = 0;
+        let mut read_line = |reader: &mut dyn BufRead| -> Result<String, (u16, String)> {
+            let mut line = String::new();
+            reader
+                .take((MAX_HEAD_LEN - head_len) as u64 + 1)
+                .read_line(&mut line)
+                .map_err(|e| (400, format!("Cannot read request: {}", e)))?;
+            head_len += line.len();
+            if head_len > MAX_HEAD_LEN {
+                return Err((431, "Request headers too large".to_string()));
+            }
+            Ok(line.trim_end_matches(['\r', '\n']).to_string())
+        };
+
+        let request_line = read_line(reader)?;
+        if request_line.is_empty() {
+            return Ok(None);
+        }
+        let mut parts = request_line.split(' ');
+        let (Some(method), Some(target), Some(version), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err((400, "Malformed request line".to_string()));
+        };
+        if !version.starts_with("HTTP/1.") {
+            return Err((505, "Only HTTP/1.x is supported".to_string()));
+        }
+        let mut headers = Vec::new();
+        loop {
+            let line = read_line(reader)?;
+            if line.is_empty() {
+                break;
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                return Err((400, format!("Malformed header: {}", line)));
+            };
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let mut request = Request {
+            method: method.to_string(),
+            path: percent_decode(path, false),
+            query: query
+                .split('&')
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| {
+                    let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                    (percent_decode(key, true), percent_decode(value, true))
+                })
+                .collect(),
+            headers,
+            body: Vec::new(),
+        };
+        if request.header("transfer-encoding").is_some() {
+            return Err((501, "Chunked request bodies are not supported".to_string()));
+        }
+        let len = match request.header("content-length") {
+            Some(len) => len
+                .parse::<usize>()
+                .map_err(|_| (400, "Invalid Content-Length".to_string()))?,
+            None => 0,
+        };
+        if len > MAX_BODY_LEN {
+            return Err((413, "Request body too large".to_string()));
+        }
+        request.body = vec![0; len];
+        reader
+            .read_exact(&mut request.body)
+            .map_err(|e| (400, format!("Cannot read request body: {}", e)))?;
+        Ok(Some(request))
+    }
+}
+
+/// Decodes `%XX` escapes, and in query strings `+` for space; malformed escapes are
+/// kept as they are.
+fn percent_decode(text: &str, plus_is_space: bool) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+            (b'+', _) if plus_is_space => decoded.push(b' '),
+            (byte, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json(status: u16, value: &serde_json::Value) -> Self {
+        Response {
+            status,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    pub fn empty(status: u16) -> Self {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// A failed [`AutomationResult`], the shape every error body has.
+    pub fn failure(status: u16, message: &str) -> Self {
+        let result = AutomationResult {
+            success: false,
+            message: Some(message.to_string()),
+            data: None,
+        };
+        Response::json(status, &serde_json::to_value(result).unwrap())
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str(&format!(
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.body.len()
+        ));
+        writer.write_all(head.as_bytes())?;
+        writer.write_all(&self.body)?;
+        writer.flush()
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        101 => "Switching Protocols",
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        426 => "Upgrade Required",
+        431 => "Request Header Fields Too Large",
+        501 => "Not Implemented",
+        505 => "HTTP Version Not Supported",
+        _ => "Internal Server Error",
+    }
+}
+
+/// What to do with a request.
+pub enum Reply {
+    Respond(Response),
+    /// Switch the connection to WebSocket and hand it over.
+    Upgrade(Box<dyn FnOnce(WebSocket) + Send>),
+}
+
+/// Who may use a [`serve`]d server.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Access {
+    /// Bearer token every request must carry.
+    pub token: Option<String>,
+    /// Web page origins, like `http://localhost:3000`, allowed to open WebSockets.
+    pub origins: Vec<String>,
+}
+
+/// A random token for [`Access::token`], URL-safe so it also fits a query string.
+pub fn generate_token() -> Result<String, String> {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).map_err(|e| format!("Cannot generate a token: {}", e))?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+/// Serves connections from `listener` until it fails, each on a thread of its own.
+/// Requests `access` does not let through are answered with 403, 401 or 415 before
+/// `handler` sees them.
+pub fn serve<F>(listener: TcpListener, access: Access, handler: F) -> Result<(), String>
+where
+    F: Fn(&Request) -> Reply + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    let access = Arc::new(access);
+    for stream in listener.incoming() {
+        let stream = stream.map_err(|e| format!("Cannot accept connection: {}", e))?;
+        let (handler, access) = (handler.clone(), access.clone());
+        thread::spawn(move || {
+            let _ = handle_connection(stream, &access, &*handler);
+        });
+    }
+    Ok(())
+}
+
+/// Why `access` refuses `request` arriving at `local`, as the status and message to
+/// answer with.
+fn refusal(request: &Request, local: SocketAddr, access: &Access) -> Option<(u16, String)> {
+    if !request.is_for_host(local) {
+        return Some((403, "Host header does not name this server".to_string()));
+    }
+    if let Some(origin) = request.header("origin") {
+        let upgrade = request.method == "GET" && request.is_websocket();
+        if !upgrade || !access.origins.iter().any(|allowed| allowed == origin) {
+            return Some((403, format!("Origin not allowed: {}", origin)));
+        }
+    }
+    if access
+        .token
+        .as_deref()
+        .is_some_and(|token| !request.is_authorized(token))
+    {
+        return Some((401, "Missing or wrong bearer token".to_string()));
+    }
+    if request.method == "POST" && !request.is_json() {
+        return Some((415, "POST bodies must be application/json".to_string()));
+    }
+    None
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    access: &Access,
+    handler: &(dyn Fn(&Request) -> Reply + Send + Sync),
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request = match Request::read_from(&mut reader) {
+        Ok(Some(request)) => request,
+        Ok(None) => return Ok(()),
+        Err((status, message)) => return Response::failure(status, &message).write_to(&mut stream),
+    };
+    if let Some((status, message)) = refusal(&request, stream.local_addr()?, access) {
+        let mut response = Response::failure(status, &message);
+        if status == 401 {
+            response
+                .headers
+                .push(("WWW-Authenticate".to_string(), "Bearer".to_string()));
+        }
+        return response.write_to(&mut stream);
+    }
+    match handler(&request) {
+        Reply::Respond(response) => response.write_to(&mut stream),
+        Reply::Upgrade(run) => {
+            let key = match request.header("sec-websocket-key") {
+                Some(key) if request.is_websocket() => key,
+                _ => {
+                    return Response::failure(426, "This endpoint needs a WebSocket")
+                        .write_to(&mut stream)
+                }
+            };
+            write!(
+                stream,
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept_key(key)
+            )?;
+            stream.set_read_timeout(None)?;
+            run(WebSocket::new(stream)?);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Option<Request>, (u16, String)> {
+        Request::read_from(&mut text.as_bytes())
+    }
+
+    #[test]
+    fn parses_requests() {
+        let request = parse(concat!(
+            "POST /window/find%20x?title=My+App&all=true&a=%zz HTTP/1.1\r\n",
+            "Host: localhost\r\n",
+            "Authorization: Bearer s3cret\r\n",
+            "Content-Length: 4\r\n",
+            "\r\n",
+            "{}\n\nignored"
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/window/find x");
+        assert_eq!(request.query_value("title"), Some("My App"));
+        assert_eq!(request.query_value("a"), Some("%zz"));
+        assert_eq!(request.header("content-length"), Some("4"));
+        assert_eq!(request.body, b"{}\n\n");
+        assert!(request.is_authorized("s3cret"));
+        assert!(!request.is_authorized("s3cre"));
+        assert!(!request.is_websocket());
+
+        assert_eq!(parse(""), Ok(None));
+        assert_eq!(parse("GET /\r\n\r\n").unwrap_err().0, 400);
+        assert_eq!(parse("GET / HTTP/2\r\n\r\n").unwrap_err().0, 505);
+        let huge = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(MAX_HEAD_LEN));
+        assert_eq!(parse(&huge).unwrap_err().0, 431);
+    }
+
+    fn request(headers: &[(&str, &str)]) -> Request {
+        Request {
+            method: "POST".to_string(),
+            path: "/rpc".to_string(),
+            query: Vec::new(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn checks_host_origin_and_content_type() {
+        let local: SocketAddr = "127.0.0.1:8123".parse().unwrap();
+        let access = Access {
+            token: Some("s3cret".to_string()),
+            origins: vec!["http://localhost:3000".to_string()],
+        };
+        let host = |host: &str| request(&[("Host", host)]).is_for_host(local);
+        assert!(host("localhost:8123"));
+        assert!(host("LOCALHOST:8123"));
+        assert!(host("127.0.0.1:8123"));
+        assert!(host("[::1]:8123"));
+        assert!(!host("localhost"));
+        assert!(!host("localhost:80"));
+        assert!(!host("attacker.example:8123"));
+        assert!(!host("10.0.0.2:8123"));
+        assert!(!host("[::1"));
+        assert!(!request(&[]).is_for_host(local));
+        assert!(!request(&[("Host", "example:80")]).is_for_host("10.0.0.2:80".parse().unwrap()));
+        assert!(request(&[("Host", "10.0.0.2")]).is_for_host("10.0.0.2:80".parse().unwrap()));
+
+        let status = |headers: &[(&str, &str)]| {
+            refusal(&request(headers), local, &access).map(|(status, _)| status)
+        };
+        let ok = [
+            ("Host", "localhost:8123"),
+            ("Authorization", "Bearer s3cret"),
+            ("Content-Type", "application/json; charset=utf-8"),
+        ];
+        assert_eq!(status(&ok), None);
+        assert_eq!(
+            status(&[("Host", "attacker.example:8123"), ok[1], ok[2]]),
+            Some(403)
+        );
+        assert_eq!(
+            status(&[ok[0], ok[1], ok[2], ("Origin", "http://localhost:3000")]),
+            Some(403)
+        );
+        assert_eq!(status(&[ok[0], ok[2]]), Some(401));
+        assert_eq!(status(&[ok[0], ok[1]]), Some(415));
+        assert_eq!(
+            status(&[ok[0], ok[1], ("Content-Type", "text/plain")]),
+            Some(415)
+        );
+
+        let mut upgrade = request(&[ok[0], ok[1], ("Upgrade", "websocket")]);
+        upgrade.method = "GET".to_string();
+        assert_eq!(refusal(&upgrade, local, &access), None);
+        upgrade
+            .headers
+            .push(("Origin".to_string(), "http://localhost:3000".to_string()));
+        assert_eq!(refusal(&upgrade, local, &access), None);
+        upgrade.headers.last_mut().unwrap().1 = "https://attacker.example".to_string();
+        assert_eq!(
+            refusal(&upgrade, local, &access).map(|(status, _)| status),
+            Some(403)
+        );
+    }
+
+    #[test]
+    fn generates_distinct_url_safe_tokens() {
+        let token = generate_token().unwrap();
+        assert_eq!(token.len(), 43);
+        assert!(token
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_'));
+        assert_ne!(token, generate_token().unwrap());
+    }
+
+    #[test]
+    fn writes_responses() {
+        let mut out = Vec::new();
+        Response::failure(404, "Unknown method: nope")
+            .write_to(&mut out)
+            .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("HTTP/1.1 404 Not Found\r\nContent-Type: application/json\r\n"));
+        assert!(text.ends_with(
+            "\r\n\r\n{\"data\":null,\"message\":\"Unknown method: nope\",\"success\":false}"
+        ));
+    }
+}
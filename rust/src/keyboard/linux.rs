@@ -0,0 +1,169 @@
+//! Keyboard input on X11, synthesized with XTEST through [`crate::events::inject`].
+
+use crate::events::linux::keycode;
+use crate::events::{self, EventKind};
+use crate::xserver::XConnection;
+use crate::AutomationResult;
+use std::os::raw::{c_int, c_uint, c_void};
+use std::thread;
+use std::time::Duration;
+use x11::{keysym, xlib};
+
+/// Time between pressing and releasing a key, as on Windows.
+const KEY_HOLD: Duration = Duration::from_millis(10);
+/// Time for clients to pick up a temporary key mapping before it changes again.
+const REMAP_SETTLE: Duration = Duration::from_millis(20);
+/// Keysyms from here on are Unicode code points.
+const UNICODE_KEYSYMS: xlib::KeySym = 0x0100_0000;
+
+pub fn type_text(text: &str, delay_ms: Option<u64>) -> Result<AutomationResult, String> {
+    let delay = Duration::from_millis(delay_ms.unwrap_or(50));
+    let conn = XConnection::open()?;
+    // Characters no key produces are typed with a spare keycode mapped to them for the
+    // moment, and the spare is cleared again afterwards
+    let mut spare = None;
+    let result = text.chars().try_for_each(|ch| {
+        let keysym = keysym_for(ch);
+        match find_key(conn.display, keysym) {
+            Some((code, shifted)) => {
+                let modifiers = if shifted {
+                    vec!["shift".to_string()]
+                } else {
+                    Vec::new()
+                };
+                press_chord(&format!("keycode{}", code), &modifiers)?;
+            }
+            None => {
+                let code = match spare {
+                    Some(code) => code,
+                    None => *spare.insert(spare_keycode(conn.display)?),
+                };
+                remap(conn.display, code, keysym);
+                thread::sleep(REMAP_SETTLE);
+                press_chord(&format!("keycode{}", code), &[])?;
+                thread::sleep(REMAP_SETTLE);
+            }
+        }
+        thread::sleep(delay);
+        Ok::<(), String>(())
+    });
+    if let Some(code) = spare {
+        remap(conn.display, code, 0);
+    }
+    result?;
+    Ok(AutomationResult {
+        success: true,
+        message: Some(format!("Typed text: {}", text)),
+        data: None,
+    })
+}
+
+pub fn press_key(key: &str, modifiers: Option<Vec<String>>) -> Result<AutomationResult, String> {
+    let modifiers = modifiers.unwrap_or_default();
+    // Refuse unknown names before pressing anything
+    let conn = XConnection::open()?;
+    for name in modifiers.iter().map(String::as_str).chain([key]) {
+        keycode(conn.display, name)?;
+    }
+    press_chord(key, &modifiers)?;
+    Ok(AutomationResult {
+        success: true,
+        message: Some(format!("Pressed key: {}", key)),
+        data: None,
+    })
+}
+
+/// Presses `modifiers` in order and then `key`, and releases whatever went down in
+/// reverse, even when a later press fails.
+fn press_chord(key: &str, modifiers: &[String]) -> Result<(), String> {
+    let event = |name: &str, down: bool| {
+        let (key, modifiers) = (name.to_string(), Vec::new());
+        if down {
+            EventKind::KeyDown { key, modifiers }
+        } else {
+            EventKind::KeyUp { key, modifiers }
+        }
+    };
+    let mut held = Vec::new();
+    let mut result = Ok(());
+    for name in modifiers.iter().map(String::as_str).chain([key]) {
+        result = events::inject(&event(name, true));
+        if result.is_err() {
+            break;
+        }
+        held.push(name);
+    }
+    if result.is_ok() {
+        thread::sleep(KEY_HOLD);
+    }
+    for name in held.into_iter().rev() {
+        result = result.and(events::inject(&event(name, false)));
+    }
+    result
+}
+
+/// The keysym that types `ch`.
+fn keysym_for(ch: char) -> xlib::KeySym {
+    match ch {
+        '\n' | '\r' => keysym::XK_Return as xlib::KeySym,
+        '\t' => keysym::XK_Tab as xlib::KeySym,
+        '\u{8}' => keysym::XK_BackSpace as xlib::KeySym,
+        // Latin-1 keysyms are the characters themselves
+        ' '..='~' | '\u{a0}'..='\u{ff}' => ch as xlib::KeySym,
+        _ => UNICODE_KEYSYMS | ch as xlib::KeySym,
+    }
+}
+
+/// The keycode producing `keysym` on its own or with Shift, and whether Shift is needed.
+fn find_key(display: *mut xlib::Display, keysym: xlib::KeySym) -> Option<(c_uint, bool)> {
+    let code = unsafe { xlib::XKeysymToKeycode(display, keysym) };
+    if code == 0 {
+        return None;
+    }
+    (0..2)
+        .find(|level| unsafe { xlib::XkbKeycodeToKeysym(display, code, 0, *level) } == keysym)
+        .map(|level| (code as c_uint, level == 1))
+}
+
+/// A keycode with nothing mapped to it.
+fn spare_keycode(display: *mut xlib::Display) -> Result<c_uint, String> {
+    let (mut min, mut max): (c_int, c_int) = (0, 0);
+    let mut per_keycode: c_int = 0;
+    unsafe { xlib::XDisplayKeycodes(display, &mut min, &mut max) };
+    let count = max - min + 1;
+    let map = unsafe { xlib::XGetKeyboardMapping(display, min as u8, count, &mut per_keycode) };
+    if map.is_null() || per_keycode <= 0 {
+        return Err("Cannot read the keyboard mapping".to_string());
+    }
+    let keysyms =
+        unsafe { std::slice::from_raw_parts(map, (count * per_keycode) as usize) }.to_vec();
+    unsafe { xlib::XFree(map as *mut c_void) };
+    keysyms
+        .chunks(per_keycode as usize)
+        .position(|mapped| mapped.iter().all(|keysym| *keysym == 0))
+        .map(|index| (min + index as c_int) as c_uint)
+        .ok_or_else(|| "No spare keycode to type characters missing from the keyboard".to_string())
+}
+
+/// Maps `code` to `keysym` with and without Shift; 0 clears it.
+fn remap(display: *mut xlib::Display, code: c_uint, keysym: xlib::KeySym) {
+    let mut keysyms = [keysym, keysym];
+    unsafe {
+        xlib::XChangeKeyboardMapping(display, code as c_int, 2, keysyms.as_mut_ptr(), 1);
+        xlib::XSync(display, xlib::False);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn characters_map_to_keysyms() {
+        assert_eq!(keysym_for('a'), 0x61);
+        assert_eq!(keysym_for('~'), 0x7e);
+        assert_eq!(keysym_for('é'), 0xe9);
+        assert_eq!(keysym_for('\n'), 0xff0d);
+        assert_eq!(keysym_for('€'), 0x0100_20ac);
+    }
+}
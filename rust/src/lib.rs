@@ -1,20 +1,89 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Point {
     pub x: i32,
     pub y: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn contains(&self, point: &Point) -> bool {
+        point.x >= self.x
+            && point.y >= self.y
+            && (point.x as i64) < self.x as i64 + self.width as i64
+            && (point.y as i64) < self.y as i64 + self.height as i64
+    }
+
+    /// Intersects the rectangle with a `width` x `height` area anchored at the origin,
+    /// returning `None` when nothing is left.
+    pub fn clamp_to(&self, width: u32, height: u32) -> Option<Rect> {
+        self.intersect(&Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        })
+    }
+
+    /// The area covered by both rectangles, or `None` if they do not overlap.
+    pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let x0 = (self.x as i64).max(other.x as i64);
+        let y0 = (self.y as i64).max(other.y as i64);
+        let x1 = (self.x as i64 + self.width as i64).min(other.x as i64 + other.width as i64);
+        let y1 = (self.y as i64 + self.height as i64).min(other.y as i64 + other.height as i64);
+        if x0 >= x1 || y0 >= y1 {
+            return None;
+        }
+        Some(Rect {
+            x: x0 as i32,
+            y: y0 as i32,
+            width: (x1 - x0) as u32,
+            height: (y1 - y0) as u32,
+        })
+    }
+}
+
+/// What a screenshot covers and what is drawn on it before encoding.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureOptions {
+    /// Capture only the foreground window; takes precedence over `region`.
+    pub active_window: bool,
+    /// Screen area to capture; `None` captures the primary screen.
+    pub region: Option<Rect>,
+    /// Capture this window's client area instead; `region` is then relative to it.
+    pub window: Option<window::WindowTarget>,
+    /// Draw the mouse pointer, which the capture APIs leave out.
+    pub draw_cursor: bool,
+    /// Drawn in order after the cursor, in screen coordinates.
+    pub annotations: Vec<annotate::Annotation>,
+    /// Areas to black out or blur; applied before the cursor and annotations.
+    pub masks: Vec<redact::Mask>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MouseEvent {
     pub action: String,
     pub x: Option<i32>,
     pub y: Option<i32>,
     pub button: Option<String>,
+    /// Wheel notches to the right, for the `wheel` action.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dx: Option<i32>,
+    /// Wheel notches away from the user, for the `wheel` action.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dy: Option<i32>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct KeyboardEvent {
     pub action: String,
     pub key: String,
@@ -28,77 +97,414 @@ pub struct AutomationResult {
     pub data: Option<serde_json::Value>,
 }
 
-#[cfg(target_os = "windows")]
-pub mod mouse;
-#[cfg(target_os = "windows")]
+/// Failures callers may need to tell apart from the plain messages most functions
+/// return; converts into its message so `?` works in those too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AutomationError {
+    /// The cursor was in one of the fail-safe corners when an input operation was about
+    /// to run (see [`failsafe`]).
+    FailSafeTriggered {
+        corner: failsafe::Corner,
+        position: Point,
+    },
+    /// The user gave input since the last automated action while yielding to them with
+    /// [`activity::YieldPolicy::Abort`] (see [`activity`]).
+    UserActivity { activity: activity::Activity },
+}
+
+const FAIL_SAFE_PREFIX: &str = "Fail-safe triggered";
+const USER_ACTIVITY_PREFIX: &str = "User activity detected";
+
+impl AutomationError {
+    /// Whether an error message came from [`AutomationError::FailSafeTriggered`].
+    pub fn is_fail_safe(message: &str) -> bool {
+        message.starts_with(FAIL_SAFE_PREFIX)
+    }
+
+    /// Whether an error message came from [`AutomationError::UserActivity`].
+    pub fn is_user_activity(message: &str) -> bool {
+        message.starts_with(USER_ACTIVITY_PREFIX)
+    }
+}
+
+impl std::fmt::Display for AutomationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AutomationError::FailSafeTriggered { corner, position } => write!(
+                f,
+                "{}: cursor at ({}, {}) in the {} screen corner",
+                FAIL_SAFE_PREFIX, position.x, position.y, corner
+            ),
+            AutomationError::UserActivity { activity } => {
+                write!(f, "{}: {}", USER_ACTIVITY_PREFIX, activity)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AutomationError {}
+
+impl From<AutomationError> for String {
+    fn from(error: AutomationError) -> String {
+        error.to_string()
+    }
+}
+
+pub mod activity;
+pub mod annotate;
+pub mod clipboard;
+pub mod color;
+pub mod events;
+pub mod failsafe;
+pub mod font;
+pub mod hash;
+pub mod hotkey;
+pub mod http;
+pub mod macros;
+pub mod mcp;
+#[cfg(feature = "ocr")]
+pub mod ocr;
+pub mod process;
+pub mod record;
+pub mod redact;
+pub mod rpc;
+pub mod script;
+pub mod websocket;
+pub mod window;
+
+#[cfg(any(target_os = "windows", target_os = "linux"))]
 pub mod keyboard;
-#[cfg(target_os = "windows")]
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+pub mod mouse;
+#[cfg(any(target_os = "windows", target_os = "linux"))]
 pub mod screen;
+#[cfg(target_os = "linux")]
+mod xserver;
 
 pub fn get_cursor_position() -> Result<Point, String> {
     #[cfg(target_os = "windows")]
     {
         mouse::windows::get_cursor_position()
     }
-    #[cfg(not(target_os = "windows"))]
+    #[cfg(target_os = "linux")]
+    {
+        mouse::linux::get_cursor_position()
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
     {
-        Err("Unsupported platform - Windows only for now".to_string())
+        Err("Unsupported platform - Windows and X11 only for now".to_string())
     }
 }
 
+/// The checks every input operation makes before acting: the fail-safe, then yielding
+/// to the user. On X11 input goes through [`events::inject`], which makes them itself.
+pub(crate) fn before_input() -> Result<(), AutomationError> {
+    failsafe::check()?;
+    activity::check()
+}
+
+#[cfg_attr(
+    not(any(target_os = "windows", target_os = "linux")),
+    allow(unused_variables)
+)]
 pub fn move_cursor(x: i32, y: i32) -> Result<AutomationResult, String> {
     #[cfg(target_os = "windows")]
     {
-        mouse::windows::move_cursor(x, y)
+        before_input()?;
+        let result = mouse::windows::move_cursor(x, y);
+        activity::note_action();
+        result
+    }
+    #[cfg(target_os = "linux")]
+    {
+        mouse::linux::move_cursor(x, y)
     }
-    #[cfg(not(target_os = "windows"))]
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
     {
-        Err("Unsupported platform - Windows only for now".to_string())
+        Err("Unsupported platform - Windows and X11 only for now".to_string())
     }
 }
 
+#[cfg_attr(
+    not(any(target_os = "windows", target_os = "linux")),
+    allow(unused_variables)
+)]
 pub fn click(button: &str, x: Option<i32>, y: Option<i32>) -> Result<AutomationResult, String> {
     #[cfg(target_os = "windows")]
     {
-        mouse::windows::click(button, x, y)
+        before_input()?;
+        let result = mouse::windows::click(button, x, y);
+        activity::note_action();
+        let result = result?;
+        if let Ok(position) = mouse::windows::get_cursor_position() {
+            record::note_click(position);
+        }
+        Ok(result)
     }
-    #[cfg(not(target_os = "windows"))]
+    #[cfg(target_os = "linux")]
     {
-        Err("Unsupported platform - Windows only for now".to_string())
+        let result = mouse::linux::click(button, x, y)?;
+        if let Ok(position) = mouse::linux::get_cursor_position() {
+            record::note_click(position);
+        }
+        Ok(result)
     }
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    {
+        Err("Unsupported platform - Windows and X11 only for now".to_string())
+    }
+}
+
+/// Moves the cursor to an offset within a window's client area, resolved when called.
+pub fn move_cursor_in_window(
+    target: &window::WindowTarget,
+    x: i32,
+    y: i32,
+) -> Result<AutomationResult, String> {
+    let point = window::window_point(target, x, y)?;
+    move_cursor(point.x, point.y)
 }
 
+/// Clicks at an offset within a window's client area, resolved when called.
+pub fn click_in_window(
+    target: &window::WindowTarget,
+    button: &str,
+    x: i32,
+    y: i32,
+) -> Result<AutomationResult, String> {
+    let point = window::window_point(target, x, y)?;
+    click(button, Some(point.x), Some(point.y))
+}
+
+#[cfg_attr(
+    not(any(target_os = "windows", target_os = "linux")),
+    allow(unused_variables)
+)]
 pub fn type_text(text: &str, delay_ms: Option<u64>) -> Result<AutomationResult, String> {
     #[cfg(target_os = "windows")]
     {
-        keyboard::windows::type_text(text, delay_ms)
+        before_input()?;
+        let result = keyboard::windows::type_text(text, delay_ms);
+        activity::note_action();
+        result
+    }
+    #[cfg(target_os = "linux")]
+    {
+        keyboard::linux::type_text(text, delay_ms)
     }
-    #[cfg(not(target_os = "windows"))]
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
     {
-        Err("Unsupported platform - Windows only for now".to_string())
+        Err("Unsupported platform - Windows and X11 only for now".to_string())
     }
 }
 
+/// How [`type_text_with`] enters text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TypeMode {
+    /// One synthetic key press per character.
+    #[default]
+    Keys,
+    /// Paste through the clipboard, which is restored afterwards (see
+    /// [`clipboard::paste_text`]).
+    Paste,
+}
+
+impl std::str::FromStr for TypeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "keys" | "type" => Ok(TypeMode::Keys),
+            "paste" => Ok(TypeMode::Paste),
+            _ => Err(format!("Unsupported type mode: {}", s)),
+        }
+    }
+}
+
+/// Like [`type_text`], entering the text as `mode` says; `delay_ms` only applies to
+/// [`TypeMode::Keys`].
+pub fn type_text_with(
+    text: &str,
+    mode: TypeMode,
+    delay_ms: Option<u64>,
+) -> Result<AutomationResult, String> {
+    match mode {
+        TypeMode::Keys => type_text(text, delay_ms),
+        TypeMode::Paste => clipboard::paste_text(text),
+    }
+}
+
+#[cfg_attr(
+    not(any(target_os = "windows", target_os = "linux")),
+    allow(unused_variables)
+)]
 pub fn press_key(key: &str, modifiers: Option<Vec<String>>) -> Result<AutomationResult, String> {
     #[cfg(target_os = "windows")]
     {
-        keyboard::windows::press_key(key, modifiers)
+        before_input()?;
+        let result = keyboard::windows::press_key(key, modifiers);
+        activity::note_action();
+        result
+    }
+    #[cfg(target_os = "linux")]
+    {
+        keyboard::linux::press_key(key, modifiers)
     }
-    #[cfg(not(target_os = "windows"))]
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
     {
-        Err("Unsupported platform - Windows only for now".to_string())
+        Err("Unsupported platform - Windows and X11 only for now".to_string())
     }
 }
 
+/// Turns the mouse wheel `dy` notches away from the user (negative towards them) and
+/// `dx` notches to the right, over (`x`, `y`) or wherever the cursor is.
+pub fn scroll(dx: i32, dy: i32, x: Option<i32>, y: Option<i32>) -> Result<AutomationResult, String> {
+    let at = match (x, y) {
+        (Some(x), Some(y)) => Point { x, y },
+        (None, None) => get_cursor_position()?,
+        _ => return Err("Give both x and y, or neither".to_string()),
+    };
+    events::inject(&events::EventKind::Wheel {
+        dx,
+        dy,
+        x: at.x,
+        y: at.y,
+    })?;
+    Ok(AutomationResult {
+        success: true,
+        message: Some(format!("Scrolled ({}, {}) at ({}, {})", dx, dy, at.x, at.y)),
+        data: None,
+    })
+}
+
 pub fn get_screen_size() -> Result<Point, String> {
     #[cfg(target_os = "windows")]
     {
         screen::windows::get_screen_size()
     }
-    #[cfg(not(target_os = "windows"))]
+    #[cfg(target_os = "linux")]
+    {
+        let conn = xserver::XConnection::open()?;
+        unsafe {
+            let screen = x11::xlib::XDefaultScreen(conn.display);
+            Ok(Point {
+                x: x11::xlib::XDisplayWidth(conn.display, screen),
+                y: x11::xlib::XDisplayHeight(conn.display, screen),
+            })
+        }
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    {
+        Err("Unsupported platform - Windows and X11 only for now".to_string())
+    }
+}
+
+#[cfg_attr(
+    not(any(target_os = "windows", target_os = "linux")),
+    allow(unused_variables)
+)]
+pub fn capture_screen(region: Option<Rect>) -> Result<image::RgbaImage, String> {
+    #[cfg(target_os = "windows")]
+    {
+        screen::windows::capture_image(region)
+    }
+    #[cfg(target_os = "linux")]
+    {
+        screen::linux::capture_image(region)
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
     {
-        Err("Unsupported platform - Windows only for now".to_string())
+        Err("Unsupported platform - Windows and X11 only for now".to_string())
+    }
+}
+
+pub fn get_active_window_rect() -> Result<Rect, String> {
+    #[cfg(target_os = "windows")]
+    {
+        screen::windows::get_active_window_rect()
+    }
+    #[cfg(target_os = "linux")]
+    {
+        window::active_window()?
+            .map(|window| window.bounds)
+            .ok_or_else(|| "No foreground window found".to_string())
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    {
+        Err("Unsupported platform - Windows and X11 only for now".to_string())
+    }
+}
+
+/// Captures the screen as described by `options`, with masks applied and the cursor and
+/// annotations drawn.
+pub fn capture(options: &CaptureOptions) -> Result<image::RgbaImage, String> {
+    let region = if options.active_window {
+        Some(get_active_window_rect()?)
+    } else if let Some(target) = &options.window {
+        Some(window::window_region(target, options.region)?)
+    } else {
+        options.region
+    };
+    let mut image = capture_screen(region)?;
+    let origin = region.map_or(Point { x: 0, y: 0 }, |r| Point { x: r.x, y: r.y });
+
+    redact::redact(&mut image, origin, &options.masks)?;
+    if options.draw_cursor {
+        // A pointer we cannot locate should not cost the whole screenshot
+        if let Ok(position) = get_cursor_position() {
+            let at = Point {
+                x: position.x - origin.x,
+                y: position.y - origin.y,
+            };
+            annotate::draw_cursor(&mut image, at);
+        }
     }
+    annotate::annotate(&mut image, origin, &options.annotations);
+    Ok(image)
+}
+
+/// Like [`capture`], encoded as PNG.
+pub fn take_screenshot(options: &CaptureOptions) -> Result<Vec<u8>, String> {
+    encode_png(&capture(options)?)
+}
+
+/// Every place the PNG at `template` appears on the screen, or within `region`, in
+/// screen coordinates and scan order. `tolerance` is the mean difference allowed per
+/// color channel.
+pub fn find_image(
+    template: &std::path::Path,
+    region: Option<Rect>,
+    tolerance: u8,
+) -> Result<Vec<Rect>, String> {
+    let template = image::open(template)
+        .map_err(|e| format!("Failed to open template {}: {}", template.display(), e))?
+        .to_rgba8();
+    let screen = capture_screen(region)?;
+    let origin = region.map_or(Point { x: 0, y: 0 }, |r| Point { x: r.x, y: r.y });
+    Ok(redact::find_template(&screen, &template, tolerance)
+        .into_iter()
+        .map(|rect| Rect {
+            x: rect.x + origin.x,
+            y: rect.y + origin.y,
+            ..rect
+        })
+        .collect())
+}
+
+pub fn encode_png(image: &image::RgbaImage) -> Result<Vec<u8>, String> {
+    use image::ImageEncoder;
+
+    let mut bytes = Vec::new();
+    image::codecs::png::PngEncoder::new(&mut bytes)
+        .write_image(
+            image.as_raw(),
+            image.width(),
+            image.height(),
+            image::ExtendedColorType::Rgba8,
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(bytes)
 }
 
 #[cfg(test)]
@@ -136,4 +542,11 @@ mod tests {
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn parses_type_modes() {
+        assert_eq!("keys".parse::<TypeMode>(), Ok(TypeMode::Keys));
+        assert_eq!("Paste".parse::<TypeMode>(), Ok(TypeMode::Paste));
+        assert!("morse".parse::<TypeMode>().is_err());
+    }
+}
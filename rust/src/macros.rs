@@ -0,0 +1,472 @@
+//! Recording user input once and replaying it later.
+
+use super::events::{self, EventKind, Listener};
+use super::{get_screen_size, AutomationResult, KeyboardEvent, MouseEvent, Point};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Version written to new macro files.
+const MACRO_VERSION: u32 = 1;
+
+/// Recorded input that [`play_macro`] can replay.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Macro {
+    pub version: u32,
+    /// Screen size during recording, for rescaling coordinates on another screen.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub screen: Option<Point>,
+    pub events: Vec<MacroEvent>,
+}
+
+/// One input event, `time_ms` after the recording started.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MacroEvent {
+    pub time_ms: u64,
+    #[serde(flatten)]
+    pub input: MacroInput,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "device", rename_all = "snake_case")]
+pub enum MacroInput {
+    /// `action` is `move`, `down`, `up` or `wheel`.
+    Mouse(MouseEvent),
+    /// `action` is `down` or `up`; `modifiers` is informational, since the modifier
+    /// keys have events of their own.
+    Keyboard(KeyboardEvent),
+}
+
+impl From<EventKind> for MacroInput {
+    fn from(kind: EventKind) -> Self {
+        let mouse = |action: &str, x, y, button| {
+            MacroInput::Mouse(MouseEvent {
+                action: action.to_string(),
+                x: Some(x),
+                y: Some(y),
+                button,
+                dx: None,
+                dy: None,
+            })
+        };
+        let keyboard = |action: &str, key, modifiers: Vec<String>| {
+            MacroInput::Keyboard(KeyboardEvent {
+                action: action.to_string(),
+                key,
+                modifiers: (!modifiers.is_empty()).then_some(modifiers),
+            })
+        };
+        match kind {
+            EventKind::KeyDown { key, modifiers } => keyboard("down", key, modifiers),
+            EventKind::KeyUp { key, modifiers } => keyboard("up", key, modifiers),
+            EventKind::MouseMove { x, y } => mouse("move", x, y, None),
+            EventKind::ButtonDown { button, x, y } => mouse("down", x, y, Some(button)),
+            EventKind::ButtonUp { button, x, y } => mouse("up", x, y, Some(button)),
+            EventKind::Wheel { dx, dy, x, y } => MacroInput::Mouse(MouseEvent {
+                action: "wheel".to_string(),
+                x: Some(x),
+                y: Some(y),
+                button: None,
+                dx: Some(dx),
+                dy: Some(dy),
+            }),
+        }
+    }
+}
+
+impl TryFrom<&MacroInput> for EventKind {
+    type Error = String;
+
+    fn try_from(input: &MacroInput) -> Result<Self, String> {
+        match input {
+            MacroInput::Keyboard(event) => {
+                let (key, modifiers) = (event.key.clone(), Vec::new());
+                match event.action.as_str() {
+                    "down" => Ok(EventKind::KeyDown { key, modifiers }),
+                    "up" => Ok(EventKind::KeyUp { key, modifiers }),
+                    other => Err(format!("Unsupported keyboard action: {}", other)),
+                }
+            }
+            MacroInput::Mouse(event) => {
+                let (Some(x), Some(y)) = (event.x, event.y) else {
+                    return Err(format!("Mouse {} needs x and y", event.action));
+                };
+                let button = || {
+                    event
+                        .button
+                        .clone()
+                        .ok_or_else(|| format!("Mouse {} needs a button", event.action))
+                };
+                match event.action.as_str() {
+                    "move" => Ok(EventKind::MouseMove { x, y }),
+                    "down" => Ok(EventKind::ButtonDown {
+                        button: button()?,
+                        x,
+                        y,
+                    }),
+                    "up" => Ok(EventKind::ButtonUp {
+                        button: button()?,
+                        x,
+                        y,
+                    }),
+                    "wheel" => Ok(EventKind::Wheel {
+                        dx: event.dx.unwrap_or(0),
+                        dy: event.dy.unwrap_or(0),
+                        x,
+                        y,
+                    }),
+                    other => Err(format!("Unsupported mouse action: {}", other)),
+                }
+            }
+        }
+    }
+}
+
+impl Macro {
+    pub fn load(path: &Path) -> Result<Macro, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("Invalid macro {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+    }
+
+    /// Length of the macro at normal speed.
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.events.last().map_or(0, |event| event.time_ms))
+    }
+}
+
+/// Records real input into a [`Macro`] until stopped.
+pub struct MacroRecorder {
+    listener: Listener,
+    events: Arc<Mutex<Vec<MacroEvent>>>,
+    screen: Option<Point>,
+}
+
+impl MacroRecorder {
+    pub fn start() -> Result<MacroRecorder, String> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let start = Instant::now();
+        let listener = Listener::start(move |event| {
+            recorded.lock().unwrap().push(MacroEvent {
+                time_ms: start.elapsed().as_millis() as u64,
+                input: event.kind.into(),
+            });
+        })?;
+        Ok(MacroRecorder {
+            listener,
+            events,
+            screen: get_screen_size().ok(),
+        })
+    }
+
+    pub fn stop(self) -> Result<Macro, String> {
+        self.listener.stop()?;
+        let events = std::mem::take(&mut *self.events.lock().unwrap());
+        Ok(Macro {
+            version: MACRO_VERSION,
+            screen: self.screen,
+            events,
+        })
+    }
+}
+
+/// Records input for `duration`.
+pub fn record_macro(duration: Duration) -> Result<Macro, String> {
+    let recorder = MacroRecorder::start()?;
+    thread::sleep(duration);
+    recorder.stop()
+}
+
+#[derive(Debug, Clone)]
+pub struct PlayOptions {
+    /// Playback speed multiplier; 2.0 plays twice as fast.
+    pub speed: f64,
+    /// How many times to play the macro, one run right after the other.
+    pub loops: u32,
+    /// Scale coordinates from the recorded screen size to the current one.
+    pub rescale: bool,
+}
+
+impl Default for PlayOptions {
+    fn default() -> Self {
+        PlayOptions {
+            speed: 1.0,
+            loops: 1,
+            rescale: true,
+        }
+    }
+}
+
+/// Replays a macro as synthesized input (see [`events::inject`]).
+pub fn play_macro(recorded: &Macro, options: &PlayOptions) -> Result<AutomationResult, String> {
+    let screen = if options.rescale {
+        get_screen_size().ok()
+    } else {
+        None
+    };
+    play_macro_with(recorded, options, screen, events::inject)
+}
+
+/// Replays a macro through `send`, rescaling coordinates to `screen` when both it and
+/// the recorded screen size are known. Keys and buttons the macro leaves held are
+/// released at the end, so a truncated recording cannot leave them stuck.
+pub fn play_macro_with<F>(
+    recorded: &Macro,
+    options: &PlayOptions,
+    screen: Option<Point>,
+    mut send: F,
+) -> Result<AutomationResult, String>
+where
+    F: FnMut(&EventKind) -> Result<(), String>,
+{
+    if !(options.speed > 0.0 && options.speed.is_finite()) {
+        return Err("Speed must be a positive number".to_string());
+    }
+    if options.loops == 0 {
+        return Err("Loop count must be at least 1".to_string());
+    }
+    let steps = recorded
+        .events
+        .iter()
+        .map(|event| {
+            let kind = EventKind::try_from(&event.input)?;
+            Ok((event.time_ms, rescale(kind, recorded.screen, screen)))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut held: Vec<EventKind> = Vec::new();
+    let mut outcome = Ok(());
+    'play: for _ in 0..options.loops {
+        let start = Instant::now();
+        for (time_ms, kind) in &steps {
+            let due = Duration::from_secs_f64(*time_ms as f64 / 1000.0 / options.speed);
+            if let Some(wait) = due.checked_sub(start.elapsed()) {
+                thread::sleep(wait);
+            }
+            if let Err(e) = send(kind) {
+                outcome = Err(e);
+                break 'play;
+            }
+            track_held(&mut held, kind);
+        }
+    }
+    for release in held.iter().rev() {
+        let _ = send(release);
+    }
+    outcome?;
+
+    Ok(AutomationResult {
+        success: true,
+        message: Some(format!(
+            "Played {} events {} time(s)",
+            steps.len(),
+            options.loops
+        )),
+        data: None,
+    })
+}
+
+/// Keeps `held` as the release events for every key and button currently down.
+fn track_held(held: &mut Vec<EventKind>, kind: &EventKind) {
+    let down = matches!(
+        kind,
+        EventKind::KeyDown { .. } | EventKind::ButtonDown { .. }
+    );
+    let release = match kind {
+        EventKind::KeyDown { key, .. } | EventKind::KeyUp { key, .. } => EventKind::KeyUp {
+            key: key.clone(),
+            modifiers: Vec::new(),
+        },
+        EventKind::ButtonDown { button, x, y } | EventKind::ButtonUp { button, x, y } => {
+            EventKind::ButtonUp {
+                button: button.clone(),
+                x: *x,
+                y: *y,
+            }
+        }
+        _ => return,
+    };
+    held.retain(|other| !same_input(other, &release));
+    if down {
+        held.push(release);
+    }
+}
+
+fn same_input(a: &EventKind, b: &EventKind) -> bool {
+    match (a, b) {
+        (EventKind::KeyUp { key: a, .. }, EventKind::KeyUp { key: b, .. }) => a == b,
+        (EventKind::ButtonUp { button: a, .. }, EventKind::ButtonUp { button: b, .. }) => a == b,
+        _ => false,
+    }
+}
+
+fn rescale(kind: EventKind, from: Option<Point>, to: Option<Point>) -> EventKind {
+    let (Some(from), Some(to)) = (from, to) else {
+        return kind;
+    };
+    if from == to || from.x <= 0 || from.y <= 0 {
+        return kind;
+    }
+    let sx = |x: i32| (x as f64 * to.x as f64 / from.x as f64).round() as i32;
+    let sy = |y: i32| (y as f64 * to.y as f64 / from.y as f64).round() as i32;
+    match kind {
+        EventKind::MouseMove { x, y } => EventKind::MouseMove { x: sx(x), y: sy(y) },
+        EventKind::ButtonDown { button, x, y } => EventKind::ButtonDown {
+            button,
+            x: sx(x),
+            y: sy(y),
+        },
+        EventKind::ButtonUp { button, x, y } => EventKind::ButtonUp {
+            button,
+            x: sx(x),
+            y: sy(y),
+        },
+        EventKind::Wheel { dx, dy, x, y } => EventKind::Wheel {
+            dx,
+            dy,
+            x: sx(x),
+            y: sy(y),
+        },
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Macro {
+        let kinds = [
+            EventKind::MouseMove { x: 100, y: 50 },
+            EventKind::ButtonDown {
+                button: "left".to_string(),
+                x: 100,
+                y: 50,
+            },
+            EventKind::KeyDown {
+                key: "ctrl".to_string(),
+                modifiers: Vec::new(),
+            },
+            EventKind::KeyDown {
+                key: "v".to_string(),
+                modifiers: vec!["ctrl".to_string()],
+            },
+            EventKind::KeyUp {
+                key: "v".to_string(),
+                modifiers: vec!["ctrl".to_string()],
+            },
+        ];
+        Macro {
+            version: MACRO_VERSION,
+            screen: Some(Point { x: 800, y: 600 }),
+            events: kinds
+                .into_iter()
+                .enumerate()
+                .map(|(i, kind)| MacroEvent {
+                    time_ms: i as u64 * 40,
+                    input: kind.into(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn macro_file_format_is_flat() {
+        let recorded = sample();
+        let json = serde_json::to_value(&recorded).unwrap();
+        assert_eq!(
+            json["events"][3],
+            serde_json::json!({
+                "time_ms": 120,
+                "device": "keyboard",
+                "action": "down",
+                "key": "v",
+                "modifiers": ["ctrl"],
+            })
+        );
+        assert_eq!(
+            json["events"][0],
+            serde_json::json!({
+                "time_ms": 0,
+                "device": "mouse",
+                "action": "move",
+                "x": 100,
+                "y": 50,
+                "button": null,
+            })
+        );
+        assert_eq!(serde_json::from_value::<Macro>(json).unwrap(), recorded);
+    }
+
+    #[test]
+    fn plays_rescaled_and_releases_held_input() {
+        let mut sent = Vec::new();
+        let options = PlayOptions {
+            speed: 100.0,
+            loops: 2,
+            rescale: true,
+        };
+        let started = Instant::now();
+        play_macro_with(
+            &sample(),
+            &options,
+            Some(Point { x: 1600, y: 900 }),
+            |kind| {
+                sent.push(kind.clone());
+                Ok(())
+            },
+        )
+        .unwrap();
+        // Two runs of 160ms at 100x speed
+        assert!(started.elapsed() >= Duration::from_millis(3));
+
+        assert_eq!(sent.len(), 5 * 2 + 2);
+        assert_eq!(sent[0], EventKind::MouseMove { x: 200, y: 75 });
+        assert_eq!(sent[5], sent[0]);
+        assert_eq!(
+            sent[10..],
+            [
+                EventKind::KeyUp {
+                    key: "ctrl".to_string(),
+                    modifiers: Vec::new(),
+                },
+                EventKind::ButtonUp {
+                    button: "left".to_string(),
+                    x: 200,
+                    y: 75,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_options_and_events() {
+        let send = |_: &EventKind| Ok(());
+        let slow = PlayOptions {
+            speed: 0.0,
+            ..PlayOptions::default()
+        };
+        assert!(play_macro_with(&sample(), &slow, None, send).is_err());
+
+        let mut broken = sample();
+        broken.events[0].input = MacroInput::Mouse(MouseEvent {
+            action: "teleport".to_string(),
+            x: Some(1),
+            y: Some(1),
+            button: None,
+            dx: None,
+            dy: None,
+        });
+        let error = play_macro_with(&broken, &PlayOptions::default(), None, send).unwrap_err();
+        assert_eq!(error, "Unsupported mouse action: teleport");
+    }
+}
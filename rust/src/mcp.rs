@@ -0,0 +1,566 @@
+//! A Model Context Protocol server, so AI agents can drive the desktop through tools
+//! instead of wrapping the command line. Speaks MCP's stdio transport: JSON-RPC 2.0, one
+//! message per line (see [`crate::rpc`]).
+//!
+//! Each tool's arguments are a struct declared with `tool_args!`, which derives the
+//! tool's input schema from the field types through [`JsonSchema`], so the schema an
+//! agent sees cannot drift from what the server accepts.
+
+use crate::redact::Mask;
+use crate::rpc::{self, RpcError};
+use crate::{AutomationResult, CaptureOptions, Rect, TypeMode};
+use base64::{engine::general_purpose, Engine as _};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+/// Protocol revisions this server speaks, newest first.
+pub const PROTOCOL_VERSIONS: [&str; 3] = ["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// The JSON Schema of a tool argument's type.
+pub trait JsonSchema {
+    fn schema() -> Value;
+    /// Whether an argument of this type may be left out.
+    const OPTIONAL: bool = false;
+}
+
+macro_rules! integer_schema {
+    ($($ty:ty),*) => {
+        $(impl JsonSchema for $ty {
+            fn schema() -> Value {
+                json!({ "type": "integer", "minimum": <$ty>::MIN, "maximum": <$ty>::MAX })
+            }
+        })*
+    };
+}
+
+integer_schema!(i32, u8, u32, u64);
+
+impl JsonSchema for bool {
+    fn schema() -> Value {
+        json!({ "type": "boolean" })
+    }
+}
+
+impl JsonSchema for String {
+    fn schema() -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl JsonSchema for PathBuf {
+    fn schema() -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Option<T> {
+    fn schema() -> Value {
+        T::schema()
+    }
+    const OPTIONAL: bool = true;
+}
+
+impl<T: JsonSchema> JsonSchema for Vec<T> {
+    fn schema() -> Value {
+        json!({ "type": "array", "items": T::schema() })
+    }
+}
+
+impl JsonSchema for Rect {
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "x": i32::schema(),
+                "y": i32::schema(),
+                "width": u32::schema(),
+                "height": u32::schema(),
+            },
+            "required": ["x", "y", "width", "height"],
+            "additionalProperties": false,
+        })
+    }
+}
+
+impl JsonSchema for TypeMode {
+    fn schema() -> Value {
+        json!({ "type": "string", "enum": ["keys", "paste"] })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Button {
+    #[default]
+    Left,
+    Right,
+    Middle,
+}
+
+impl Button {
+    fn name(self) -> &'static str {
+        match self {
+            Button::Left => "left",
+            Button::Right => "right",
+            Button::Middle => "middle",
+        }
+    }
+}
+
+impl JsonSchema for Button {
+    fn schema() -> Value {
+        json!({ "type": "string", "enum": ["left", "right", "middle"] })
+    }
+}
+
+/// Declares a tool's argument struct along with its input schema. The struct's doc
+/// comment becomes the tool description and each field's the property description.
+macro_rules! tool_args {
+    (
+        $(#[doc = $doc:literal])*
+        $name:ident {
+            $($(#[doc = $field_doc:literal])* $field:ident: $ty:ty,)*
+        }
+    ) => {
+        $(#[doc = $doc])*
+        #[derive(Debug, Deserialize)]
+        #[serde(deny_unknown_fields)]
+        pub struct $name {
+            $($(#[doc = $field_doc])* pub $field: $ty,)*
+        }
+
+        impl $name {
+            pub const DESCRIPTION: &'static str = concat!($($doc),*);
+        }
+
+        impl JsonSchema for $name {
+            #[allow(unused_mut)]
+            fn schema() -> Value {
+                let mut properties = serde_json::Map::new();
+                let mut required: Vec<&str> = Vec::new();
+                $(
+                    let mut property = <$ty as JsonSchema>::schema();
+                    property["description"] = json!(concat!($($field_doc),*).trim());
+                    properties.insert(stringify!($field).to_string(), property);
+                    if !<$ty as JsonSchema>::OPTIONAL {
+                        required.push(stringify!($field));
+                    }
+                )*
+                json!({
+                    "type": "object",
+                    "properties": properties,
+                    "required": required,
+                    "additionalProperties": false,
+                })
+            }
+        }
+    };
+}
+
+tool_args! {
+    /// Capture the screen, a region of it or the foreground window as a PNG image.
+    ScreenshotArgs {
+        /// Screen area to capture; the whole primary screen if left out.
+        region: Option<Rect>,
+        /// Capture only the foreground window.
+        active_window: Option<bool>,
+        /// Draw the mouse pointer into the image.
+        cursor: Option<bool>,
+    }
+}
+
+tool_args! {
+    /// Click a mouse button, at screen coordinates (x, y) or where the cursor is.
+    ClickArgs {
+        /// Horizontal screen coordinate; give x and y together.
+        x: Option<i32>,
+        /// Vertical screen coordinate; give x and y together.
+        y: Option<i32>,
+        /// Which button to click; left if left out.
+        button: Option<Button>,
+    }
+}
+
+tool_args! {
+    /// Type text into the focused window.
+    TypeTextArgs {
+        /// The text to type.
+        text: String,
+        /// keys sends a key press per character; paste goes through the clipboard.
+        mode: Option<TypeMode>,
+        /// Milliseconds between characters when typing keys.
+        delay_ms: Option<u64>,
+    }
+}
+
+tool_args! {
+    /// Press and release a key, optionally while holding modifiers.
+    PressKeyArgs {
+        /// Key name, e.g. enter, tab, escape, a, f5.
+        key: String,
+        /// Modifiers to hold: ctrl, shift, alt or win.
+        modifiers: Option<Vec<String>>,
+    }
+}
+
+tool_args! {
+    /// Turn the mouse wheel, over screen coordinates (x, y) or where the cursor is.
+    ScrollArgs {
+        /// Notches away from the user (up); negative scrolls down.
+        dy: Option<i32>,
+        /// Notches to the right; negative scrolls left.
+        dx: Option<i32>,
+        /// Horizontal screen coordinate; give x and y together.
+        x: Option<i32>,
+        /// Vertical screen coordinate; give x and y together.
+        y: Option<i32>,
+    }
+}
+
+tool_args! {
+    /// List the top-level windows with their ids, titles, processes and bounds.
+    ListWindowsArgs {}
+}
+
+tool_args! {
+    /// Find every place a PNG image appears on the screen, with the center of each match
+    /// to click.
+    FindImageArgs {
+        /// Path of the PNG to look for.
+        template: PathBuf,
+        /// Screen area to search; the whole primary screen if left out.
+        region: Option<Rect>,
+        /// Mean difference allowed per color channel, 0-255; 8 if left out.
+        tolerance: Option<u8>,
+    }
+}
+
+/// Settings for [`serve`].
+#[derive(Debug, Clone, Default)]
+pub struct McpOptions {
+    /// Applied to every screenshot the `screenshot` tool returns.
+    pub masks: Vec<Mask>,
+}
+
+struct Tool {
+    name: &'static str,
+    description: &'static str,
+    input_schema: fn() -> Value,
+    call: fn(Value, &McpOptions) -> Result<Vec<Value>, String>,
+}
+
+const TOOLS: [Tool; 7] = [
+    Tool {
+        name: "screenshot",
+        description: ScreenshotArgs::DESCRIPTION,
+        input_schema: ScreenshotArgs::schema,
+        call: screenshot,
+    },
+    Tool {
+        name: "click",
+        description: ClickArgs::DESCRIPTION,
+        input_schema: ClickArgs::schema,
+        call: click,
+    },
+    Tool {
+        name: "type_text",
+        description: TypeTextArgs::DESCRIPTION,
+        input_schema: TypeTextArgs::schema,
+        call: type_text,
+    },
+    Tool {
+        name: "press_key",
+        description: PressKeyArgs::DESCRIPTION,
+        input_schema: PressKeyArgs::schema,
+        call: press_key,
+    },
+    Tool {
+        name: "scroll",
+        description: ScrollArgs::DESCRIPTION,
+        input_schema: ScrollArgs::schema,
+        call: scroll,
+    },
+    Tool {
+        name: "list_windows",
+        description: ListWindowsArgs::DESCRIPTION,
+        input_schema: ListWindowsArgs::schema,
+        call: list_windows,
+    },
+    Tool {
+        name: "find_image",
+        description: FindImageArgs::DESCRIPTION,
+        input_schema: FindImageArgs::schema,
+        call: find_image,
+    },
+];
+
+/// Tool arguments are checked before the tool runs, so a mistake costs no input.
+fn parse<T: DeserializeOwned>(arguments: Value) -> Result<T, String> {
+    let arguments = if arguments.is_null() {
+        json!({})
+    } else {
+        arguments
+    };
+    serde_json::from_value(arguments).map_err(|e| format!("Invalid arguments: {}", e))
+}
+
+fn text(text: String) -> Value {
+    json!({ "type": "text", "text": text })
+}
+
+/// The outcome of an action as the text agents read.
+fn outcome(result: Result<AutomationResult, String>) -> Result<Vec<Value>, String> {
+    let result = result?;
+    if !result.success {
+        return Err(result.message.unwrap_or_else(|| "Failed".to_string()));
+    }
+    Ok(vec![text(match (result.data, result.message) {
+        (Some(data), _) => data.to_string(),
+        (None, Some(message)) => message,
+        (None, None) => "Done".to_string(),
+    })])
+}
+
+fn screenshot(arguments: Value, options: &McpOptions) -> Result<Vec<Value>, String> {
+    let args: ScreenshotArgs = parse(arguments)?;
+    let png = crate::take_screenshot(&CaptureOptions {
+        active_window: args.active_window.unwrap_or(false),
+        region: args.region,
+        draw_cursor: args.cursor.unwrap_or(false),
+        masks: options.masks.clone(),
+        ..CaptureOptions::default()
+    })?;
+    Ok(vec![json!({
+        "type": "image",
+        "data": general_purpose::STANDARD.encode(png),
+        "mimeType": "image/png",
+    })])
+}
+
+fn click(arguments: Value, _: &McpOptions) -> Result<Vec<Value>, String> {
+    let args: ClickArgs = parse(arguments)?;
+    if args.x.is_some() != args.y.is_some() {
+        return Err("Give both x and y, or neither".to_string());
+    }
+    let button = args.button.unwrap_or_default();
+    outcome(crate::click(button.name(), args.x, args.y))
+}
+
+fn type_text(arguments: Value, _: &McpOptions) -> Result<Vec<Value>, String> {
+    let args: TypeTextArgs = parse(arguments)?;
+    outcome(crate::type_text_with(
+        &args.text,
+        args.mode.unwrap_or_default(),
+        args.delay_ms,
+    ))
+}
+
+fn press_key(arguments: Value, _: &McpOptions) -> Result<Vec<Value>, String> {
+    let args: PressKeyArgs = parse(arguments)?;
+    outcome(crate::press_key(&args.key, args.modifiers))
+}
+
+fn scroll(arguments: Value, _: &McpOptions) -> Result<Vec<Value>, String> {
+    let args: ScrollArgs = parse(arguments)?;
+    outcome(crate::scroll(
+        args.dx.unwrap_or(0),
+        args.dy.unwrap_or(0),
+        args.x,
+        args.y,
+    ))
+}
+
+fn list_windows(arguments: Value, _: &McpOptions) -> Result<Vec<Value>, String> {
+    let ListWindowsArgs {} = parse(arguments)?;
+    let windows = crate::window::list_windows()?;
+    Ok(vec![text(serde_json::to_string(&windows).unwrap())])
+}
+
+fn find_image(arguments: Value, _: &McpOptions) -> Result<Vec<Value>, String> {
+    let args: FindImageArgs = parse(arguments)?;
+    let found = crate::find_image(&args.template, args.region, args.tolerance.unwrap_or(8))?;
+    let matches: Vec<Value> = found
+        .iter()
+        .map(|rect| {
+            json!({
+                "rect": rect,
+                "center": {
+                    "x": rect.x + rect.width as i32 / 2,
+                    "y": rect.y + rect.height as i32 / 2,
+                },
+            })
+        })
+        .collect();
+    Ok(vec![text(json!({ "matches": matches }).to_string())])
+}
+
+/// Answers one MCP request. Failures of a tool itself are results with `isError` set,
+/// which agents are shown, rather than protocol errors.
+pub fn handle(method: &str, params: Value, options: &McpOptions) -> Result<Value, RpcError> {
+    match method {
+        "initialize" => {
+            let requested = params.get("protocolVersion").and_then(Value::as_str);
+            let version = PROTOCOL_VERSIONS
+                .iter()
+                .find(|version| Some(**version) == requested)
+                .unwrap_or(&PROTOCOL_VERSIONS[0]);
+            Ok(json!({
+                "protocolVersion": version,
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "autoanchor", "version": env!("CARGO_PKG_VERSION") },
+            }))
+        }
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({
+            "tools": TOOLS
+                .iter()
+                .map(|tool| json!({
+                    "name": tool.name,
+                    "description": tool.description.trim(),
+                    "inputSchema": (tool.input_schema)(),
+                }))
+                .collect::<Vec<_>>(),
+        })),
+        "tools/call" => {
+            let name = params.get("name").and_then(Value::as_str).unwrap_or("");
+            let Some(tool) = TOOLS.iter().find(|tool| tool.name == name) else {
+                return Err(RpcError::new(
+                    rpc::INVALID_PARAMS,
+                    format!("Unknown tool: {}", name),
+                ));
+            };
+            let arguments = params.get("arguments").cloned().unwrap_or(Value::Null);
+            Ok(match (tool.call)(arguments, options) {
+                Ok(content) => json!({ "content": content, "isError": false }),
+                Err(e) => json!({ "content": [text(e)], "isError": true }),
+            })
+        }
+        // Notifications such as notifications/initialized need no answer
+        _ if method.starts_with("notifications/") => Ok(Value::Null),
+        _ => Err(RpcError::new(
+            rpc::METHOD_NOT_FOUND,
+            format!("Unknown method: {}", method),
+        )),
+    }
+}
+
+/// Serves MCP on `input` and `output` until `input` ends.
+pub fn serve<R: BufRead, W: Write>(input: R, output: W, options: &McpOptions) -> io::Result<()> {
+    rpc::serve(input, output, &mut |method, params| {
+        handle(method, params, options)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_schemas_from_the_argument_types() {
+        let schema = ClickArgs::schema();
+        assert_eq!(schema["required"], json!([]));
+        assert_eq!(schema["properties"]["x"]["type"], "integer");
+        assert_eq!(
+            schema["properties"]["button"]["enum"],
+            json!(["left", "right", "middle"])
+        );
+        assert_eq!(
+            schema["properties"]["button"]["description"],
+            "Which button to click; left if left out."
+        );
+
+        let schema = PressKeyArgs::schema();
+        assert_eq!(schema["required"], json!(["key"]));
+        assert_eq!(schema["properties"]["modifiers"]["items"]["type"], "string");
+        assert_eq!(
+            FindImageArgs::schema()["properties"]["region"]["required"]
+                .as_array()
+                .unwrap()
+                .len(),
+            4
+        );
+        assert_eq!(
+            FindImageArgs::DESCRIPTION.trim(),
+            "Find every place a PNG image appears on the screen, with the center of each match to click."
+        );
+    }
+
+    #[test]
+    fn answers_the_protocol() {
+        let options = McpOptions::default();
+        let init = handle(
+            "initialize",
+            json!({ "protocolVersion": "2024-11-05", "capabilities": {} }),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(init["protocolVersion"], "2024-11-05");
+        assert_eq!(init["serverInfo"]["name"], "autoanchor");
+        let init = handle(
+            "initialize",
+            json!({ "protocolVersion": "1999-01-01" }),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(init["protocolVersion"], PROTOCOL_VERSIONS[0]);
+
+        let tools = handle("tools/list", Value::Null, &options).unwrap();
+        let names: Vec<&str> = tools["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "screenshot",
+                "click",
+                "type_text",
+                "press_key",
+                "scroll",
+                "list_windows",
+                "find_image"
+            ]
+        );
+
+        let err = handle("tools/call", json!({ "name": "nope" }), &options).unwrap_err();
+        assert_eq!(err.code, rpc::INVALID_PARAMS);
+        assert_eq!(
+            handle("resources/list", Value::Null, &options)
+                .unwrap_err()
+                .code,
+            rpc::METHOD_NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn reports_bad_arguments_as_tool_errors() {
+        let options = McpOptions::default();
+        let result = handle(
+            "tools/call",
+            json!({ "name": "press_key", "arguments": { "modifiers": ["ctrl"] } }),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(result["isError"], true);
+        assert!(result["content"][0]["text"]
+            .as_str()
+            .unwrap()
+            .starts_with("Invalid arguments: missing field `key`"));
+
+        let result = handle(
+            "tools/call",
+            json!({ "name": "click", "arguments": { "x": 5 } }),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            result["content"][0]["text"],
+            "Give both x and y, or neither"
+        );
+    }
+}
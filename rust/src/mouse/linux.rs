@@ -0,0 +1,75 @@
+//! Pointer control on X11, synthesized with XTEST through [`crate::events::inject`].
+
+use crate::events::{self, EventKind};
+use crate::xserver::XConnection;
+use crate::{AutomationResult, Point};
+use std::os::raw::{c_int, c_uint};
+use std::thread;
+use std::time::Duration;
+use x11::xlib;
+
+/// Time between pressing and releasing a button, as on Windows.
+const CLICK_HOLD: Duration = Duration::from_millis(10);
+
+pub fn get_cursor_position() -> Result<Point, String> {
+    let conn = XConnection::open()?;
+    let (mut root, mut child): (xlib::Window, xlib::Window) = (0, 0);
+    let (mut root_x, mut root_y, mut win_x, mut win_y): (c_int, c_int, c_int, c_int) = (0, 0, 0, 0);
+    let mut mask: c_uint = 0;
+    let same_screen = unsafe {
+        xlib::XQueryPointer(
+            conn.display,
+            conn.root,
+            &mut root,
+            &mut child,
+            &mut root_x,
+            &mut root_y,
+            &mut win_x,
+            &mut win_y,
+            &mut mask,
+        )
+    };
+    if same_screen == 0 {
+        return Err("The cursor is on another screen".to_string());
+    }
+    Ok(Point {
+        x: root_x,
+        y: root_y,
+    })
+}
+
+pub fn move_cursor(x: i32, y: i32) -> Result<AutomationResult, String> {
+    events::inject(&EventKind::MouseMove { x, y })?;
+    Ok(AutomationResult {
+        success: true,
+        message: Some(format!("Cursor moved to ({}, {})", x, y)),
+        data: None,
+    })
+}
+
+pub fn click(button: &str, x: Option<i32>, y: Option<i32>) -> Result<AutomationResult, String> {
+    let point = match (x, y) {
+        (Some(x), Some(y)) => Point { x, y },
+        _ => get_cursor_position()?,
+    };
+    let button = button.to_lowercase();
+    events::inject(&EventKind::ButtonDown {
+        button: button.clone(),
+        x: point.x,
+        y: point.y,
+    })?;
+    thread::sleep(CLICK_HOLD);
+    events::inject(&EventKind::ButtonUp {
+        button: button.clone(),
+        x: point.x,
+        y: point.y,
+    })?;
+    Ok(AutomationResult {
+        success: true,
+        message: Some(format!(
+            "Clicked {} button at ({}, {})",
+            button, point.x, point.y
+        )),
+        data: None,
+    })
+}
@@ -1,3 +1,6 @@
+#[cfg(target_os = "linux")]
+pub mod linux;
+#[cfg(target_os = "windows")]
 pub mod windows;
 
 #[cfg(target_os = "windows")]
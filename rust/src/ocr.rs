@@ -0,0 +1,1359 @@
+//! Offline text recognition for screen regions, in pure Rust.
+//!
+//! The image is binarized, solid panels such as buttons are read on their own, and the
+//! text is cut into lines and glyphs. Each glyph's shape, size and placement is
+//! classified by the small neural network bundled in `models/ocr-glyphs.txt`, trained
+//! on every printable ASCII character rendered anti-aliased from the DejaVu Sans, Serif
+//! and Mono families at UI sizes, and on touching glyphs, which are then cut apart.
+//! Nothing is sent over the network and no system libraries are needed.
+
+use super::{capture_screen, Rect};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// Words whose confidence falls below this are ignored by `find_text`.
+pub const MIN_CONFIDENCE: f32 = 0.6;
+
+const MODEL: &str = include_str!("../models/ocr-glyphs.txt");
+/// Glyph shapes are resampled to a square grid this many cells wide.
+const GRID: usize = 16;
+/// Grid cells, then width, height and top of the glyph relative to the line's ascender
+/// height.
+const FEATURES: usize = GRID * GRID + 3;
+/// Pixels covered this much by ink belong to a glyph.
+const INK_COVERAGE: f32 = 0.35;
+/// Glyphs matched with less confidence than this are taken for non-text and end words.
+const MIN_GLYPH_CONFIDENCE: f32 = 0.3;
+/// Glyphs matched with less confidence than this are tried as several touching ones.
+const SPLIT_BELOW: f32 = 0.5;
+/// What each cut costs a split reading, as a share of the ascender height.
+const CUT_COST: f32 = 0.1;
+/// Gaps between glyphs wider than this share of the ascender height separate words...
+const WORD_GAP: f32 = 0.3;
+/// ...as long as they are also this many times the line's typical gap, which is wide in
+/// monospaced text.
+const GAP_FACTOR: f32 = 2.0;
+/// How many times solid panels (buttons, banners) are re-binarized to read text on them.
+const MAX_PANEL_DEPTH: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextBox {
+    pub text: String,
+    pub bounds: Rect,
+    /// Mean per-glyph match quality between 0.0 and 1.0.
+    pub confidence: f32,
+}
+
+/// Captures `region` of the screen (or the primary screen) and recognizes its words.
+pub fn read_text(region: Option<Rect>) -> Result<Vec<TextBox>, String> {
+    let capture = capture_screen(region)?;
+    Ok(offset(recognize(&capture, None), region))
+}
+
+/// Captures `region` of the screen and returns the screen rectangle of `needle`.
+pub fn find_text(needle: &str, region: Option<Rect>) -> Result<Option<Rect>, String> {
+    let capture = capture_screen(region)?;
+    let found = find_text_in(&capture, None, needle);
+    Ok(found.map(|rect| offset_rect(rect, region)))
+}
+
+/// Recognizes the words inside `region` of `image` (or the whole image). Bounds are in
+/// image coordinates.
+pub fn recognize(image: &RgbaImage, region: Option<Rect>) -> Vec<TextBox> {
+    recognize_lines(image, region)
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Returns the bounds of the first occurrence of `needle` (which may span several
+/// words of one line) among confidently recognized text.
+pub fn find_text_in(image: &RgbaImage, region: Option<Rect>, needle: &str) -> Option<Rect> {
+    let needle = needle.split_whitespace().collect::<Vec<_>>().join(" ");
+    if needle.is_empty() {
+        return None;
+    }
+    recognize_lines(image, region)
+        .iter()
+        .find_map(|line| find_in_line(line, &needle))
+}
+
+fn find_in_line(line: &[TextBox], needle: &str) -> Option<Rect> {
+    let words: Vec<&TextBox> = line
+        .iter()
+        .filter(|word| word.confidence >= MIN_CONFIDENCE)
+        .collect();
+    let mut text = String::new();
+    let mut spans = Vec::new();
+    for word in &words {
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        spans.push((text.len(), text.len() + word.text.len()));
+        text.push_str(&word.text);
+    }
+
+    // Only whole words match: "Submit" is not found in "Resubmit"
+    let (start, end) = text
+        .match_indices(needle)
+        .map(|(start, _)| (start, start + needle.len()))
+        .find(|(start, end)| {
+            spans.iter().any(|span| span.0 == *start) && spans.iter().any(|span| span.1 == *end)
+        })?;
+    words
+        .iter()
+        .zip(spans)
+        .filter(|(_, (word_start, word_end))| *word_start < end && *word_end > start)
+        .map(|(word, _)| word.bounds)
+        .reduce(union)
+}
+
+fn offset(boxes: Vec<TextBox>, region: Option<Rect>) -> Vec<TextBox> {
+    boxes
+        .into_iter()
+        .map(|word| TextBox {
+            bounds: offset_rect(word.bounds, region),
+            ..word
+        })
+        .collect()
+}
+
+fn offset_rect(rect: Rect, region: Option<Rect>) -> Rect {
+    match region {
+        Some(region) => Rect {
+            x: rect.x + region.x,
+            y: rect.y + region.y,
+            ..rect
+        },
+        None => rect,
+    }
+}
+
+fn union(a: Rect, b: Rect) -> Rect {
+    let x = a.x.min(b.x);
+    let y = a.y.min(b.y);
+    let right = (a.x + a.width as i32).max(b.x + b.width as i32);
+    let bottom = (a.y + a.height as i32).max(b.y + b.height as i32);
+    Rect {
+        x,
+        y,
+        width: (right - x) as u32,
+        height: (bottom - y) as u32,
+    }
+}
+
+/// A fully connected layer of the recognizer network.
+#[derive(Debug, Clone, PartialEq)]
+struct Layer {
+    inputs: usize,
+    outputs: usize,
+    /// Row-major, one row of `inputs` weights per output.
+    weights: Vec<f32>,
+    biases: Vec<f32>,
+}
+
+/// The glyph classifier: ReLU layers and a softmax over the characters it knows.
+#[derive(Debug, Clone, PartialEq)]
+struct Network {
+    layers: Vec<Layer>,
+    /// What each output stands for; `None` is ink that is not a single character, like
+    /// two glyphs touching.
+    classes: Vec<Option<char>>,
+}
+
+impl Network {
+    /// The probability of each class for a glyph's features.
+    fn run(&self, features: &[f32]) -> Vec<f32> {
+        let mut values = features.to_vec();
+        for (number, layer) in self.layers.iter().enumerate() {
+            values = layer.forward(&values);
+            if number + 1 < self.layers.len() {
+                values.iter_mut().for_each(|value| *value = value.max(0.0));
+            }
+        }
+        softmax(values)
+    }
+}
+
+impl Layer {
+    fn forward(&self, inputs: &[f32]) -> Vec<f32> {
+        self.weights
+            .chunks(self.inputs)
+            .zip(&self.biases)
+            .map(|(row, bias)| bias + row.iter().zip(inputs).map(|(w, v)| w * v).sum::<f32>())
+            .collect()
+    }
+}
+
+fn softmax(values: Vec<f32>) -> Vec<f32> {
+    let max = values.iter().copied().fold(f32::MIN, f32::max);
+    let exps: Vec<f32> = values.iter().map(|v| (v - max).exp()).collect();
+    let total: f32 = exps.iter().sum();
+    exps.iter().map(|e| e / total).collect()
+}
+
+/// Parses a network in the format of `models/ocr-glyphs.txt`: a `classes` line of hex
+/// code points (`-` for "not a character"), then per layer a `layer` line with its input
+/// and output counts and weight scale, and one line per output with the bias and the
+/// weights as signed bytes in hex.
+fn parse_model(source: &str) -> Result<Network, String> {
+    let mut classes = Vec::new();
+    let mut layers: Vec<Layer> = Vec::new();
+    let mut scale = 0.0;
+    for (number, line) in source.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let malformed = || format!("Line {}: malformed model", number + 1);
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields.as_slice() {
+            ["classes", codes] => {
+                classes = codes
+                    .split(' ')
+                    .map(|code| match code {
+                        "-" => Ok(None),
+                        code => u32::from_str_radix(code, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .map(Some)
+                            .ok_or_else(malformed),
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            ["layer", inputs, outputs, layer_scale] => {
+                let inputs = inputs.parse().map_err(|_| malformed())?;
+                if layers.last().is_some_and(|last| last.outputs != inputs) {
+                    return Err(malformed());
+                }
+                layers.push(Layer {
+                    inputs,
+                    outputs: outputs.parse().map_err(|_| malformed())?,
+                    weights: Vec::new(),
+                    biases: Vec::new(),
+                });
+                scale = layer_scale.parse().map_err(|_| malformed())?;
+            }
+            [bias, weights] => {
+                let layer = layers.last_mut().ok_or_else(malformed)?;
+                if weights.len() != layer.inputs * 2 || layer.biases.len() == layer.outputs {
+                    return Err(malformed());
+                }
+                layer.biases.push(bias.parse().map_err(|_| malformed())?);
+                for i in (0..weights.len()).step_by(2) {
+                    let byte =
+                        u8::from_str_radix(&weights[i..i + 2], 16).map_err(|_| malformed())?;
+                    layer.weights.push(byte as i8 as f32 * scale);
+                }
+            }
+            _ => return Err(malformed()),
+        }
+    }
+    let complete = layers
+        .iter()
+        .all(|layer| layer.biases.len() == layer.outputs);
+    match (layers.first(), layers.last()) {
+        (Some(first), Some(last))
+            if complete && first.inputs == FEATURES && last.outputs == classes.len() =>
+        {
+            Ok(Network { layers, classes })
+        }
+        _ => Err("Incomplete model".to_string()),
+    }
+}
+
+fn model() -> &'static Network {
+    static NETWORK: OnceLock<Network> = OnceLock::new();
+    NETWORK.get_or_init(|| parse_model(MODEL).expect("bundled OCR model is valid"))
+}
+
+/// The most likely character for a glyph's features and its probability.
+fn classify(features: &[f32; FEATURES]) -> (char, f32) {
+    let network = model();
+    network
+        .run(features)
+        .into_iter()
+        .zip(&network.classes)
+        .filter_map(|(probability, class)| class.map(|ch| (ch, probability)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or((' ', 0.0))
+}
+
+/// How much of each pixel of an area is ink, with ink pixels marked.
+struct InkMap {
+    area: Rect,
+    coverage: Vec<f32>,
+    ink: Vec<bool>,
+}
+
+impl InkMap {
+    /// Binarizes `area` of `image` with Otsu's method, taking whatever dominates its
+    /// border for background, and clears solid panels and frames. Returns the map and the
+    /// panels, or `None` when the area is a single flat color.
+    fn new(image: &RgbaImage, area: Rect) -> Option<(InkMap, Vec<Rect>)> {
+        let mut luma = Vec::with_capacity((area.width * area.height) as usize);
+        for y in 0..area.height {
+            for x in 0..area.width {
+                let [r, g, b, _] = image.get_pixel(area.x as u32 + x, area.y as u32 + y).0;
+                luma.push(((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8);
+            }
+        }
+
+        let threshold = otsu_threshold(&luma)?;
+        let (width, height) = (area.width as usize, area.height as usize);
+        let border: Vec<u8> = (0..width)
+            .flat_map(|x| [luma[x], luma[(height - 1) * width + x]])
+            .chain((0..height).flat_map(|y| [luma[y * width], luma[y * width + width - 1]]))
+            .collect();
+        let dark_border = border.iter().filter(|v| **v <= threshold).count();
+        let ink_is_dark = dark_border * 2 < border.len();
+        let mut ink: Vec<bool> = luma
+            .iter()
+            .map(|v| (*v <= threshold) == ink_is_dark)
+            .collect();
+        let (panels, cleared) = take_panels(area, &mut ink);
+
+        // Coverage runs from the typical background level to the strongest ink, so
+        // anti-aliased edges get partial values
+        let (mut background, mut foreground) = (Vec::new(), Vec::new());
+        for ((value, is_ink), is_cleared) in luma.iter().zip(&ink).zip(&cleared) {
+            match (is_ink, is_cleared) {
+                (_, true) => {}
+                (true, false) => foreground.push(*value as f32),
+                (false, false) => background.push(*value as f32),
+            }
+        }
+        if foreground.is_empty() || background.is_empty() {
+            return Some((InkMap::from_coverage(area, vec![0.0; luma.len()]), panels));
+        }
+        background.sort_by(f32::total_cmp);
+        foreground.sort_by(f32::total_cmp);
+        if !ink_is_dark {
+            foreground.reverse();
+        }
+        let background = background[background.len() / 2];
+        let strongest = foreground[foreground.len() / 20];
+        let coverage = luma
+            .iter()
+            .zip(&cleared)
+            .map(|(value, is_cleared)| match is_cleared {
+                true => 0.0,
+                false => ((background - *value as f32) / (background - strongest)).clamp(0.0, 1.0),
+            })
+            .collect();
+        Some((InkMap::from_coverage(area, coverage), panels))
+    }
+
+    fn from_coverage(area: Rect, coverage: Vec<f32>) -> InkMap {
+        let ink = coverage.iter().map(|c| *c >= INK_COVERAGE).collect();
+        InkMap {
+            area,
+            coverage,
+            ink,
+        }
+    }
+
+    fn index(&self, x: i64, y: i64) -> usize {
+        (y * self.area.width as i64 + x) as usize
+    }
+
+    fn row_has_ink(&self, y: i64) -> bool {
+        (0..self.area.width as i64).any(|x| self.ink[self.index(x, y)])
+    }
+}
+
+/// Finds the 4-connected components of `ink`, starting from every unseen ink pixel.
+fn components(width: i64, height: i64, ink: &[bool], mut found: impl FnMut(Vec<usize>)) {
+    let mut seen = vec![false; ink.len()];
+    for start in 0..ink.len() {
+        if !ink[start] || seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        let mut stack = vec![start];
+        let mut pixels = Vec::new();
+        while let Some(index) = stack.pop() {
+            pixels.push(index);
+            let (x, y) = ((index as i64) % width, (index as i64) / width);
+            for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                if nx >= 0 && ny >= 0 && nx < width && ny < height {
+                    let next = (ny * width + nx) as usize;
+                    if ink[next] && !seen[next] {
+                        seen[next] = true;
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+        found(pixels);
+    }
+}
+
+/// Clears blocks of ink too large and too dense to be glyphs, and the thin outlines of
+/// fields and boxes, from `ink`. Returns the blocks in image coordinates, to be read on
+/// their own, and which pixels were cleared.
+fn take_panels(area: Rect, ink: &mut [bool]) -> (Vec<Rect>, Vec<bool>) {
+    let (width, height) = (area.width as i64, area.height as i64);
+    let mut panels = Vec::new();
+    let mut cleared = vec![false; ink.len()];
+    components(width, height, ink, |pixels| {
+        let xs = pixels.iter().map(|i| *i as i64 % width);
+        let ys = pixels.iter().map(|i| *i as i64 / width);
+        let (x0, x1) = (xs.clone().min().unwrap(), xs.max().unwrap() + 1);
+        let (y0, y1) = (ys.clone().min().unwrap(), ys.max().unwrap() + 1);
+        if x1 - x0 < 16 || y1 - y0 < 12 {
+            return;
+        }
+        let fill = pixels.len() as f64 / ((x1 - x0) * (y1 - y0)) as f64;
+        let on_edge = pixels
+            .iter()
+            .filter(|i| {
+                let (x, y) = (**i as i64 % width, **i as i64 / width);
+                x - x0 < 2 || x1 - x <= 2 || y - y0 < 2 || y1 - y <= 2
+            })
+            .count();
+        if fill >= 0.75 {
+            panels.push(Rect {
+                x: area.x + x0 as i32,
+                y: area.y + y0 as i32,
+                width: (x1 - x0) as u32,
+                height: (y1 - y0) as u32,
+            });
+            // The whole block goes, so text inside it is not read twice
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    cleared[(y * width + x) as usize] = true;
+                }
+            }
+        } else if on_edge * 10 >= pixels.len() * 9 {
+            for i in pixels {
+                cleared[i] = true;
+            }
+        }
+    });
+    for (is_ink, is_cleared) in ink.iter_mut().zip(&cleared) {
+        *is_ink &= !is_cleared;
+    }
+    (panels, cleared)
+}
+
+/// Otsu's method; `None` when the area is a single flat color.
+fn otsu_threshold(luma: &[u8]) -> Option<u8> {
+    let mut histogram = [0u64; 256];
+    for v in luma {
+        histogram[*v as usize] += 1;
+    }
+    let total = luma.len() as f64;
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(v, n)| v as f64 * *n as f64)
+        .sum();
+
+    let (mut best, mut best_variance) = (None, 0.0);
+    let (mut weight_below, mut sum_below) = (0.0, 0.0);
+    for (v, n) in histogram.iter().enumerate() {
+        weight_below += *n as f64;
+        sum_below += v as f64 * *n as f64;
+        let weight_above = total - weight_below;
+        if weight_below == 0.0 || weight_above == 0.0 {
+            continue;
+        }
+        let mean_below = sum_below / weight_below;
+        let mean_above = (sum - sum_below) / weight_above;
+        let variance = weight_below * weight_above * (mean_below - mean_above).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best = Some(v as u8);
+        }
+    }
+    best
+}
+
+fn recognize_lines(image: &RgbaImage, region: Option<Rect>) -> Vec<Vec<TextBox>> {
+    let full = Rect {
+        x: 0,
+        y: 0,
+        width: image.width(),
+        height: image.height(),
+    };
+    let mut lines = match region
+        .unwrap_or(full)
+        .clamp_to(image.width(), image.height())
+    {
+        Some(area) if area.width > 0 && area.height > 0 => read_area(image, area, 0),
+        _ => Vec::new(),
+    };
+    lines.sort_by_key(|line| (line[0].bounds.y, line[0].bounds.x));
+    lines
+}
+
+fn read_area(image: &RgbaImage, area: Rect, depth: u32) -> Vec<Vec<TextBox>> {
+    let Some((map, panels)) = InkMap::new(image, area) else {
+        return Vec::new();
+    };
+    let mut lines: Vec<Vec<TextBox>> = line_bands(&map)
+        .into_iter()
+        .map(|(y0, y1)| read_line(&map, y0, y1))
+        .filter(|words| !words.is_empty())
+        .collect();
+    if depth < MAX_PANEL_DEPTH {
+        for panel in panels {
+            lines.extend(read_area(image, panel, depth + 1));
+        }
+    }
+    lines
+}
+
+/// Splits the area into text lines on blank rows. Small bands right next to a taller
+/// one (the dot of an `i` or `j`) are folded into it.
+fn line_bands(map: &InkMap) -> Vec<(i64, i64)> {
+    let mut bands: Vec<(i64, i64)> = Vec::new();
+    let mut start = None;
+    for y in 0..=map.area.height as i64 {
+        let has_ink = y < map.area.height as i64 && map.row_has_ink(y);
+        match (has_ink, start) {
+            (true, None) => start = Some(y),
+            (false, Some(y0)) => {
+                bands.push((y0, y));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    let mut merged = true;
+    while merged {
+        merged = false;
+        for i in 1..bands.len() {
+            let (a, b) = (bands[i - 1], bands[i]);
+            let (small, large) = if a.1 - a.0 <= b.1 - b.0 {
+                (a, b)
+            } else {
+                (b, a)
+            };
+            let gap = b.0 - a.1;
+            if (small.1 - small.0) * 3 <= large.1 - large.0 && gap <= (small.1 - small.0) * 2 {
+                bands[i - 1] = (a.0, b.1);
+                bands.remove(i);
+                merged = true;
+                break;
+            }
+        }
+    }
+    bands
+}
+
+/// The ink of one text line, each pixel labelled with its 8-connected component
+/// (0 for none).
+struct LineInk<'a> {
+    map: &'a InkMap,
+    y0: i64,
+    y1: i64,
+    labels: Vec<u32>,
+}
+
+/// A glyph: ink components that overlap horizontally, like the dot and stem of an `i`.
+/// Coordinates are in the map's area.
+#[derive(Debug, Clone)]
+struct Blob {
+    x0: i64,
+    x1: i64,
+    top: i64,
+    bottom: i64,
+    labels: Vec<u32>,
+}
+
+impl Blob {
+    fn absorb(&mut self, other: Blob) {
+        self.x0 = self.x0.min(other.x0);
+        self.x1 = self.x1.max(other.x1);
+        self.top = self.top.min(other.top);
+        self.bottom = self.bottom.max(other.bottom);
+        self.labels.extend(other.labels);
+    }
+}
+
+impl<'a> LineInk<'a> {
+    /// Labels the ink in rows `y0..y1` and returns its glyphs from left to right.
+    fn new(map: &'a InkMap, y0: i64, y1: i64) -> (LineInk<'a>, Vec<Blob>) {
+        let width = map.area.width as i64;
+        let mut labels = vec![0u32; (width * (y1 - y0)) as usize];
+        let mut parts = Vec::new();
+        for start_y in y0..y1 {
+            for start_x in 0..width {
+                let start = ((start_y - y0) * width + start_x) as usize;
+                if !map.ink[map.index(start_x, start_y)] || labels[start] != 0 {
+                    continue;
+                }
+                let label = parts.len() as u32 + 1;
+                labels[start] = label;
+                let mut blob = Blob {
+                    x0: start_x,
+                    x1: start_x + 1,
+                    top: start_y,
+                    bottom: start_y + 1,
+                    labels: vec![label],
+                };
+                let mut stack = vec![(start_x, start_y)];
+                while let Some((x, y)) = stack.pop() {
+                    blob.x0 = blob.x0.min(x);
+                    blob.x1 = blob.x1.max(x + 1);
+                    blob.top = blob.top.min(y);
+                    blob.bottom = blob.bottom.max(y + 1);
+                    for ny in (y - 1).max(y0)..(y + 2).min(y1) {
+                        for nx in (x - 1).max(0)..(x + 2).min(width) {
+                            let next = ((ny - y0) * width + nx) as usize;
+                            if map.ink[map.index(nx, ny)] && labels[next] == 0 {
+                                labels[next] = label;
+                                stack.push((nx, ny));
+                            }
+                        }
+                    }
+                }
+                parts.push(blob);
+            }
+        }
+
+        parts.sort_by_key(|part| part.x0);
+        let mut blobs: Vec<Blob> = Vec::new();
+        for part in parts {
+            let overlapping = blobs.iter_mut().rev().take(3).find(|blob| {
+                let overlap = blob.x1.min(part.x1) - blob.x0.max(part.x0);
+                overlap * 2 >= (blob.x1 - blob.x0).min(part.x1 - part.x0)
+            });
+            match overlapping {
+                Some(blob) => blob.absorb(part),
+                None => blobs.push(part),
+            }
+        }
+        blobs.sort_by_key(|blob| blob.x0);
+        (
+            LineInk {
+                map,
+                y0,
+                y1,
+                labels,
+            },
+            blobs,
+        )
+    }
+
+    /// The part of `blob` in columns `x0..x1`, if it has ink there.
+    fn slice(&self, blob: &Blob, x0: i64, x1: i64) -> Option<Blob> {
+        let rows: Vec<i64> = (blob.top..blob.bottom)
+            .filter(|y| (x0..x1).any(|x| blob.labels.contains(&self.label(x, *y))))
+            .collect();
+        Some(Blob {
+            x0,
+            x1,
+            top: *rows.first()?,
+            bottom: rows.last()? + 1,
+            labels: blob.labels.clone(),
+        })
+    }
+
+    /// Reads `blob` as one glyph, or as several touching ones when cutting it at thin
+    /// columns gives better matches.
+    fn read_blob(&self, blob: &Blob, baseline: i64, ascender: f32) -> Vec<Glyph> {
+        let read = |piece: &Blob| {
+            let (ch, confidence) = classify(&self.features(piece, baseline, ascender));
+            Glyph {
+                ch,
+                confidence,
+                x0: piece.x0,
+                x1: piece.x1,
+                top: piece.top,
+                bottom: piece.bottom,
+            }
+        };
+        let whole = read(blob);
+        if whole.confidence >= SPLIT_BELOW {
+            return vec![whole];
+        }
+
+        // Cut only where few pixels are ink, into pieces of plausible glyph widths
+        let column_ink: Vec<usize> = (blob.x0..blob.x1)
+            .map(|x| {
+                (blob.top..blob.bottom)
+                    .filter(|y| blob.labels.contains(&self.label(x, *y)))
+                    .count()
+            })
+            .collect();
+        let thickest = column_ink.iter().copied().max().unwrap_or(0);
+        let min_width = ((ascender * 0.15) as i64).max(2);
+        let max_width = (ascender * 1.2) as i64;
+        let mut cuts = vec![blob.x0];
+        cuts.extend(
+            (blob.x0 + min_width..blob.x1 - min_width + 1)
+                .filter(|x| column_ink[(x - blob.x0) as usize] * 10 <= thickest * 4),
+        );
+        cuts.push(blob.x1);
+
+        // The best reading of each prefix, scoring pieces by confidence times width
+        let mut best: Vec<Option<(f32, Vec<Glyph>)>> = vec![None; cuts.len()];
+        best[0] = Some((0.0, Vec::new()));
+        for end in 1..cuts.len() {
+            for start in 0..end {
+                let width = cuts[end] - cuts[start];
+                let whole_blob = start == 0 && end == cuts.len() - 1;
+                if !whole_blob && (width < min_width || width > max_width) {
+                    continue;
+                }
+                let Some((score, glyphs)) = &best[start] else {
+                    continue;
+                };
+                let glyph = match whole_blob {
+                    true => whole.clone(),
+                    false => match self.slice(blob, cuts[start], cuts[end]) {
+                        Some(piece) => read(&piece),
+                        None => continue,
+                    },
+                };
+                let cost = if start == 0 { 0.0 } else { CUT_COST * ascender };
+                let score = score + glyph.confidence * width as f32 - cost;
+                if best[end]
+                    .as_ref()
+                    .is_none_or(|(best_score, _)| score > *best_score)
+                {
+                    let mut glyphs = glyphs.clone();
+                    glyphs.push(glyph);
+                    best[end] = Some((score, glyphs));
+                }
+            }
+        }
+        best.pop()
+            .flatten()
+            .map(|(_, glyphs)| glyphs)
+            .unwrap_or_else(|| vec![whole])
+    }
+
+    fn label(&self, x: i64, y: i64) -> u32 {
+        if x < 0 || y < self.y0 || x >= self.map.area.width as i64 || y >= self.y1 {
+            return 0;
+        }
+        self.labels[((y - self.y0) * self.map.area.width as i64 + x) as usize]
+    }
+
+    /// The features of `blob` on a line with the given baseline and ascender height.
+    /// Only its own ink counts, plus the anti-aliased fringe around it.
+    fn features(&self, blob: &Blob, baseline: i64, ascender: f32) -> [f32; FEATURES] {
+        let (width, height) = (blob.x1 - blob.x0, blob.bottom - blob.top);
+        let owned = |x: i64, y: i64| blob.labels.contains(&self.label(x, y));
+        let mut features = [0.0; FEATURES];
+        for y in blob.top..blob.bottom {
+            for x in blob.x0..blob.x1 {
+                let value = match self.label(x, y) {
+                    0 if (-1..=1).any(|dy| (-1..=1).any(|dx| owned(x + dx, y + dy))) => {
+                        self.map.coverage[self.map.index(x, y)]
+                    }
+                    label if blob.labels.contains(&label) => {
+                        self.map.coverage[self.map.index(x, y)]
+                    }
+                    _ => continue,
+                };
+                // Spread the pixel over the grid cells it overlaps
+                let (px, py) = (x - blob.x0, y - blob.top);
+                for (gy, wy) in overlaps(py, height) {
+                    for (gx, wx) in overlaps(px, width) {
+                        features[gy * GRID + gx] += value * wx * wy;
+                    }
+                }
+            }
+        }
+        let cell_area = (width as f32 / GRID as f32) * (height as f32 / GRID as f32);
+        for cell in &mut features[..GRID * GRID] {
+            *cell = (*cell / cell_area).min(1.0);
+        }
+        features[GRID * GRID] = width as f32 / ascender;
+        features[GRID * GRID + 1] = height as f32 / ascender;
+        features[GRID * GRID + 2] = (baseline - blob.top) as f32 / ascender;
+        features
+    }
+}
+
+/// The grid cells pixel `p` of a span `len` pixels long falls into, with the share of
+/// the pixel in each.
+fn overlaps(p: i64, len: i64) -> impl Iterator<Item = (usize, f32)> {
+    let scale = GRID as f32 / len as f32;
+    let (start, end) = (p as f32 * scale, (p + 1) as f32 * scale);
+    (start.floor() as usize..(end.ceil() as usize).min(GRID)).filter_map(move |cell| {
+        let share = (end.min(cell as f32 + 1.0) - start.max(cell as f32)) / scale;
+        (share > 0.0).then_some((cell, share))
+    })
+}
+
+/// The baseline of a line of glyphs (where most of them end) and its ascender height
+/// (how far the tallest of those rise above it).
+fn line_metrics(blobs: &[Blob]) -> (i64, f32) {
+    let mut bottoms: Vec<i64> = blobs.iter().map(|blob| blob.bottom).collect();
+    bottoms.sort_unstable();
+    let baseline = bottoms[bottoms.len() / 2];
+    let top = blobs.iter().map(|blob| blob.top).min().unwrap_or(baseline);
+    let tolerance = ((baseline - top) / 8).max(1);
+    let ascender = blobs
+        .iter()
+        .filter(|blob| (blob.bottom - baseline).abs() <= tolerance)
+        .map(|blob| baseline - blob.top)
+        .max()
+        .unwrap_or(1)
+        .max(1);
+    (baseline, ascender as f32)
+}
+
+/// A recognized character and where it is, in the map's area.
+#[derive(Debug, Clone)]
+struct Glyph {
+    ch: char,
+    confidence: f32,
+    x0: i64,
+    x1: i64,
+    top: i64,
+    bottom: i64,
+}
+
+fn read_line(map: &InkMap, y0: i64, y1: i64) -> Vec<TextBox> {
+    let (line, blobs) = LineInk::new(map, y0, y1);
+    if blobs.is_empty() {
+        return Vec::new();
+    }
+    let (baseline, ascender) = line_metrics(&blobs);
+    let mut glyphs: Vec<Glyph> = blobs
+        .iter()
+        .flat_map(|blob| line.read_blob(blob, baseline, ascender))
+        .collect();
+    settle_case(&mut glyphs);
+    words(map, &glyphs, ascender)
+}
+
+/// Letters whose lower and upper case differ in size only.
+const SAME_SHAPE_CASES: &str = "cosvwxzCOSVWXZ";
+
+/// Picks the case of letters that only differ in size by comparing them with the line's
+/// x-height, which the ascender height alone cannot give on lines without ascenders.
+fn settle_case(glyphs: &mut [Glyph]) {
+    let mut x_heights: Vec<i64> = glyphs
+        .iter()
+        .filter(|glyph| "aemnru".contains(glyph.ch))
+        .map(|glyph| glyph.bottom - glyph.top)
+        .collect();
+    x_heights.sort_unstable();
+    let capitals = glyphs
+        .iter()
+        .filter(|glyph| "ABDEFHKLMNPRT".contains(glyph.ch))
+        .map(|glyph| glyph.bottom - glyph.top)
+        .max();
+    if let (Some(x_height), Some(cap_height)) = (x_heights.get(x_heights.len() / 2), capitals) {
+        for glyph in glyphs.iter_mut() {
+            if SAME_SHAPE_CASES.contains(glyph.ch) {
+                let tall = (glyph.bottom - glyph.top) * 2 > x_height + cap_height;
+                glyph.ch = match tall {
+                    true => glyph.ch.to_ascii_uppercase(),
+                    false => glyph.ch.to_ascii_lowercase(),
+                };
+            }
+        }
+    }
+}
+
+/// Resolves glyphs that look alike in letters and digits (`O` and `0`, `l` and `1`)
+/// by what most of the word is, and an `I` next to lower-case letters to an `l`.
+fn settle_lookalikes(word: &str) -> String {
+    let chars: Vec<char> = word.chars().collect();
+    let digits = chars.iter().filter(|ch| ch.is_ascii_digit()).count();
+    let letters = chars.iter().filter(|ch| ch.is_ascii_alphabetic()).count();
+    let lower = |i: Option<usize>| {
+        i.and_then(|i| chars.get(i))
+            .is_some_and(char::is_ascii_lowercase)
+    };
+    (0..chars.len())
+        .map(|i| match chars[i] {
+            'O' | 'o' if digits > letters => '0',
+            'l' | 'I' if digits > letters => '1',
+            'S' if digits > letters => '5',
+            '0' if letters > digits => match lower(i.checked_sub(1)) || lower(Some(i + 1)) {
+                true => 'o',
+                false => 'O',
+            },
+            '1' if letters > digits => 'l',
+            'I' if i > 0 && (lower(i.checked_sub(1)) || lower(Some(i + 1))) => 'l',
+            ch => ch,
+        })
+        .collect()
+}
+
+/// Groups glyphs into words, on wide gaps and on glyphs that look like no character.
+fn words(map: &InkMap, glyphs: &[Glyph], ascender: f32) -> Vec<TextBox> {
+    let mut gaps: Vec<i64> = glyphs
+        .windows(2)
+        .map(|pair| pair[1].x0 - pair[0].x1)
+        .collect();
+    gaps.sort_unstable();
+    let typical = gaps.get(gaps.len() / 2).copied().unwrap_or(0).max(0) as f32;
+    let word_gap = (WORD_GAP * ascender).max(typical * GAP_FACTOR);
+    let mut words = Vec::new();
+    let mut current: Option<(String, Rect, f32, u32)> = None;
+    let mut previous_end = None;
+    for glyph in glyphs {
+        if glyph.confidence < MIN_GLYPH_CONFIDENCE {
+            words.extend(current.take());
+            previous_end = None;
+            continue;
+        }
+        let bounds = Rect {
+            x: map.area.x + glyph.x0 as i32,
+            y: map.area.y + glyph.top as i32,
+            width: (glyph.x1 - glyph.x0) as u32,
+            height: (glyph.bottom - glyph.top) as u32,
+        };
+        let adjacent = previous_end.is_some_and(|end: i64| ((glyph.x0 - end) as f32) < word_gap);
+        current = match current {
+            Some((mut text, rect, confidence, count)) if adjacent => {
+                text.push(glyph.ch);
+                Some((
+                    text,
+                    union(rect, bounds),
+                    confidence + glyph.confidence,
+                    count + 1,
+                ))
+            }
+            finished => {
+                words.extend(finished);
+                Some((glyph.ch.to_string(), bounds, glyph.confidence, 1))
+            }
+        };
+        previous_end = Some(glyph.x1);
+    }
+    words.extend(current);
+
+    words
+        .into_iter()
+        .map(|(text, bounds, confidence, count)| TextBox {
+            // Two apostrophes side by side are one double quote
+            text: settle_lookalikes(&text.replace("''", "\"")),
+            bounds,
+            confidence: confidence / count as f32,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+
+    /// The fonts the model is trained from, as shipped by the fonts-dejavu packages.
+    const TRAINING_FONTS: [&str; 6] = [
+        "DejaVuSans.ttf",
+        "DejaVuSans-Bold.ttf",
+        "DejaVuSansCondensed.ttf",
+        "DejaVuSansMono.ttf",
+        "DejaVuSerif.ttf",
+        "DejaVuSerif-Bold.ttf",
+    ];
+    const FONT_DIR: &str = "/usr/share/fonts/truetype/dejavu";
+    /// Sizes the glyphs are rendered at, in half pixels.
+    const TRAINING_SIZES: std::ops::RangeInclusive<u32> = 18..=56;
+    /// Subpixel positions each glyph is rendered at.
+    const OFFSETS: [(f32, f32); 4] = [(0.0, 0.0), (0.25, 0.5), (0.5, 0.25), (0.75, 0.75)];
+    /// Exponents applied to the coverage, for lighter and heavier anti-aliasing.
+    const GAMMAS: [f32; 3] = [0.7, 1.0, 1.4];
+    /// Random letter pairs rendered per font, size and position to teach the network
+    /// what touching glyphs look like.
+    const PAIRS: usize = 24;
+    const HIDDEN: usize = 128;
+    const EPOCHS: usize = 16;
+    const BATCH: usize = 32;
+    const LEARNING_RATE: f32 = 0.001;
+
+    /// Renders `text` with its baseline at `y` into a coverage map, `tracking` pixels
+    /// between glyphs.
+    fn render(
+        font: &FontRef,
+        size: f32,
+        text: &str,
+        x: f32,
+        y: f32,
+        tracking: f32,
+        area: Rect,
+    ) -> Vec<f32> {
+        let scaled = font.as_scaled(PxScale::from(size));
+        let mut coverage = vec![0.0f32; (area.width * area.height) as usize];
+        let mut caret = point(x, y);
+        let mut last = None;
+        for ch in text.chars() {
+            let id = scaled.glyph_id(ch);
+            if let Some(previous) = last {
+                caret.x += scaled.kern(previous, id) + tracking;
+            }
+            let glyph = id.with_scale_and_position(PxScale::from(size), caret);
+            caret.x += scaled.h_advance(id);
+            last = Some(id);
+            if let Some(outline) = font.outline_glyph(glyph) {
+                let bounds = outline.px_bounds();
+                outline.draw(|gx, gy, c| {
+                    let (px, py) = (
+                        bounds.min.x as i64 + gx as i64,
+                        bounds.min.y as i64 + gy as i64,
+                    );
+                    if px >= 0 && py >= 0 && px < area.width as i64 && py < area.height as i64 {
+                        let cell = &mut coverage[(py * area.width as i64 + px) as usize];
+                        *cell = (*cell + c).min(1.0);
+                    }
+                });
+            }
+        }
+        coverage
+    }
+
+    /// How a sample is rendered.
+    #[derive(Clone, Copy)]
+    struct Style<'a> {
+        font: &'a FontRef<'a>,
+        size: f32,
+        offset: (f32, f32),
+        gamma: f32,
+    }
+
+    /// `text` rendered alone, as the recognizer would see it: all of its ink as one blob,
+    /// and how many blobs that ink fell into.
+    fn training_blob(style: Style, text: &str, tracking: f32) -> Option<(InkMap, Blob, usize)> {
+        let side = (style.size * 3.0) as u32 + 8;
+        let area = Rect {
+            x: 0,
+            y: 0,
+            width: side,
+            height: side,
+        };
+        let (x, y) = (
+            4.0 + style.offset.0,
+            4.0 + style.size * 1.2 + style.offset.1,
+        );
+        let coverage = render(style.font, style.size, text, x, y, tracking, area)
+            .into_iter()
+            .map(|c| c.powf(style.gamma))
+            .collect();
+        let map = InkMap::from_coverage(area, coverage);
+        let (_, blobs) = LineInk::new(&map, 0, area.height as i64);
+        let count = blobs.len();
+        let mut blobs = blobs.into_iter();
+        let mut blob = blobs.next()?;
+        for other in blobs {
+            blob.absorb(other);
+        }
+        Some((map, blob, count))
+    }
+
+    /// The line's baseline and ascender height, from the bottom of `x` and top of `d`.
+    fn training_metrics(style: Style) -> Option<(i64, f32)> {
+        let (_, x, _) = training_blob(style, "x", 0.0)?;
+        let (_, d, _) = training_blob(style, "d", 0.0)?;
+        Some((x.bottom, (x.bottom - d.top).max(1) as f32))
+    }
+
+    fn training_features(
+        style: Style,
+        text: &str,
+        tracking: f32,
+    ) -> Option<([f32; FEATURES], usize)> {
+        let (baseline, ascender) = training_metrics(style)?;
+        let (map, blob, count) = training_blob(style, text, tracking)?;
+        let (line, _) = LineInk::new(&map, 0, map.area.height as i64);
+        Some((line.features(&blob, baseline, ascender), count))
+    }
+
+    /// A deterministic xorshift generator, so the model regenerates identically.
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn unit(&mut self) -> f32 {
+            (self.next() >> 40) as f32 / (1u64 << 24) as f32
+        }
+    }
+
+    /// The characters the network tells apart, then the class for touching glyphs. The
+    /// double quote is read as two apostrophes.
+    fn training_classes() -> Vec<Option<char>> {
+        ('!'..='~')
+            .filter(|ch| *ch != '"')
+            .map(Some)
+            .chain([None])
+            .collect()
+    }
+
+    fn training_set(random: &mut Random) -> Vec<([f32; FEATURES], usize)> {
+        let classes = training_classes();
+        let letters: Vec<char> = ('a'..='z')
+            .chain('a'..='z')
+            .chain('A'..='Z')
+            .chain('0'..='9')
+            .collect();
+        let mut samples = Vec::new();
+        for name in TRAINING_FONTS {
+            let path = format!("{}/{}", FONT_DIR, name);
+            let data = std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+            let font = FontRef::try_from_slice(&data).unwrap();
+            for half_pixels in TRAINING_SIZES {
+                for offset in OFFSETS {
+                    let style = Style {
+                        font: &font,
+                        size: half_pixels as f32 / 2.0,
+                        offset,
+                        gamma: GAMMAS[random.below(GAMMAS.len())],
+                    };
+                    for (class, ch) in classes.iter().enumerate() {
+                        if let Some((features, _)) =
+                            ch.and_then(|ch| training_features(style, &ch.to_string(), 0.0))
+                        {
+                            samples.push((features, class));
+                        }
+                    }
+                    for _ in 0..PAIRS {
+                        let pair: String = [
+                            letters[random.below(letters.len())],
+                            letters[random.below(letters.len())],
+                        ]
+                        .iter()
+                        .collect();
+                        let tracking = -random.unit() * style.size * 0.2;
+                        if let Some((features, 1)) = training_features(style, &pair, tracking) {
+                            samples.push((features, classes.len() - 1));
+                        }
+                    }
+                }
+            }
+        }
+        samples
+    }
+
+    /// A layer being trained, with its gradients and Adam moments.
+    struct Training {
+        layer: Layer,
+        gradient: Vec<f32>,
+        moments: Vec<(f32, f32)>,
+    }
+
+    impl Training {
+        fn new(inputs: usize, outputs: usize, random: &mut Random) -> Training {
+            let bound = (6.0 / inputs as f32).sqrt();
+            let parameters = (inputs + 1) * outputs;
+            Training {
+                layer: Layer {
+                    inputs,
+                    outputs,
+                    weights: (0..inputs * outputs)
+                        .map(|_| (random.unit() * 2.0 - 1.0) * bound)
+                        .collect(),
+                    biases: vec![0.0; outputs],
+                },
+                gradient: vec![0.0; parameters],
+                moments: vec![(0.0, 0.0); parameters],
+            }
+        }
+
+        /// Adds the gradient of `outputs_gradient` for `inputs`, and returns the gradient
+        /// of the inputs.
+        fn backward(&mut self, inputs: &[f32], outputs_gradient: &[f32]) -> Vec<f32> {
+            let mut inputs_gradient = vec![0.0; self.layer.inputs];
+            let (weights_gradient, biases_gradient) = self
+                .gradient
+                .split_at_mut(self.layer.inputs * self.layer.outputs);
+            for (output, g) in outputs_gradient.iter().enumerate() {
+                if *g == 0.0 {
+                    continue;
+                }
+                biases_gradient[output] += g;
+                let row = output * self.layer.inputs..(output + 1) * self.layer.inputs;
+                for ((wg, w), (i, ig)) in weights_gradient[row.clone()]
+                    .iter_mut()
+                    .zip(&self.layer.weights[row])
+                    .zip(inputs.iter().zip(&mut inputs_gradient))
+                {
+                    *wg += g * i;
+                    *ig += g * w;
+                }
+            }
+            inputs_gradient
+        }
+
+        fn step(&mut self, step: i32) {
+            let (beta1, beta2) = (0.9f32, 0.999f32);
+            let correction = (1.0 - beta2.powi(step)).sqrt() / (1.0 - beta1.powi(step));
+            let parameters = self.layer.weights.iter_mut().chain(&mut self.layer.biases);
+            for ((parameter, gradient), (m, v)) in
+                parameters.zip(&mut self.gradient).zip(&mut self.moments)
+            {
+                let g = *gradient / BATCH as f32;
+                *m = beta1 * *m + (1.0 - beta1) * g;
+                *v = beta2 * *v + (1.0 - beta2) * g * g;
+                *parameter -= LEARNING_RATE * correction * *m / (v.sqrt() + 1e-8);
+                *gradient = 0.0;
+            }
+        }
+    }
+
+    fn train() -> Network {
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+        let mut samples = training_set(&mut random);
+        let classes = training_classes();
+        let mut layers = [
+            Training::new(FEATURES, HIDDEN, &mut random),
+            Training::new(HIDDEN, classes.len(), &mut random),
+        ];
+        let mut step = 0;
+        for epoch in 0..EPOCHS {
+            for i in (1..samples.len()).rev() {
+                samples.swap(i, random.below(i + 1));
+            }
+            let mut right = 0;
+            for batch in samples.chunks(BATCH) {
+                for (features, class) in batch {
+                    let hidden: Vec<f32> = layers[0]
+                        .layer
+                        .forward(features)
+                        .into_iter()
+                        .map(|v| v.max(0.0))
+                        .collect();
+                    let probabilities = softmax(layers[1].layer.forward(&hidden));
+                    let best = (0..probabilities.len())
+                        .max_by(|a, b| probabilities[*a].total_cmp(&probabilities[*b]));
+                    right += usize::from(best == Some(*class));
+                    let mut gradient = probabilities;
+                    gradient[*class] -= 1.0;
+                    let hidden_gradient: Vec<f32> = layers[1]
+                        .backward(&hidden, &gradient)
+                        .into_iter()
+                        .zip(&hidden)
+                        .map(|(g, h)| if *h > 0.0 { g } else { 0.0 })
+                        .collect();
+                    layers[0].backward(features, &hidden_gradient);
+                }
+                step += 1;
+                for layer in &mut layers {
+                    layer.step(step);
+                }
+            }
+            println!(
+                "epoch {}: {:.2}% of {} samples right",
+                epoch + 1,
+                right as f32 * 100.0 / samples.len() as f32,
+                samples.len()
+            );
+        }
+        Network {
+            layers: layers.into_iter().map(|training| training.layer).collect(),
+            classes,
+        }
+    }
+
+    fn model_text(network: &Network) -> String {
+        let mut text = String::from(concat!(
+            "# autoanchor OCR model: the glyph classifier network of src/ocr.rs.\n",
+            "# Trained on printable ASCII rendered from DejaVu Sans, Sans Bold, Sans Condensed, Sans Mono,\n",
+            "# Serif and Serif Bold at 9-28 px, and on touching letter pairs (class `-`); regenerate with\n",
+            "# `cargo test --release --features ocr -- --ignored regenerate_model`.\n",
+            "# `classes` lists the outputs as hex code points. Each `layer` line gives the input and output\n",
+            "# counts and the weight scale, and is followed per output by its bias and its weights as signed\n",
+            "# bytes in hex.\n",
+        ));
+        let classes: Vec<String> = network
+            .classes
+            .iter()
+            .map(|class| class.map_or("-".to_string(), |ch| format!("{:x}", ch as u32)))
+            .collect();
+        text.push_str(&format!("classes\t{}\n", classes.join(" ")));
+        for layer in &network.layers {
+            let largest = layer.weights.iter().fold(0.0f32, |max, w| max.max(w.abs()));
+            let scale = largest / 127.0;
+            text.push_str(&format!(
+                "layer\t{}\t{}\t{:e}\n",
+                layer.inputs, layer.outputs, scale
+            ));
+            for (row, bias) in layer.weights.chunks(layer.inputs).zip(&layer.biases) {
+                let weights: String = row
+                    .iter()
+                    .map(|w| format!("{:02x}", (w / scale).round() as i8 as u8))
+                    .collect();
+                text.push_str(&format!("{:.5}\t{}\n", bias, weights));
+            }
+        }
+        text
+    }
+
+    fn word(text: &str, x: i32, confidence: f32) -> TextBox {
+        TextBox {
+            text: text.to_string(),
+            bounds: Rect {
+                x,
+                y: 36,
+                width: 8 * text.len() as u32,
+                height: 14,
+            },
+            confidence,
+        }
+    }
+
+    #[test]
+    fn finds_phrases_among_confident_words() {
+        let line = [
+            word("Sign", 48, 0.95),
+            word("in", 86, 0.9),
+            word("t0", 106, 0.4),
+        ];
+        let found = find_in_line(&line, "Sign in").unwrap();
+        assert_eq!((found.x, found.width), (48, 54));
+        // "t0" was recognized with too little confidence to be matched
+        assert_eq!(find_in_line(&line, "in t0"), None);
+        assert_eq!(find_in_line(&line, "Cancel"), None);
+    }
+
+    #[test]
+    fn matches_whole_words_only() {
+        let line = [
+            word("Login", 10, 0.9),
+            word("Resubmit", 60, 0.9),
+            word("Sign", 140, 0.9),
+            word("in", 178, 0.9),
+            word("submit", 200, 0.9),
+        ];
+        // Both also appear inside a longer word earlier on the line
+        assert_eq!(find_in_line(&line, "submit").unwrap().x, 200);
+        assert_eq!(find_in_line(&line, "in").unwrap().x, 178);
+        assert_eq!(find_in_line(&line, "Sig"), None);
+        assert_eq!(find_in_line(&line, "gn in"), None);
+    }
+
+    #[test]
+    fn settles_lookalikes_by_their_neighbours() {
+        assert_eq!(settle_lookalikes("2O1l9"), "20119");
+        assert_eq!(settle_lookalikes("C0ntro1"), "Control");
+        assert_eq!(settle_lookalikes("FiIe"), "File");
+        assert_eq!(settle_lookalikes("It"), "It");
+    }
+
+    #[test]
+    fn bundled_model_parses_and_malformed_ones_do_not() {
+        let network = parse_model(MODEL).unwrap();
+        assert_eq!(network.classes.last(), Some(&None));
+        assert!(parse_model("classes\t41 -\nlayer\t2\t2\t0.01\n0.5\t7f81\n").is_err());
+        assert!(parse_model("layer\tx\t2\t0.01\n").is_err());
+    }
+
+    #[test]
+    #[ignore = "needs the DejaVu fonts; rewrites models/ocr-glyphs.txt"]
+    fn regenerate_model() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/models/ocr-glyphs.txt");
+        std::fs::write(path, model_text(&train())).unwrap();
+    }
+}
@@ -0,0 +1,37 @@
+use std::fs;
+
+/// Fields of `/proc/<pid>/stat` after the command name, which may itself contain spaces
+/// and parentheses.
+fn stat_fields(pid: u32) -> Option<Vec<String>> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let (_, rest) = stat.rsplit_once(')')?;
+    Some(rest.split_whitespace().map(str::to_string).collect())
+}
+
+pub fn is_running(pid: u32) -> Result<bool, String> {
+    // Zombies have exited and only wait for their parent to collect the status
+    Ok(stat_fields(pid).is_some_and(|fields| fields.first().is_some_and(|state| state != "Z")))
+}
+
+pub fn kill(pid: u32) -> Result<(), String> {
+    let pid = libc::pid_t::try_from(pid).map_err(|_| format!("Invalid process id {}", pid))?;
+    if unsafe { libc::kill(pid, libc::SIGKILL) } != 0 {
+        return Err(format!(
+            "Failed to kill process {}: {}",
+            pid,
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(())
+}
+
+pub fn parent_pids() -> Result<Vec<(u32, u32)>, String> {
+    let entries = fs::read_dir("/proc").map_err(|e| format!("Failed to read /proc: {}", e))?;
+    Ok(entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter_map(|pid| {
+            let parent = stat_fields(pid)?.get(1)?.parse().ok()?;
+            Some((pid, parent))
+        })
+        .collect())
+}
@@ -0,0 +1,256 @@
+#[cfg(target_os = "linux")]
+pub mod linux;
+#[cfg(target_os = "windows")]
+pub mod windows;
+
+#[cfg(target_os = "linux")]
+use linux as platform;
+#[cfg(target_os = "windows")]
+use windows as platform;
+
+use super::window::{self, WindowInfo};
+use super::AutomationResult;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::process::{Child, Command};
+use std::time::Duration;
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+mod platform {
+    const UNSUPPORTED: &str = "Unsupported platform - Windows and Linux only for now";
+
+    pub fn is_running(_pid: u32) -> Result<bool, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn kill(_pid: u32) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn parent_pids() -> Result<Vec<(u32, u32)>, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+}
+
+/// How [`launch`] starts an application.
+#[derive(Debug, Clone, Default)]
+pub struct LaunchOptions {
+    pub args: Vec<String>,
+    /// Set on top of the inherited environment.
+    pub env: BTreeMap<String, String>,
+    /// Working directory; `None` inherits ours.
+    pub cwd: Option<PathBuf>,
+    /// Wait up to this long for the application's first window before returning.
+    pub wait_for_window: Option<Duration>,
+}
+
+/// A process started by [`launch`]. Dropping the handle leaves the process running.
+#[derive(Debug)]
+pub struct ProcessHandle {
+    child: Child,
+    window: Option<WindowInfo>,
+}
+
+impl ProcessHandle {
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// The window found while launching with [`LaunchOptions::wait_for_window`].
+    pub fn window(&self) -> Option<&WindowInfo> {
+        self.window.as_ref()
+    }
+
+    pub fn is_running(&mut self) -> Result<bool, String> {
+        self.child
+            .try_wait()
+            .map(|status| status.is_none())
+            .map_err(|e| format!("Failed to query process {}: {}", self.pid(), e))
+    }
+
+    /// Terminates the process forcefully and reaps it.
+    pub fn kill(&mut self) -> Result<(), String> {
+        if !self.is_running()? {
+            return Ok(());
+        }
+        self.child
+            .kill()
+            .and_then(|_| self.child.wait())
+            .map(|_| ())
+            .map_err(|e| format!("Failed to kill process {}: {}", self.pid(), e))
+    }
+
+    /// Waits for the process to exit and returns its exit code, which is `None` when a
+    /// signal ended it.
+    pub fn wait_exit(&mut self, timeout: Duration) -> Result<Option<i32>, String> {
+        let pid = self.pid();
+        window::poll(timeout, || {
+            self.child
+                .try_wait()
+                .map_err(|e| format!("Failed to query process {}: {}", pid, e))
+        })?
+        .map(|status| status.code())
+        .ok_or_else(|| exit_timed_out(pid, timeout))
+    }
+
+    /// The topmost window belonging to the process or one of its children.
+    pub fn main_window(&self) -> Result<Option<WindowInfo>, String> {
+        main_window(self.pid())
+    }
+
+    /// Waits until the process or one of its children shows a window and returns it.
+    pub fn wait_for_window(&mut self, timeout: Duration) -> Result<WindowInfo, String> {
+        let pid = self.pid();
+        let found = window::poll(timeout, || {
+            if let Some(window) = main_window(pid)?.filter(|w| w.visible) {
+                return Ok(Some(window));
+            }
+            // Launchers that hand over to an already running instance exit right away
+            match self.child.try_wait() {
+                Ok(Some(status)) => Err(format!(
+                    "Process {} exited with {} before showing a window",
+                    pid, status
+                )),
+                _ => Ok(None),
+            }
+        })?;
+        found.ok_or_else(|| {
+            format!(
+                "Process {} showed no window within {:.1}s",
+                pid,
+                timeout.as_secs_f64()
+            )
+        })
+    }
+}
+
+/// Starts `app`, found through `PATH` unless it is a path, and optionally waits for its
+/// first window so that input is not sent before the application can take it.
+pub fn launch(app: &str, options: &LaunchOptions) -> Result<ProcessHandle, String> {
+    let mut command = Command::new(app);
+    command.args(&options.args).envs(&options.env);
+    if let Some(cwd) = &options.cwd {
+        command.current_dir(cwd);
+    }
+    let child = command
+        .spawn()
+        .map_err(|e| format!("Failed to launch {}: {}", app, e))?;
+    let mut handle = ProcessHandle {
+        child,
+        window: None,
+    };
+    if let Some(timeout) = options.wait_for_window {
+        handle.window = Some(handle.wait_for_window(timeout)?);
+    }
+    Ok(handle)
+}
+
+/// Whether any process, not only one we launched, has this id.
+pub fn is_running(pid: u32) -> Result<bool, String> {
+    platform::is_running(pid)
+}
+
+/// Terminates a process forcefully.
+pub fn kill(pid: u32) -> Result<AutomationResult, String> {
+    platform::kill(pid)?;
+    Ok(AutomationResult {
+        success: true,
+        message: Some(format!("Process {} killed", pid)),
+        data: None,
+    })
+}
+
+/// Waits for any process to exit. Only the parent can learn a process's exit code, so
+/// unlike [`ProcessHandle::wait_exit`] this reports none.
+pub fn wait_exit(pid: u32, timeout: Duration) -> Result<(), String> {
+    window::poll(timeout, || Ok((!is_running(pid)?).then_some(())))?
+        .ok_or_else(|| exit_timed_out(pid, timeout))
+}
+
+/// The topmost window belonging to `pid` or one of its descendants, since applications
+/// often draw their windows from a child process.
+pub fn main_window(pid: u32) -> Result<Option<WindowInfo>, String> {
+    let family = descendants(pid, &platform::parent_pids()?);
+    Ok(window::list_windows()?
+        .into_iter()
+        .find(|w| w.pid.is_some_and(|p| family.contains(&p)) && (w.visible || w.minimized)))
+}
+
+/// `pid` and every process below it, given `(pid, parent pid)` pairs.
+fn descendants(pid: u32, parents: &[(u32, u32)]) -> Vec<u32> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for &(child, parent) in parents {
+        // Some systems list the idle process as its own parent
+        if child != parent {
+            children.entry(parent).or_default().push(child);
+        }
+    }
+    let mut family = vec![pid];
+    let mut next = 0;
+    while let Some(&current) = family.get(next) {
+        for &child in children.get(&current).into_iter().flatten() {
+            if !family.contains(&child) {
+                family.push(child);
+            }
+        }
+        next += 1;
+    }
+    family
+}
+
+fn exit_timed_out(pid: u32, timeout: Duration) -> String {
+    format!(
+        "Process {} still running after {:.1}s",
+        pid,
+        timeout.as_secs_f64()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descendants_follow_the_whole_tree() {
+        let parents = [
+            (1, 0),
+            (10, 1),
+            (11, 10),
+            (12, 10),
+            (13, 12),
+            (20, 1),
+            (0, 0),
+        ];
+        let mut family = descendants(10, &parents);
+        family.sort();
+        assert_eq!(family, [10, 11, 12, 13]);
+        assert_eq!(descendants(99, &parents), [99]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn launch_wait_and_kill() {
+        let mut options = LaunchOptions {
+            args: vec!["-c".to_string(), "exit $CODE".to_string()],
+            ..LaunchOptions::default()
+        };
+        options.env.insert("CODE".to_string(), "3".to_string());
+        let mut done = launch("sh", &options).unwrap();
+        assert_eq!(done.wait_exit(Duration::from_secs(5)), Ok(Some(3)));
+        assert!(!done.is_running().unwrap());
+
+        let mut sleeper = launch(
+            "sleep",
+            &LaunchOptions {
+                args: vec!["30".to_string()],
+                ..LaunchOptions::default()
+            },
+        )
+        .unwrap();
+        assert!(is_running(sleeper.pid()).unwrap());
+        assert!(sleeper.wait_exit(Duration::from_millis(100)).is_err());
+        sleeper.kill().unwrap();
+        assert!(!is_running(sleeper.pid()).unwrap());
+        assert!(launch("autoanchor-no-such-program", &LaunchOptions::default()).is_err());
+    }
+}
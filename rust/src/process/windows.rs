@@ -0,0 +1,61 @@
+use winapi::shared::minwindef::DWORD;
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::minwinbase::STILL_ACTIVE;
+use winapi::um::processthreadsapi::{GetExitCodeProcess, OpenProcess, TerminateProcess};
+use winapi::um::tlhelp32::{
+    CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+};
+use winapi::um::winnt::{PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_TERMINATE};
+
+pub fn is_running(pid: u32) -> Result<bool, String> {
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if process.is_null() {
+            return Ok(false);
+        }
+        let mut code: DWORD = 0;
+        let ok = GetExitCodeProcess(process, &mut code) != 0;
+        CloseHandle(process);
+        Ok(ok && code == STILL_ACTIVE)
+    }
+}
+
+pub fn kill(pid: u32) -> Result<(), String> {
+    unsafe {
+        let process = OpenProcess(PROCESS_TERMINATE, 0, pid);
+        if process.is_null() {
+            return Err(format!(
+                "Failed to open process {}: {}",
+                pid,
+                GetLastError()
+            ));
+        }
+        let ok = TerminateProcess(process, 1) != 0;
+        let error = GetLastError();
+        CloseHandle(process);
+        if !ok {
+            return Err(format!("Failed to kill process {}: {}", pid, error));
+        }
+    }
+    Ok(())
+}
+
+pub fn parent_pids() -> Result<Vec<(u32, u32)>, String> {
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
+        if snapshot == INVALID_HANDLE_VALUE {
+            return Err(format!("Failed to list processes: {}", GetLastError()));
+        }
+        let mut entry: PROCESSENTRY32W = std::mem::zeroed();
+        entry.dwSize = std::mem::size_of::<PROCESSENTRY32W>() as DWORD;
+        let mut pairs = Vec::new();
+        let mut more = Process32FirstW(snapshot, &mut entry) != 0;
+        while more {
+            pairs.push((entry.th32ProcessID, entry.th32ParentProcessID));
+            more = Process32NextW(snapshot, &mut entry) != 0;
+        }
+        CloseHandle(snapshot);
+        Ok(pairs)
+    }
+}
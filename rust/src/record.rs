@@ -0,0 +1,675 @@
+use super::annotate::{draw_click_marker, draw_cursor};
+use super::redact::{redact, Mask};
+use super::{capture_screen, encode_png, get_cursor_position, Point, Rect};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long a click marker stays visible in the recording.
+const CLICK_MARKER_TTL: Duration = Duration::from_millis(600);
+const MAX_FPS: f64 = 60.0;
+
+#[derive(Debug, Clone)]
+pub struct RecordOptions {
+    pub fps: f64,
+    /// Stop after this long; `None` records until [`Recorder::stop`] is called.
+    pub duration: Option<Duration>,
+    /// Screen area to record; `None` records the primary screen.
+    pub region: Option<Rect>,
+    pub draw_cursor: bool,
+    /// Mark clicks issued through [`crate::click`] (see [`note_click`]); clicks by the
+    /// user or other programs are not seen.
+    pub mark_clicks: bool,
+    /// Areas to black out or blur in every frame.
+    pub masks: Vec<Mask>,
+}
+
+impl RecordOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.fps > 0.0 && self.fps <= MAX_FPS) {
+            return Err(format!("FPS must be between 0 and {}", MAX_FPS));
+        }
+        Ok(())
+    }
+}
+
+impl Default for RecordOptions {
+    fn default() -> Self {
+        RecordOptions {
+            fps: 5.0,
+            duration: None,
+            region: None,
+            draw_cursor: true,
+            mark_clicks: true,
+            masks: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RecordFormat {
+    Gif,
+    Apng,
+    /// A directory of numbered PNG files plus a `frames.json` index of timestamps.
+    PngSequence,
+}
+
+impl RecordFormat {
+    /// Guesses the format from the output path: `.gif`, `.png`/`.apng`, anything else
+    /// is treated as a directory for a PNG sequence.
+    pub fn from_path(path: &Path) -> Self {
+        match path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase())
+            .as_deref()
+        {
+            Some("gif") => RecordFormat::Gif,
+            Some("png") | Some("apng") => RecordFormat::Apng,
+            _ => RecordFormat::PngSequence,
+        }
+    }
+}
+
+impl FromStr for RecordFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gif" => Ok(RecordFormat::Gif),
+            "apng" => Ok(RecordFormat::Apng),
+            "png" | "png-sequence" | "frames" => Ok(RecordFormat::PngSequence),
+            _ => Err(format!("Unsupported recording format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordingSummary {
+    pub frames: u32,
+    pub duration_ms: u64,
+}
+
+/// Receives frames as they are captured so recordings never have to fit in memory
+/// as raw RGBA.
+pub trait FrameSink: Send {
+    fn push(&mut self, frame: RgbaImage, timestamp: Duration) -> Result<(), String>;
+    /// Flushes the output; `end` is the time the recording stopped.
+    fn finish(self: Box<Self>, end: Duration) -> Result<(), String>;
+}
+
+/// Creates the sink writing `format` to `output` (a file, or a directory for
+/// [`RecordFormat::PngSequence`]).
+pub fn sink_for(format: RecordFormat, output: &Path) -> Result<Box<dyn FrameSink>, String> {
+    let create = |path: &Path| {
+        File::create(path)
+            .map(BufWriter::new)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))
+    };
+    Ok(match format {
+        RecordFormat::Gif => Box::new(GifSink::new(create(output)?)?),
+        RecordFormat::Apng => Box::new(ApngSink::new(create(output)?)),
+        RecordFormat::PngSequence => Box::new(PngSequenceSink::new(output)?),
+    })
+}
+
+/// Writes an endlessly looping GIF. Each frame is held back until the next one arrives
+/// so its delay matches the real capture interval.
+pub struct GifSink<W: Write + Send> {
+    encoder: GifEncoder<W>,
+    pending: Option<(RgbaImage, Duration)>,
+}
+
+impl<W: Write + Send> GifSink<W> {
+    pub fn new(writer: W) -> Result<Self, String> {
+        // Speed 30 is the fastest palette quantization, which keeps up with live capture
+        let mut encoder = GifEncoder::new_with_speed(writer, 30);
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(|e| e.to_string())?;
+        Ok(GifSink {
+            encoder,
+            pending: None,
+        })
+    }
+
+    fn flush_pending(&mut self, until: Duration) -> Result<(), String> {
+        if let Some((image, timestamp)) = self.pending.take() {
+            let delay = Delay::from_saturating_duration(until.saturating_sub(timestamp));
+            self.encoder
+                .encode_frame(image::Frame::from_parts(image, 0, 0, delay))
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> FrameSink for GifSink<W> {
+    fn push(&mut self, frame: RgbaImage, timestamp: Duration) -> Result<(), String> {
+        self.flush_pending(timestamp)?;
+        self.pending = Some((frame, timestamp));
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>, end: Duration) -> Result<(), String> {
+        self.flush_pending(end)
+    }
+}
+
+/// Writes an animated PNG. APNG needs the frame count up front, so frames are kept
+/// PNG-compressed in memory and assembled when the recording finishes.
+pub struct ApngSink<W: Write + Send> {
+    writer: W,
+    frames: Vec<(Vec<u8>, Duration)>,
+    size: Option<(u32, u32)>,
+}
+
+impl<W: Write + Send> ApngSink<W> {
+    pub fn new(writer: W) -> Self {
+        ApngSink {
+            writer,
+            frames: Vec::new(),
+            size: None,
+        }
+    }
+}
+
+impl<W: Write + Send> FrameSink for ApngSink<W> {
+    fn push(&mut self, frame: RgbaImage, timestamp: Duration) -> Result<(), String> {
+        let size = *self.size.get_or_insert(frame.dimensions());
+        if size != frame.dimensions() {
+            return Err("All APNG frames must have the same size".to_string());
+        }
+        self.frames.push((encode_png(&frame)?, timestamp));
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>, end: Duration) -> Result<(), String> {
+        let ApngSink {
+            writer,
+            frames,
+            size,
+        } = *self;
+        let (width, height) = match size {
+            Some(size) => size,
+            None => return Err("Recording captured no frames".to_string()),
+        };
+
+        let mut encoder = png::Encoder::new(writer, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .set_animated(frames.len() as u32, 0)
+            .map_err(|e| e.to_string())?;
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+
+        for (index, (bytes, timestamp)) in frames.iter().enumerate() {
+            let next = frames.get(index + 1).map_or(end, |(_, next)| *next);
+            let delay_ms = next
+                .saturating_sub(*timestamp)
+                .as_millis()
+                .min(u16::MAX as u128);
+            writer
+                .set_frame_delay(delay_ms as u16, 1000)
+                .map_err(|e| e.to_string())?;
+            let frame = image::load_from_memory(bytes)
+                .map_err(|e| e.to_string())?
+                .to_rgba8();
+            writer
+                .write_image_data(frame.as_raw())
+                .map_err(|e| e.to_string())?;
+        }
+        writer.finish().map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FrameEntry {
+    file: String,
+    timestamp_ms: u64,
+}
+
+/// Writes `frame-00001.png`, `frame-00002.png`, ... into a directory, plus a
+/// `frames.json` index with each frame's capture time.
+pub struct PngSequenceSink {
+    dir: PathBuf,
+    entries: Vec<FrameEntry>,
+}
+
+impl PngSequenceSink {
+    pub fn new(dir: &Path) -> Result<Self, String> {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        Ok(PngSequenceSink {
+            dir: dir.to_path_buf(),
+            entries: Vec::new(),
+        })
+    }
+}
+
+impl FrameSink for PngSequenceSink {
+    fn push(&mut self, frame: RgbaImage, timestamp: Duration) -> Result<(), String> {
+        let file = format!("frame-{:05}.png", self.entries.len() + 1);
+        let path = self.dir.join(&file);
+        fs::write(&path, encode_png(&frame)?)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        self.entries.push(FrameEntry {
+            file,
+            timestamp_ms: timestamp.as_millis() as u64,
+        });
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>, _end: Duration) -> Result<(), String> {
+        let path = self.dir.join("frames.json");
+        let index = serde_json::to_vec_pretty(&self.entries).map_err(|e| e.to_string())?;
+        fs::write(&path, index).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+static ACTIVE_RECORDERS: AtomicUsize = AtomicUsize::new(0);
+static CLICKS: Mutex<Vec<(Instant, Point)>> = Mutex::new(Vec::new());
+
+/// Tells running recorders that a click happened at `point` (screen coordinates).
+/// Called by [`crate::click`]; a no-op when nothing is recording.
+pub fn note_click(point: Point) {
+    if ACTIVE_RECORDERS.load(Ordering::SeqCst) == 0 {
+        return;
+    }
+    let now = Instant::now();
+    let mut clicks = CLICKS.lock().unwrap_or_else(|e| e.into_inner());
+    clicks.retain(|(at, _)| now.duration_since(*at) < CLICK_MARKER_TTL);
+    clicks.push((now, point));
+}
+
+fn recent_clicks(now: Instant, since: Instant) -> Vec<Point> {
+    let clicks = CLICKS.lock().unwrap_or_else(|e| e.into_inner());
+    clicks
+        .iter()
+        .filter(|(at, _)| *at >= since && now.saturating_duration_since(*at) < CLICK_MARKER_TTL)
+        .map(|(_, point)| *point)
+        .collect()
+}
+
+/// A recording running on a background thread. Dropping it stops the recording and
+/// waits for the thread, discarding the outcome.
+pub struct Recorder {
+    stop: Arc<AtomicBool>,
+    /// Taken by whichever of `wait` or `drop` joins the thread.
+    handle: Option<JoinHandle<Result<RecordingSummary, String>>>,
+}
+
+impl Recorder {
+    /// Starts recording the screen into `output`.
+    pub fn start(
+        options: RecordOptions,
+        output: &Path,
+        format: RecordFormat,
+    ) -> Result<Recorder, String> {
+        // Before the sink creates, and so truncates, the output
+        options.validate()?;
+        let sink = sink_for(format, output)?;
+        Recorder::start_with(options, sink, capture_screen, || get_cursor_position().ok())
+    }
+
+    /// Starts recording frames produced by `capture` into `sink`. `cursor` reports the
+    /// pointer position in screen coordinates, if known.
+    pub fn start_with<C, P>(
+        options: RecordOptions,
+        sink: Box<dyn FrameSink>,
+        capture: C,
+        cursor: P,
+    ) -> Result<Recorder, String>
+    where
+        C: FnMut(Option<Rect>) -> Result<RgbaImage, String> + Send + 'static,
+        P: FnMut() -> Option<Point> + Send + 'static,
+    {
+        options.validate()?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = stop.clone();
+        let start = Instant::now();
+        ACTIVE_RECORDERS.fetch_add(1, Ordering::SeqCst);
+        let handle = thread::spawn(move || {
+            let result = run(options, sink, capture, cursor, start, &flag);
+            ACTIVE_RECORDERS.fetch_sub(1, Ordering::SeqCst);
+            result
+        });
+        Ok(Recorder {
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle
+            .as_ref()
+            .is_none_or(|handle| handle.is_finished())
+    }
+
+    /// Stops the recording and waits for the output to be written.
+    pub fn stop(self) -> Result<RecordingSummary, String> {
+        self.stop.store(true, Ordering::SeqCst);
+        self.wait()
+    }
+
+    /// Waits for a recording with a duration to end on its own.
+    pub fn wait(mut self) -> Result<RecordingSummary, String> {
+        self.handle
+            .take()
+            .expect("only wait and drop join the thread")
+            .join()
+            .map_err(|_| "Recording thread panicked".to_string())?
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            self.stop.store(true, Ordering::SeqCst);
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Records for `options.duration` and blocks until the output is written.
+pub fn record(
+    options: RecordOptions,
+    output: &Path,
+    format: RecordFormat,
+) -> Result<RecordingSummary, String> {
+    if options.duration.is_none() {
+        return Err(
+            "A duration is required; use Recorder::start to record until stopped".to_string(),
+        );
+    }
+    Recorder::start(options, output, format)?.wait()
+}
+
+fn run<C, P>(
+    options: RecordOptions,
+    mut sink: Box<dyn FrameSink>,
+    mut capture: C,
+    mut cursor: P,
+    start: Instant,
+    stop: &AtomicBool,
+) -> Result<RecordingSummary, String>
+where
+    C: FnMut(Option<Rect>) -> Result<RgbaImage, String>,
+    P: FnMut() -> Option<Point>,
+{
+    let origin = options
+        .region
+        .map_or(Point { x: 0, y: 0 }, |r| Point { x: r.x, y: r.y });
+    let interval = Duration::from_secs_f64(1.0 / options.fps);
+    let mut next = start;
+    let mut frames = 0u32;
+
+    let outcome = loop {
+        let elapsed = start.elapsed();
+        if stop.load(Ordering::SeqCst) || options.duration.is_some_and(|d| elapsed >= d) {
+            break Ok(());
+        }
+
+        let mut frame = match capture(options.region) {
+            Ok(frame) => frame,
+            Err(e) => break Err(e),
+        };
+        if let Err(e) = redact(&mut frame, origin, &options.masks) {
+            break Err(e);
+        }
+        if options.mark_clicks {
+            for click in recent_clicks(Instant::now(), start) {
+                draw_click_marker(&mut frame, relative(click, origin));
+            }
+        }
+        if options.draw_cursor {
+            if let Some(position) = cursor() {
+                draw_cursor(&mut frame, relative(position, origin));
+            }
+        }
+        if let Err(e) = sink.push(frame, elapsed) {
+            break Err(e);
+        }
+        frames += 1;
+
+        // Sleep in short slices so stop() is honoured quickly; if capture fell behind,
+        // carry on from now instead of bursting to catch up
+        next += interval;
+        if Instant::now() > next {
+            next = Instant::now();
+        }
+        while !stop.load(Ordering::SeqCst) {
+            let now = Instant::now();
+            if now >= next {
+                break;
+            }
+            thread::sleep((next - now).min(Duration::from_millis(50)));
+        }
+    };
+
+    // Keep whatever was captured before a failure, it is the interesting part
+    let end = start.elapsed();
+    let finished = if frames > 0 { sink.finish(end) } else { Ok(()) };
+    outcome?;
+    finished?;
+    if frames == 0 {
+        return Err("Recording captured no frames".to_string());
+    }
+    Ok(RecordingSummary {
+        frames,
+        duration_ms: end.as_millis() as u64,
+    })
+}
+
+fn relative(point: Point, origin: Point) -> Point {
+    Point {
+        x: point.x - origin.x,
+        y: point.y - origin.y,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{AnimationDecoder, Rgba};
+    use std::io::Cursor;
+
+    fn solid(shade: u8) -> RgbaImage {
+        RgbaImage::from_pixel(32, 24, Rgba([shade, shade, shade, 255]))
+    }
+
+    /// Sink that keeps frames in memory so the capture loop can be inspected.
+    struct Collect(Arc<Mutex<Vec<(RgbaImage, Duration)>>>);
+
+    impl FrameSink for Collect {
+        fn push(&mut self, frame: RgbaImage, timestamp: Duration) -> Result<(), String> {
+            self.0.lock().unwrap().push((frame, timestamp));
+            Ok(())
+        }
+
+        fn finish(self: Box<Self>, _end: Duration) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn gif_sink_writes_one_frame_per_push_with_real_delays() {
+        let mut bytes = Vec::new();
+        let mut sink: Box<dyn FrameSink> = Box::new(GifSink::new(&mut bytes).unwrap());
+        sink.push(solid(0), Duration::ZERO).unwrap();
+        sink.push(solid(128), Duration::from_millis(200)).unwrap();
+        sink.push(solid(255), Duration::from_millis(500)).unwrap();
+        sink.finish(Duration::from_millis(600)).unwrap();
+
+        let decoder = image::codecs::gif::GifDecoder::new(Cursor::new(bytes)).unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        let delays: Vec<u32> = frames
+            .iter()
+            .map(|f| {
+                let (numer, denom) = f.delay().numer_denom_ms();
+                numer / denom
+            })
+            .collect();
+        assert_eq!(delays, [200, 300, 100]);
+    }
+
+    #[test]
+    fn apng_sink_declares_every_frame() {
+        let mut bytes = Vec::new();
+        let mut sink: Box<dyn FrameSink> = Box::new(ApngSink::new(&mut bytes));
+        for i in 0..4 {
+            sink.push(solid(i * 60), Duration::from_millis(i as u64 * 250))
+                .unwrap();
+        }
+        sink.finish(Duration::from_secs(1)).unwrap();
+
+        let reader = png::Decoder::new(Cursor::new(bytes)).read_info().unwrap();
+        let control = reader.info().animation_control().unwrap();
+        assert_eq!(control.num_frames, 4);
+        assert_eq!(control.num_plays, 0);
+    }
+
+    #[test]
+    fn png_sequence_writes_frames_and_index() {
+        let dir = std::env::temp_dir().join(format!("autoanchor-record-{}", std::process::id()));
+        let mut sink: Box<dyn FrameSink> = Box::new(PngSequenceSink::new(&dir).unwrap());
+        sink.push(solid(10), Duration::from_millis(0)).unwrap();
+        sink.push(solid(20), Duration::from_millis(400)).unwrap();
+        sink.finish(Duration::from_millis(800)).unwrap();
+
+        let index: Vec<FrameEntry> =
+            serde_json::from_slice(&fs::read(dir.join("frames.json")).unwrap()).unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index[1].file, "frame-00002.png");
+        assert_eq!(index[1].timestamp_ms, 400);
+        assert!(dir.join("frame-00001.png").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn recorder_overlays_cursor_relative_to_region_and_stops_on_request() {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let options = RecordOptions {
+            fps: 50.0,
+            region: Some(Rect {
+                x: 100,
+                y: 200,
+                width: 32,
+                height: 24,
+            }),
+            mark_clicks: false,
+            ..RecordOptions::default()
+        };
+        let recorder = Recorder::start_with(
+            options,
+            Box::new(Collect(frames.clone())),
+            |_| Ok(solid(128)),
+            || Some(Point { x: 105, y: 210 }),
+        )
+        .unwrap();
+        while frames.lock().unwrap().len() < 3 {
+            thread::sleep(Duration::from_millis(5));
+        }
+        let summary = recorder.stop().unwrap();
+
+        let frames = frames.lock().unwrap();
+        assert_eq!(summary.frames as usize, frames.len());
+        assert!(frames.windows(2).all(|w| w[0].1 < w[1].1));
+        // Cursor hotspot lands at (5, 10) within the region, with white fill below it
+        assert_eq!(frames[0].0.get_pixel(5, 10), &Rgba([0, 0, 0, 255]));
+        assert_eq!(frames[0].0.get_pixel(6, 13), &Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn recorder_honours_duration_and_marks_clicks() {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let options = RecordOptions {
+            fps: 20.0,
+            duration: Some(Duration::from_millis(150)),
+            draw_cursor: false,
+            ..RecordOptions::default()
+        };
+        let recorder = Recorder::start_with(
+            options,
+            Box::new(Collect(frames.clone())),
+            |_| Ok(solid(128)),
+            || None,
+        )
+        .unwrap();
+        note_click(Point { x: 16, y: 12 });
+        let summary = recorder.wait().unwrap();
+
+        assert!(summary.frames >= 2);
+        let frames = frames.lock().unwrap();
+        let marked = frames
+            .iter()
+            .any(|(frame, _)| frame.get_pixel(16 + 10, 12) == &Rgba([255, 40, 40, 255]));
+        assert!(marked);
+    }
+
+    #[test]
+    fn format_is_inferred_from_output_path() {
+        assert_eq!(
+            RecordFormat::from_path(Path::new("run.gif")),
+            RecordFormat::Gif
+        );
+        assert_eq!(
+            RecordFormat::from_path(Path::new("run.PNG")),
+            RecordFormat::Apng
+        );
+        assert_eq!(
+            RecordFormat::from_path(Path::new("frames")),
+            RecordFormat::PngSequence
+        );
+    }
+
+    #[test]
+    fn invalid_fps_is_rejected() {
+        let options = RecordOptions {
+            fps: 0.0,
+            ..RecordOptions::default()
+        };
+        let sink = Box::new(Collect(Arc::new(Mutex::new(Vec::new()))));
+        assert!(Recorder::start_with(options.clone(), sink, |_| Ok(solid(0)), || None).is_err());
+
+        // The output is left alone rather than truncated
+        let path = std::env::temp_dir().join(format!("autoanchor-keep-{}.gif", std::process::id()));
+        fs::write(&path, b"earlier recording").unwrap();
+        assert!(Recorder::start(options, &path, RecordFormat::Gif).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"earlier recording");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn dropping_a_recorder_stops_its_thread() {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Recorder::start_with(
+            RecordOptions {
+                fps: 50.0,
+                ..RecordOptions::default()
+            },
+            Box::new(Collect(frames.clone())),
+            |_| Ok(solid(0)),
+            || None,
+        )
+        .unwrap();
+        while frames.lock().unwrap().is_empty() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        drop(recorder);
+
+        let captured = frames.lock().unwrap().len();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(frames.lock().unwrap().len(), captured);
+    }
+}
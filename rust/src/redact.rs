@@ -0,0 +1,486 @@
+use super::color::{find_all_colors, Tolerance};
+use super::window::{WindowInfo, WindowTarget};
+use super::{get_active_window_rect, Point, Rect};
+use image::imageops;
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaskStyle {
+    /// Solid black fill.
+    #[default]
+    Blackout,
+    /// Pixelated and then blurred, so the layout stays recognisable but text does not.
+    Blur,
+}
+
+impl FromStr for MaskStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "blackout" | "black" => Ok(MaskStyle::Blackout),
+            "blur" => Ok(MaskStyle::Blur),
+            _ => Err(format!("Unsupported mask style: {}", s)),
+        }
+    }
+}
+
+/// Which parts of a capture a [`Mask`] covers. Rectangles are in screen coordinates.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MaskTarget {
+    Rect {
+        rect: Rect,
+    },
+    /// The foreground window at capture time.
+    ActiveWindow,
+    /// A particular window, e.g. a password manager, whether or not it is in the
+    /// foreground. Nothing is masked while it is closed or minimized.
+    Window {
+        target: WindowTarget,
+    },
+    /// Every cluster of `rgba`, grown by `padding` pixels on each side.
+    Color {
+        rgba: [u8; 4],
+        tolerance: Tolerance,
+        #[serde(default)]
+        padding: u32,
+    },
+    /// Every place the PNG at `path` appears. `tolerance` is the mean difference
+    /// allowed per color channel.
+    Template {
+        path: PathBuf,
+        #[serde(default = "default_template_tolerance")]
+        tolerance: u8,
+        #[serde(default)]
+        padding: u32,
+    },
+}
+
+fn default_template_tolerance() -> u8 {
+    8
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mask {
+    #[serde(flatten)]
+    pub target: MaskTarget,
+    #[serde(default)]
+    pub style: MaskStyle,
+}
+
+/// Masks every area covered by `masks` in `image`, which was captured with its top-left
+/// corner at `origin` on the screen. Returns the number of areas masked.
+///
+/// Fails instead of returning a partially masked image when a mask cannot be resolved,
+/// e.g. when a template file is missing.
+pub fn redact(image: &mut RgbaImage, origin: Point, masks: &[Mask]) -> Result<usize, String> {
+    let areas = resolve(image, origin, masks)?;
+    for (rect, style) in &areas {
+        apply(image, *rect, *style);
+    }
+    Ok(areas.len())
+}
+
+/// Image-relative areas covered by `masks`, clipped to the image.
+pub fn resolve(
+    image: &RgbaImage,
+    origin: Point,
+    masks: &[Mask],
+) -> Result<Vec<(Rect, MaskStyle)>, String> {
+    let mut areas = Vec::new();
+    for mask in masks {
+        let rects = match &mask.target {
+            MaskTarget::Rect { rect } => vec![to_image(*rect, origin)],
+            MaskTarget::ActiveWindow => vec![to_image(get_active_window_rect()?, origin)],
+            MaskTarget::Window { target } => {
+                window_rect(target.find()?, origin).into_iter().collect()
+            }
+            MaskTarget::Color {
+                rgba,
+                tolerance,
+                padding,
+            } => find_all_colors(image, None, *rgba, *tolerance)
+                .into_iter()
+                .filter_map(|cluster| grow(cluster.bounds, *padding, image))
+                .collect(),
+            MaskTarget::Template {
+                path,
+                tolerance,
+                padding,
+            } => {
+                let template = image::open(path)
+                    .map_err(|e| format!("Failed to open mask template {}: {}", path.display(), e))?
+                    .to_rgba8();
+                find_template(image, &template, *tolerance)
+                    .into_iter()
+                    .filter_map(|rect| grow(rect, *padding, image))
+                    .collect()
+            }
+        };
+        areas.extend(
+            rects
+                .into_iter()
+                .filter_map(|rect| rect.clamp_to(image.width(), image.height()))
+                .map(|rect| (rect, mask.style)),
+        );
+    }
+    Ok(areas)
+}
+
+fn to_image(rect: Rect, origin: Point) -> Rect {
+    Rect {
+        x: rect.x - origin.x,
+        y: rect.y - origin.y,
+        ..rect
+    }
+}
+
+/// Image-relative frame of `window`, if it is on the screen.
+fn window_rect(window: Option<WindowInfo>, origin: Point) -> Option<Rect> {
+    window
+        .filter(|window| window.visible && !window.minimized)
+        .map(|window| to_image(window.bounds, origin))
+}
+
+/// `rect` with `padding` added on every side, clipped to `image`.
+fn grow(rect: Rect, padding: u32, image: &RgbaImage) -> Option<Rect> {
+    let padding = padding as i64;
+    let x0 = (rect.x as i64 - padding).max(0);
+    let y0 = (rect.y as i64 - padding).max(0);
+    let x1 = (rect.x as i64 + rect.width as i64 + padding).min(image.width() as i64);
+    let y1 = (rect.y as i64 + rect.height as i64 + padding).min(image.height() as i64);
+    (x0 < x1 && y0 < y1).then(|| Rect {
+        x: x0 as i32,
+        y: y0 as i32,
+        width: (x1 - x0) as u32,
+        height: (y1 - y0) as u32,
+    })
+}
+
+/// Masks `rect`, which must already lie within the image.
+pub fn apply(image: &mut RgbaImage, rect: Rect, style: MaskStyle) {
+    let (x, y) = (rect.x as u32, rect.y as u32);
+    match style {
+        MaskStyle::Blackout => {
+            for py in y..y + rect.height {
+                for px in x..x + rect.width {
+                    image.put_pixel(px, py, Rgba([0, 0, 0, 255]));
+                }
+            }
+        }
+        MaskStyle::Blur => {
+            // A plain blur of large text can still be read, so average into coarse blocks
+            // first and only blur to soften the block edges
+            const BLOCK: u32 = 12;
+            let mut area = imageops::crop_imm(image, x, y, rect.width, rect.height).to_image();
+            for by in (0..rect.height).step_by(BLOCK as usize) {
+                for bx in (0..rect.width).step_by(BLOCK as usize) {
+                    let (bw, bh) = (BLOCK.min(rect.width - bx), BLOCK.min(rect.height - by));
+                    let mut sum = [0u64; 4];
+                    for py in by..by + bh {
+                        for px in bx..bx + bw {
+                            for (total, channel) in sum.iter_mut().zip(area.get_pixel(px, py).0) {
+                                *total += channel as u64;
+                            }
+                        }
+                    }
+                    let count = (bw * bh) as u64;
+                    let average = Rgba(sum.map(|total| (total / count) as u8));
+                    for py in by..by + bh {
+                        for px in bx..bx + bw {
+                            area.put_pixel(px, py, average);
+                        }
+                    }
+                }
+            }
+            let blurred = imageops::fast_blur(&area, BLOCK as f32 / 2.0);
+            imageops::replace(image, &blurred, x as i64, y as i64);
+        }
+    }
+}
+
+/// Places where `template` appears in `image`, in scan order. Positions inside an earlier
+/// match are skipped so one occurrence is reported once.
+pub(crate) fn find_template(image: &RgbaImage, template: &RgbaImage, tolerance: u8) -> Vec<Rect> {
+    let (width, height) = template.dimensions();
+    let mut found: Vec<Rect> = Vec::new();
+    if width == 0 || height == 0 || width > image.width() || height > image.height() {
+        return found;
+    }
+    let budget = tolerance as u64 * 3 * (width * height) as u64;
+
+    for y in 0..=image.height() - height {
+        for x in 0..=image.width() - width {
+            let at = Point {
+                x: x as i32,
+                y: y as i32,
+            };
+            if found.iter().any(|rect| rect.contains(&at)) {
+                continue;
+            }
+            if template_matches(image, template, x, y, budget) {
+                found.push(Rect {
+                    x: at.x,
+                    y: at.y,
+                    width,
+                    height,
+                });
+            }
+        }
+    }
+    found
+}
+
+fn template_matches(image: &RgbaImage, template: &RgbaImage, x: u32, y: u32, budget: u64) -> bool {
+    let mut difference = 0u64;
+    for (tx, ty, expected) in template.enumerate_pixels() {
+        let actual = image.get_pixel(x + tx, y + ty);
+        difference += (0..3)
+            .map(|c| expected.0[c].abs_diff(actual.0[c]) as u64)
+            .sum::<u64>();
+        // Most candidate positions fail within the first few pixels
+        if difference > budget {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::window::WindowMatcher;
+
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+    fn form() -> RgbaImage {
+        // White page with a striped "password" field at (20, 10) and another at (20, 40)
+        let mut image = RgbaImage::from_pixel(80, 60, WHITE);
+        for top in [10, 40] {
+            for y in top..top + 8 {
+                for x in 20..44 {
+                    let shade = if x % 3 == 0 { 30 } else { 200 };
+                    image.put_pixel(x, y, Rgba([shade, shade, 230, 255]));
+                }
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn rect_mask_uses_screen_coordinates() {
+        let mut image = form();
+        let masks = [Mask {
+            target: MaskTarget::Rect {
+                rect: Rect {
+                    x: 110,
+                    y: 205,
+                    width: 10,
+                    height: 10,
+                },
+            },
+            style: MaskStyle::Blackout,
+        }];
+        assert_eq!(redact(&mut image, Point { x: 100, y: 200 }, &masks), Ok(1));
+        assert_eq!(image.get_pixel(10, 5), &BLACK);
+        assert_eq!(image.get_pixel(19, 14), &BLACK);
+        assert_eq!(image.get_pixel(20, 5), &WHITE);
+    }
+
+    #[test]
+    fn blur_hides_detail_inside_the_area_only() {
+        let mut image = form();
+        let field = Rect {
+            x: 20,
+            y: 10,
+            width: 24,
+            height: 8,
+        };
+        apply(&mut image, field, MaskStyle::Blur);
+        let row: Vec<u8> = (20..44).map(|x| image.get_pixel(x, 13).0[0]).collect();
+        let spread = row.iter().max().unwrap() - row.iter().min().unwrap();
+        assert!(spread < 60, "stripes still visible: {:?}", row);
+        assert_eq!(image.get_pixel(19, 13), &WHITE);
+        assert_eq!(image.get_pixel(30, 9), &WHITE);
+    }
+
+    #[test]
+    fn color_mask_covers_padded_clusters() {
+        let mut image = RgbaImage::from_pixel(40, 40, WHITE);
+        for y in 10..14 {
+            for x in 10..14 {
+                image.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+            }
+        }
+        let masks = [Mask {
+            target: MaskTarget::Color {
+                rgba: [255, 0, 0, 255],
+                tolerance: Tolerance::PerChannel(10),
+                padding: 2,
+            },
+            style: MaskStyle::Blackout,
+        }];
+        redact(&mut image, Point { x: 0, y: 0 }, &masks).unwrap();
+        assert_eq!(image.get_pixel(8, 8), &BLACK);
+        assert_eq!(image.get_pixel(15, 15), &BLACK);
+        assert_eq!(image.get_pixel(7, 7), &WHITE);
+    }
+
+    #[test]
+    fn huge_padding_covers_the_image_without_overflow() {
+        let mut image = RgbaImage::from_pixel(40, 40, WHITE);
+        image.put_pixel(39, 0, Rgba([255, 0, 0, 255]));
+        let masks = [Mask {
+            target: MaskTarget::Color {
+                rgba: [255, 0, 0, 255],
+                tolerance: Tolerance::PerChannel(10),
+                padding: u32::MAX,
+            },
+            style: MaskStyle::Blackout,
+        }];
+        let areas = resolve(&image, Point { x: 0, y: 0 }, &masks).unwrap();
+        assert_eq!(
+            areas,
+            [(
+                Rect {
+                    x: 0,
+                    y: 0,
+                    width: 40,
+                    height: 40
+                },
+                MaskStyle::Blackout
+            )]
+        );
+        redact(&mut image, Point { x: 0, y: 0 }, &masks).unwrap();
+        assert!(image.pixels().all(|pixel| *pixel == BLACK));
+    }
+
+    #[test]
+    fn template_mask_finds_every_occurrence() {
+        let image = form();
+        let template = imageops::crop_imm(&image, 20, 10, 24, 8).to_image();
+        let path = std::env::temp_dir().join(format!("autoanchor-mask-{}.png", std::process::id()));
+        template.save(&path).unwrap();
+
+        let masks = [Mask {
+            target: MaskTarget::Template {
+                path: path.clone(),
+                tolerance: 8,
+                padding: 0,
+            },
+            style: MaskStyle::Blackout,
+        }];
+        let areas = resolve(&image, Point { x: 0, y: 0 }, &masks).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let rects: Vec<Rect> = areas.into_iter().map(|(rect, _)| rect).collect();
+        assert_eq!(
+            rects,
+            [
+                Rect {
+                    x: 20,
+                    y: 10,
+                    width: 24,
+                    height: 8
+                },
+                Rect {
+                    x: 20,
+                    y: 40,
+                    width: 24,
+                    height: 8
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unresolvable_mask_fails_the_capture() {
+        let mut image = form();
+        let masks = [Mask {
+            target: MaskTarget::Template {
+                path: PathBuf::from("/nonexistent/field.png"),
+                tolerance: 8,
+                padding: 0,
+            },
+            style: MaskStyle::Blur,
+        }];
+        assert!(redact(&mut image, Point { x: 0, y: 0 }, &masks).is_err());
+    }
+
+    #[test]
+    fn masks_parse_from_json() {
+        let masks: Vec<Mask> = serde_json::from_str(
+            r#"[{"kind": "rect", "rect": {"x": 0, "y": 0, "width": 5, "height": 5}},
+                {"kind": "color", "rgba": [1, 2, 3, 255], "tolerance": {"delta_e": 4.0}, "style": "blur"}]"#,
+        )
+        .unwrap();
+        assert_eq!(masks[0].style, MaskStyle::Blackout);
+        assert_eq!(masks[1].style, MaskStyle::Blur);
+        assert!(matches!(
+            masks[1].target,
+            MaskTarget::Color { padding: 0, .. }
+        ));
+
+        let masks: Vec<Mask> = serde_json::from_str(
+            r#"[{"kind": "window", "target": {"title": "KeePass"}},
+                {"kind": "window", "target": 42, "style": "blur"}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            masks[0].target,
+            MaskTarget::Window {
+                target: WindowTarget::Matcher(WindowMatcher::title("KeePass"))
+            }
+        );
+        assert_eq!(
+            masks[1].target,
+            MaskTarget::Window {
+                target: WindowTarget::Id(42)
+            }
+        );
+    }
+
+    #[test]
+    fn window_mask_covers_the_window_frame_while_it_is_shown() {
+        let bounds = Rect {
+            x: 120,
+            y: 210,
+            width: 30,
+            height: 20,
+        };
+        let mut window = WindowInfo {
+            id: 7,
+            title: "Database.kdbx - KeePass".to_string(),
+            class: "KeePass".to_string(),
+            pid: Some(300),
+            process_name: None,
+            bounds,
+            client_area: bounds,
+            visible: true,
+            minimized: false,
+            maximized: false,
+            focused: false,
+            z_order: 3,
+        };
+        let origin = Point { x: 100, y: 200 };
+        assert_eq!(
+            window_rect(Some(window.clone()), origin),
+            Some(Rect {
+                x: 20,
+                y: 10,
+                width: 30,
+                height: 20
+            })
+        );
+
+        window.minimized = true;
+        assert_eq!(window_rect(Some(window), origin), None);
+        assert_eq!(window_rect(None, origin), None);
+    }
+}
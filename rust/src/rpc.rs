@@ -0,0 +1,237 @@
+//! JSON-RPC 2.0 framing for long-running servers: one request or batch per line in, one
+//! response per line out. What the methods do is up to the caller's handler.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// An automation call ran and failed; `message` says why.
+pub const AUTOMATION_FAILED: i64 = -32000;
+/// See [`crate::AutomationError::FailSafeTriggered`].
+pub const FAIL_SAFE_TRIGGERED: i64 = -32001;
+/// See [`crate::AutomationError::UserActivity`].
+pub const USER_ACTIVITY: i64 = -32002;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    /// The error for a failed automation call, with a code of its own for the failures
+    /// callers are likely to handle specially.
+    pub fn automation(message: String) -> Self {
+        let code = if crate::AutomationError::is_fail_safe(&message) {
+            FAIL_SAFE_TRIGGERED
+        } else if crate::AutomationError::is_user_activity(&message) {
+            USER_ACTIVITY
+        } else {
+            AUTOMATION_FAILED
+        };
+        RpcError::new(code, message)
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+/// Calls a method by name with its params (`null` when the request has none).
+pub type Handler<'a> = dyn FnMut(&str, Value) -> Result<Value, RpcError> + 'a;
+
+fn response(id: Value, outcome: Result<Value, RpcError>) -> Value {
+    match outcome {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    }
+}
+
+/// Handles one request object, returning its response; notifications (requests
+/// without an id) get none.
+fn handle_request(request: Value, handler: &mut Handler) -> Option<Value> {
+    let Value::Object(mut request) = request else {
+        return Some(response(
+            Value::Null,
+            Err(RpcError::new(INVALID_REQUEST, "Request must be an object")),
+        ));
+    };
+    let id = request.remove("id");
+    if !matches!(
+        id,
+        None | Some(Value::Null) | Some(Value::String(_)) | Some(Value::Number(_))
+    ) {
+        return Some(response(
+            Value::Null,
+            Err(RpcError::new(
+                INVALID_REQUEST,
+                "Request id must be a string, number or null",
+            )),
+        ));
+    }
+    let checked = if request.get("jsonrpc") != Some(&json!("2.0")) {
+        Err(RpcError::new(
+            INVALID_REQUEST,
+            "Only JSON-RPC 2.0 is supported",
+        ))
+    } else {
+        match (request.remove("method"), request.remove("params")) {
+            (Some(Value::String(method)), None) => Ok((method, Value::Null)),
+            (Some(Value::String(method)), Some(params @ (Value::Array(_) | Value::Object(_)))) => {
+                Ok((method, params))
+            }
+            (Some(Value::String(_)), Some(_)) => Err(RpcError::new(
+                INVALID_REQUEST,
+                "Params must be an array or an object",
+            )),
+            _ => Err(RpcError::new(INVALID_REQUEST, "Method must be a string")),
+        }
+    };
+    let outcome = checked.and_then(|(method, params)| handler(&method, params));
+    id.map(|id| response(id, outcome))
+}
+
+/// Handles one line of input, a request or a batch of them, returning the response
+/// to write back, if any.
+pub fn handle_message(text: &str, handler: &mut Handler) -> Option<Value> {
+    match serde_json::from_str(text) {
+        Err(e) => Some(response(
+            Value::Null,
+            Err(RpcError::new(PARSE_ERROR, format!("Invalid JSON: {}", e))),
+        )),
+        Ok(Value::Array(batch)) if batch.is_empty() => Some(response(
+            Value::Null,
+            Err(RpcError::new(INVALID_REQUEST, "Empty batch")),
+        )),
+        Ok(Value::Array(batch)) => {
+            let responses: Vec<Value> = batch
+                .into_iter()
+                .filter_map(|request| handle_request(request, handler))
+                .collect();
+            (!responses.is_empty()).then_some(Value::Array(responses))
+        }
+        Ok(request) => handle_request(request, handler),
+    }
+}
+
+/// Serves newline-delimited JSON-RPC until `input` ends. Requests run one at a time in
+/// the order they arrive, so calls from concurrent clients never interleave their
+/// input; each response is written and flushed as a single line.
+pub fn serve<R: BufRead, W: Write>(
+    input: R,
+    mut output: W,
+    handler: &mut Handler,
+) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle_message(&line, handler) {
+            writeln!(output, "{}", response)?;
+            output.flush()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn echo(method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "echo" => Ok(params),
+            "fail" => Err(RpcError::automation(
+                "Fail-safe triggered: cursor at (0, 0) in the top-left screen corner".to_string(),
+            )),
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method: {}", method),
+            )),
+        }
+    }
+
+    fn reply(text: &str) -> Option<Value> {
+        handle_message(text, &mut echo)
+    }
+
+    #[test]
+    fn answers_requests_by_id() {
+        assert_eq!(
+            reply(r#"{"jsonrpc":"2.0","id":7,"method":"echo","params":{"x":1}}"#),
+            Some(json!({"jsonrpc":"2.0","id":7,"result":{"x":1}}))
+        );
+        assert_eq!(
+            reply(r#"{"jsonrpc":"2.0","id":"a","method":"nope"}"#).unwrap()["error"]["code"],
+            METHOD_NOT_FOUND
+        );
+        assert_eq!(
+            reply(r#"{"jsonrpc":"2.0","id":1,"method":"fail"}"#).unwrap()["error"]["code"],
+            FAIL_SAFE_TRIGGERED
+        );
+        // Notifications are run but not answered
+        assert_eq!(reply(r#"{"jsonrpc":"2.0","method":"echo"}"#), None);
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        let code = |text| reply(text).unwrap()["error"]["code"].as_i64().unwrap();
+        assert_eq!(code("{"), PARSE_ERROR);
+        assert_eq!(code("[]"), INVALID_REQUEST);
+        assert_eq!(code(r#"{"id":1,"method":"echo"}"#), INVALID_REQUEST);
+        assert_eq!(
+            code(r#"{"jsonrpc":"2.0","id":1,"method":5}"#),
+            INVALID_REQUEST
+        );
+        assert_eq!(
+            code(r#"{"jsonrpc":"2.0","id":1,"method":"echo","params":3}"#),
+            INVALID_REQUEST
+        );
+        assert_eq!(
+            code(r#"{"jsonrpc":"2.0","id":{},"method":"echo"}"#),
+            INVALID_REQUEST
+        );
+    }
+
+    #[test]
+    fn serves_lines_and_batches_in_order() {
+        let input = concat!(
+            r#"[{"jsonrpc":"2.0","id":1,"method":"echo","params":[1]},"#,
+            r#"{"jsonrpc":"2.0","method":"echo"},"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"echo","params":[2]}]"#,
+            "\n\n",
+            r#"{"jsonrpc":"2.0","id":3,"method":"echo","params":[3]}"#,
+            "\n"
+        );
+        let mut output = Vec::new();
+        serve(input.as_bytes(), &mut output, &mut echo).unwrap();
+        let lines: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0][0]["id"], 1);
+        assert_eq!(lines[0][1]["result"], json!([2]));
+        assert_eq!(lines[1]["id"], 3);
+    }
+}
@@ -0,0 +1,52 @@
+//! Screen capture on X11, read from the root window.
+
+use crate::xserver::{to_rgba, XConnection};
+use crate::Rect;
+use image::RgbaImage;
+use std::os::raw::c_uint;
+use x11::xlib;
+
+pub fn capture_image(region: Option<Rect>) -> Result<RgbaImage, String> {
+    let conn = XConnection::open()?;
+    let screen = unsafe {
+        let number = xlib::XDefaultScreen(conn.display);
+        Rect {
+            x: 0,
+            y: 0,
+            width: xlib::XDisplayWidth(conn.display, number) as u32,
+            height: xlib::XDisplayHeight(conn.display, number) as u32,
+        }
+    };
+    let area = match region {
+        Some(rect) => {
+            // The server refuses reads that leave the root window
+            if rect.intersect(&screen) != Some(rect) {
+                return Err(format!(
+                    "Region {}x{} at ({}, {}) extends beyond the {}x{} screen",
+                    rect.width, rect.height, rect.x, rect.y, screen.width, screen.height
+                ));
+            }
+            rect
+        }
+        None => screen,
+    };
+
+    unsafe {
+        let image = xlib::XGetImage(
+            conn.display,
+            conn.root,
+            area.x,
+            area.y,
+            area.width as c_uint,
+            area.height as c_uint,
+            xlib::XAllPlanes(),
+            xlib::ZPixmap,
+        );
+        if image.is_null() {
+            return Err("Failed to read the screen".to_string());
+        }
+        let rgba = to_rgba(image, area.width, area.height);
+        xlib::XDestroyImage(image);
+        rgba
+    }
+}
@@ -1,6 +1,7 @@
-use super::super::Point;
+use super::super::{CaptureOptions, Point, Rect};
 use winapi::um::winuser::*;
-use screenshots::{Screen, Compression};
+use screenshots::Screen;
+use image::RgbaImage;
 
 pub fn get_screen_size() -> Result<Point, String> {
     unsafe {
@@ -14,53 +15,59 @@ pub fn get_screen_size() -> Result<Point, String> {
     }
 }
 
-pub fn take_screenshot(active_window: bool) -> Result<Vec<u8>, String> {
-    // If active_window is true, use the Windows API to get the foreground window rectangle
-    // and capture that area. Otherwise capture the primary screen.
-    let image = if active_window {
-        unsafe {
-            use winapi::um::winuser::{GetForegroundWindow, GetWindowRect};
-            use winapi::shared::windef::RECT;
+pub fn get_active_window_rect() -> Result<Rect, String> {
+    unsafe {
+        use winapi::um::winuser::{GetForegroundWindow, GetWindowRect};
+        use winapi::shared::windef::RECT;
 
-            let hwnd = GetForegroundWindow();
-            if hwnd.is_null() {
-                return Err("No foreground window found".to_string());
-            }
+        let hwnd = GetForegroundWindow();
+        if hwnd.is_null() {
+            return Err("No foreground window found".to_string());
+        }
 
-            let mut rect: RECT = std::mem::zeroed();
-            if GetWindowRect(hwnd, &mut rect) == 0 {
-                return Err("Failed to get foreground window rect".to_string());
-            }
+        let mut rect: RECT = std::mem::zeroed();
+        if GetWindowRect(hwnd, &mut rect) == 0 {
+            return Err("Failed to get foreground window rect".to_string());
+        }
 
-            let left = rect.left;
-            let top = rect.top;
-            let width = (rect.right - rect.left) as u32;
-            let height = (rect.bottom - rect.top) as u32;
+        Ok(Rect {
+            x: rect.left,
+            y: rect.top,
+            width: (rect.right - rect.left) as u32,
+            height: (rect.bottom - rect.top) as u32,
+        })
+    }
+}
 
-            // Find the display that contains the top-left corner of the window
-            let screen = Screen::from_point(left, top).map_err(|e| e.to_string())?;
-            // DisplayInfo::from_point returns display origin; capture_area expects coords relative to display
-            let display_x = screen.display_info.x;
-            let display_y = screen.display_info.y;
-            let rel_x = left - display_x;
-            let rel_y = top - display_y;
+pub fn take_screenshot(active_window: bool) -> Result<Vec<u8>, String> {
+    // If active_window is true, capture the foreground window rectangle.
+    // Otherwise capture the primary screen.
+    super::super::take_screenshot(&CaptureOptions {
+        active_window,
+        ..CaptureOptions::default()
+    })
+}
 
+pub fn capture_image(region: Option<Rect>) -> Result<RgbaImage, String> {
+    let image = match region {
+        Some(rect) => {
+            let screen = Screen::from_point(rect.x, rect.y).map_err(|e| e.to_string())?;
+            // capture_area expects coords relative to the display containing the region
+            let rel_x = rect.x - screen.display_info.x;
+            let rel_y = rect.y - screen.display_info.y;
             screen
-                .capture_area(rel_x, rel_y, width, height)
+                .capture_area(rel_x, rel_y, rect.width, rect.height)
                 .map_err(|e| e.to_string())?
         }
-    } else {
-        // Map any screenshots errors into Strings and return the raw byte buffer
-        let screen = Screen::from_point(0, 0).map_err(|e| e.to_string())?;
-        screen.capture().map_err(|e| e.to_string())?
+        None => {
+            let screen = Screen::from_point(0, 0).map_err(|e| e.to_string())?;
+            screen.capture().map_err(|e| e.to_string())?
+        }
     };
-    // The `Image` type exposes `From<Image> for Vec<u8>` which gives the RGBA buffer.
-    // Encode to PNG bytes so consumers receive a PNG file buffer
-    let png = image
-        .to_png(Some(Compression::Default))
-        .map_err(|e| e.to_string())?;
 
-    Ok(png)
+    let (width, height) = (image.width(), image.height());
+    RgbaImage::from_raw(width, height, image.into())
+        .ok_or_else(|| "Captured buffer does not match its dimensions".to_string())
 }
 
 // Unit tests moved to an integration test file (`tests/take_screenshot_integration.rs`)
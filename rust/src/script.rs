@@ -0,0 +1,419 @@
+//! Running an ordered list of actions in one process, for flows too long to spell as
+//! separate command-line calls.
+//!
+//! A script is JSON: an array of steps, or an object with a `steps` array.
+//!
+//! ```json
+//! [
+//!   {"action": "click", "x": 400, "y": 300},
+//!   {"action": "type", "text": "hello"},
+//!   {"action": "press", "key": "enter", "modifiers": ["ctrl"]},
+//!   {"action": "wait", "ms": 500},
+//!   {"action": "assert", "condition": {"kind": "window", "title": "Saved"}},
+//!   {"action": "screenshot", "path": "after.png"}
+//! ]
+//! ```
+
+use crate::color::{find_color, Tolerance};
+use crate::redact::Mask;
+use crate::window::{self, WindowMatcher};
+use crate::{AutomationResult, CaptureOptions, Rect, TypeMode};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Step {
+    Move {
+        x: i32,
+        y: i32,
+    },
+    /// Clicks at (`x`, `y`), or where the cursor is when both are left out.
+    Click {
+        x: Option<i32>,
+        y: Option<i32>,
+        #[serde(default = "default_button")]
+        button: String,
+    },
+    Type {
+        text: String,
+        #[serde(default)]
+        mode: TypeMode,
+        delay_ms: Option<u64>,
+    },
+    Press {
+        key: String,
+        #[serde(default)]
+        modifiers: Vec<String>,
+    },
+    Wait {
+        ms: u64,
+    },
+    /// Saves a PNG to `path`, or returns it base64-encoded without one.
+    Screenshot {
+        path: Option<PathBuf>,
+        region: Option<Rect>,
+        #[serde(default)]
+        active_window: bool,
+        #[serde(default)]
+        cursor: bool,
+    },
+    /// Fails unless `condition` holds, or with `negate` unless it does not.
+    Assert {
+        condition: Condition,
+        #[serde(default)]
+        negate: bool,
+    },
+}
+
+fn default_button() -> String {
+    "left".to_string()
+}
+
+impl Step {
+    /// The step's `action` as written in scripts.
+    pub fn action(&self) -> &'static str {
+        match self {
+            Step::Move { .. } => "move",
+            Step::Click { .. } => "click",
+            Step::Type { .. } => "type",
+            Step::Press { .. } => "press",
+            Step::Wait { .. } => "wait",
+            Step::Screenshot { .. } => "screenshot",
+            Step::Assert { .. } => "assert",
+        }
+    }
+}
+
+/// What an `assert` step checks, at the moment it runs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Condition {
+    /// Some top-level window matches.
+    Window(WindowMatcher),
+    /// The foreground window matches.
+    WindowFocused(WindowMatcher),
+    /// The PNG at `template` appears on the screen, or within `region`.
+    Image {
+        template: PathBuf,
+        region: Option<Rect>,
+        #[serde(default = "default_template_tolerance")]
+        tolerance: u8,
+    },
+    /// The screen pixel at (`x`, `y`) is `rgba`, within `tolerance`.
+    Pixel {
+        x: i32,
+        y: i32,
+        rgba: [u8; 4],
+        #[serde(default = "default_pixel_tolerance")]
+        tolerance: Tolerance,
+    },
+    CursorAt {
+        x: i32,
+        y: i32,
+    },
+    ProcessRunning {
+        pid: u32,
+    },
+}
+
+fn default_template_tolerance() -> u8 {
+    8
+}
+
+fn default_pixel_tolerance() -> Tolerance {
+    Tolerance::PerChannel(0)
+}
+
+impl Condition {
+    pub fn holds(&self) -> Result<bool, String> {
+        match self {
+            Condition::Window(matcher) => Ok(window::find_window(matcher)?.is_some()),
+            Condition::WindowFocused(matcher) => Ok(match window::active_window()? {
+                Some(active) => !matcher.filter(vec![active])?.is_empty(),
+                None => false,
+            }),
+            Condition::Image {
+                template,
+                region,
+                tolerance,
+            } => Ok(!crate::find_image(template, *region, *tolerance)?.is_empty()),
+            Condition::Pixel {
+                x,
+                y,
+                rgba,
+                tolerance,
+            } => {
+                let pixel = crate::capture_screen(Some(Rect {
+                    x: *x,
+                    y: *y,
+                    width: 1,
+                    height: 1,
+                }))?;
+                Ok(find_color(&pixel, None, *rgba, *tolerance).is_some())
+            }
+            Condition::CursorAt { x, y } => {
+                let position = crate::get_cursor_position()?;
+                Ok(position.x == *x && position.y == *y)
+            }
+            Condition::ProcessRunning { pid } => crate::process::is_running(*pid),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Window(matcher) => write!(f, "a window matches {}", matcher),
+            Condition::WindowFocused(matcher) => {
+                write!(f, "the focused window matches {}", matcher)
+            }
+            Condition::Image { template, .. } => {
+                write!(f, "{} is on the screen", template.display())
+            }
+            Condition::Pixel { x, y, rgba, .. } => {
+                write!(f, "the pixel at ({}, {}) is {:?}", x, y, rgba)
+            }
+            Condition::CursorAt { x, y } => write!(f, "the cursor is at ({}, {})", x, y),
+            Condition::ProcessRunning { pid } => write!(f, "process {} is running", pid),
+        }
+    }
+}
+
+/// Reads a script: a JSON array of steps, or an object with a `steps` array. Errors name
+/// the step at fault.
+pub fn parse(json: &str) -> Result<Vec<Step>, String> {
+    let script: Value = serde_json::from_str(json).map_err(|e| format!("Invalid script: {}", e))?;
+    let steps = match script {
+        Value::Array(steps) => steps,
+        Value::Object(mut script) => match script.remove("steps") {
+            Some(Value::Array(steps)) => steps,
+            _ => return Err("Invalid script: expected a \"steps\" array".to_string()),
+        },
+        _ => return Err("Invalid script: expected an array of steps".to_string()),
+    };
+    steps
+        .into_iter()
+        .enumerate()
+        .map(|(index, step)| {
+            serde_json::from_value(step).map_err(|e| format!("Invalid step {}: {}", index, e))
+        })
+        .collect()
+}
+
+/// Settings for [`run`].
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Run the remaining steps after one fails.
+    pub keep_going: bool,
+    /// Applied to every screenshot step.
+    pub masks: Vec<Mask>,
+}
+
+/// How one step went.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepResult {
+    /// Position in the script, from 0.
+    pub index: usize,
+    pub action: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+/// Runs one step as if on its own.
+pub fn run_step(step: &Step, options: &RunOptions) -> Result<AutomationResult, String> {
+    match step {
+        Step::Move { x, y } => crate::move_cursor(*x, *y),
+        Step::Click { x, y, button } => {
+            if x.is_some() != y.is_some() {
+                return Err("Give both x and y, or neither".to_string());
+            }
+            crate::click(button, *x, *y)
+        }
+        Step::Type {
+            text,
+            mode,
+            delay_ms,
+        } => crate::type_text_with(text, *mode, *delay_ms),
+        Step::Press { key, modifiers } => {
+            crate::press_key(key, (!modifiers.is_empty()).then(|| modifiers.clone()))
+        }
+        Step::Wait { ms } => {
+            thread::sleep(Duration::from_millis(*ms));
+            Ok(AutomationResult {
+                success: true,
+                message: None,
+                data: None,
+            })
+        }
+        Step::Screenshot {
+            path,
+            region,
+            active_window,
+            cursor,
+        } => {
+            let png = crate::take_screenshot(&CaptureOptions {
+                active_window: *active_window,
+                region: *region,
+                draw_cursor: *cursor,
+                masks: options.masks.clone(),
+                ..CaptureOptions::default()
+            })?;
+            let data = match path {
+                Some(path) => {
+                    std::fs::write(path, &png)
+                        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+                    json!({ "path": path })
+                }
+                None => json!(general_purpose::STANDARD.encode(&png)),
+            };
+            Ok(AutomationResult {
+                success: true,
+                message: None,
+                data: Some(data),
+            })
+        }
+        Step::Assert { condition, negate } => {
+            if condition.holds()? == *negate {
+                let expected = if *negate { "not " } else { "" };
+                return Err(format!(
+                    "Assertion failed: expected {}that {}",
+                    expected, condition
+                ));
+            }
+            Ok(AutomationResult {
+                success: true,
+                message: None,
+                data: None,
+            })
+        }
+    }
+}
+
+/// Runs `steps` in order, stopping after the first that fails unless
+/// [`RunOptions::keep_going`] is set. Returns a result for every step that ran.
+pub fn run(steps: &[Step], options: &RunOptions) -> Vec<StepResult> {
+    let mut results = Vec::new();
+    for (index, step) in steps.iter().enumerate() {
+        let outcome = run_step(step, options).and_then(|result| {
+            if result.success {
+                Ok(result)
+            } else {
+                Err(result.message.unwrap_or_else(|| "Failed".to_string()))
+            }
+        });
+        let failed = outcome.is_err();
+        results.push(match outcome {
+            Ok(result) => StepResult {
+                index,
+                action: step.action().to_string(),
+                success: true,
+                message: result.message,
+                data: result.data,
+            },
+            Err(e) => StepResult {
+                index,
+                action: step.action().to_string(),
+                success: false,
+                message: Some(e),
+                data: None,
+            },
+        });
+        if failed && !options.keep_going {
+            break;
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_scripts() {
+        let steps = parse(
+            r#"{"steps": [
+                {"action": "click"},
+                {"action": "press", "key": "s", "modifiers": ["ctrl"]},
+                {"action": "assert", "condition": {"kind": "window", "title": "Saved"}, "negate": true}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            steps[0],
+            Step::Click {
+                x: None,
+                y: None,
+                button: "left".to_string()
+            }
+        );
+        assert_eq!(steps[1].action(), "press");
+        assert_eq!(
+            steps[2],
+            Step::Assert {
+                condition: Condition::Window(WindowMatcher::title("Saved")),
+                negate: true
+            }
+        );
+
+        assert_eq!(parse("[]"), Ok(Vec::new()));
+        let err = parse(r#"[{"action": "wait", "ms": 1}, {"action": "jump"}]"#).unwrap_err();
+        assert!(
+            err.starts_with("Invalid step 1: unknown variant `jump`"),
+            "{}",
+            err
+        );
+        let err = parse(r#"[{"action": "wait", "ms": 1, "seconds": 2}]"#).unwrap_err();
+        assert!(err.contains("unknown field `seconds`"), "{}", err);
+        assert!(parse(r#"{"actions": []}"#).is_err());
+    }
+
+    #[test]
+    fn stops_at_the_first_failure_unless_told_to_keep_going() {
+        let pid = std::process::id();
+        let steps = vec![
+            Step::Wait { ms: 0 },
+            Step::Assert {
+                condition: Condition::ProcessRunning { pid },
+                negate: true,
+            },
+            Step::Assert {
+                condition: Condition::ProcessRunning { pid },
+                negate: false,
+            },
+        ];
+
+        let results = run(&steps, &RunOptions::default());
+        assert_eq!(results.len(), 2);
+        assert!(results[0].success);
+        assert!(!results[1].success);
+        assert_eq!(
+            results[1].message.as_deref(),
+            Some(
+                format!(
+                    "Assertion failed: expected not that process {} is running",
+                    pid
+                )
+                .as_str()
+            )
+        );
+
+        let options = RunOptions {
+            keep_going: true,
+            ..RunOptions::default()
+        };
+        let results = run(&steps, &options);
+        assert_eq!(results.len(), 3);
+        assert!(results[2].success);
+        assert_eq!(results[2].index, 2);
+    }
+}
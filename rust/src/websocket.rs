@@ -0,0 +1,263 @@
+//! Server side of the WebSocket protocol (RFC 6455), enough for streaming JSON to local
+//! clients: the opening handshake, text frames, pings and closing.
+
+use base64::{engine::general_purpose, Engine as _};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+
+/// Appended to the client's key to prove the server speaks WebSocket.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Largest message accepted from a client; they only send small control messages.
+const MAX_MESSAGE_LEN: u64 = 1 << 20;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// An open connection after the handshake. Clones share the connection, so one thread
+/// can send while another receives.
+pub struct WebSocket {
+    reader: TcpStream,
+    writer: Arc<Mutex<TcpStream>>,
+}
+
+impl WebSocket {
+    /// Wraps a connection whose handshake response has already been sent.
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        Ok(WebSocket {
+            writer: Arc::new(Mutex::new(stream.try_clone()?)),
+            reader: stream,
+        })
+    }
+
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(WebSocket {
+            reader: self.reader.try_clone()?,
+            writer: self.writer.clone(),
+        })
+    }
+
+    pub fn send_text(&self, text: &str) -> io::Result<()> {
+        self.send_frame(OPCODE_TEXT, text.as_bytes())
+    }
+
+    /// Starts the closing handshake; the client's reply ends [`WebSocket::receive`].
+    pub fn close(&self) -> io::Result<()> {
+        self.send_frame(OPCODE_CLOSE, &[])
+    }
+
+    fn send_frame(&self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let frame = encode_frame(opcode, payload);
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        writer.write_all(&frame)?;
+        writer.flush()
+    }
+
+    /// The next message from the client, answering pings on the way; `None` once the
+    /// client has closed the connection.
+    pub fn receive(&mut self) -> io::Result<Option<Message>> {
+        let mut message: Option<(u8, Vec<u8>)> = None;
+        loop {
+            let Some((fin, opcode, payload)) = read_frame(&mut self.reader)? else {
+                return Ok(None);
+            };
+            match opcode {
+                OPCODE_PING => self.send_frame(OPCODE_PONG, &payload)?,
+                OPCODE_PONG => {}
+                OPCODE_CLOSE => {
+                    // Echo the close; the client may already be gone
+                    let _ = self.send_frame(OPCODE_CLOSE, &payload[..payload.len().min(2)]);
+                    return Ok(None);
+                }
+                OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => {
+                    // Only continuations may follow the first frame of a message
+                    if message.is_some() != (opcode == OPCODE_CONTINUATION) {
+                        return Err(invalid("Unexpected WebSocket frame order"));
+                    }
+                    let (_, data) = message.get_or_insert((opcode, Vec::new()));
+                    data.extend_from_slice(&payload);
+                    if data.len() as u64 > MAX_MESSAGE_LEN {
+                        return Err(invalid("WebSocket message too large"));
+                    }
+                    if fin {
+                        let (kind, data) = message.take().unwrap();
+                        return Ok(Some(if kind == OPCODE_TEXT {
+                            Message::Text(
+                                String::from_utf8(data)
+                                    .map_err(|_| invalid("WebSocket text is not UTF-8"))?,
+                            )
+                        } else {
+                            Message::Binary(data)
+                        }));
+                    }
+                }
+                _ => return Err(invalid("Unknown WebSocket opcode")),
+            }
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The `Sec-WebSocket-Accept` value answering a client's `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    let digest = sha1(format!("{}{}", key.trim(), HANDSHAKE_GUID).as_bytes());
+    general_purpose::STANDARD.encode(digest)
+}
+
+/// Server frames go out unmasked and unfragmented.
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Reads one client frame as (final, opcode, unmasked payload); `None` at end of stream.
+fn read_frame(reader: &mut impl Read) -> io::Result<Option<(bool, u8, Vec<u8>)>> {
+    let mut header = [0u8; 2];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let fin = header[0] & 0x80 != 0;
+    let opcode = header[0] & 0x0F;
+    let masked = header[1] & 0x80 != 0;
+    let len = match header[1] & 0x7F {
+        126 => {
+            let mut bytes = [0u8; 2];
+            reader.read_exact(&mut bytes)?;
+            u16::from_be_bytes(bytes) as u64
+        }
+        127 => {
+            let mut bytes = [0u8; 8];
+            reader.read_exact(&mut bytes)?;
+            u64::from_be_bytes(bytes)
+        }
+        len => len as u64,
+    };
+    if !masked {
+        return Err(invalid("Client WebSocket frames must be masked"));
+    }
+    if len > MAX_MESSAGE_LEN {
+        return Err(invalid("WebSocket message too large"));
+    }
+    let mut mask = [0u8; 4];
+    reader.read_exact(&mut mask)?;
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok(Some((fin, opcode, payload)))
+}
+
+/// SHA-1, which the handshake requires; not for anything security related.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, word) in digest.chunks_mut(4).zip(h) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_the_handshake() {
+        let hex: String = sha1(b"abc").iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, "a9993e364706816aba3e25717850c26c9cd0d89d");
+        // The example from RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        assert_eq!(encode_frame(OPCODE_TEXT, b"hi"), [0x81, 2, b'h', b'i']);
+        assert_eq!(
+            &encode_frame(OPCODE_TEXT, &[0; 300])[..4],
+            [0x81, 126, 1, 44]
+        );
+
+        // A masked "Hello" from RFC 6455
+        let frame = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        let (fin, opcode, payload) = read_frame(&mut &frame[..]).unwrap().unwrap();
+        assert!(fin);
+        assert_eq!(opcode, OPCODE_TEXT);
+        assert_eq!(payload, b"Hello");
+        assert!(read_frame(&mut &[][..]).unwrap().is_none());
+        // Unmasked client frames are refused
+        assert!(read_frame(&mut &[0x81, 0x01, b'x'][..]).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+use super::{WindowInfo, WindowOp};
+use crate::xserver::{to_rgba, XConnection};
+use crate::Rect;
+use image::RgbaImage;
+use std::os::raw::{c_int, c_long, c_uint};
+use std::time::Duration;
+use x11::xlib;
+
+/// Requests name their source; 2 is a pager or similar tool acting for the user, which
+/// window managers honour without applying focus-stealing prevention.
+const SOURCE_PAGER: c_long = 2;
+const NET_WM_STATE_REMOVE: c_long = 0;
+const NET_WM_STATE_ADD: c_long = 1;
+
+/// The server keeps redirected contents up to date itself.
+const COMPOSITE_REDIRECT_AUTOMATIC: c_int = 0;
+/// Time for a newly redirected window to repaint the parts that were covered.
+const REDIRECT_SETTLE: Duration = Duration::from_millis(100);
+
+// The x11 crate has no bindings for libXcomposite
+#[link(name = "Xcomposite")]
+extern "C" {
+    fn XCompositeQueryExtension(
+        display: *mut xlib::Display,
+        event_base: *mut c_int,
+        error_base: *mut c_int,
+    ) -> xlib::Bool;
+    fn XCompositeRedirectWindow(display: *mut xlib::Display, window: xlib::Window, update: c_int);
+    fn XCompositeUnredirectWindow(display: *mut xlib::Display, window: xlib::Window, update: c_int);
+    fn XCompositeNameWindowPixmap(
+        display: *mut xlib::Display,
+        window: xlib::Window,
+    ) -> xlib::Pixmap;
+}
+
+pub fn list_windows() -> Result<Vec<WindowInfo>, String> {
+    let conn = XConnection::open()?;
+    list(&conn)
+}
+
+pub(crate) fn list(conn: &XConnection) -> Result<Vec<WindowInfo>, String> {
+    // The stacking list is bottom-to-top; older window managers only publish the
+    // plain client list, which is in mapping order
+    let clients = conn
+        .longs(conn.root, "_NET_CLIENT_LIST_STACKING")
+        .or_else(|| conn.longs(conn.root, "_NET_CLIENT_LIST"))
+        .ok_or_else(|| {
+            "The window manager does not publish _NET_CLIENT_LIST (EWMH); is one running?"
+                .to_string()
+        })?;
+    let active = active_id(conn);
+
+    Ok(clients
+        .iter()
+        .rev()
+        .filter_map(|id| window_info(conn, *id, active))
+        .enumerate()
+        .map(|(z_order, info)| WindowInfo {
+            z_order: z_order as u32,
+            ..info
+        })
+        .collect())
+}
+
+pub fn active_window_id() -> Result<Option<u64>, String> {
+    Ok(active_id(&XConnection::open()?))
+}
+
+pub(crate) fn active_id(conn: &XConnection) -> Option<xlib::Window> {
+    conn.longs(conn.root, "_NET_ACTIVE_WINDOW")
+        .and_then(|ids| ids.first().copied())
+        .filter(|id| *id != 0)
+}
+
+/// Reads one client window, or `None` if it was destroyed meanwhile.
+pub(crate) fn window_info(
+    conn: &XConnection,
+    id: xlib::Window,
+    active: Option<xlib::Window>,
+) -> Option<WindowInfo> {
+    let mut attributes: xlib::XWindowAttributes = unsafe { std::mem::zeroed() };
+    if unsafe { xlib::XGetWindowAttributes(conn.display, id, &mut attributes) } == 0 {
+        return None;
+    }
+    let (mut x, mut y): (c_int, c_int) = (0, 0);
+    let mut child: xlib::Window = 0;
+    unsafe {
+        xlib::XTranslateCoordinates(
+            conn.display,
+            id,
+            conn.root,
+            0,
+            0,
+            &mut x,
+            &mut y,
+            &mut child,
+        );
+    }
+    let client_area = Rect {
+        x,
+        y,
+        width: attributes.width.max(0) as u32,
+        height: attributes.height.max(0) as u32,
+    };
+
+    // left, right, top, bottom
+    let extents = conn
+        .longs(id, "_NET_FRAME_EXTENTS")
+        .filter(|e| e.len() == 4)
+        .unwrap_or_else(|| vec![0; 4]);
+    let bounds = Rect {
+        x: x - extents[0] as i32,
+        y: y - extents[2] as i32,
+        width: client_area.width + (extents[0] + extents[1]) as u32,
+        height: client_area.height + (extents[2] + extents[3]) as u32,
+    };
+
+    let state = conn.longs(id, "_NET_WM_STATE").unwrap_or_default();
+    let has_state = |name: &str| state.contains(&conn.atom(name));
+    let minimized = has_state("_NET_WM_STATE_HIDDEN");
+    let maximized =
+        has_state("_NET_WM_STATE_MAXIMIZED_VERT") && has_state("_NET_WM_STATE_MAXIMIZED_HORZ");
+
+    let title = conn
+        .text(id, "_NET_WM_NAME")
+        .or_else(|| conn.text(id, "WM_NAME"))
+        .unwrap_or_default();
+    // WM_CLASS holds "instance\0class\0"
+    let class = conn
+        .text(id, "WM_CLASS")
+        .and_then(|value| value.split('\0').nth(1).map(str::to_string))
+        .unwrap_or_default();
+    let pid = conn
+        .longs(id, "_NET_WM_PID")
+        .and_then(|pids| pids.first().map(|pid| *pid as u32));
+
+    Some(WindowInfo {
+        id,
+        title,
+        class,
+        pid,
+        process_name: pid.and_then(process_name),
+        bounds,
+        client_area,
+        visible: attributes.map_state == xlib::IsViewable && !minimized,
+        minimized,
+        maximized,
+        focused: active == Some(id),
+        z_order: 0,
+    })
+}
+
+pub(crate) fn control(id: u64, op: WindowOp) -> Result<(), String> {
+    let conn = XConnection::open()?;
+    let info =
+        window_info(&conn, id, None).ok_or_else(|| format!("Window {} no longer exists", id))?;
+    let maximized = [
+        conn.atom("_NET_WM_STATE_MAXIMIZED_VERT") as c_long,
+        conn.atom("_NET_WM_STATE_MAXIMIZED_HORZ") as c_long,
+    ];
+    // _NET_MOVERESIZE_WINDOW flags: gravity, then bits 8-11 for which of x, y, width
+    // and height are given, then the source
+    let move_resize =
+        |fields: c_long| xlib::NorthWestGravity as c_long | fields << 8 | SOURCE_PAGER << 12;
+
+    match op {
+        WindowOp::Focus => {
+            if info.minimized {
+                unsafe { xlib::XMapWindow(conn.display, id) };
+            }
+            conn.send_client_message(id, "_NET_ACTIVE_WINDOW", [SOURCE_PAGER, 0, 0, 0, 0]);
+        }
+        WindowOp::Raise => {
+            // Detail 0 is Above; without a sibling that means the top of the stack
+            conn.send_client_message(id, "_NET_RESTACK_WINDOW", [SOURCE_PAGER, 0, 0, 0, 0]);
+        }
+        WindowOp::Move { x, y } => conn.send_client_message(
+            id,
+            "_NET_MOVERESIZE_WINDOW",
+            [move_resize(0b0011), x as c_long, y as c_long, 0, 0],
+        ),
+        WindowOp::Resize { width, height } => conn.send_client_message(
+            id,
+            "_NET_MOVERESIZE_WINDOW",
+            [move_resize(0b1100), 0, 0, width as c_long, height as c_long],
+        ),
+        WindowOp::Minimize => {
+            let screen = unsafe { xlib::XDefaultScreen(conn.display) };
+            if unsafe { xlib::XIconifyWindow(conn.display, id, screen) } == 0 {
+                return Err(format!("Failed to minimize window {}", id));
+            }
+        }
+        WindowOp::Maximize => conn.send_client_message(
+            id,
+            "_NET_WM_STATE",
+            [
+                NET_WM_STATE_ADD,
+                maximized[0],
+                maximized[1],
+                SOURCE_PAGER,
+                0,
+            ],
+        ),
+        WindowOp::Restore => {
+            if info.maximized {
+                conn.send_client_message(
+                    id,
+                    "_NET_WM_STATE",
+                    [
+                        NET_WM_STATE_REMOVE,
+                        maximized[0],
+                        maximized[1],
+                        SOURCE_PAGER,
+                        0,
+                    ],
+                );
+            }
+            if info.minimized {
+                unsafe { xlib::XMapWindow(conn.display, id) };
+            }
+        }
+        WindowOp::Close => {
+            // The window manager turns this into WM_DELETE_WINDOW, letting the app prompt
+            conn.send_client_message(id, "_NET_CLOSE_WINDOW", [0, SOURCE_PAGER, 0, 0, 0]);
+        }
+    }
+    unsafe { xlib::XFlush(conn.display) };
+    Ok(())
+}
+
+/// Reads the window's contents from its Composite backing pixmap, so windows in front of
+/// it do not show up.
+pub(crate) fn capture(id: u64, include_frame: bool) -> Result<RgbaImage, String> {
+    let conn = XConnection::open()?;
+    let info =
+        window_info(&conn, id, None).ok_or_else(|| format!("Window {} no longer exists", id))?;
+    if !info.visible {
+        return Err(format!(
+            "Window {} is not shown; restore it before capturing",
+            id
+        ));
+    }
+    let (mut event_base, mut error_base) = (0, 0);
+    if unsafe { XCompositeQueryExtension(conn.display, &mut event_base, &mut error_base) } == 0 {
+        return Err("The X server does not support the Composite extension".to_string());
+    }
+
+    // Reparenting window managers put the client inside a frame window, and only
+    // children of the root get their own pixmap
+    let toplevel = toplevel(&conn, id);
+    let mut attributes: xlib::XWindowAttributes = unsafe { std::mem::zeroed() };
+    if unsafe { xlib::XGetWindowAttributes(conn.display, toplevel, &mut attributes) } == 0 {
+        return Err(format!("Window {} no longer exists", id));
+    }
+    let (mut x, mut y): (c_int, c_int) = (0, 0);
+    let mut child: xlib::Window = 0;
+    unsafe {
+        xlib::XTranslateCoordinates(
+            conn.display,
+            toplevel,
+            conn.root,
+            0,
+            0,
+            &mut x,
+            &mut y,
+            &mut child,
+        );
+    }
+    let wanted = if include_frame {
+        info.bounds
+    } else {
+        info.client_area
+    };
+    let area = Rect {
+        x: wanted.x - x,
+        y: wanted.y - y,
+        ..wanted
+    }
+    .clamp_to(
+        attributes.width.max(0) as u32,
+        attributes.height.max(0) as u32,
+    )
+    .ok_or_else(|| format!("Window {} has nothing to capture", id))?;
+
+    unsafe {
+        XCompositeRedirectWindow(conn.display, toplevel, COMPOSITE_REDIRECT_AUTOMATIC);
+        xlib::XSync(conn.display, xlib::False);
+    }
+    std::thread::sleep(REDIRECT_SETTLE);
+    let result = unsafe {
+        let pixmap = XCompositeNameWindowPixmap(conn.display, toplevel);
+        let image = xlib::XGetImage(
+            conn.display,
+            pixmap,
+            area.x,
+            area.y,
+            area.width as c_uint,
+            area.height as c_uint,
+            xlib::XAllPlanes(),
+            xlib::ZPixmap,
+        );
+        let result = if image.is_null() {
+            Err(format!("Failed to read the contents of window {}", id))
+        } else {
+            let rgba = to_rgba(image, area.width, area.height);
+            xlib::XDestroyImage(image);
+            rgba
+        };
+        if pixmap != 0 {
+            xlib::XFreePixmap(conn.display, pixmap);
+        }
+        result
+    };
+    unsafe {
+        XCompositeUnredirectWindow(conn.display, toplevel, COMPOSITE_REDIRECT_AUTOMATIC);
+        xlib::XFlush(conn.display);
+    }
+    result
+}
+
+/// The ancestor of `id` that is a direct child of the root window.
+fn toplevel(conn: &XConnection, id: xlib::Window) -> xlib::Window {
+    let mut window = id;
+    loop {
+        let (mut root, mut parent): (xlib::Window, xlib::Window) = (0, 0);
+        let mut children: *mut xlib::Window = std::ptr::null_mut();
+        let mut count: c_uint = 0;
+        let ok = unsafe {
+            xlib::XQueryTree(
+                conn.display,
+                window,
+                &mut root,
+                &mut parent,
+                &mut children,
+                &mut count,
+            )
+        };
+        if !children.is_null() {
+            unsafe { xlib::XFree(children as *mut _) };
+        }
+        if ok == 0 || parent == 0 || parent == root {
+            return window;
+        }
+        window = parent;
+    }
+}
+
+fn process_name(pid: u32) -> Option<String> {
+    std::fs::read_to_string(format!("/proc/{}/comm", pid))
+        .ok()
+        .map(|name| name.trim_end().to_string())
+}
@@ -0,0 +1,612 @@
+#[cfg(target_os = "linux")]
+pub mod linux;
+#[cfg(target_os = "windows")]
+pub mod windows;
+
+#[cfg(target_os = "linux")]
+use linux as platform;
+#[cfg(target_os = "windows")]
+use windows as platform;
+
+use super::{AutomationResult, Point, Rect};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long [`focus_window`] waits for the window system to report the new focus.
+const FOCUS_TIMEOUT: Duration = Duration::from_secs(2);
+/// How often the `wait_for_*` functions look at the window list.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A top-level window as reported by the window system.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowInfo {
+    /// `HWND` on Windows, X window id on X11.
+    pub id: u64,
+    pub title: String,
+    pub class: String,
+    pub pid: Option<u32>,
+    pub process_name: Option<String>,
+    /// Outer bounds including frame decorations, in screen coordinates.
+    pub bounds: Rect,
+    /// Client area without decorations, in screen coordinates.
+    pub client_area: Rect,
+    pub visible: bool,
+    pub minimized: bool,
+    pub maximized: bool,
+    pub focused: bool,
+    /// Stacking position, 0 being the topmost window.
+    pub z_order: u32,
+}
+
+/// Criteria for [`find_window`]; every criterion that is set must match.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowMatcher {
+    /// Regular expression searched for in the title.
+    pub title: Option<String>,
+    /// Window class, compared case-insensitively.
+    pub class: Option<String>,
+    pub pid: Option<u32>,
+}
+
+impl WindowMatcher {
+    pub fn title(pattern: &str) -> Self {
+        WindowMatcher {
+            title: Some(pattern.to_string()),
+            ..WindowMatcher::default()
+        }
+    }
+
+    /// Windows from `windows` matching every criterion, in their original order.
+    pub fn filter(&self, windows: Vec<WindowInfo>) -> Result<Vec<WindowInfo>, String> {
+        let title = match &self.title {
+            Some(pattern) => {
+                Some(Regex::new(pattern).map_err(|e| format!("Invalid title pattern: {}", e))?)
+            }
+            None => None,
+        };
+        Ok(windows
+            .into_iter()
+            .filter(|window| {
+                title.as_ref().is_none_or(|re| re.is_match(&window.title))
+                    && self
+                        .class
+                        .as_ref()
+                        .is_none_or(|class| class.eq_ignore_ascii_case(&window.class))
+                    && self.pid.is_none_or(|pid| window.pid == Some(pid))
+            })
+            .collect())
+    }
+}
+
+impl fmt::Display for WindowMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(title) = &self.title {
+            parts.push(format!("title /{}/", title));
+        }
+        if let Some(class) = &self.class {
+            parts.push(format!("class {:?}", class));
+        }
+        if let Some(pid) = self.pid {
+            parts.push(format!("pid {}", pid));
+        }
+        if parts.is_empty() {
+            f.write_str("any window")
+        } else {
+            f.write_str(&parts.join(", "))
+        }
+    }
+}
+
+/// A window named either by id or by a matcher that is resolved on every use.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum WindowTarget {
+    Id(u64),
+    Matcher(WindowMatcher),
+}
+
+impl WindowTarget {
+    /// The window as it is right now.
+    pub fn resolve(&self) -> Result<WindowInfo, String> {
+        self.find_in(list_windows()?)
+    }
+
+    /// The window as it is right now, or `None` when it is not open.
+    pub fn find(&self) -> Result<Option<WindowInfo>, String> {
+        match self {
+            WindowTarget::Id(id) => Ok(list_windows()?.into_iter().find(|window| window.id == *id)),
+            WindowTarget::Matcher(matcher) => find_window(matcher),
+        }
+    }
+
+    fn find_in(&self, windows: Vec<WindowInfo>) -> Result<WindowInfo, String> {
+        match self {
+            WindowTarget::Id(id) => windows
+                .into_iter()
+                .find(|window| window.id == *id)
+                .ok_or_else(|| format!("Window {} no longer exists", id)),
+            WindowTarget::Matcher(matcher) => matcher
+                .filter(windows)?
+                .into_iter()
+                .next()
+                .ok_or_else(|| format!("No window matches {}", matcher)),
+        }
+    }
+}
+
+impl fmt::Display for WindowTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WindowTarget::Id(id) => write!(f, "window {}", id),
+            WindowTarget::Matcher(matcher) => write!(f, "window matching {}", matcher),
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+mod platform {
+    use super::{WindowInfo, WindowOp};
+
+    const UNSUPPORTED: &str = "Unsupported platform - Windows and X11 only for now";
+
+    pub fn list_windows() -> Result<Vec<WindowInfo>, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn active_window_id() -> Result<Option<u64>, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn control(_id: u64, _op: WindowOp) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn capture(_id: u64, _include_frame: bool) -> Result<image::RgbaImage, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+}
+
+/// A request to the window manager, carried out by the platform backends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WindowOp {
+    Focus,
+    Raise,
+    /// New top-left corner of the outer bounds.
+    Move {
+        x: i32,
+        y: i32,
+    },
+    /// New client area size.
+    Resize {
+        width: u32,
+        height: u32,
+    },
+    Minimize,
+    Maximize,
+    Restore,
+    Close,
+}
+
+/// All top-level windows, topmost first.
+pub fn list_windows() -> Result<Vec<WindowInfo>, String> {
+    platform::list_windows()
+}
+
+/// Every window matching `matcher`, topmost first.
+pub fn find_windows(matcher: &WindowMatcher) -> Result<Vec<WindowInfo>, String> {
+    matcher.filter(list_windows()?)
+}
+
+/// The topmost window matching `matcher`.
+pub fn find_window(matcher: &WindowMatcher) -> Result<Option<WindowInfo>, String> {
+    Ok(find_windows(matcher)?.into_iter().next())
+}
+
+/// The topmost window matching `matcher`, or an error naming the matcher.
+pub fn require_window(matcher: &WindowMatcher) -> Result<WindowInfo, String> {
+    find_window(matcher)?.ok_or_else(|| format!("No window matches {}", matcher))
+}
+
+/// The window that currently has keyboard focus, if any.
+pub fn active_window() -> Result<Option<WindowInfo>, String> {
+    Ok(list_windows()?.into_iter().find(|window| window.focused))
+}
+
+/// Converts an offset from the top-left of the target's client area to screen
+/// coordinates, failing if the window is minimized or the point is covered by another
+/// window, where input would land somewhere else.
+pub fn window_point(target: &WindowTarget, x: i32, y: i32) -> Result<Point, String> {
+    let windows = list_windows()?;
+    let window = target.find_in(windows.clone())?;
+    client_point(&windows, &window, x, y)
+}
+
+/// Converts a region relative to the target's client area to screen coordinates, the
+/// whole client area if `region` is `None`. Fails if the window is minimized or another
+/// window overlaps the region, which would then show up in a capture.
+pub fn window_region(target: &WindowTarget, region: Option<Rect>) -> Result<Rect, String> {
+    let windows = list_windows()?;
+    let window = target.find_in(windows.clone())?;
+    client_region(&windows, &window, region)
+}
+
+fn client_point(
+    windows: &[WindowInfo],
+    window: &WindowInfo,
+    x: i32,
+    y: i32,
+) -> Result<Point, String> {
+    if window.minimized {
+        return Err(format!("Window {} is minimized", window.id));
+    }
+    let client = window.client_area;
+    let point = Point {
+        x: client.x + x,
+        y: client.y + y,
+    };
+    if !client.contains(&point) {
+        return Err(format!(
+            "({}, {}) lies outside the {}x{} client area of window {}",
+            x, y, client.width, client.height, window.id
+        ));
+    }
+    match covering(windows, window, |bounds| bounds.contains(&point)) {
+        Some(cover) => Err(format!(
+            "({}, {}) in window {} is covered by {:?}",
+            x, y, window.id, cover.title
+        )),
+        None => Ok(point),
+    }
+}
+
+fn client_region(
+    windows: &[WindowInfo],
+    window: &WindowInfo,
+    region: Option<Rect>,
+) -> Result<Rect, String> {
+    if window.minimized {
+        return Err(format!("Window {} is minimized", window.id));
+    }
+    let client = window.client_area;
+    let rect = match region {
+        Some(region) => Rect {
+            x: client.x + region.x,
+            y: client.y + region.y,
+            ..region
+        }
+        .intersect(&client)
+        .ok_or_else(|| {
+            format!(
+                "Region lies outside the {}x{} client area of window {}",
+                client.width, client.height, window.id
+            )
+        })?,
+        None => client,
+    };
+    match covering(windows, window, |bounds| bounds.intersect(&rect).is_some()) {
+        Some(cover) => Err(format!(
+            "Window {} is partly covered by {:?}",
+            window.id, cover.title
+        )),
+        None => Ok(rect),
+    }
+}
+
+/// The topmost visible window stacked above `window` whose bounds satisfy `hits`.
+fn covering<'a>(
+    windows: &'a [WindowInfo],
+    window: &WindowInfo,
+    hits: impl Fn(&Rect) -> bool,
+) -> Option<&'a WindowInfo> {
+    windows
+        .iter()
+        .filter(|other| other.visible && other.z_order < window.z_order)
+        .find(|other| hits(&other.bounds))
+}
+
+/// Captures the window's own contents rather than the screen area it occupies, so windows
+/// in front of it are left out and background windows can be captured. The client area
+/// only, unless `include_frame` adds the title bar and borders.
+pub fn capture_window(id: u64, include_frame: bool) -> Result<image::RgbaImage, String> {
+    platform::capture(id, include_frame)
+}
+
+/// Waits until a visible window matches `matcher` and returns it, e.g. after launching
+/// an application and before typing into it.
+pub fn wait_for_window(matcher: &WindowMatcher, timeout: Duration) -> Result<WindowInfo, String> {
+    poll(timeout, || {
+        Ok(find_windows(matcher)?
+            .into_iter()
+            .find(|window| window.visible))
+    })?
+    .ok_or_else(|| timed_out(timeout, &format!("a window matching {}", matcher)))
+}
+
+/// Waits until no window matches `matcher`.
+pub fn wait_for_window_closed(matcher: &WindowMatcher, timeout: Duration) -> Result<(), String> {
+    poll(timeout, || {
+        Ok(find_window(matcher)?.is_none().then_some(()))
+    })?
+    .ok_or_else(|| timed_out(timeout, &format!("{} to close", matcher)))
+}
+
+/// Waits until a window matching `matcher` has keyboard focus and returns it.
+pub fn wait_for_focus(matcher: &WindowMatcher, timeout: Duration) -> Result<WindowInfo, String> {
+    poll(timeout, || {
+        Ok(find_windows(matcher)?
+            .into_iter()
+            .find(|window| window.focused))
+    })?
+    .ok_or_else(|| timed_out(timeout, &format!("{} to get focus", matcher)))
+}
+
+/// Calls `check` until it returns a value or `timeout` passes, checking at least once.
+pub(crate) fn poll<T>(
+    timeout: Duration,
+    mut check: impl FnMut() -> Result<Option<T>, String>,
+) -> Result<Option<T>, String> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(value) = check()? {
+            return Ok(Some(value));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+fn timed_out(timeout: Duration, what: &str) -> String {
+    format!(
+        "Timed out after {:.1}s waiting for {}",
+        timeout.as_secs_f64(),
+        what
+    )
+}
+
+fn done(message: String) -> AutomationResult {
+    AutomationResult {
+        success: true,
+        message: Some(message),
+        data: None,
+    }
+}
+
+/// Restores, raises and focuses the window, then waits until the window system reports
+/// it as focused so that input never goes to the previous window.
+pub fn focus_window(id: u64) -> Result<AutomationResult, String> {
+    platform::control(id, WindowOp::Focus)?;
+    let deadline = Instant::now() + FOCUS_TIMEOUT;
+    while platform::active_window_id()? != Some(id) {
+        if Instant::now() >= deadline {
+            return Err(format!("Window {} did not receive focus", id));
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    Ok(done(format!("Window {} focused", id)))
+}
+
+/// Brings the window to the top of the stacking order without focusing it.
+pub fn raise_window(id: u64) -> Result<AutomationResult, String> {
+    platform::control(id, WindowOp::Raise)?;
+    Ok(done(format!("Window {} raised", id)))
+}
+
+/// Moves the window so its outer top-left corner is at `x`, `y`.
+pub fn move_window(id: u64, x: i32, y: i32) -> Result<AutomationResult, String> {
+    platform::control(id, WindowOp::Move { x, y })?;
+    Ok(done(format!("Window {} moved to ({}, {})", id, x, y)))
+}
+
+/// Resizes the window's client area to `width` x `height`.
+pub fn resize_window(id: u64, width: u32, height: u32) -> Result<AutomationResult, String> {
+    if width == 0 || height == 0 {
+        return Err("Window width and height must be positive".to_string());
+    }
+    platform::control(id, WindowOp::Resize { width, height })?;
+    Ok(done(format!(
+        "Window {} resized to {}x{}",
+        id, width, height
+    )))
+}
+
+pub fn minimize_window(id: u64) -> Result<AutomationResult, String> {
+    platform::control(id, WindowOp::Minimize)?;
+    Ok(done(format!("Window {} minimized", id)))
+}
+
+pub fn maximize_window(id: u64) -> Result<AutomationResult, String> {
+    platform::control(id, WindowOp::Maximize)?;
+    Ok(done(format!("Window {} maximized", id)))
+}
+
+/// Undoes minimize and maximize.
+pub fn restore_window(id: u64) -> Result<AutomationResult, String> {
+    platform::control(id, WindowOp::Restore)?;
+    Ok(done(format!("Window {} restored", id)))
+}
+
+/// Asks the window to close, as if the user clicked its close button; the application
+/// may still prompt or refuse.
+pub fn close_window(id: u64) -> Result<AutomationResult, String> {
+    platform::control(id, WindowOp::Close)?;
+    Ok(done(format!("Asked window {} to close", id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: u64, title: &str, class: &str, pid: u32) -> WindowInfo {
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 100,
+        };
+        WindowInfo {
+            id,
+            title: title.to_string(),
+            class: class.to_string(),
+            pid: Some(pid),
+            process_name: None,
+            bounds: rect,
+            client_area: rect,
+            visible: true,
+            minimized: false,
+            maximized: false,
+            focused: false,
+            z_order: id as u32,
+        }
+    }
+
+    fn desktop() -> Vec<WindowInfo> {
+        vec![
+            window(1, "Untitled - Notepad", "Notepad", 40),
+            window(2, "Calculator", "ApplicationFrameWindow", 41),
+            window(3, "notes.txt - Notepad", "Notepad", 42),
+        ]
+    }
+
+    #[test]
+    fn title_is_a_regex_search() {
+        let ids: Vec<u64> = WindowMatcher::title("Notepad$")
+            .filter(desktop())
+            .unwrap()
+            .iter()
+            .map(|w| w.id)
+            .collect();
+        assert_eq!(ids, [1, 3]);
+    }
+
+    #[test]
+    fn all_criteria_must_match() {
+        let matcher = WindowMatcher {
+            title: Some("Notepad".to_string()),
+            class: Some("notepad".to_string()),
+            pid: Some(42),
+        };
+        let found = matcher.filter(desktop()).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].title, "notes.txt - Notepad");
+        assert!(WindowMatcher::default().filter(desktop()).unwrap().len() == 3);
+    }
+
+    #[test]
+    fn invalid_title_pattern_is_an_error() {
+        assert!(WindowMatcher::title("(").filter(desktop()).is_err());
+    }
+
+    fn placed(mut window: WindowInfo, x: i32, y: i32, width: u32, height: u32) -> WindowInfo {
+        window.bounds = Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        window.client_area = Rect {
+            x: x + 1,
+            y: y + 20,
+            width: width - 2,
+            height: height - 21,
+        };
+        window
+    }
+
+    #[test]
+    fn offsets_are_relative_to_the_client_area() {
+        let windows = vec![placed(
+            window(0, "Calculator", "Calc", 1),
+            100,
+            50,
+            200,
+            300,
+        )];
+        let point = client_point(&windows, &windows[0], 50, 120).unwrap();
+        assert_eq!(point, Point { x: 151, y: 190 });
+        assert!(client_point(&windows, &windows[0], 198, 0).is_err());
+
+        let rect = client_region(&windows, &windows[0], None).unwrap();
+        assert_eq!(rect, windows[0].client_area);
+        let clipped = client_region(
+            &windows,
+            &windows[0],
+            Some(Rect {
+                x: 150,
+                y: 0,
+                width: 100,
+                height: 10,
+            }),
+        )
+        .unwrap();
+        assert_eq!((clipped.x, clipped.width), (251, 48));
+    }
+
+    #[test]
+    fn occluded_and_minimized_windows_are_errors() {
+        let windows = vec![
+            placed(window(0, "Popup", "Popup", 1), 140, 150, 50, 50),
+            placed(window(1, "Calculator", "Calc", 2), 100, 50, 200, 300),
+        ];
+        let target = &windows[1];
+        assert!(client_point(&windows, target, 10, 10).is_ok());
+        let covered = client_point(&windows, target, 50, 120).unwrap_err();
+        assert!(covered.contains("Popup"), "{}", covered);
+        assert!(client_region(&windows, target, None).is_err());
+
+        let mut minimized = target.clone();
+        minimized.minimized = true;
+        assert!(client_point(&windows, &minimized, 10, 10).is_err());
+    }
+
+    #[test]
+    fn targets_resolve_by_id_or_matcher() {
+        assert_eq!(
+            WindowTarget::Id(2).find_in(desktop()).unwrap().title,
+            "Calculator"
+        );
+        assert!(WindowTarget::Id(9).find_in(desktop()).is_err());
+        let target = WindowTarget::Matcher(WindowMatcher::title("notes"));
+        assert_eq!(target.find_in(desktop()).unwrap().id, 3);
+        assert_eq!(
+            serde_json::from_str::<WindowTarget>(r#"{"title":"Calc"}"#).unwrap(),
+            WindowTarget::Matcher(WindowMatcher::title("Calc"))
+        );
+    }
+
+    #[test]
+    fn poll_returns_the_first_value() {
+        let mut calls = 0;
+        let value = poll(Duration::from_secs(5), || {
+            calls += 1;
+            Ok((calls == 3).then_some(calls))
+        });
+        assert_eq!(value, Ok(Some(3)));
+    }
+
+    #[test]
+    fn poll_gives_up_after_timeout() {
+        let start = Instant::now();
+        let value: Result<Option<()>, String> = poll(Duration::from_millis(120), || Ok(None));
+        assert_eq!(value, Ok(None));
+        assert!(start.elapsed() >= Duration::from_millis(120));
+        assert!(poll::<()>(Duration::ZERO, || Err("gone".to_string())).is_err());
+    }
+
+    #[test]
+    fn matcher_describes_itself() {
+        let matcher = WindowMatcher {
+            title: Some("Calc".to_string()),
+            pid: Some(7),
+            ..WindowMatcher::default()
+        };
+        assert_eq!(matcher.to_string(), "title /Calc/, pid 7");
+    }
+}
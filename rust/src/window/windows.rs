@@ -0,0 +1,272 @@
+use super::{WindowInfo, WindowOp};
+use crate::Rect;
+use image::RgbaImage;
+use winapi::shared::minwindef::{BOOL, DWORD, LPARAM, TRUE};
+use winapi::shared::windef::{HWND, POINT, RECT};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::processthreadsapi::OpenProcess;
+use winapi::um::winbase::QueryFullProcessImageNameW;
+use winapi::um::wingdi::{
+    CreateCompatibleBitmap, CreateCompatibleDC, DeleteDC, DeleteObject, GetDIBits, SelectObject,
+    BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS,
+};
+use winapi::um::winnt::PROCESS_QUERY_LIMITED_INFORMATION;
+use winapi::um::winuser::*;
+
+unsafe extern "system" fn collect(hwnd: HWND, lparam: LPARAM) -> BOOL {
+    let handles = &mut *(lparam as *mut Vec<HWND>);
+    handles.push(hwnd);
+    TRUE
+}
+
+pub fn list_windows() -> Result<Vec<WindowInfo>, String> {
+    // EnumWindows walks top-level windows in z-order, topmost first
+    let mut handles: Vec<HWND> = Vec::new();
+    unsafe {
+        if EnumWindows(Some(collect), &mut handles as *mut Vec<HWND> as LPARAM) == 0 {
+            return Err("Failed to enumerate windows".to_string());
+        }
+    }
+    let foreground = unsafe { GetForegroundWindow() };
+
+    // Skip the many hidden helper windows every process owns
+    Ok(handles
+        .into_iter()
+        .map(|hwnd| window_info(hwnd, foreground))
+        .filter(|info| !info.title.is_empty() && (info.visible || info.minimized))
+        .enumerate()
+        .map(|(z_order, info)| WindowInfo {
+            z_order: z_order as u32,
+            ..info
+        })
+        .collect())
+}
+
+pub(crate) fn window_info(hwnd: HWND, foreground: HWND) -> WindowInfo {
+    unsafe {
+        let mut title = vec![0u16; GetWindowTextLengthW(hwnd).max(0) as usize + 1];
+        let length = GetWindowTextW(hwnd, title.as_mut_ptr(), title.len() as i32);
+        let mut class = [0u16; 256];
+        let class_length = GetClassNameW(hwnd, class.as_mut_ptr(), class.len() as i32);
+
+        let mut pid: DWORD = 0;
+        GetWindowThreadProcessId(hwnd, &mut pid);
+
+        let mut outer: RECT = std::mem::zeroed();
+        GetWindowRect(hwnd, &mut outer);
+        let mut client: RECT = std::mem::zeroed();
+        GetClientRect(hwnd, &mut client);
+        let mut origin = POINT { x: 0, y: 0 };
+        ClientToScreen(hwnd, &mut origin);
+
+        let minimized = IsIconic(hwnd) != 0;
+        WindowInfo {
+            id: hwnd as usize as u64,
+            title: String::from_utf16_lossy(&title[..length.max(0) as usize]),
+            class: String::from_utf16_lossy(&class[..class_length.max(0) as usize]),
+            pid: if pid == 0 { None } else { Some(pid) },
+            process_name: if pid == 0 { None } else { process_name(pid) },
+            bounds: Rect {
+                x: outer.left,
+                y: outer.top,
+                width: (outer.right - outer.left).max(0) as u32,
+                height: (outer.bottom - outer.top).max(0) as u32,
+            },
+            client_area: Rect {
+                x: origin.x,
+                y: origin.y,
+                width: client.right.max(0) as u32,
+                height: client.bottom.max(0) as u32,
+            },
+            visible: IsWindowVisible(hwnd) != 0 && !minimized,
+            minimized,
+            maximized: IsZoomed(hwnd) != 0,
+            focused: hwnd == foreground,
+            z_order: 0,
+        }
+    }
+}
+
+pub fn active_window_id() -> Result<Option<u64>, String> {
+    let hwnd = unsafe { GetForegroundWindow() };
+    Ok(if hwnd.is_null() {
+        None
+    } else {
+        Some(hwnd as usize as u64)
+    })
+}
+
+pub(crate) fn control(id: u64, op: WindowOp) -> Result<(), String> {
+    let hwnd = id as usize as HWND;
+    unsafe {
+        if IsWindow(hwnd) == 0 {
+            return Err(format!("Window {} no longer exists", id));
+        }
+        let ok = match op {
+            WindowOp::Focus => {
+                if IsIconic(hwnd) != 0 {
+                    ShowWindow(hwnd, SW_RESTORE);
+                }
+                // Windows only lets the process that received the last input event change
+                // the foreground window; a synthetic Alt tap makes that us
+                keybd_event(VK_MENU as u8, 0, 0, 0);
+                keybd_event(VK_MENU as u8, 0, KEYEVENTF_KEYUP, 0);
+                BringWindowToTop(hwnd);
+                SetForegroundWindow(hwnd) != 0
+            }
+            WindowOp::Raise => {
+                SetWindowPos(
+                    hwnd,
+                    HWND_TOP,
+                    0,
+                    0,
+                    0,
+                    0,
+                    SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE,
+                ) != 0
+            }
+            WindowOp::Move { x, y } => {
+                SetWindowPos(
+                    hwnd,
+                    std::ptr::null_mut(),
+                    x,
+                    y,
+                    0,
+                    0,
+                    SWP_NOSIZE | SWP_NOZORDER | SWP_NOACTIVATE,
+                ) != 0
+            }
+            WindowOp::Resize { width, height } => {
+                // SetWindowPos takes the outer size, so add the current frame size
+                let info = window_info(hwnd, std::ptr::null_mut());
+                let frame_width = info.bounds.width - info.client_area.width;
+                let frame_height = info.bounds.height - info.client_area.height;
+                SetWindowPos(
+                    hwnd,
+                    std::ptr::null_mut(),
+                    0,
+                    0,
+                    (width + frame_width) as i32,
+                    (height + frame_height) as i32,
+                    SWP_NOMOVE | SWP_NOZORDER | SWP_NOACTIVATE,
+                ) != 0
+            }
+            // ShowWindow returns the previous visibility, not success
+            WindowOp::Minimize => {
+                ShowWindow(hwnd, SW_MINIMIZE);
+                true
+            }
+            WindowOp::Maximize => {
+                ShowWindow(hwnd, SW_MAXIMIZE);
+                true
+            }
+            WindowOp::Restore => {
+                ShowWindow(hwnd, SW_RESTORE);
+                true
+            }
+            WindowOp::Close => PostMessageW(hwnd, WM_CLOSE, 0, 0) != 0,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(format!(
+                "Failed to {:?} window {}: {}",
+                op,
+                id,
+                GetLastError()
+            ))
+        }
+    }
+}
+
+/// Renders the window into a bitmap with `PrintWindow`, which works for covered and
+/// off-screen windows.
+pub(crate) fn capture(id: u64, include_frame: bool) -> Result<RgbaImage, String> {
+    let hwnd = id as usize as HWND;
+    unsafe {
+        if IsWindow(hwnd) == 0 {
+            return Err(format!("Window {} no longer exists", id));
+        }
+        if IsIconic(hwnd) != 0 {
+            return Err(format!(
+                "Window {} is minimized; restore it before capturing",
+                id
+            ));
+        }
+        let info = window_info(hwnd, std::ptr::null_mut());
+        let area = if include_frame {
+            info.bounds
+        } else {
+            info.client_area
+        };
+        if area.width == 0 || area.height == 0 {
+            return Err(format!("Window {} has nothing to capture", id));
+        }
+        let (width, height) = (area.width as i32, area.height as i32);
+
+        let screen = GetDC(std::ptr::null_mut());
+        let dc = CreateCompatibleDC(screen);
+        let bitmap = CreateCompatibleBitmap(screen, width, height);
+        let previous = SelectObject(dc, bitmap as _);
+        // PW_RENDERFULLCONTENT also gets DirectComposition content such as browser pages
+        let flags = PW_RENDERFULLCONTENT | if include_frame { 0 } else { PW_CLIENTONLY };
+        let printed = PrintWindow(hwnd, dc, flags) != 0;
+        SelectObject(dc, previous);
+
+        let mut header: BITMAPINFO = std::mem::zeroed();
+        header.bmiHeader.biSize = std::mem::size_of::<BITMAPINFOHEADER>() as DWORD;
+        header.bmiHeader.biWidth = width;
+        // Negative height asks for top-down rows
+        header.bmiHeader.biHeight = -height;
+        header.bmiHeader.biPlanes = 1;
+        header.bmiHeader.biBitCount = 32;
+        header.bmiHeader.biCompression = BI_RGB;
+        let mut pixels = vec![0u8; area.width as usize * area.height as usize * 4];
+        let lines = GetDIBits(
+            dc,
+            bitmap,
+            0,
+            area.height,
+            pixels.as_mut_ptr() as *mut _,
+            &mut header,
+            DIB_RGB_COLORS,
+        );
+        DeleteObject(bitmap as _);
+        DeleteDC(dc);
+        ReleaseDC(std::ptr::null_mut(), screen);
+        if !printed || lines == 0 {
+            return Err(format!(
+                "Failed to capture window {}: {}",
+                id,
+                GetLastError()
+            ));
+        }
+
+        // BGRA with an undefined alpha channel
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+            pixel[3] = 255;
+        }
+        RgbaImage::from_raw(area.width, area.height, pixels)
+            .ok_or_else(|| "Captured bitmap has an unexpected size".to_string())
+    }
+}
+
+fn process_name(pid: DWORD) -> Option<String> {
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if process.is_null() {
+            return None;
+        }
+        let mut path = [0u16; 1024];
+        let mut size = path.len() as DWORD;
+        let ok = QueryFullProcessImageNameW(process, 0, path.as_mut_ptr(), &mut size) != 0;
+        CloseHandle(process);
+        if !ok {
+            return None;
+        }
+        let path = String::from_utf16_lossy(&path[..size as usize]);
+        path.rsplit('\\').next().map(str::to_string)
+    }
+}
@@ -0,0 +1,248 @@
+//! Connection to the X server shared by the Linux backends.
+
+use image::RgbaImage;
+use std::ffi::CString;
+use std::os::raw::{c_int, c_long, c_uchar, c_ulong};
+use std::ptr;
+use std::sync::{Mutex, Once};
+use x11::xlib;
+
+/// The first error the server reported on each connection since it was last checked
+/// with [`XConnection::sync_error`], by display address.
+static ERRORS: Mutex<Vec<(usize, u8)>> = Mutex::new(Vec::new());
+
+pub(crate) struct XConnection {
+    pub display: *mut xlib::Display,
+    pub root: xlib::Window,
+}
+
+/// Raw contents of a window property.
+pub(crate) struct Property {
+    pub kind: xlib::Atom,
+    pub format: c_int,
+    pub data: Vec<u8>,
+    pub items: usize,
+}
+
+unsafe extern "C" fn note_error(
+    display: *mut xlib::Display,
+    event: *mut xlib::XErrorEvent,
+) -> c_int {
+    let mut errors = ERRORS.lock().unwrap_or_else(|e| e.into_inner());
+    if !errors.iter().any(|(noted, _)| *noted == display as usize) {
+        errors.push((display as usize, (*event).error_code));
+    }
+    0
+}
+
+impl XConnection {
+    pub fn open() -> Result<Self, String> {
+        // The default handler exits the process, e.g. when a window disappears between
+        // listing it and reading its properties; calls report failure through their
+        // return values or sync_error instead
+        static HANDLER: Once = Once::new();
+        HANDLER.call_once(|| unsafe {
+            // Background threads such as the clipboard owner hold their own connections
+            xlib::XInitThreads();
+            xlib::XSetErrorHandler(Some(note_error));
+        });
+
+        let display = unsafe { xlib::XOpenDisplay(ptr::null()) };
+        if display.is_null() {
+            return Err("Cannot open X display (is DISPLAY set?)".to_string());
+        }
+        let root = unsafe { xlib::XDefaultRootWindow(display) };
+        Ok(XConnection { display, root })
+    }
+
+    pub fn atom(&self, name: &str) -> xlib::Atom {
+        let name = CString::new(name).expect("atom names contain no NUL");
+        unsafe { xlib::XInternAtom(self.display, name.as_ptr(), xlib::False) }
+    }
+
+    pub fn atom_name(&self, atom: xlib::Atom) -> Option<String> {
+        let name = unsafe { xlib::XGetAtomName(self.display, atom) };
+        if name.is_null() {
+            return None;
+        }
+        let text = unsafe { std::ffi::CStr::from_ptr(name) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { xlib::XFree(name as *mut _) };
+        Some(text)
+    }
+
+    pub fn property(&self, window: xlib::Window, name: &str) -> Option<Property> {
+        let mut kind: xlib::Atom = 0;
+        let mut format: c_int = 0;
+        let mut items: c_ulong = 0;
+        let mut remaining: c_ulong = 0;
+        let mut data: *mut c_uchar = ptr::null_mut();
+        let status = unsafe {
+            xlib::XGetWindowProperty(
+                self.display,
+                window,
+                self.atom(name),
+                0,
+                // Length is in 32-bit units; large enough for any property we read
+                c_long::MAX / 4,
+                xlib::False,
+                xlib::AnyPropertyType as c_ulong,
+                &mut kind,
+                &mut format,
+                &mut items,
+                &mut remaining,
+                &mut data,
+            )
+        };
+        if status != xlib::Success as c_int || data.is_null() {
+            return None;
+        }
+
+        // Xlib hands out 32-bit items as C longs
+        let item_size = match format {
+            8 => 1,
+            16 => std::mem::size_of::<i16>(),
+            _ => std::mem::size_of::<c_long>(),
+        };
+        let bytes =
+            unsafe { std::slice::from_raw_parts(data, items as usize * item_size) }.to_vec();
+        unsafe { xlib::XFree(data as *mut _) };
+        if kind == 0 {
+            return None;
+        }
+        Some(Property {
+            kind,
+            format,
+            data: bytes,
+            items: items as usize,
+        })
+    }
+
+    /// Items of a 32-bit property such as `CARDINAL`, `WINDOW` or `ATOM`.
+    pub fn longs(&self, window: xlib::Window, name: &str) -> Option<Vec<c_ulong>> {
+        let property = self.property(window, name)?;
+        if property.format != 32 {
+            return None;
+        }
+        let size = std::mem::size_of::<c_ulong>();
+        Some(
+            property
+                .data
+                .chunks_exact(size)
+                .take(property.items)
+                .map(|chunk| c_ulong::from_ne_bytes(chunk.try_into().unwrap()))
+                .collect(),
+        )
+    }
+
+    /// A text property, decoding `STRING` as Latin-1 and everything else as UTF-8.
+    pub fn text(&self, window: xlib::Window, name: &str) -> Option<String> {
+        let property = self.property(window, name)?;
+        if property.format != 8 {
+            return None;
+        }
+        let data = property.data;
+        Some(if property.kind == xlib::XA_STRING {
+            data.iter().map(|b| *b as char).collect()
+        } else {
+            String::from_utf8_lossy(&data).into_owned()
+        })
+    }
+
+    /// Waits until the server has handled every request so far, returning the error code
+    /// of the first one that failed, if any.
+    pub fn sync_error(&self) -> Option<u8> {
+        unsafe { xlib::XSync(self.display, xlib::False) };
+        take_error(self.display)
+    }
+
+    /// Sends an EWMH client message about `window` to the root window, which is how
+    /// requests reach the window manager.
+    pub fn send_client_message(&self, window: xlib::Window, message: &str, data: [c_long; 5]) {
+        let mut event = xlib::XClientMessageEvent {
+            type_: xlib::ClientMessage,
+            serial: 0,
+            send_event: xlib::True,
+            display: self.display,
+            window,
+            message_type: self.atom(message),
+            format: 32,
+            data: xlib::ClientMessageData::from(data),
+        };
+        unsafe {
+            xlib::XSendEvent(
+                self.display,
+                self.root,
+                xlib::False,
+                xlib::SubstructureRedirectMask | xlib::SubstructureNotifyMask,
+                &mut event as *mut xlib::XClientMessageEvent as *mut xlib::XEvent,
+            );
+            xlib::XFlush(self.display);
+        }
+    }
+}
+
+impl Drop for XConnection {
+    fn drop(&mut self) {
+        unsafe {
+            xlib::XCloseDisplay(self.display);
+        }
+        take_error(self.display);
+    }
+}
+
+fn take_error(display: *mut xlib::Display) -> Option<u8> {
+    let mut errors = ERRORS.lock().unwrap_or_else(|e| e.into_inner());
+    let index = errors
+        .iter()
+        .position(|(noted, _)| *noted == display as usize)?;
+    Some(errors.swap_remove(index).1)
+}
+
+/// Converts the first `width` x `height` pixels of an `XGetImage` result to RGBA. Only
+/// true-color visuals are supported; images without color masks, e.g. from palette
+/// visuals, are refused.
+pub(crate) unsafe fn to_rgba(
+    image: *mut xlib::XImage,
+    width: u32,
+    height: u32,
+) -> Result<RgbaImage, String> {
+    let masks = [(*image).red_mask, (*image).green_mask, (*image).blue_mask];
+    if masks.contains(&0) {
+        return Err(format!(
+            "Unsupported visual: {}-bit pixels without color masks",
+            (*image).depth
+        ));
+    }
+    Ok(RgbaImage::from_fn(width, height, |x, y| {
+        let pixel = xlib::XGetPixel(image, x as c_int, y as c_int);
+        let [r, g, b] = masks.map(|mask| channel(pixel, mask));
+        image::Rgba([r, g, b, 255])
+    }))
+}
+
+/// The bits of `pixel` under `mask`, a non-zero contiguous run, scaled to 0..=255.
+fn channel(pixel: c_ulong, mask: c_ulong) -> u8 {
+    let value = (pixel & mask) >> mask.trailing_zeros();
+    let max = mask >> mask.trailing_zeros();
+    (value * 255 / max) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels_scale_to_eight_bits() {
+        // 24-bit true color
+        assert_eq!(channel(0x12_34_56, 0xff_00_00), 0x12);
+        assert_eq!(channel(0x12_34_56, 0x00_00_ff), 0x56);
+        // 16-bit 5-6-5
+        assert_eq!(channel(0xf800, 0xf800), 255);
+        assert_eq!(channel(0x07e0, 0x07e0), 255);
+        assert_eq!(channel(0x0010, 0x001f), 131);
+        // 30-bit deep color
+        assert_eq!(channel(0x3ff << 20, 0x3ff << 20), 255);
+    }
+}
@@ -0,0 +1,77 @@
+// Drives `autoanchor mcp` through a pipe the way an MCP client would, with calls that
+// need no display.
+
+use serde_json::{json, Value};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn session(messages: &[Value]) -> Vec<Value> {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_autoanchor"))
+        .arg("mcp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("server should start");
+    let mut stdin = server.stdin.take().unwrap();
+    for message in messages {
+        writeln!(stdin, "{}", message).unwrap();
+    }
+    drop(stdin);
+    let output = server.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn handshakes_and_lists_tools() {
+    let responses = session(&[
+        json!({"jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {
+            "protocolVersion": "2025-03-26",
+            "capabilities": {},
+            "clientInfo": {"name": "test", "version": "1"},
+        }}),
+        json!({"jsonrpc": "2.0", "method": "notifications/initialized"}),
+        json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}),
+    ]);
+
+    assert_eq!(responses.len(), 2);
+    assert_eq!(responses[0]["result"]["protocolVersion"], "2025-03-26");
+    assert!(responses[0]["result"]["capabilities"]["tools"].is_object());
+    let tools = responses[1]["result"]["tools"].as_array().unwrap();
+    assert_eq!(tools.len(), 7);
+    for tool in tools {
+        assert_eq!(tool["inputSchema"]["type"], "object", "{}", tool["name"]);
+        assert!(!tool["description"].as_str().unwrap().is_empty());
+    }
+}
+
+#[test]
+fn reports_tool_failures_as_results() {
+    let responses = session(&[
+        json!({"jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": {
+            "name": "find_image",
+            "arguments": {"template": "/nonexistent.png"},
+        }}),
+        json!({"jsonrpc": "2.0", "id": 2, "method": "tools/call", "params": {
+            "name": "scroll",
+            "arguments": {"dy": 1, "speed": 3},
+        }}),
+        json!({"jsonrpc": "2.0", "id": 3, "method": "tools/call", "params": {"name": "drag"}}),
+    ]);
+
+    assert_eq!(responses[0]["result"]["isError"], true);
+    assert!(responses[0]["result"]["content"][0]["text"]
+        .as_str()
+        .unwrap()
+        .starts_with("Failed to open template /nonexistent.png"));
+    assert_eq!(responses[1]["result"]["isError"], true);
+    assert!(responses[1]["result"]["content"][0]["text"]
+        .as_str()
+        .unwrap()
+        .contains("unknown field `speed`"));
+    assert_eq!(responses[2]["error"]["code"], -32602);
+}
@@ -0,0 +1,68 @@
+// Reads a sign-in dialog rendered with anti-aliased DejaVu Sans, a common Linux UI
+// font, end to end: PNG decode, text recognition, and click-by-label lookup of the
+// button caption.
+
+#![cfg(feature = "ocr")]
+
+use autoanchor_core::ocr::{find_text_in, recognize};
+use autoanchor_core::Rect;
+
+fn sign_in_dialog() -> image::RgbaImage {
+    image::open(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/sign_in_dialog.png"
+    ))
+    .expect("fixture should decode")
+    .to_rgba8()
+}
+
+#[test]
+fn reads_every_label_on_the_dialog() {
+    let words: Vec<String> = recognize(&sign_in_dialog(), None)
+        .into_iter()
+        .map(|word| word.text)
+        .collect();
+    assert_eq!(
+        words,
+        [
+            "Sign",
+            "in",
+            "to",
+            "Anchorclick",
+            "Username:",
+            "admin",
+            "Submit"
+        ]
+    );
+}
+
+#[test]
+fn finds_the_submit_button_caption() {
+    let rect = find_text_in(&sign_in_dialog(), None, "Submit")
+        .expect("Submit should be found");
+    // The caption sits inside the blue button drawn at (200, 100) 120x36
+    assert!(
+        rect.x >= 200 && rect.x + rect.width as i32 <= 320,
+        "{:?}",
+        rect
+    );
+    assert!(
+        rect.y >= 100 && rect.y + rect.height as i32 <= 136,
+        "{:?}",
+        rect
+    );
+}
+
+#[test]
+fn region_limits_the_search() {
+    let heading = Rect {
+        x: 0,
+        y: 0,
+        width: 360,
+        height: 40,
+    };
+    assert_eq!(find_text_in(&sign_in_dialog(), Some(heading), "Submit"), None);
+    let rect = find_text_in(&sign_in_dialog(), Some(heading), "Anchorclick")
+        .expect("the heading should be read");
+    assert!(rect.y < 40);
+}
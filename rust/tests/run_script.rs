@@ -0,0 +1,75 @@
+// Runs scripts through `autoanchor run` with steps that need no display.
+
+use serde_json::{json, Value};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn run(args: &[&str], stdin: &str) -> Value {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_autoanchor"))
+        .arg("run")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("autoanchor should start");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+fn script(pid: u32) -> String {
+    json!([
+        {"action": "wait", "ms": 1},
+        {"action": "assert", "condition": {"kind": "process_running", "pid": pid}, "negate": true},
+        {"action": "assert", "condition": {"kind": "process_running", "pid": pid}},
+    ])
+    .to_string()
+}
+
+#[test]
+fn stops_at_the_first_failing_step() {
+    let result = run(&["-"], &script(std::process::id()));
+
+    assert_eq!(result["success"], false);
+    assert!(result["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("Step 1 (assert) failed: Assertion failed"));
+    let steps = result["data"].as_array().unwrap();
+    assert_eq!(steps.len(), 2);
+    assert_eq!(steps[0]["action"], "wait");
+    assert_eq!(steps[0]["success"], true);
+    assert_eq!(steps[1]["index"], 1);
+}
+
+#[test]
+fn keeps_going_from_a_file() {
+    let path = std::env::temp_dir().join(format!("autoanchor-script-{}.json", std::process::id()));
+    std::fs::write(&path, script(std::process::id())).unwrap();
+    let result = run(&[path.to_str().unwrap(), "--keep-going"], "");
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(result["success"], false);
+    let steps = result["data"].as_array().unwrap();
+    assert_eq!(steps.len(), 3);
+    assert_eq!(steps[2]["success"], true);
+}
+
+#[test]
+fn rejects_invalid_scripts_before_running_anything() {
+    let result = run(
+        &["-"],
+        r#"[{"action": "wait", "ms": 1}, {"action": "click", "x": "left"}]"#,
+    );
+    assert_eq!(result["success"], false);
+    assert!(result["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("Invalid step 1:"));
+    assert!(result["data"].is_null());
+}
@@ -0,0 +1,262 @@
+// Drives `autoanchor serve --http` with a plain TCP client, using requests that need
+// no display.
+
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+
+const TOKEN: &str = "test-token";
+
+struct Server {
+    child: Child,
+    address: String,
+    /// The line printed at startup.
+    started: Value,
+}
+
+impl Server {
+    fn start() -> Server {
+        Server::start_with(&["--token", TOKEN])
+    }
+
+    fn start_with(args: &[&str]) -> Server {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_autoanchor"))
+            .args(["serve", "--http", "0"])
+            .args(args)
+            .env_remove("AUTOANCHOR_TOKEN")
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("server should start");
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        let started: Value = serde_json::from_str(&line).unwrap();
+        let address = started["data"]["address"].as_str().unwrap().to_string();
+        Server {
+            child,
+            address,
+            started,
+        }
+    }
+
+    /// Sends a raw request and returns the status and the whole response.
+    fn send(&self, request: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(&self.address).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        (response[9..12].parse().unwrap(), response)
+    }
+
+    /// Sends a raw request and returns the status and the response head, leaving the
+    /// connection open only as long as that takes.
+    fn send_for_head(&self, request: &str) -> (u16, String) {
+        let stream = TcpStream::connect(&self.address).unwrap();
+        (&stream).write_all(request.as_bytes()).unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut head = String::new();
+        while !head.ends_with("\r\n\r\n") && reader.read_line(&mut head).unwrap() > 0 {}
+        (head[9..12].parse().unwrap(), head)
+    }
+
+    fn post(&self, path: &str, body: &Value) -> (u16, Value) {
+        let body = body.to_string();
+        let (status, response) = self.send(&format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            path,
+            self.address,
+            TOKEN,
+            body.len(),
+            body
+        ));
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap_or(Value::Null))
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn runs_methods_over_rest_and_json_rpc() {
+    let server = Server::start();
+    let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/login_form.png");
+
+    let (status, body) = server.post("/hash", &json!({ "file": fixture }));
+    assert_eq!(status, 200);
+    assert!(body["data"]["hash"].as_str().unwrap().starts_with("dhash:"));
+
+    let (status, body) = server.post("/process/running", &json!({ "pid": std::process::id() }));
+    assert_eq!((status, &body["data"]), (200, &json!(true)));
+
+    let (status, body) = server.post(
+        "/rpc",
+        &json!({"jsonrpc": "2.0", "id": 9, "method": "hash", "params": {"file": fixture}}),
+    );
+    assert_eq!((status, &body["id"]), (200, &json!(9)));
+
+    let (status, body) = server.post("/no-such-method", &json!({}));
+    assert_eq!((status, &body["data"]["code"]), (404, &json!(-32601)));
+    let (status, _) = server.post("/hash", &json!({ "file": "/nonexistent.png" }));
+    assert_eq!(status, 500);
+}
+
+/// The head of a WebSocket handshake for `path`, ending in `extra` headers.
+fn upgrade(path: &str, host: &str, extra: &str) -> String {
+    format!(
+        concat!(
+            "GET {} HTTP/1.1\r\n",
+            "Host: {}\r\n",
+            "Authorization: Bearer {}\r\n",
+            "Connection: Upgrade\r\n",
+            "Upgrade: websocket\r\n",
+            "Sec-WebSocket-Version: 13\r\n",
+            "Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n",
+            "{}\r\n",
+        ),
+        path, host, TOKEN, extra
+    )
+}
+
+#[test]
+fn requires_the_bearer_token() {
+    let server = Server::start();
+    let (status, response) = server.send(&format!(
+        "POST /process/running HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: 10\r\n\r\n{{\"pid\": 1}}",
+        server.address
+    ));
+    assert_eq!(status, 401);
+    assert!(response.contains("WWW-Authenticate: Bearer"));
+
+    let (status, response) = server.send(&format!(
+        "POST /process/running?access_token={} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: 10\r\n\r\n{{\"pid\": 1}}",
+        TOKEN, server.address
+    ));
+    assert_eq!(status, 200, "{}", response);
+}
+
+#[test]
+fn generates_a_token_when_none_is_given() {
+    let server = Server::start_with(&[]);
+    let token = server.started["data"]["token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert!(token.len() >= 32);
+    let request = |token: &str| {
+        format!(
+            "POST /process/running HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {}\r\nContent-Type: application/json\r\nContent-Length: 10\r\n\r\n{{\"pid\": 1}}",
+            server.address, token
+        )
+    };
+    assert_eq!(server.send(&request("")).0, 401);
+    assert_eq!(server.send(&request(&token)).0, 200);
+
+    // A given token is not echoed
+    assert_eq!(Server::start().started["data"].get("token"), None);
+}
+
+#[test]
+fn refuses_foreign_hosts() {
+    let server = Server::start();
+    let port = server.address.rsplit_once(':').unwrap().1;
+    let request = |host: &str| {
+        format!(
+            "POST /process/running HTTP/1.1\r\n{}Authorization: Bearer {}\r\nContent-Type: application/json\r\nContent-Length: 10\r\n\r\n{{\"pid\": 1}}",
+            host, TOKEN
+        )
+    };
+    assert_eq!(
+        server
+            .send(&request(&format!("Host: localhost:{}\r\n", port)))
+            .0,
+        200
+    );
+    // What a page on a rebound domain sends
+    let (status, response) = server.send(&request(&format!("Host: attacker.example:{}\r\n", port)));
+    assert_eq!(status, 403, "{}", response);
+    assert_eq!(server.send(&request("Host: localhost\r\n")).0, 403);
+    assert_eq!(server.send(&request("")).0, 403);
+    assert_eq!(
+        server
+            .send_for_head(&upgrade("/ws/events", "attacker.example", ""))
+            .0,
+        403
+    );
+}
+
+#[test]
+fn refuses_requests_from_web_pages() {
+    let server = Server::start_with(&["--token", TOKEN, "--allow-origin", "http://localhost:3000"]);
+    let (status, response) = server.send(&format!(
+        "POST /process/running HTTP/1.1\r\nHost: {}\r\nOrigin: http://localhost:3000\r\nAuthorization: Bearer {}\r\nContent-Type: application/json\r\nContent-Length: 10\r\n\r\n{{\"pid\": 1}}",
+        server.address, TOKEN
+    ));
+    assert_eq!(status, 403, "{}", response);
+
+    let from = |origin: &str| format!("Origin: {}\r\n", origin);
+    let (status, _) = server.send_for_head(&upgrade(
+        "/ws/screenshots",
+        &server.address,
+        &from("https://attacker.example"),
+    ));
+    assert_eq!(status, 403);
+    let (status, response) = server.send_for_head(&upgrade(
+        "/ws/screenshots",
+        &server.address,
+        &from("http://localhost:3000"),
+    ));
+    assert_eq!(status, 101, "{}", response);
+}
+
+#[test]
+fn requires_json_bodies() {
+    let server = Server::start();
+    let request = |content_type: &str| {
+        format!(
+            "POST /process/running HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {}\r\n{}Content-Length: 10\r\n\r\n{{\"pid\": 1}}",
+            server.address, TOKEN, content_type
+        )
+    };
+    // What a page's simple cross-site form or fetch can send without a preflight
+    assert_eq!(server.send(&request("Content-Type: text/plain\r\n")).0, 415);
+    assert_eq!(server.send(&request("")).0, 415);
+    assert_eq!(
+        server
+            .send(&request(
+                "Content-Type: application/json; charset=utf-8\r\n"
+            ))
+            .0,
+        200
+    );
+}
+
+#[test]
+fn calls_methods_only_with_post() {
+    let server = Server::start();
+    let (status, response) = server.send(&format!(
+        "GET /process/running?pid=1 HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {}\r\n\r\n",
+        server.address, TOKEN
+    ));
+    assert_eq!(status, 405, "{}", response);
+}
+
+#[test]
+fn upgrades_streams_to_websocket() {
+    let server = Server::start();
+    let (status, response) = server.send_for_head(&upgrade("/ws/screenshots", &server.address, ""));
+    assert_eq!(status, 101, "{}", response);
+    assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+    let (status, _) = server.send(&format!(
+        "GET /ws/events HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {}\r\n\r\n",
+        server.address, TOKEN
+    ));
+    assert_eq!(status, 426);
+}
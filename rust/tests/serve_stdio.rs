@@ -0,0 +1,135 @@
+// Drives `autoanchor serve --stdio` through a pipe with requests that need no display.
+
+use serde_json::{json, Value};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn serve(requests: &[Value]) -> Vec<Value> {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_autoanchor"))
+        .args(["serve", "--stdio"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("server should start");
+    let mut stdin = server.stdin.take().unwrap();
+    for request in requests {
+        writeln!(stdin, "{}", request).unwrap();
+    }
+    drop(stdin);
+    let output = server.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn maps_methods_and_params_to_subcommands() {
+    let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/login_form.png");
+    let responses = serve(&[
+        json!({"jsonrpc": "2.0", "id": 1, "method": "hash", "params": {"file": fixture, "algo": "ahash"}}),
+        json!({"jsonrpc": "2.0", "method": "process.running", "params": {"pid": 1}}),
+        json!({"jsonrpc": "2.0", "id": "two", "method": "hash", "params": ["--file", fixture]}),
+        json!({"jsonrpc": "2.0", "id": 3, "method": "process.running", "params": {"pid": std::process::id()}}),
+    ]);
+
+    assert_eq!(responses.len(), 3);
+    assert_eq!(responses[0]["id"], 1);
+    let hash = responses[0]["result"]["data"]["hash"].as_str().unwrap();
+    assert!(hash.starts_with("ahash:"), "{}", hash);
+    assert_eq!(responses[1]["id"], "two");
+    assert!(responses[1]["result"]["data"]["hash"]
+        .as_str()
+        .unwrap()
+        .starts_with("dhash:"));
+    assert_eq!(responses[2]["result"]["data"], true);
+}
+
+#[test]
+fn reports_structured_errors() {
+    let responses = serve(&[
+        json!({"jsonrpc": "2.0", "id": 1, "method": "window"}),
+        json!({"jsonrpc": "2.0", "id": 2, "method": "hash", "params": {"colour": "red"}}),
+        json!({"jsonrpc": "2.0", "id": 3, "method": "hash", "params": {"file": "/nonexistent.png"}}),
+        json!({"jsonrpc": "2.0", "id": 4, "method": "listen", "params": {"count": 1}}),
+        json!({"jsonrpc": "2.0", "id": 5, "method": "serve", "params": {"stdio": true}}),
+    ]);
+
+    let codes: Vec<i64> = responses
+        .iter()
+        .map(|response| response["error"]["code"].as_i64().unwrap())
+        .collect();
+    assert_eq!(codes, [-32601, -32602, -32000, -32602, -32602]);
+    assert_eq!(
+        responses[1]["error"]["message"],
+        "Unknown parameter for hash: colour"
+    );
+}
+
+#[test]
+fn refuses_global_flags_per_call() {
+    let responses = serve(&[
+        json!({"jsonrpc": "2.0", "id": 1, "method": "process.running", "params": {"pid": 1, "no_failsafe": true}}),
+        json!({"jsonrpc": "2.0", "id": 2, "method": "process.running", "params": ["1", "--yield-on-activity", "abort"]}),
+        json!({"jsonrpc": "2.0", "id": 3, "method": "process.running", "params": ["1", "--resume-after-ms", "10"]}),
+        json!({"jsonrpc": "2.0", "id": 4, "method": "process.running", "params": {"pid": 1, "masks": "masks.json"}}),
+    ]);
+
+    let refused: Vec<(i64, &str)> = responses
+        .iter()
+        .map(|response| {
+            (
+                response["error"]["code"].as_i64().unwrap(),
+                response["error"]["message"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    let message = |flag| {
+        format!(
+            "--{} applies to the whole server; pass it to autoanchor serve instead",
+            flag
+        )
+    };
+    assert_eq!(
+        refused,
+        [
+            (-32602, message("no-failsafe").as_str()),
+            (-32602, message("yield-on-activity").as_str()),
+            (-32602, message("resume-after-ms").as_str()),
+            (-32602, message("masks").as_str()),
+        ]
+    );
+}
+
+#[test]
+fn refuses_calls_that_would_block_forever() {
+    let responses = serve(&[
+        json!({"jsonrpc": "2.0", "id": 1, "method": "hotkey.wait", "params": ["ctrl+alt+p"]}),
+        json!({"jsonrpc": "2.0", "id": 2, "method": "record", "params": {"output": "out.gif"}}),
+    ]);
+
+    let refused: Vec<(i64, &str)> = responses
+        .iter()
+        .map(|response| {
+            (
+                response["error"]["code"].as_i64().unwrap(),
+                response["error"]["message"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        refused,
+        [
+            (
+                -32602,
+                "hotkey.wait needs a timeout when served, as stdin cannot stop it"
+            ),
+            (
+                -32602,
+                "Recording needs a duration when served, as stdin cannot stop it"
+            ),
+        ]
+    );
+}
@@ -19,6 +19,8 @@ fn take_screenshot_writes_png_to_disk() {
             fs::write(target, &bytes).expect("failed to write screenshot to disk");
             println!("Wrote screenshot to {}", target);
         }
+        Err(e) => panic!("take_screenshot failed: {}", e),
+    }
 
     // Also test active-window capture if available
     match take_screenshot(true) {
@@ -32,6 +34,4 @@ fn take_screenshot_writes_png_to_disk() {
         }
         Err(e) => println!("Active-window screenshot unavailable: {}", e),
     }
-        Err(e) => panic!("take_screenshot failed: {}", e),
-    }
 }
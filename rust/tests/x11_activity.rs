@@ -0,0 +1,46 @@
+// User activity tests against a real X server with the XTEST and MIT-SCREEN-SAVER
+// extensions, e.g. `Xvfb :99 & DISPLAY=:99 cargo test`; skipped when DISPLAY is unset.
+#![cfg(target_os = "linux")]
+
+use autoanchor_core::activity::{self, YieldOnActivity, YieldPolicy};
+use autoanchor_core::events::{self, EventKind};
+use autoanchor_core::AutomationError;
+use std::ptr;
+use std::time::Duration;
+use x11::xlib;
+
+/// Moves the pointer the way another client would, behind the library's back.
+fn warp_pointer(x: i32, y: i32) {
+    unsafe {
+        let display = xlib::XOpenDisplay(ptr::null());
+        assert!(!display.is_null());
+        let root = xlib::XDefaultRootWindow(display);
+        xlib::XWarpPointer(display, 0, root, 0, 0, 0, 0, x, y);
+        xlib::XSync(display, xlib::False);
+        xlib::XCloseDisplay(display);
+    }
+}
+
+#[test]
+fn moving_the_cursor_away_aborts_next_action() {
+    if std::env::var_os("DISPLAY").is_none() {
+        return;
+    }
+
+    events::inject(&EventKind::MouseMove { x: 200, y: 200 }).unwrap();
+    assert!(activity::idle_time().unwrap() < Duration::from_secs(1));
+
+    activity::set_yield_on_activity(YieldOnActivity {
+        policy: YieldPolicy::Abort,
+        ..YieldOnActivity::default()
+    });
+    events::inject(&EventKind::MouseMove { x: 210, y: 210 }).unwrap();
+    events::inject(&EventKind::MouseMove { x: 220, y: 220 }).unwrap();
+
+    warp_pointer(400, 300);
+    let error = events::inject(&EventKind::MouseMove { x: 230, y: 230 }).unwrap_err();
+    assert!(AutomationError::is_user_activity(&error), "{}", error);
+
+    activity::set_yield_on_activity(YieldOnActivity::default());
+    events::inject(&EventKind::MouseMove { x: 230, y: 230 }).unwrap();
+}
@@ -0,0 +1,55 @@
+// Clipboard tests against a real X server, e.g. `Xvfb :99 & DISPLAY=:99 cargo test`;
+// skipped when DISPLAY is unset. One test, since they would all share the clipboard.
+#![cfg(target_os = "linux")]
+
+use autoanchor_core::clipboard;
+use image::{Rgba, RgbaImage};
+use std::path::PathBuf;
+
+#[test]
+fn contents_round_trip_through_clipboard() {
+    if std::env::var_os("DISPLAY").is_none() {
+        return;
+    }
+
+    clipboard::set_text("grüße, clipboard").unwrap();
+    assert_eq!(
+        clipboard::get_text().unwrap().as_deref(),
+        Some("grüße, clipboard")
+    );
+
+    // Large enough to need the incremental (INCR) transfer
+    let long: String = (0..200_000)
+        .map(|i| char::from(b'a' + (i % 26) as u8))
+        .collect();
+    clipboard::set_text(&long).unwrap();
+    assert_eq!(clipboard::get_text().unwrap(), Some(long));
+
+    clipboard::set_html("<p>Hello <b>world</b></p>", None).unwrap();
+    assert_eq!(
+        clipboard::get_html().unwrap().as_deref(),
+        Some("<p>Hello <b>world</b></p>")
+    );
+    assert_eq!(
+        clipboard::get_text().unwrap().as_deref(),
+        Some("Hello world")
+    );
+    let formats = clipboard::formats().unwrap();
+    assert!(formats.iter().any(|f| f == "text/html"), "{:?}", formats);
+
+    let image = RgbaImage::from_fn(40, 30, |x, y| Rgba([x as u8 * 6, y as u8 * 8, 90, 255]));
+    clipboard::set_image(&image).unwrap();
+    assert_eq!(clipboard::get_image().unwrap(), Some(image));
+    assert_eq!(clipboard::get_text().unwrap(), None);
+
+    let files = vec![
+        PathBuf::from("/tmp/report 1.pdf"),
+        PathBuf::from("/etc/hosts"),
+    ];
+    clipboard::set_files(&files).unwrap();
+    assert_eq!(clipboard::get_files().unwrap(), Some(files));
+
+    clipboard::clear().unwrap();
+    assert_eq!(clipboard::get_text().unwrap(), None);
+    clipboard::wait_until_replaced();
+}
@@ -0,0 +1,104 @@
+// Input event tests against a real X server with the RECORD and XTEST extensions, e.g.
+// `Xvfb :99 & DISPLAY=:99 cargo test`; skipped when DISPLAY is unset.
+#![cfg(target_os = "linux")]
+
+use autoanchor_core::events::{self, EventKind};
+use std::time::{Duration, Instant};
+
+#[test]
+fn injected_input_reaches_listener() {
+    if std::env::var_os("DISPLAY").is_none() {
+        return;
+    }
+
+    let (listener, received) = events::listen().unwrap();
+    let sent = [
+        EventKind::MouseMove { x: 40, y: 30 },
+        EventKind::ButtonDown {
+            button: "left".to_string(),
+            x: 40,
+            y: 30,
+        },
+        EventKind::ButtonUp {
+            button: "left".to_string(),
+            x: 40,
+            y: 30,
+        },
+        EventKind::Wheel {
+            dx: 0,
+            dy: -1,
+            x: 40,
+            y: 30,
+        },
+        EventKind::KeyDown {
+            key: "shift".to_string(),
+            modifiers: Vec::new(),
+        },
+        EventKind::KeyDown {
+            key: "a".to_string(),
+            modifiers: Vec::new(),
+        },
+        EventKind::KeyUp {
+            key: "a".to_string(),
+            modifiers: Vec::new(),
+        },
+        EventKind::KeyUp {
+            key: "shift".to_string(),
+            modifiers: Vec::new(),
+        },
+    ];
+    for event in &sent {
+        events::inject(event).unwrap();
+    }
+
+    // Motion is left out: moving to where the pointer already is may not produce any
+    let mut seen = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while seen.len() < sent.len() - 1 && Instant::now() < deadline {
+        if let Ok(event) = received.recv_timeout(Duration::from_millis(100)) {
+            if !event.kind.is_motion() {
+                seen.push(event.kind);
+            }
+        }
+    }
+    listener.stop().unwrap();
+
+    let with_shift = vec!["shift".to_string()];
+    assert_eq!(
+        seen,
+        [
+            EventKind::ButtonDown {
+                button: "left".to_string(),
+                x: 40,
+                y: 30,
+            },
+            EventKind::ButtonUp {
+                button: "left".to_string(),
+                x: 40,
+                y: 30,
+            },
+            EventKind::Wheel {
+                dx: 0,
+                dy: -1,
+                x: 40,
+                y: 30,
+            },
+            EventKind::KeyDown {
+                key: "shift".to_string(),
+                modifiers: Vec::new(),
+            },
+            EventKind::KeyDown {
+                key: "a".to_string(),
+                modifiers: with_shift.clone(),
+            },
+            EventKind::KeyUp {
+                key: "a".to_string(),
+                modifiers: with_shift.clone(),
+            },
+            EventKind::KeyUp {
+                key: "shift".to_string(),
+                modifiers: with_shift,
+            },
+        ]
+    );
+}
@@ -0,0 +1,35 @@
+// Fail-safe tests against a real X server with the XTEST extension, e.g.
+// `Xvfb :99 & DISPLAY=:99 cargo test`; skipped when DISPLAY is unset.
+#![cfg(target_os = "linux")]
+
+use autoanchor_core::events::{self, EventKind};
+use autoanchor_core::failsafe::{self, FailSafe};
+use autoanchor_core::AutomationError;
+
+#[test]
+fn cursor_in_corner_aborts_input() {
+    if std::env::var_os("DISPLAY").is_none() {
+        return;
+    }
+
+    // Off until enabled
+    events::inject(&EventKind::MouseMove { x: 0, y: 0 }).unwrap();
+    events::inject(&EventKind::MouseMove { x: 50, y: 50 }).unwrap();
+
+    failsafe::set_failsafe(FailSafe {
+        enabled: true,
+        ..FailSafe::default()
+    });
+    events::inject(&EventKind::MouseMove { x: 0, y: 0 }).unwrap();
+    let error = events::inject(&EventKind::MouseMove { x: 60, y: 60 }).unwrap_err();
+    assert!(AutomationError::is_fail_safe(&error), "{}", error);
+    // Releases still go through
+    let release = EventKind::KeyUp {
+        key: "shift".to_string(),
+        modifiers: Vec::new(),
+    };
+    events::inject(&release).unwrap();
+
+    failsafe::set_failsafe(FailSafe::default());
+    events::inject(&EventKind::MouseMove { x: 60, y: 60 }).unwrap();
+}
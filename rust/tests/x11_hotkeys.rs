@@ -0,0 +1,51 @@
+// Hotkey tests against a real X server with the XTEST extension, e.g.
+// `Xvfb :99 & DISPLAY=:99 cargo test`; skipped when DISPLAY is unset.
+#![cfg(target_os = "linux")]
+
+use autoanchor_core::events::{self, EventKind};
+use autoanchor_core::hotkey::{self, register_hotkey};
+use std::sync::mpsc;
+use std::time::Duration;
+
+fn key(down: bool, key: &str) -> EventKind {
+    let (key, modifiers) = (key.to_string(), Vec::new());
+    if down {
+        EventKind::KeyDown { key, modifiers }
+    } else {
+        EventKind::KeyUp { key, modifiers }
+    }
+}
+
+fn press_chord(keys: &[&str]) {
+    for name in keys {
+        events::inject(&key(true, name)).unwrap();
+    }
+    for name in keys.iter().rev() {
+        events::inject(&key(false, name)).unwrap();
+    }
+}
+
+#[test]
+fn registered_hotkey_fires_once_per_press() {
+    if std::env::var_os("DISPLAY").is_none() {
+        return;
+    }
+
+    let (sender, pressed) = mpsc::channel();
+    let handle = register_hotkey("ctrl+alt+p", move || {
+        let _ = sender.send(());
+    })
+    .unwrap();
+    // The same keys cannot be grabbed twice
+    assert!(register_hotkey("alt+ctrl+p", || {}).is_err());
+
+    // Other modifiers do not trigger it
+    press_chord(&["ctrl", "p"]);
+    press_chord(&["ctrl", "alt", "p"]);
+    pressed.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(pressed.recv_timeout(Duration::from_millis(200)).is_err());
+
+    hotkey::unregister_hotkey(handle).unwrap();
+    press_chord(&["ctrl", "alt", "p"]);
+    assert!(pressed.recv_timeout(Duration::from_millis(200)).is_err());
+}
@@ -0,0 +1,158 @@
+// Cursor, click, keyboard and capture tests against a real X server with the XTEST and
+// RECORD extensions and an EWMH window manager, e.g. `Xvfb :99 & DISPLAY=:99 openbox &`;
+// skipped when DISPLAY is unset.
+#![cfg(target_os = "linux")]
+
+use autoanchor_core::events::{self, EventKind};
+use autoanchor_core::window::{self, WindowMatcher, WindowTarget};
+use autoanchor_core::{CaptureOptions, Point, Rect};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use x11::xlib;
+
+/// Both tests move the pointer, so they take turns.
+static POINTER: Mutex<()> = Mutex::new(());
+
+/// A plain red top-level window.
+struct RedWindow {
+    display: *mut xlib::Display,
+    id: xlib::Window,
+}
+
+impl RedWindow {
+    fn open(title: &str) -> RedWindow {
+        unsafe {
+            let display = xlib::XOpenDisplay(std::ptr::null());
+            assert!(!display.is_null());
+            let root = xlib::XDefaultRootWindow(display);
+            let id = xlib::XCreateSimpleWindow(display, root, 80, 90, 240, 160, 0, 0, 0xff0000);
+            let title = std::ffi::CString::new(title).unwrap();
+            xlib::XStoreName(display, id, title.as_ptr());
+            xlib::XMapRaised(display, id);
+            xlib::XSync(display, xlib::False);
+            RedWindow { display, id }
+        }
+    }
+}
+
+impl Drop for RedWindow {
+    fn drop(&mut self) {
+        unsafe {
+            xlib::XDestroyWindow(self.display, self.id);
+            xlib::XCloseDisplay(self.display);
+        }
+    }
+}
+
+fn key(down: bool, key: &str, modifiers: &[&str]) -> EventKind {
+    let key = key.to_string();
+    let modifiers = modifiers.iter().map(|m| m.to_string()).collect();
+    if down {
+        EventKind::KeyDown { key, modifiers }
+    } else {
+        EventKind::KeyUp { key, modifiers }
+    }
+}
+
+#[test]
+fn moves_clicks_and_types() {
+    if std::env::var_os("DISPLAY").is_none() {
+        return;
+    }
+    let _turn = POINTER.lock().unwrap_or_else(|e| e.into_inner());
+
+    autoanchor_core::move_cursor(123, 77).unwrap();
+    assert_eq!(
+        autoanchor_core::get_cursor_position().unwrap(),
+        Point { x: 123, y: 77 }
+    );
+
+    let (listener, received) = events::listen().unwrap();
+    autoanchor_core::click("left", Some(60), Some(50)).unwrap();
+    autoanchor_core::type_text("aB", Some(0)).unwrap();
+    autoanchor_core::press_key("s", Some(vec!["ctrl".to_string()])).unwrap();
+    let expected = vec![
+        EventKind::ButtonDown {
+            button: "left".to_string(),
+            x: 60,
+            y: 50,
+        },
+        EventKind::ButtonUp {
+            button: "left".to_string(),
+            x: 60,
+            y: 50,
+        },
+        key(true, "a", &[]),
+        key(false, "a", &[]),
+        key(true, "shift", &[]),
+        key(true, "b", &["shift"]),
+        key(false, "b", &["shift"]),
+        key(false, "shift", &["shift"]),
+        key(true, "ctrl", &[]),
+        key(true, "s", &["ctrl"]),
+        key(false, "s", &["ctrl"]),
+        key(false, "ctrl", &["ctrl"]),
+    ];
+
+    let mut seen = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while seen.len() < expected.len() && Instant::now() < deadline {
+        if let Ok(event) = received.recv_timeout(Duration::from_millis(100)) {
+            if !event.kind.is_motion() {
+                seen.push(event.kind);
+            }
+        }
+    }
+    listener.stop().unwrap();
+    assert_eq!(seen, expected);
+    assert_eq!(
+        autoanchor_core::get_cursor_position().unwrap(),
+        Point { x: 60, y: 50 }
+    );
+}
+
+#[test]
+fn moves_to_and_captures_within_a_window() {
+    if std::env::var_os("DISPLAY").is_none() {
+        return;
+    }
+    let _turn = POINTER.lock().unwrap_or_else(|e| e.into_inner());
+    let test = RedWindow::open("autoanchor input test");
+
+    let target = WindowTarget::Matcher(WindowMatcher::title("^autoanchor input test$"));
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let found = loop {
+        if let Some(found) = window::find_window(&WindowMatcher::title("^autoanchor input test$"))
+            .unwrap()
+            .filter(|found| found.visible)
+        {
+            break found;
+        }
+        assert!(Instant::now() < deadline, "window never showed up");
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    assert_eq!(found.id, test.id);
+
+    autoanchor_core::move_cursor_in_window(&target, 30, 20).unwrap();
+    assert_eq!(
+        autoanchor_core::get_cursor_position().unwrap(),
+        Point {
+            x: found.client_area.x + 30,
+            y: found.client_area.y + 20,
+        }
+    );
+
+    let image = autoanchor_core::capture(&CaptureOptions {
+        window: Some(target),
+        region: Some(Rect {
+            x: 10,
+            y: 10,
+            width: 50,
+            height: 40,
+        }),
+        ..CaptureOptions::default()
+    })
+    .unwrap();
+    assert_eq!(image.dimensions(), (50, 40));
+    assert!(image.pixels().all(|pixel| pixel.0 == [255, 0, 0, 255]));
+}
@@ -0,0 +1,40 @@
+// Input state tests against a real X server with the XTEST extension, e.g.
+// `Xvfb :99 & DISPLAY=:99 cargo test`; skipped when DISPLAY is unset.
+#![cfg(target_os = "linux")]
+
+use autoanchor_core::activity;
+use autoanchor_core::events::{self, EventKind};
+
+#[test]
+fn held_keys_and_buttons_are_reported() {
+    if std::env::var_os("DISPLAY").is_none() {
+        return;
+    }
+
+    let (key, modifiers) = ("shift".to_string(), Vec::new());
+    events::inject(&EventKind::KeyDown {
+        key: key.clone(),
+        modifiers: modifiers.clone(),
+    })
+    .unwrap();
+    events::inject(&EventKind::ButtonDown {
+        button: "right".to_string(),
+        x: 80,
+        y: 60,
+    })
+    .unwrap();
+    assert_eq!(activity::pressed_keys().unwrap(), ["shift"]);
+    assert_eq!(activity::pressed_buttons().unwrap(), ["right"]);
+
+    events::inject(&EventKind::ButtonUp {
+        button: "right".to_string(),
+        x: 80,
+        y: 60,
+    })
+    .unwrap();
+    events::inject(&EventKind::KeyUp { key, modifiers }).unwrap();
+    assert!(activity::pressed_keys().unwrap().is_empty());
+    assert!(activity::pressed_buttons().unwrap().is_empty());
+    assert!(activity::idle_time().unwrap().as_secs() < 1);
+    activity::lock_keys().unwrap();
+}
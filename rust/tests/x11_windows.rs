@@ -0,0 +1,201 @@
+// Window tests against a real X server. They need DISPLAY to point at a server with an
+// EWMH window manager, e.g. `Xvfb :99 & DISPLAY=:99 openbox &`, and are skipped otherwise.
+#![cfg(target_os = "linux")]
+
+use autoanchor_core::window::{self, WindowInfo, WindowMatcher};
+use std::ffi::CString;
+use std::os::raw::c_long;
+use std::time::{Duration, Instant};
+use x11::xlib;
+
+struct TestWindow {
+    display: *mut xlib::Display,
+    id: xlib::Window,
+}
+
+impl TestWindow {
+    fn open(title: &str, class: &str) -> Option<TestWindow> {
+        std::env::var_os("DISPLAY")?;
+        unsafe {
+            let display = xlib::XOpenDisplay(std::ptr::null());
+            if display.is_null() {
+                return None;
+            }
+            let root = xlib::XDefaultRootWindow(display);
+            let id = xlib::XCreateSimpleWindow(display, root, 40, 60, 320, 200, 0, 0, 0xffffff);
+
+            let utf8 = intern(display, "UTF8_STRING");
+            let title_bytes = title.as_bytes();
+            xlib::XChangeProperty(
+                display,
+                id,
+                intern(display, "_NET_WM_NAME"),
+                utf8,
+                8,
+                xlib::PropModeReplace,
+                title_bytes.as_ptr(),
+                title_bytes.len() as i32,
+            );
+            let class_bytes = format!("{}\0{}\0", class.to_lowercase(), class);
+            xlib::XChangeProperty(
+                display,
+                id,
+                xlib::XA_WM_CLASS,
+                xlib::XA_STRING,
+                8,
+                xlib::PropModeReplace,
+                class_bytes.as_ptr(),
+                class_bytes.len() as i32,
+            );
+            let pid = std::process::id() as c_long;
+            xlib::XChangeProperty(
+                display,
+                id,
+                intern(display, "_NET_WM_PID"),
+                xlib::XA_CARDINAL,
+                32,
+                xlib::PropModeReplace,
+                &pid as *const c_long as *const u8,
+                1,
+            );
+            xlib::XMapWindow(display, id);
+            xlib::XFlush(display);
+            Some(TestWindow { display, id })
+        }
+    }
+}
+
+impl Drop for TestWindow {
+    fn drop(&mut self) {
+        unsafe {
+            xlib::XDestroyWindow(self.display, self.id);
+            xlib::XCloseDisplay(self.display);
+        }
+    }
+}
+
+unsafe fn intern(display: *mut xlib::Display, name: &str) -> xlib::Atom {
+    let name = CString::new(name).unwrap();
+    xlib::XInternAtom(display, name.as_ptr(), xlib::False)
+}
+
+/// Waits for the window manager to pick the window up.
+fn wait_listed(matcher: &WindowMatcher) -> WindowInfo {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        if let Some(found) = window::find_window(matcher).unwrap() {
+            return found;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "window never showed up in the client list"
+        );
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn lists_and_finds_own_window() {
+    let Some(test) = TestWindow::open("autoanchor enumeration test", "AutoanchorTest") else {
+        return;
+    };
+
+    let found = wait_listed(&WindowMatcher::title("^autoanchor enumeration"));
+    assert_eq!(found.id, test.id);
+    assert_eq!(found.class, "AutoanchorTest");
+    assert_eq!(found.pid, Some(std::process::id()));
+    assert!(found.process_name.is_some());
+    assert_eq!(
+        (found.client_area.width, found.client_area.height),
+        (320, 200)
+    );
+    assert!(found.bounds.width >= found.client_area.width);
+
+    let by_pid = WindowMatcher {
+        pid: Some(std::process::id()),
+        class: Some("autoanchortest".to_string()),
+        ..WindowMatcher::default()
+    };
+    assert_eq!(
+        window::find_window(&by_pid).unwrap().map(|w| w.id),
+        Some(test.id)
+    );
+
+    let windows = window::list_windows().unwrap();
+    let z_orders: Vec<u32> = windows.iter().map(|w| w.z_order).collect();
+    assert_eq!(z_orders, (0..windows.len() as u32).collect::<Vec<_>>());
+}
+
+/// Polls until `check` accepts the window's current state.
+fn wait_until(id: u64, what: &str, check: impl Fn(&WindowInfo) -> bool) -> WindowInfo {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let current = window::list_windows()
+            .unwrap()
+            .into_iter()
+            .find(|w| w.id == id);
+        if let Some(info) = current.filter(|info| check(info)) {
+            return info;
+        }
+        assert!(Instant::now() < deadline, "window never {}", what);
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn moves_resizes_and_focuses_window() {
+    let Some(test) = TestWindow::open("autoanchor control test", "AutoanchorTest") else {
+        return;
+    };
+    let id = wait_listed(&WindowMatcher::title("^autoanchor control")).id;
+    assert_eq!(id, test.id);
+
+    window::move_window(id, 100, 120).unwrap();
+    wait_until(id, "moved", |w| (w.bounds.x, w.bounds.y) == (100, 120));
+
+    window::resize_window(id, 400, 250).unwrap();
+    wait_until(id, "resized", |w| {
+        (w.client_area.width, w.client_area.height) == (400, 250)
+    });
+
+    window::focus_window(id).unwrap();
+    assert_eq!(window::active_window().unwrap().map(|w| w.id), Some(id));
+    assert!(window::resize_window(id, 0, 10).is_err());
+}
+
+#[test]
+fn captures_window_contents() {
+    let Some(test) = TestWindow::open("autoanchor capture test", "AutoanchorTest") else {
+        return;
+    };
+    let id = wait_listed(&WindowMatcher::title("^autoanchor capture")).id;
+    assert_eq!(id, test.id);
+
+    let client = window::capture_window(id, false).unwrap();
+    assert_eq!(client.dimensions(), (320, 200));
+    assert_eq!(client.get_pixel(160, 100).0, [255, 255, 255, 255]);
+
+    let framed = window::capture_window(id, true).unwrap();
+    assert!(framed.width() >= client.width() && framed.height() >= client.height());
+}
+
+#[test]
+fn waits_for_window_to_appear_and_close() {
+    let matcher = WindowMatcher::title("^autoanchor wait test");
+    let opener = std::thread::spawn(|| {
+        std::thread::sleep(Duration::from_millis(200));
+        let test = TestWindow::open("autoanchor wait test", "AutoanchorTest");
+        std::thread::sleep(Duration::from_millis(500));
+        drop(test);
+    });
+    if std::env::var_os("DISPLAY").is_none() {
+        opener.join().unwrap();
+        return;
+    }
+
+    let found = window::wait_for_window(&matcher, Duration::from_secs(5)).unwrap();
+    assert_eq!(found.class, "AutoanchorTest");
+    window::wait_for_window_closed(&matcher, Duration::from_secs(5)).unwrap();
+    opener.join().unwrap();
+    assert!(window::wait_for_window(&matcher, Duration::from_millis(100)).is_err());
+}